@@ -0,0 +1,267 @@
+//! A browser client for public rooms, sharing the protocol, crypto and
+//! tickets with the native client so the two can talk.
+//!
+//!     import init, { join } from "./pkg/p2p_chat_browser.js";
+//!
+//!     await init();
+//!     const room = await join(ticket, "alice", event => console.log(event));
+//!     await room.send("hello");
+//!
+//! Events are objects shaped like the WebSocket gateway's (see
+//! gateway::Event): "chat", "name" and "left". Our own messages do not come
+//! back as events. Rooms that ask for proof of work drop what we send, as
+//! this client does not mint it.
+
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{bail, Result};
+use futures_lite::StreamExt;
+use iroh::{address_lookup::MemoryLookup, protocol::Router, Endpoint, EndpointId, SecretKey};
+use iroh_gossip::{
+    api::{Event, GossipReceiver, GossipSender},
+    net::Gossip,
+    proto::TopicId,
+};
+use js_sys::{Function, JSON};
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{future_to_promise, spawn_local};
+
+use chat::crypto::{
+    decrypt_message, encrypt_message, get_encryption_key, sign_about_me, sign_key_request, unwrap_sender_key,
+    verify_about_me, verify_key_request, wrap_sender_key, RoomKey, SenderKeys,
+};
+use chat::protocol::{ChatPayload, Message, MessageBody, Ticket};
+
+// ── Room ──────────────────────────────────────────────────────────────────────
+
+/*
+Struct:     -Member
+Purpose:    -What sending to and reading from the room takes, shared by the
+             Room JavaScript holds and the task receiving for it.
+
+Fields:
+            - GossipSender sender:  Broadcasts to the room.
+            - SecretKey secret_key:  Our identity, fresh for each join.
+            - TopicId topic:  The room.
+            - RoomKey key:  Derived from the topic, as in every public room.
+            - SenderKeys sender_keys:  Ours and those peers handed us.
+*/
+#[derive(Clone)]
+struct Member {
+    sender: GossipSender,
+    secret_key: SecretKey,
+    topic: TopicId,
+    key: RoomKey,
+    sender_keys: SenderKeys,
+}
+
+impl Member {
+    fn id(&self) -> EndpointId {
+        self.secret_key.public()
+    }
+
+    async fn broadcast(&self, message: MessageBody) -> Result<()> {
+        self.sender.broadcast(Message::new(message).to_vec().into()).await?;
+        Ok(())
+    }
+
+    async fn send(&self, text: String) -> Result<()> {
+        let Some(room) = self.key.current() else {
+            bail!("the room has no key");
+        };
+        let key = self.sender_keys.own(room.epoch);
+        let message = encrypt_message(&ChatPayload::text(text), &self.secret_key, &self.topic, key, rand::random())?;
+        self.sender.broadcast(message.to_vec().into()).await?;
+        Ok(())
+    }
+
+    /// Send our sender key to one peer, wrapped for them alone (see
+    /// gossip::hand_over).
+    async fn hand_over(&self, to: EndpointId) -> Result<()> {
+        let Some(room) = self.key.current() else {
+            return Ok(());
+        };
+        let own = self.sender_keys.own(room.epoch);
+        let (wrapped, nonce) = wrap_sender_key(&own, &room.key, &self.secret_key, to, &self.topic)?;
+        self.broadcast(MessageBody::SenderKey {
+            from: self.id(),
+            to,
+            room_epoch: room.epoch,
+            room_step: room.step,
+            key_id: own.epoch,
+            step: own.step,
+            wrapped,
+            nonce,
+        })
+        .await
+    }
+}
+
+/// A room joined from JavaScript.
+#[wasm_bindgen]
+pub struct Room {
+    member: Member,
+    router: Router,
+}
+
+#[wasm_bindgen]
+impl Room {
+    /// Send a text message; resolves once it is on its way.
+    pub fn send(&self, text: String) -> js_sys::Promise {
+        let member = self.member.clone();
+        future_to_promise(async move {
+            member.send(text).await.map_err(error)?;
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Tell the room we are going and close the connection.
+    pub async fn leave(self) -> Result<(), JsError> {
+        let from = self.member.id();
+        self.member.broadcast(MessageBody::Leave { from }).await.map_err(js_error)?;
+        self.router.shutdown().await.map_err(js_error)?;
+        Ok(())
+    }
+}
+
+// ── Joining ───────────────────────────────────────────────────────────────────
+
+/*
+Function:   -join
+Purpose:    -Join the public room a ticket names.
+
+Parameters:
+            - String ticket:  As the native client prints it.
+            - String name:  Shown to the room's members.
+            - Function on_event:  Called with each event.
+
+Details:
+            - Browsers cannot open UDP sockets, so the ticket's peers are
+              reached through their relays.
+            - Announces us and asks every member for their sender key, as
+              the native client does on joining; without a name members hold
+              our messages back.
+            - Private rooms are refused: they need the creator to admit us,
+              which this client does not do.
+*/
+#[wasm_bindgen]
+pub async fn join(ticket: String, name: String, on_event: Function) -> Result<Room, JsError> {
+    let ticket = Ticket::from_str(&ticket).map_err(js_error)?;
+    if ticket.private {
+        return Err(JsError::new("private rooms cannot be joined from the browser"));
+    }
+    let secret_key = SecretKey::from_bytes(&rand::random());
+    let endpoint = Endpoint::builder().secret_key(secret_key.clone()).bind().await.map_err(js_error)?;
+    let ticket_addrs = MemoryLookup::new();
+    for addr in &ticket.endpoints {
+        ticket_addrs.add_endpoint_info(addr.clone());
+    }
+    endpoint.address_lookup().add(ticket_addrs);
+    let gossip = Gossip::builder().spawn(endpoint.clone());
+    let router = Router::builder(endpoint).accept(iroh_gossip::ALPN, gossip.clone()).spawn();
+
+    let peers = ticket.endpoints.iter().map(|addr| addr.id).collect();
+    let (sender, receiver) = gossip.subscribe_and_join(ticket.topic, peers).await.map_err(js_error)?.split();
+    let member = Member {
+        sender,
+        secret_key,
+        topic: ticket.topic,
+        key: RoomKey::public(get_encryption_key(&ticket.topic)),
+        sender_keys: SenderKeys::default(),
+    };
+    let about = sign_about_me(name, &member.secret_key, &member.topic);
+    member.sender.broadcast(about.to_vec().into()).await.map_err(js_error)?;
+    let request = sign_key_request(&member.secret_key, &member.topic);
+    member.sender.broadcast(request.to_vec().into()).await.map_err(js_error)?;
+    spawn_local(receive(member.clone(), receiver, on_event));
+    Ok(Room { member, router })
+}
+
+/// Act on everything sent to the room until we leave it: hand out and
+/// collect sender keys, and report members and their messages.
+async fn receive(member: Member, mut receiver: GossipReceiver, on_event: Function) {
+    let mut names: HashMap<EndpointId, String> = HashMap::new();
+    while let Ok(Some(event)) = receiver.try_next().await {
+        let Event::Received(msg) = event else {
+            continue;
+        };
+        let Ok(message) = Message::from_bytes(&msg.content) else {
+            continue;
+        };
+        match message.body {
+            MessageBody::AboutMe { from, name, nonce, signature } => {
+                if verify_about_me((&name, nonce), signature.as_ref(), from, &member.topic).is_err() {
+                    continue;
+                }
+                emit(&on_event, json!({ "type": "name", "from": from.to_string(), "name": name }));
+                names.insert(from, name);
+            }
+            MessageBody::Leave { from } => {
+                emit(&on_event, json!({ "type": "left", "from": from.to_string() }));
+            }
+            MessageBody::KeyRequest { from, sent, nonce, signature } => {
+                if verify_key_request((sent, nonce), &signature, from, &member.topic).is_ok() {
+                    let _ = member.hand_over(from).await;
+                }
+            }
+            MessageBody::SenderKey { from, to, room_epoch, room_step, key_id, step, wrapped, nonce } => {
+                if to != member.id() {
+                    continue;
+                }
+                let Some(room_key) = member.key.for_message(room_epoch, room_step) else {
+                    continue;
+                };
+                let unwrapped =
+                    unwrap_sender_key(&wrapped, &nonce, (key_id, step), &room_key, &member.secret_key, from, &member.topic);
+                // Return the favour: they asked before we joined, or never.
+                if let Ok(key) = unwrapped
+                    && member.sender_keys.insert(from, key)
+                {
+                    let _ = member.hand_over(from).await;
+                }
+            }
+            MessageBody::EncryptedMessage { from, id, key_id, step, ciphertext, nonce, .. } => {
+                // Key ID 0 at step 0 is the topic key of older clients.
+                let key = if (key_id, step) == (0, 0) {
+                    member.key.for_message(0, 0)
+                } else {
+                    member.sender_keys.for_message(from, key_id, step)
+                };
+                let Some(payload) = key.and_then(|key| decrypt_message(&ciphertext, &nonce, &key).ok()) else {
+                    continue;
+                };
+                let sender = names.get(&from).cloned().unwrap_or_else(|| from.fmt_short().to_string());
+                emit(
+                    &on_event,
+                    json!({
+                        "type": "chat",
+                        "id": id.to_string(),
+                        "from": from.to_string(),
+                        "sender": sender,
+                        "content": payload.text,
+                        "at": String::from(js_sys::Date::new_0().to_iso_string()),
+                    }),
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+// ── JavaScript ────────────────────────────────────────────────────────────────
+
+/// Hand an event to the page as an object.
+fn emit(on_event: &Function, event: Value) {
+    if let Ok(event) = JSON::parse(&event.to_string()) {
+        let _ = on_event.call1(&JsValue::NULL, &event);
+    }
+}
+
+fn error(e: anyhow::Error) -> JsValue {
+    JsValue::from_str(&format!("{:#}", e))
+}
+
+fn js_error(e: impl std::fmt::Display) -> JsError {
+    JsError::new(&e.to_string())
+}
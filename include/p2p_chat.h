@@ -0,0 +1,102 @@
+#ifndef P2P_CHAT_H
+#define P2P_CHAT_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdint.h>
+
+typedef struct P2pChatClient P2pChatClient;
+
+#ifdef __cplusplus
+extern "C" {
+#endif  // __cplusplus
+
+/**
+ * Create a client running as the profile kept in `profile_dir` (created if
+ * missing), with `nickname`, or the profile's nickname if null. Returns
+ * null on failure. Free with p2p_chat_client_free.
+ *
+ * # Safety
+ *
+ * `profile_dir` must point to a NUL-terminated string, `nickname` too or
+ * be null.
+ */
+P2pChatClient *p2p_chat_client_new(const char *profile_dir, const char *nickname);
+
+/**
+ * Join the room a ticket names; blocks until the session is up. Returns 0,
+ * or -1 on failure (see p2p_chat_last_error).
+ *
+ * # Safety
+ *
+ * `client` must come from p2p_chat_client_new and not be freed; `ticket`
+ * must point to a NUL-terminated string.
+ */
+int p2p_chat_join(P2pChatClient *client, const char *ticket);
+
+/**
+ * The ticket others can join the room with, or null before joining. Free
+ * with p2p_chat_string_free.
+ *
+ * # Safety
+ *
+ * `client` must come from p2p_chat_client_new and not be freed.
+ */
+char *p2p_chat_ticket(const P2pChatClient *client);
+
+/**
+ * Send a text message to the room. Returns 0 once it is queued, or -1 on
+ * failure (see p2p_chat_last_error). It comes back as a "chat" event.
+ *
+ * # Safety
+ *
+ * `client` must come from p2p_chat_client_new and not be freed; `text`
+ * must point to a NUL-terminated string.
+ */
+int p2p_chat_send(P2pChatClient *client, const char *text);
+
+/**
+ * The next event, as the JSON the gateway sends (see gateway::Event),
+ * waiting up to `timeout_ms` for one. Null if none came or the client is
+ * not in a room. Free with p2p_chat_string_free.
+ *
+ * # Safety
+ *
+ * `client` must come from p2p_chat_client_new and not be freed.
+ */
+char *p2p_chat_poll_event(P2pChatClient *client, uint64_t timeout_ms);
+
+/**
+ * Why the last call on this client failed; empty if none has. Valid until
+ * the next call on the client; do not free.
+ *
+ * # Safety
+ *
+ * `client` must come from p2p_chat_client_new and not be freed.
+ */
+const char *p2p_chat_last_error(const P2pChatClient *client);
+
+/**
+ * Free a string this library returned.
+ *
+ * # Safety
+ *
+ * `text` must come from this library and not be freed already, or be null.
+ */
+void p2p_chat_string_free(char *text);
+
+/**
+ * Leave the room, sending what is still queued, and free the client.
+ *
+ * # Safety
+ *
+ * `client` must come from p2p_chat_client_new and not be freed already,
+ * or be null.
+ */
+void p2p_chat_client_free(P2pChatClient *client);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* P2P_CHAT_H */
@@ -0,0 +1,29 @@
+"""Join a room and repeat every message back to it.
+
+    cd python && maturin develop
+    python examples/echo_bot.py <ticket>
+"""
+
+import asyncio
+import sys
+
+import p2p_chat
+
+
+async def main(ticket: str) -> None:
+    client = await p2p_chat.join(ticket, "echo-bot-profile", nickname="echo")
+    try:
+        async for event in client:
+            if event["type"] == "chat":
+                await client.send(f"{event['sender']} said: {event['content']}")
+            elif event["type"] == "system":
+                print(event["text"])
+    finally:
+        await client.close()
+
+
+if __name__ == "__main__":
+    try:
+        asyncio.run(main(sys.argv[1]))
+    except KeyboardInterrupt:
+        pass
@@ -0,0 +1,166 @@
+//! The `p2p_chat` Python module: rooms from asyncio.
+//!
+//!     import asyncio, p2p_chat
+//!
+//!     async def main():
+//!         client = await p2p_chat.join(ticket, "bots/echo", nickname="echo")
+//!         async for event in client:
+//!             if event["type"] == "chat":
+//!                 await client.send("you said: " + event["content"])
+//!
+//! Events are the dicts the WebSocket gateway sends as JSON (see
+//! gateway::Event). Our own messages do not come back as events.
+
+use std::{str::FromStr, sync::Arc};
+
+use pyo3::exceptions::{PyRuntimeError, PyStopAsyncIteration};
+use pyo3::prelude::*;
+use pyo3_async_runtimes::tokio::future_into_py;
+use tokio::sync::Mutex;
+
+use chat::gateway::{self, Event};
+use chat::protocol::{ChatPayload, Ticket};
+use chat::{ChatClientBuilder, CryptoMode, Session, Subscription};
+
+// ── Client ────────────────────────────────────────────────────────────────────
+
+/*
+Struct:     -Client
+Purpose:    -A room as Python sees it.
+
+Fields:
+            - Arc<Mutex<Option<Session>>> session:  The room; None once
+              closed.
+            - Arc<Mutex<Subscription>> events:  Where events are read from,
+              apart from the session so sending never waits on a reader.
+            - String ticket:  Ticket others can join with.
+*/
+#[pyclass]
+struct Client {
+    session: Arc<Mutex<Option<Session>>>,
+    events: Arc<Mutex<Subscription>>,
+    ticket: String,
+}
+
+impl Client {
+    fn new(session: Session) -> Self {
+        Self {
+            events: Arc::new(Mutex::new(session.subscribe())),
+            ticket: session.ticket.to_string(),
+            session: Arc::new(Mutex::new(Some(session))),
+        }
+    }
+}
+
+#[pymethods]
+impl Client {
+    /// Ticket others can join the room with.
+    #[getter]
+    fn ticket(&self) -> String {
+        self.ticket.clone()
+    }
+
+    /// Send a text message; resolves to its ID.
+    fn send<'py>(&self, py: Python<'py>, text: String) -> PyResult<Bound<'py, PyAny>> {
+        let session = self.session.clone();
+        future_into_py(py, async move {
+            let session = session.lock().await;
+            let session = session.as_ref().ok_or_else(closed)?;
+            let msg = gateway::send(session, ChatPayload::text(text))
+                .await
+                .map_err(PyRuntimeError::new_err)?;
+            Ok(msg.id.to_string())
+        })
+    }
+
+    /// The next event as a dict; None once the client is closed.
+    fn next_event<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let events = self.events.clone();
+        future_into_py(py, async move { next_event(&events).await })
+    }
+
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let events = self.events.clone();
+        future_into_py(py, async move {
+            next_event(&events)
+                .await?
+                .ok_or_else(|| PyStopAsyncIteration::new_err(()))
+        })
+    }
+
+    /// Leave the room, sending what is still queued.
+    fn close<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let session = self.session.clone();
+        future_into_py(py, async move {
+            if let Some(session) = session.lock().await.take() {
+                session.shutdown().await.map_err(error)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+async fn next_event(events: &Mutex<Subscription>) -> PyResult<Option<PyObject>> {
+    let mut events = events.lock().await;
+    while let Some(ui) = events.recv().await {
+        if let Some(event) = Event::from_ui(&ui) {
+            let event = Python::with_gil(|py| -> PyResult<PyObject> {
+                Ok(py.import("json")?.call_method1("loads", (event.to_json(),))?.unbind())
+            })?;
+            return Ok(Some(event));
+        }
+    }
+    Ok(None)
+}
+
+fn error(e: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", e))
+}
+
+fn closed() -> PyErr {
+    PyRuntimeError::new_err("the client is closed")
+}
+
+// ── Functions ─────────────────────────────────────────────────────────────────
+
+/// Join the room a ticket names, running as the profile kept in
+/// `profile_dir` (created if missing).
+#[pyfunction]
+#[pyo3(signature = (ticket, profile_dir, nickname = None))]
+fn join(py: Python<'_>, ticket: String, profile_dir: String, nickname: Option<String>) -> PyResult<Bound<'_, PyAny>> {
+    let ticket = Ticket::from_str(&ticket).map_err(error)?;
+    future_into_py(py, async move {
+        let mut client = ChatClientBuilder::new().identity_dir(profile_dir).map_err(error)?.join(ticket);
+        if let Some(name) = nickname {
+            client = client.nickname(name);
+        }
+        Ok(Client::new(client.build().await.map_err(error)?))
+    })
+}
+
+/// Open a new room as its creator, private (members admitted one by one)
+/// if asked.
+#[pyfunction]
+#[pyo3(signature = (profile_dir, nickname = None, private = false))]
+fn open(py: Python<'_>, profile_dir: String, nickname: Option<String>, private: bool) -> PyResult<Bound<'_, PyAny>> {
+    let crypto = if private { CryptoMode::Private } else { CryptoMode::Public };
+    future_into_py(py, async move {
+        let mut client = ChatClientBuilder::new().identity_dir(profile_dir).map_err(error)?.open().crypto(crypto);
+        if let Some(name) = nickname {
+            client = client.nickname(name);
+        }
+        Ok(Client::new(client.build().await.map_err(error)?))
+    })
+}
+
+#[pymodule]
+fn p2p_chat(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Client>()?;
+    m.add_function(wrap_pyfunction!(join, m)?)?;
+    m.add_function(wrap_pyfunction!(open, m)?)?;
+    Ok(())
+}
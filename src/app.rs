@@ -1,3 +1,16 @@
+use std::{collections::HashMap, time::Instant};
+
+use chrono::{DateTime, Local, TimeDelta};
+use iroh::EndpointId;
+
+use crate::directory::Listing;
+use crate::history::{History, DEFAULT_HISTORY};
+use crate::i18n::t;
+use crate::keymap::{Chord, Keymap};
+use crate::notify::Rules;
+use crate::protocol::{ChatPayload, Component, Membership, Poll, Presence, Role, VoiceClip};
+use crate::transcript::TranscriptLog;
+
 // ── UI types ──────────────────────────────────────────────────────────────────
 
 /*
@@ -8,47 +21,236 @@ Fields:
             - u64 id:  Unique identifier for the message. Used for cooperative
               deletion across peers so that all participants can remove the
               same message consistently.
+            - EndpointId from:  The sender's endpoint, for addressing replies such
+              as component interactions.
             - String sender:  The display name or identifier of the message sender.
             - String content:  The textual content of the message.
-            - bool encrypted:  Indicates whether the message was received in
-              encrypted form (true) or plaintext (false).
+            - Option<Value> meta:  Machine-readable metadata attached by bots.
+              Not rendered; kept for plugins and integrations.
+            - Vec<Component> components:  Buttons/selects attached by bots.
+            - DateTime<Local> at:  When the message was received (or sent, for
+              our own), used in transcripts.
+            - Option<DateTime<Local>> expires:  For ephemeral messages, when
+              the message is removed from the history.
+            - Option<VoiceClip> voice:  Recorded audio; `p` in NORMAL mode
+              plays it.
+            - Option<Poll> poll:  A poll; rendered with its options and the
+              live tally, and voted in with 1-9 in NORMAL mode.
+            - bool failed:  Our own message that could not be broadcast even
+              after retrying; Ctrl+R sends it again.
+            - Option<Delivery> delivery:  How far our own message has got;
+              None for everyone else's.
+            - Trust trust:  How far the message can be attributed to its
+              sender; shown as a badge.
+            - bool highlight:  Marked by a filter (see filters::Filter); shown
+              and notified like a mention.
 
 Details:
             - This struct represents user-visible chat messages only.
             - The `id` field enables distributed deletion by uniquely identifying
               each message across the network.
-            - The `encrypted` flag can be used by the UI to visually indicate
-              whether the message was encrypted.
+            - A message that did not decrypt is kept as a placeholder with
+              Trust::Failed, so the failure shows against the right sender and
+              message ID (and a later delete still finds it).
 */
 #[derive(Debug, Clone)]
 pub struct ChatMessage {
     /// Unique ID used for cooperative deletion across peers.
     pub id: u64,
+    pub from: EndpointId,
     pub sender: String,
     pub content: String,
+    /// Bot/plugin metadata from the encrypted payload, if any.
+    pub meta: Option<serde_json::Value>,
+    pub components: Vec<Component>,
+    pub at: DateTime<Local>,
+    /// Removal time of an ephemeral message, counted from `at`.
+    pub expires: Option<DateTime<Local>>,
+    pub voice: Option<VoiceClip>,
+    /// Boxed: polls are rare and ChatEvent would otherwise grow for all.
+    pub poll: Option<Box<Poll>>,
+    pub failed: bool,
+    pub delivery: Option<Delivery>,
+    pub trust: Trust,
+    pub highlight: bool,
 }
 
+/*
+Enum:       -Delivery
+Purpose:    -How far one of our own messages has got, shown after it.
+
+Variants:
+            - Sending:  Handed to the send queue; not out yet. …
+            - Queued:  Held in the outbox because no peer is connected; sent
+              once one is. … pending
+            - Broadcast:  Sent to our gossip neighbours. ✓
+            - Delivered:  At least one member received it and said so. ✓✓
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Delivery {
+    Sending,
+    Queued,
+    Broadcast,
+    Delivered,
+}
 
 /*
-Enum:       -UiMessage
-Purpose:    -Represents all message types that can be rendered or processed by the UI.
+Enum:       -Trust
+Purpose:    -What decrypting a message tells us about who sent it.
+
+Variants:
+            - Verified:  Signed with the sender's endpoint key and decrypted
+              with their sender key (or it is ours). 🔒
+            - SharedKey:  Decrypted with a key other members hold too, and
+              not signed: the room key older clients send with, or a sender
+              key from a client that does not sign. Anyone in the room could
+              have written it. 🔓
+            - Failed:  Did not decrypt or failed authentication. ⚠
+            - Unencrypted:  Sent in plaintext by a client of the legacy
+              protocol; anyone could have written or read it. 🔓
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trust {
+    Verified,
+    SharedKey,
+    Failed,
+    Unencrypted,
+}
+
+impl ChatMessage {
+    /// Build a message received (or sent) now from a decrypted payload.
+    pub fn new(id: u64, from: EndpointId, sender: String, payload: ChatPayload) -> Self {
+        let at = Local::now();
+        let expires = payload
+            .ttl
+            .map(|ttl| at + TimeDelta::seconds(ttl as i64));
+        Self {
+            id,
+            from,
+            sender,
+            content: payload.text,
+            meta: payload.meta,
+            components: payload.components,
+            at,
+            expires,
+            voice: payload.voice,
+            poll: payload.poll.map(Box::new),
+            failed: false,
+            delivery: None,
+            trust: Trust::Verified,
+            highlight: false,
+        }
+    }
+
+    pub fn with_trust(mut self, trust: Trust) -> Self {
+        self.trust = trust;
+        self
+    }
+
+    pub fn with_delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = Some(delivery);
+        self
+    }
+
+    /// Move our message on to `delivery`. States only ever advance, since
+    /// an ack may overtake the news that the message went out.
+    fn advance(&mut self, delivery: Delivery) {
+        if self.delivery.is_some() {
+            self.delivery = self.delivery.max(Some(delivery));
+        }
+    }
+
+    /// Placeholder for a message that could not be decrypted.
+    pub fn undecryptable(id: u64, from: EndpointId, sender: String, error: impl std::fmt::Display) -> Self {
+        let mut msg = Self::new(id, from, sender, ChatPayload::text(t!("(could not decrypt: {})", error)));
+        msg.trust = Trust::Failed;
+        msg
+    }
+
+    /// The payload to send this message again with.
+    pub fn payload(&self) -> ChatPayload {
+        ChatPayload {
+            text: self.content.clone(),
+            meta: self.meta.clone(),
+            components: self.components.clone(),
+            ttl: self.expires.map(|expires| (expires - self.at).num_seconds() as u64),
+            voice: self.voice.clone(),
+            subject: None,
+            poll: self.poll.as_deref().cloned(),
+            vote: None,
+        }
+    }
+}
+
+
+/*
+Enum:       -ChatEvent
+Purpose:    -Everything a session tells its front ends: messages to render
+             and events to process.
 
 Variants:
             - Chat(ChatMessage):  A standard user chat message.
             - System(String):  A system-generated informational message.
             - Delete(u64):  Instruction to remove a chat message with the given ID.
+            - Pin { id, name, pinned }:  Member `name` pinned (or unpinned)
+              the message with this ID.
+            - Subject { name, subject }:  Member `name` changed the room topic;
+              empty when they cleared it.
+            - Vote { poll, from, choice }:  A member voted in the poll carried
+              by message `poll`.
+            - Name { from, name }:  A peer announced (or changed) its nickname.
+            - Presence { from, presence }:  A member (or we) went online,
+              away or busy.
+            - Left(EndpointId):  A peer quit the room.
+            - SendFailed(u64):  One of our messages could not be broadcast.
+            - Queued(u64):  One of our messages is waiting for a peer to
+              connect.
+            - Sent(u64):  One of our messages was broadcast.
+            - Delivered(u64):  A member acknowledged one of our messages.
+            - Recorded(VoiceClip):  A /voice recording finished; the TUI
+              sends it.
+            - Knock { from, name }:  Someone asks to join our private room.
+            - Membership(Membership):  The room's roles changed.
+            - Directory(Vec<Listing>):  Results of a /directory search.
+            - Qr(Vec<String>):  Rows of a QR code, drawn as-is (/ticket qr).
 
 Details:
             - This enum abstracts different kinds of UI events into a single type.
+            - Published on the session's event bus, so any number of front
+              ends may follow one room (see bus::Subscription).
             - The Delete variant is used to propagate message deletion events
               across peers and instruct the UI to remove the message locally.
             - System messages are informational and not associated with a user.
+            - Name updates are not displayed; they keep App's copy of the
+              names map in sync for mention completion.
 */
 #[derive(Debug, Clone)]
-pub enum UiMessage {
+pub enum ChatEvent {
     Chat(ChatMessage),
     System(String),
     Delete(u64),
+    Pin { id: u64, name: String, pinned: bool },
+    Subject { name: String, subject: String },
+    Vote { poll: u64, from: EndpointId, choice: u32 },
+    Name { from: EndpointId, name: String },
+    Presence { from: EndpointId, presence: Presence },
+    /// A peer quit the room (MessageBody::Leave).
+    Left(EndpointId),
+    /// Our message with this ID never made it out (see ChatMessage::failed).
+    SendFailed(u64),
+    /// Our message with this ID is held until a peer connects (see
+    /// ChatMessage::delivery).
+    Queued(u64),
+    /// Our message with this ID was broadcast.
+    Sent(u64),
+    /// A member received our message with this ID.
+    Delivered(u64),
+    /// A finished /voice recording, to send.
+    Recorded(VoiceClip),
+    Knock { from: EndpointId, name: String },
+    Membership(Membership),
+    Directory(Vec<Listing>),
+    Qr(Vec<String>),
 }
 
 // ── Modal editing ─────────────────────────────────────────────────────────────
@@ -62,19 +264,43 @@ Variants:
 
 Details:
             - Insert mode allows the user to compose messages normally.
-            - Normal mode enables command-style controls:
+            - Normal mode enables command-style controls (the default keys;
+              the [keys] table of the config can change most, see
+              keymap::Action):
                 - Ctrl+C: Quit the application.
-                - Ctrl+D: Delete the most recent message sent by this user.
+                - Ctrl+R: Resend the selected (or latest) message that failed.
+                - p: Play the selected (or latest) voice message.
+                - 1-9: Vote in the selected poll, or press that button of
+                  the selected message's components. Otherwise digits are a
+                  count for the motion after them, as in 5k.
+                - j/k or Down/Up: Move the selection to a newer/older message.
+                - gg/G: Select the oldest/newest message.
+                - u/b: Jump to the first unread message / back to the
+                  bottom.
+                - y: Copy the selected message to the clipboard.
+                - P: Pin or unpin the selected message for everyone (admins
+                  only, in rooms with a creator).
+                - z: Fold or unfold the pinned strip.
+                - Esc: Clear the selection.
+                - PageUp/PageDown: Scroll the history.
+                - Ctrl+U/Ctrl+D: Scroll the history by half a page.
+                - dd: Delete the selected message, if it is ours (admins may
+                  delete anyone's).
+                - D: Delete the most recent message sent by this user, or
+                  for admins the selected message, whoever sent it.
             - Mode switching allows for modal interaction similar to modal text editors.
 */
 #[derive(PartialEq)]
 pub enum Mode {
     /// Typing mode – keys go into the input buffer.
     Insert,
-    /// Command mode – Ctrl+C quits, Ctrl+D deletes last message everywhere.
+    /// Command mode – Ctrl+C quits, j/k select, dd deletes the selected message.
     Normal,
 }
 
+/// Largest count a NORMAL mode motion takes (999j).
+pub const MAX_COUNT: usize = 999;
+
 // ── App state ─────────────────────────────────────────────────────────────────
 /*
 Struct:     -App
@@ -82,12 +308,70 @@ Purpose:    -Maintains the complete runtime state of the chat user interface.
 
 Fields:
             - String input:  The current text input buffer.
-            - Vec<UiMessage> messages:  List of all messages displayed in the UI.
+            - History messages:  The messages displayed in the UI, newest
+              last, up to [display] history of them.
             - Mode mode:  Current interaction mode (Insert or Normal).
             - Vec<u64> my_sent_ids:  IDs of messages sent by this user, stored
               oldest-first to support cooperative deletion.
-            - usize scroll_offset:  Number of lines scrolled up from the bottom.
-              A value of 0 indicates the view is pinned to the newest messages.
+            - usize scroll_offset:  Number of rendered (wrapped) lines scrolled
+              up from the bottom. 0 means pinned to the newest messages.
+            - usize max_scroll:  Largest useful scroll_offset, set by the
+              renderer once it knows the wrapped height of the history.
+            - bool focused:  Whether this view is on screen in a focused
+              terminal window (false for background tabs).
+            - bool dnd:  "Do not disturb" – suppresses notifications when set.
+            - String my_name:  Our own nickname, used to detect mentions.
+            - HashMap<EndpointId, String> names:  Known peer nicknames, fed by
+              Name updates from the gossip loop.
+            - Rules notify:  When a received message notifies (see
+              should_notify).
+            - Option<(String, usize)> completion:  In-progress tab completion –
+              the typed prefix and the index of the candidate last inserted.
+            - usize unread:  Chat messages received while away, not yet seen.
+            - Option<u64> first_unread:  ID of the first message received in
+              the latest away period; the "new messages" separator is drawn
+              above it.
+            - Option<u64> selected:  ID of the chat message under the selection
+              cursor, if any. Tracked by ID so it survives history trimming.
+            - bool reveal_selection:  Scroll the selected message into view on
+              the next draw; set when the selection moves by keyboard.
+            - Keymap keys:  Key bindings, from the profile's [keys] table.
+            - Option<usize> count:  A count typed before a NORMAL mode motion
+              (the 5 of 5j), at most MAX_COUNT.
+            - Option<Chord> pending_key:  First key of a two-key NORMAL mode
+              command (the g of gg), waiting for the second.
+            - HashMap<(u64, String), String> choices:  Option we picked in each
+              select component, keyed by (message ID, component ID).
+            - Option<EndpointId> creator:  The room's creator, always an admin.
+            - Membership membership:  Latest role assignments, for badges and
+              permission checks.
+            - HashMap<EndpointId, String> knocks:  Join requests to our
+              private room awaiting /approve or /deny.
+            - Option<TranscriptLog> transcript:  Continuous transcript that every
+              displayed chat message is appended to (--log-transcript).
+            - Vec<Listing> directory:  Results of the last /directory search,
+              numbered for /directory join.
+            - Option<(String, String, Instant)> published:  Name and
+              description this room is listed under with /publish, and when
+              the listing was last sent.
+            - bool net_panel:  The /net connection diagnostics overlay is
+              open; Esc closes it.
+            - bool swarm_panel:  The /swarm overlay is open; Esc closes it.
+            - Vec<(u64, String)> pins:  Pinned messages, oldest pin first,
+              each with a "sender: text" snapshot taken when it was pinned so
+              it outlives history trimming.
+            - bool pins_collapsed:  The pinned strip under the header is
+              folded to one line (z in NORMAL mode).
+            - bool pins_panel:  The /pins overlay is open; Esc closes it.
+            - Option<String> subject:  The room topic, shown in the header and
+              written at the top of exported transcripts.
+            - HashMap<u64, HashMap<EndpointId, u32>> votes:  Each member's
+              latest pick in each poll, by poll message ID; ours included.
+            - HashMap<u64, u32> my_votes:  Our own picks, shown as checked.
+            - HashMap<EndpointId, Presence> presence:  Members who said they
+              are away or busy (or back); the rest are online.
+            - bool auto_away:  We are away only because no key was pressed
+              for a while; the next one brings us back.
 
 Details:
             - This struct acts as the central state container for the UI.
@@ -96,13 +380,55 @@ Details:
 */
 pub struct App {
     pub input: String,
-    pub messages: Vec<UiMessage>,
+    pub messages: History,
     pub mode: Mode,
     /// Tracks the IDs of messages *we* sent, oldest-first, so we can delete
-    /// the most recent one with Ctrl+D.
+    /// the most recent one with D.
     pub my_sent_ids: Vec<u64>,
     /// How many lines from the bottom we are scrolled. 0 = pinned to bottom.
     pub scroll_offset: usize,
+    /// Scroll limit from the last draw; depends on terminal width.
+    pub max_scroll: usize,
+    /// Visible in a focused terminal, per crossterm focus events and tabs.
+    pub focused: bool,
+    /// Do-not-disturb, toggled with /dnd.
+    pub dnd: bool,
+    pub my_name: String,
+    /// Peer nicknames learned from AboutMe, for @mention completion.
+    pub names: HashMap<EndpointId, String>,
+    pub notify: Rules,
+    /// Prefix being completed and the candidate index, while Tab is cycling.
+    pub completion: Option<(String, usize)>,
+    /// Messages that arrived while away and have not been seen yet.
+    pub unread: usize,
+    /// Where to draw the "new messages" separator.
+    pub first_unread: Option<u64>,
+    /// Chat message under the selection cursor.
+    pub selected: Option<u64>,
+    pub reveal_selection: bool,
+    pub keys: Keymap,
+    pub count: Option<usize>,
+    pub pending_key: Option<Chord>,
+    /// Our picks in select components, shown as the checked option.
+    pub choices: HashMap<(u64, String), String>,
+    pub creator: Option<EndpointId>,
+    pub membership: Membership,
+    /// Pending join requests, by endpoint, with the name they gave.
+    pub knocks: HashMap<EndpointId, String>,
+    /// Tee of the conversation to disk, if enabled.
+    pub transcript: Option<TranscriptLog>,
+    pub directory: Vec<Listing>,
+    pub published: Option<(String, String, Instant)>,
+    pub net_panel: bool,
+    pub swarm_panel: bool,
+    pub pins: Vec<(u64, String)>,
+    pub pins_collapsed: bool,
+    pub pins_panel: bool,
+    pub subject: Option<String>,
+    pub votes: HashMap<u64, HashMap<EndpointId, u32>>,
+    pub my_votes: HashMap<u64, u32>,
+    pub presence: HashMap<EndpointId, Presence>,
+    pub auto_away: bool,
 }
 
 /*
@@ -110,7 +436,7 @@ Function:   -new
 Purpose:    -Create and initialize a new App instance with default state.
 
 Parameters:
-            - None
+            - String my_name:  Our own nickname.
 
 Details:
             - Initializes an empty input buffer.
@@ -118,16 +444,50 @@ Details:
             - Sets the initial mode to Insert.
             - Initializes an empty list of sent message IDs.
             - Sets scroll_offset to 0 (view pinned to bottom).
+            - Assumes the terminal is focused and do-not-disturb is off.
+            - Starts with an empty names map and the default notification
+              rules.
             - Returns a fully initialized App instance.
 */
 impl App {
-    pub fn new() -> Self {
+    pub fn new(my_name: String) -> Self {
         Self {
             input: String::new(),
-            messages: Vec::new(),
+            messages: History::new(DEFAULT_HISTORY),
             mode: Mode::Insert,
             my_sent_ids: Vec::new(),
             scroll_offset: 0,
+            max_scroll: 0,
+            focused: true,
+            dnd: false,
+            my_name,
+            names: HashMap::new(),
+            notify: Rules::default(),
+            completion: None,
+            unread: 0,
+            first_unread: None,
+            selected: None,
+            reveal_selection: false,
+            keys: Keymap::default(),
+            count: None,
+            pending_key: None,
+            choices: HashMap::new(),
+            creator: None,
+            membership: Membership::default(),
+            knocks: HashMap::new(),
+            transcript: None,
+            directory: Vec::new(),
+            published: None,
+            net_panel: false,
+            swarm_panel: false,
+            pins: Vec::new(),
+            pins_collapsed: false,
+            pins_panel: false,
+            subject: None,
+            votes: HashMap::new(),
+            my_votes: HashMap::new(),
+            presence: HashMap::new(),
+            auto_away: false,
         }
     }
 
@@ -136,36 +496,238 @@ impl App {
     Purpose:    -Add a new UI message to the application state and handle deletions.
 
     Parameters:
-                - UiMessage msg:  The message or event to be processed.
+                - ChatEvent msg:  The message or event to be processed.
 
     Details:
                 - If the message is a Delete variant:
-                    - Removes all chat messages matching the specified ID.
-                    - Removes the ID from my_sent_ids if present.
-                    - Appends a system notification indicating a message was deleted.
+                    - Removes the chat message with the specified ID.
+                    - Removes the ID from my_sent_ids if present, clears the
+                      selection if it was on that message, unpins it and
+                      forgets votes if it was a poll.
+                    - Appends a system notification indicating a message was
+                      deleted, if there was one to delete.
                     - Returns immediately after processing.
+                - If the message is a Name variant, records the nickname and
+                  returns without displaying anything.
+                - Left forgets the peer's nickname and says they left.
+                - SendFailed marks our message as failed and says how to
+                  resend it.
+                - Queued, Sent and Delivered move our message through its
+                  delivery states; Queued says why when it is the first
+                  message held.
+                - Pin adds or removes the message in the pinned list and says
+                  who did it.
+                - Subject replaces the room topic and says who changed it.
+                - A Vote replaces the member's earlier pick in that poll. It
+                  may arrive before the poll itself, so it is kept either way.
+                - A Membership update replaces the role assignments.
+                - Directory results replace the numbered list kept for
+                  /directory join and are shown as system messages.
+                - A Knock is recorded as pending and shown as a system
+                  message explaining how to answer it.
+                - Chat messages from others that arrive while away count as
+                  unread; the first one of an away period moves the separator.
+                - Chat messages are appended to the transcript log, if any. A
+                  write failure disables the log and is reported once.
+                  Ephemeral messages are never written to disk.
                 - Otherwise:
                     - Appends the message to the message list.
-                - Maintains a rolling history limit of 1000 messages.
-                - If the message count exceeds 1000, removes the oldest 100 messages.
+                - The history keeps the newest [display] history entries,
+                  dropping the oldest one by one (see History).
                 - Prevents unbounded memory growth during long sessions.
     */
-    pub fn add_message(&mut self, msg: UiMessage) {
-        if let UiMessage::Delete(id) = &msg {
+    pub fn add_message(&mut self, msg: ChatEvent) {
+        if let ChatEvent::Delete(id) = &msg {
             let id = *id;
-            self.messages.retain(|m| match m {
-                UiMessage::Chat(c) => c.id != id,
-                _ => true,
-            });
+            let removed = self.messages.remove(id);
             self.my_sent_ids.retain(|&i| i != id);
-            self.messages
-                .push(UiMessage::System("A message was deleted.".to_string()));
+            if self.selected == Some(id) {
+                self.selected = None;
+            }
+            self.pins.retain(|(pinned, _)| *pinned != id);
+            self.votes.remove(&id);
+            self.my_votes.remove(&id);
+            if removed {
+                self.messages.push(ChatEvent::System(t!("A message was deleted.").to_string()));
+            }
             return;
         }
 
+        if let ChatEvent::Name { from, name } = msg {
+            self.names.insert(from, name);
+            return;
+        }
+
+        if let ChatEvent::Presence { from, presence } = msg {
+            self.presence.insert(from, presence);
+            return;
+        }
+
+        if let ChatEvent::Left(from) = msg {
+            self.presence.remove(&from);
+            if let Some(name) = self.names.remove(&from) {
+                self.messages.push(ChatEvent::System(t!("{} left the chat", name)));
+            }
+            return;
+        }
+
+        if let ChatEvent::SendFailed(id) = msg {
+            if let Some(chat) = self.chat_mut(id) {
+                chat.failed = true;
+                self.messages.push(ChatEvent::System(
+                    t!("A message could not be sent. Select it and press Ctrl+R (NORMAL mode) to retry.").to_string(),
+                ));
+            }
+            return;
+        }
+
+        if let ChatEvent::Queued(id) = msg {
+            let first = !self
+                .messages
+                .iter()
+                .any(|m| matches!(m, ChatEvent::Chat(c) if c.delivery == Some(Delivery::Queued)));
+            if let Some(chat) = self.chat_mut(id) {
+                chat.advance(Delivery::Queued);
+                if first {
+                    self.messages.push(ChatEvent::System(
+                        t!("No one is connected. Messages will be sent when someone is.").to_string(),
+                    ));
+                }
+            }
+            return;
+        }
+
+        if let ChatEvent::Sent(id) = msg {
+            if let Some(chat) = self.chat_mut(id) {
+                chat.advance(Delivery::Broadcast);
+            }
+            return;
+        }
+
+        if let ChatEvent::Delivered(id) = msg {
+            if let Some(chat) = self.chat_mut(id) {
+                chat.advance(Delivery::Delivered);
+            }
+            return;
+        }
+
+        if let ChatEvent::Pin { id, name, pinned } = msg {
+            let known = self.pins.iter().any(|(p, _)| *p == id);
+            if pinned && !known {
+                let snapshot = self
+                    .chat(id)
+                    .map(|chat| format!("{}: {}", chat.sender, chat.content.replace('\n', " ")))
+                    .unwrap_or_else(|| t!("(a message you have not seen)").to_string());
+                self.pins.push((id, snapshot));
+                self.messages.push(ChatEvent::System(t!("{} pinned a message.", name)));
+            } else if !pinned && known {
+                self.pins.retain(|(p, _)| *p != id);
+                self.messages.push(ChatEvent::System(t!("{} unpinned a message.", name)));
+            }
+            return;
+        }
+
+        if let ChatEvent::Subject { name, subject } = msg {
+            let note = if subject.is_empty() {
+                t!("{} cleared the topic.", name)
+            } else {
+                t!("{} set the topic to: {}", name, subject)
+            };
+            self.subject = Some(subject).filter(|s| !s.is_empty());
+            self.messages.push(ChatEvent::System(note));
+            return;
+        }
+
+        if let ChatEvent::Vote { poll, from, choice } = msg {
+            self.votes.entry(poll).or_default().insert(from, choice);
+            return;
+        }
+
+        if let ChatEvent::Membership(membership) = msg {
+            self.membership = membership;
+            return;
+        }
+
+        if let ChatEvent::Directory(listings) = msg {
+            if listings.is_empty() {
+                self.messages.push(ChatEvent::System(t!("No rooms found.").to_string()));
+            }
+            for (i, listing) in listings.iter().enumerate() {
+                self.messages.push(ChatEvent::System(t!(
+                    "{}. {} – {} ({} members, {})",
+                    i + 1,
+                    listing.name,
+                    listing.description,
+                    listing.members,
+                    listing.policy()
+                )));
+            }
+            if !listings.is_empty() {
+                self.messages.push(ChatEvent::System(t!("Join one with /directory join <number>.").to_string()));
+            }
+            self.directory = listings;
+            return;
+        }
+
+        if let ChatEvent::Knock { from, name } = msg {
+            self.messages.push(ChatEvent::System(t!(
+                "{} ({}) wants to join – /approve {} or /deny {}",
+                name,
+                from.fmt_short(),
+                name,
+                name
+            )));
+            self.knocks.insert(from, name);
+            return;
+        }
+
+        if let ChatEvent::Chat(chat) = &msg
+            && self.is_away()
+            && !self.my_sent_ids.contains(&chat.id)
+        {
+            if self.unread == 0 {
+                self.first_unread = Some(chat.id);
+            }
+            self.unread += 1;
+        }
+
+        if let (ChatEvent::Chat(chat), Some(log)) = (&msg, &mut self.transcript)
+            && chat.expires.is_none()
+            && let Err(e) = log.append(chat)
+        {
+            self.transcript = None;
+            self.messages.push(ChatEvent::System(t!("Transcript logging stopped: {}", e)));
+        }
+
         self.messages.push(msg);
-        if self.messages.len() > 1000 {
-            self.messages.drain(0..100);
+    }
+
+    /*
+    Function:   -expire_messages
+    Purpose:    -Remove ephemeral messages whose TTL has elapsed.
+
+    Details:
+                - Called from the TUI loop, so removal happens within one tick
+                  of the deadline whether or not anything else is going on.
+                - Expired messages are dropped silently, with no "deleted"
+                  notice, and forgotten by my_sent_ids and the selection.
+    */
+    pub fn expire_messages(&mut self) {
+        let now = Local::now();
+        let mut expired = Vec::new();
+        self.messages.retain(|m| match m {
+            ChatEvent::Chat(c) if c.expires.is_some_and(|t| t <= now) => {
+                expired.push(c.id);
+                false
+            }
+            _ => true,
+        });
+        if expired.is_empty() {
+            return;
+        }
+        self.my_sent_ids.retain(|id| !expired.contains(id));
+        if self.selected.is_some_and(|id| expired.contains(&id)) {
+            self.selected = None;
         }
     }
 
@@ -178,12 +740,12 @@ impl App {
 
     Details:
                 - Increases scroll_offset by n.
-                - Clamps the value so it does not exceed the number of available messages.
-                - Uses saturating_sub to prevent underflow when message list is empty.
+                - Clamps to max_scroll, so the top of the history stops at the
+                  top of the pane however long the wrapped messages are.
                 - Ensures scrolling remains within valid bounds.
     */
     pub fn scroll_up(&mut self, n: usize) {
-        self.scroll_offset = (self.scroll_offset + n).min(self.messages.len().saturating_sub(1));
+        self.scroll_offset = (self.scroll_offset + n).min(self.max_scroll);
     }
 
 
@@ -203,4 +765,292 @@ impl App {
     pub fn scroll_down(&mut self, n: usize) {
         self.scroll_offset = self.scroll_offset.saturating_sub(n);
     }
+
+    /*
+    Function:   -should_notify
+    Purpose:    -Decide whether an incoming chat message warrants a notification.
+
+    Parameters:
+                - &ChatMessage chat:  The message, from someone else.
+
+    Details:
+                - Always returns false while do-not-disturb is enabled, and
+                  for messages that did not decrypt.
+                - Otherwise up to the notification rules (notify::Rules),
+                  told whether it mentions us and whether we are away.
+    */
+    pub fn should_notify(&self, chat: &ChatMessage) -> bool {
+        if self.dnd || chat.trust == Trust::Failed {
+            return false;
+        }
+        let mentioned = chat.highlight || self.mentions_me(&chat.content);
+        self.notify
+            .allows(chat.from, &chat.content, mentioned, self.is_away(), Local::now().time())
+    }
+
+    /// Close whichever overlay panel is open; false if none was.
+    pub fn close_panel(&mut self) -> bool {
+        let open = self.net_panel || self.swarm_panel || self.pins_panel;
+        self.net_panel = false;
+        self.swarm_panel = false;
+        self.pins_panel = false;
+        open
+    }
+
+    /// True when the newest messages are not on screen: unfocused, a
+    /// background tab, or scrolled up.
+    pub fn is_away(&self) -> bool {
+        !self.focused || self.scroll_offset > 0
+    }
+
+    /*
+    Function:   -mark_read
+    Purpose:    -Clear the unread counter once the newest messages are visible.
+
+    Parameters:
+                - None
+
+    Details:
+                - Does nothing while away.
+                - Keeps first_unread so the separator still shows where the
+                  catch-up started; the next away period replaces it.
+    */
+    pub fn mark_read(&mut self) {
+        if !self.is_away() {
+            self.unread = 0;
+        }
+    }
+
+    /*
+    Function:   -member_named
+    Purpose:    -Look up a room member by nickname, for commands like /kick.
+
+    Parameters:
+                - &str name:  Nickname, with or without a leading '@'.
+
+    Details:
+                - Case-insensitive. Fails with a displayable message when no
+                  one or more than one member has that name.
+    */
+    pub fn member_named(&self, name: &str) -> anyhow::Result<EndpointId> {
+        let name = name.trim_start_matches('@');
+        let matches: Vec<EndpointId> = self
+            .names
+            .iter()
+            .filter(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|(id, _)| *id)
+            .collect();
+        match matches.as_slice() {
+            [id] => Ok(*id),
+            [] => anyhow::bail!(t!("No one called '{}' is in the room", name)),
+            _ => anyhow::bail!(t!("Several members are called '{}'", name)),
+        }
+    }
+
+    /// The chat message with this ID, if it is still in the history.
+    pub fn chat(&self, id: u64) -> Option<&ChatMessage> {
+        self.messages.chat(id)
+    }
+
+    fn chat_mut(&mut self, id: u64) -> Option<&mut ChatMessage> {
+        self.messages.chat_mut(id)
+    }
+
+    /// Take back a failed message for resending: the selected one if it
+    /// failed, else our most recent failure. Clears its failed mark and
+    /// starts its delivery over.
+    pub fn retry_failed(&mut self) -> Option<(u64, ChatPayload)> {
+        let selected = self.selected.filter(|id| self.chat(*id).is_some_and(|chat| chat.failed));
+        let id = selected.or_else(|| {
+            self.messages.iter().rev().find_map(|m| match m {
+                ChatEvent::Chat(chat) if chat.failed => Some(chat.id),
+                _ => None,
+            })
+        })?;
+        let chat = self.chat_mut(id)?;
+        chat.failed = false;
+        chat.delivery = Some(Delivery::Sending);
+        Some((id, chat.payload()))
+    }
+
+    /// The voice message to play: the selected one, else the most recent.
+    pub fn voice_clip(&self) -> Option<VoiceClip> {
+        let selected = self.selected_chat().and_then(|chat| chat.voice.clone());
+        selected.or_else(|| {
+            self.messages.iter().rev().find_map(|m| match m {
+                ChatEvent::Chat(chat) => chat.voice.clone(),
+                _ => None,
+            })
+        })
+    }
+
+    /*
+    Function:   -move_selection
+    Purpose:    -Move the selection cursor some chat messages older or newer.
+
+    Parameters:
+                - bool older:  Towards older messages (k / Up) rather than newer.
+                - usize by:  How many messages (the count of 5k).
+
+    Details:
+                - With nothing selected, moving older counts from the newest
+                  message; moving newer past the newest clears the selection.
+                - The selection stops at the oldest message.
+    */
+    pub fn move_selection(&mut self, older: bool, by: usize) {
+        let ids: Vec<u64> = self
+            .messages
+            .iter()
+            .filter_map(|m| match m {
+                ChatEvent::Chat(chat) => Some(chat.id),
+                _ => None,
+            })
+            .collect();
+        let current = self.selected.and_then(|id| ids.iter().position(|&i| i == id));
+        let target = match (current, older) {
+            (None, true) => Some(ids.len().saturating_sub(by)),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(by)),
+            (Some(i), false) => Some(i + by),
+        };
+        self.selected = target.and_then(|i| ids.get(i).copied());
+        self.reveal_selection = true;
+    }
+
+    /// Select the oldest (gg) or newest (G) chat message.
+    pub fn jump_selection(&mut self, oldest: bool) {
+        let mut chats = self.messages.iter().filter_map(|m| match m {
+            ChatEvent::Chat(chat) => Some(chat.id),
+            _ => None,
+        });
+        self.selected = if oldest { chats.next() } else { chats.next_back() };
+        self.reveal_selection = true;
+    }
+
+    /// Select the first message of the latest unread stretch, where the
+    /// "new messages" separator is, and scroll it into view. False when
+    /// there is none, or it has been trimmed or deleted since.
+    pub fn jump_to_unread(&mut self) -> bool {
+        let Some(id) = self.first_unread.filter(|id| self.chat(*id).is_some()) else {
+            return false;
+        };
+        self.selected = Some(id);
+        self.reveal_selection = true;
+        true
+    }
+
+    /// Empty the message pane (/clear), e.g. before sharing the screen.
+    /// Only what is shown goes: archives and the transcript keep theirs.
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.selected = None;
+        self.scroll_offset = 0;
+        self.unread = 0;
+        self.first_unread = None;
+    }
+
+    /// Back to the newest messages; being there marks them read.
+    pub fn jump_to_bottom(&mut self) {
+        self.selected = None;
+        self.scroll_offset = 0;
+    }
+
+    /// The chat message under the selection cursor, if it is still there.
+    pub fn selected_chat(&self) -> Option<&ChatMessage> {
+        self.selected.and_then(|id| self.chat(id))
+    }
+
+    /// Votes for each option of the poll in message `id`.
+    pub fn tally(&self, id: u64, options: usize) -> Vec<usize> {
+        let mut counts = vec![0; options];
+        for choice in self.votes.get(&id).into_iter().flat_map(|votes| votes.values()) {
+            if let Some(count) = counts.get_mut(*choice as usize) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    /// Role of a member in this room.
+    pub fn role(&self, id: EndpointId) -> Role {
+        self.membership.role(id, self.creator)
+    }
+
+    /// Take the pending knock from the member called `name` (case-insensitive).
+    pub fn take_knock(&mut self, name: &str) -> anyhow::Result<(EndpointId, String)> {
+        let name = name.trim_start_matches('@');
+        let Some(id) = self
+            .knocks
+            .iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|(id, _)| *id)
+        else {
+            anyhow::bail!(t!("No one called '{}' is waiting to join", name));
+        };
+        let name = self.knocks.remove(&id).unwrap_or_default();
+        Ok((id, name))
+    }
+
+    /*
+    Function:   -mentions_me
+    Purpose:    -Check whether message content mentions our own nickname.
+
+    Parameters:
+                - &str content:  Message text.
+
+    Details:
+                - Matches "@<my_name>" case-insensitively anywhere in the text.
+    */
+    pub fn mentions_me(&self, content: &str) -> bool {
+        let needle = format!("@{}", self.my_name.to_lowercase());
+        content.to_lowercase().contains(&needle)
+    }
+
+    /*
+    Function:   -complete_mention
+    Purpose:    -Tab-complete an @mention at the end of the input buffer.
+
+    Parameters:
+                - None
+
+    Details:
+                - Only acts when the last word of the input starts with '@'.
+                - Candidates are known names starting with the typed prefix
+                  (case-insensitive), sorted alphabetically.
+                - Repeated calls cycle through the candidates; any other key
+                  should reset `completion` so the next Tab starts afresh.
+    */
+    pub fn complete_mention(&mut self) {
+        let start = match &self.completion {
+            Some(_) => self.input.rfind('@'),
+            None => {
+                let word_start = self.input.rfind(' ').map(|i| i + 1).unwrap_or(0);
+                self.input[word_start..].starts_with('@').then_some(word_start)
+            }
+        };
+        let Some(start) = start else { return };
+
+        let (prefix, index) = match &self.completion {
+            Some((prefix, index)) => (prefix.clone(), index + 1),
+            None => (self.input[start + 1..].to_string(), 0),
+        };
+
+        let lower = prefix.to_lowercase();
+        let mut candidates: Vec<&String> = self
+            .names
+            .values()
+            .filter(|n| **n != self.my_name && n.to_lowercase().starts_with(&lower))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        if candidates.is_empty() {
+            return;
+        }
+
+        let pick = candidates[index % candidates.len()].clone();
+        self.input.truncate(start);
+        self.input.push('@');
+        self.input.push_str(&pick);
+        self.completion = Some((prefix, index));
+    }
 }
@@ -0,0 +1,660 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use data_encoding::BASE64;
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, EndpointId,
+};
+use iroh_gossip::proto::TopicId;
+use tracing::{debug, warn};
+
+use crate::crypto::{open_with_key, passphrase_key, seal_with_key, sealed_salt};
+use crate::keychain;
+use crate::profile::{write_private, Profile};
+use crate::protocol::{Message, MessageBody};
+
+// ── Archive (store-and-forward) ───────────────────────────────────────────────
+
+/// ALPN for history requests, served by peers started with --archive.
+pub const ARCHIVE_ALPN: &[u8] = b"p2p-chat/archive/0";
+
+/// Messages an archive keeps per room; the oldest go first.
+const MAX_ARCHIVED: usize = 5000;
+
+/// Largest history a member downloads in one go.
+const MAX_HISTORY: usize = 64 * 1024 * 1024;
+
+/// How often a changed archive is written to disk.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Replaced and deleted messages an archive leaves in place before it
+/// compacts itself.
+const COMPACT_AT: usize = MAX_ARCHIVED / 4;
+
+/// What an archived message stands for: a newer one for the same slot
+/// replaces it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Slot {
+    /// A chat message, by ID.
+    Chat(u64),
+    /// The latest pin or unpin of a message, by its ID.
+    Pin(u64),
+    /// A member's latest name.
+    Name(EndpointId),
+    /// A sender key one member handed another, by key ID: messages under
+    /// each of a member's keys need that key.
+    Key(EndpointId, EndpointId, u64),
+}
+
+impl Slot {
+    /// The slot for a message worth keeping, or None for the rest.
+    fn of(message: &Message) -> Option<Self> {
+        Some(match &message.body {
+            MessageBody::EncryptedMessage { id, .. } => Slot::Chat(*id),
+            MessageBody::Pin { id, .. } => Slot::Pin(*id),
+            MessageBody::AboutMe { from, .. } => Slot::Name(*from),
+            MessageBody::SenderKey { from, to, key_id, .. } => Slot::Key(*from, *to, *key_id),
+            _ => return None,
+        })
+    }
+}
+
+/*
+Enum:       -StoreKey
+Purpose:    -How an archive is kept on disk.
+
+Variants:
+            - Plain:  Unencrypted, though the chat messages in it still are.
+            - Passphrase:  Sealed with a key stretched from this passphrase.
+            - Keychain:  Sealed with a random key kept in the OS keychain.
+*/
+#[derive(Clone, Default)]
+pub enum StoreKey {
+    #[default]
+    Plain,
+    Passphrase(String),
+    Keychain,
+}
+
+impl fmt::Debug for StoreKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StoreKey::Plain => "Plain",
+            StoreKey::Passphrase(_) => "Passphrase",
+            StoreKey::Keychain => "Keychain",
+        })
+    }
+}
+
+/// The key a sealed archive is written with, and the salt that goes in
+/// front of it (see crypto::seal_with_key).
+#[derive(Clone)]
+struct Sealing {
+    key: [u8; 32],
+    salt: [u8; 16],
+}
+
+impl fmt::Debug for Sealing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sealing")
+    }
+}
+
+impl Sealing {
+    /*
+    Function:   -for_file
+    Purpose:    -The key to open and write a sealed archive with.
+
+    Parameters:
+                - &StoreKey store_key:  Passphrase or keychain.
+                - Option<&[u8]> sealed:  The file as it is, if there is one.
+                - &Profile profile:  Whose keychain entry to use.
+                - &TopicId topic:  The room, naming the keychain entry.
+
+    Details:
+                - A passphrase is stretched with the salt already in the
+                  file, or a fresh one. Slow; call from a blocking context.
+                - A keychain key is made and saved on first use.
+                - None for plain archives.
+    */
+    fn for_file(store_key: &StoreKey, sealed: Option<&[u8]>, profile: &Profile, topic: &TopicId) -> Result<Option<Self>> {
+        let salt = match sealed {
+            Some(blob) => *sealed_salt(blob)?,
+            None => rand::random(),
+        };
+        let key = match store_key {
+            StoreKey::Plain => return Ok(None),
+            StoreKey::Passphrase(passphrase) => passphrase_key(passphrase, &salt)?,
+            StoreKey::Keychain => {
+                let account = profile.keychain_account(&format!("archive/{}", topic));
+                match keychain::get(&account)? {
+                    Some(text) => hex::decode(text)
+                        .ok()
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .context("the archive key in the keychain is invalid")?,
+                    None if sealed.is_some() => bail!("the archive's key is missing from the keychain"),
+                    None => {
+                        let key: [u8; 32] = rand::random();
+                        keychain::set(&account, &hex::encode(key))?;
+                        key
+                    }
+                }
+            }
+        };
+        Ok(Some(Self { key, salt }))
+    }
+}
+
+/*
+Struct:     -State
+Purpose:    -The messages an archive keeps, indexed by slot.
+
+Fields:
+            - VecDeque<Option<(Slot, Vec<u8>)>> messages:  Oldest first.
+              None marks one replaced or deleted since (a tombstone), left in
+              place so positions do not shift; compact drops them.
+            - usize first:  Sequence number of messages[0].
+            - HashMap<Slot, usize> index:  Where each slot's message sits,
+              by sequence number.
+            - bool dirty:  Changed since last saved.
+*/
+#[derive(Debug, Default)]
+struct State {
+    messages: VecDeque<Option<(Slot, Vec<u8>)>>,
+    first: usize,
+    index: HashMap<Slot, usize>,
+    dirty: bool,
+}
+
+impl State {
+    /// Keep a message, replacing the one in its slot and dropping the
+    /// oldest if MAX_ARCHIVED are kept.
+    fn push(&mut self, slot: Slot, bytes: Vec<u8>) {
+        self.remove(&slot);
+        if self.index.len() == MAX_ARCHIVED {
+            while let Some(oldest) = self.messages.pop_front() {
+                self.first += 1;
+                if let Some((slot, _)) = oldest {
+                    self.index.remove(&slot);
+                    break;
+                }
+            }
+        }
+        self.index.insert(slot, self.first + self.messages.len());
+        self.messages.push_back(Some((slot, bytes)));
+        self.dirty = true;
+    }
+
+    /// Leave a tombstone where a slot's message was, if there is one.
+    fn remove(&mut self, slot: &Slot) -> bool {
+        let Some(seq) = self.index.remove(slot) else {
+            return false;
+        };
+        self.messages[seq - self.first] = None;
+        self.dirty = true;
+        true
+    }
+
+    fn tombstones(&self) -> usize {
+        self.messages.len() - self.index.len()
+    }
+
+    /// The messages kept, oldest first.
+    fn iter(&self) -> impl Iterator<Item = &(Slot, Vec<u8>)> {
+        self.messages.iter().flatten()
+    }
+
+    /*
+    Function:   -compact
+    Purpose:    -Drop the tombstones, and the messages no member needs any
+                 more. Returns how many of those there were.
+
+    Details:
+                - A pin is only needed while the message it pins is kept.
+                - A sender key is only needed while a message under it is
+                  kept, or until its sender hands the same member a newer
+                  one: the latest may be for messages still to come.
+    */
+    fn compact(&mut self) -> usize {
+        let mut under = HashSet::new();
+        for (_, bytes) in self.iter() {
+            if let Ok(message) = Message::from_bytes(bytes)
+                && let MessageBody::EncryptedMessage { from, key_id, .. } = message.body
+            {
+                under.insert((from, key_id));
+            }
+        }
+        let mut latest = HashSet::new();
+        let mut kept = VecDeque::with_capacity(self.index.len());
+        for (slot, bytes) in self.messages.drain(..).rev().flatten() {
+            let needed = match slot {
+                Slot::Pin(id) => self.index.contains_key(&Slot::Chat(id)),
+                Slot::Key(from, to, key_id) => latest.insert((from, to)) || under.contains(&(from, key_id)),
+                Slot::Chat(_) | Slot::Name(_) => true,
+            };
+            if needed {
+                kept.push_front((slot, bytes));
+            }
+        }
+        let dropped = self.index.len() - kept.len();
+        self.first = 0;
+        self.index = kept.iter().enumerate().map(|(seq, (slot, _))| (*slot, seq)).collect();
+        self.messages = kept.into_iter().map(Some).collect();
+        self.dirty = true;
+        dropped
+    }
+}
+
+/*
+Struct:     -Compaction
+Purpose:    -What compacting an archive got rid of.
+
+Fields:
+            - usize dropped:  Messages no member needs any more: pins of
+              messages no longer kept and sender keys nothing is under.
+            - u64 reclaimed:  Bytes the file shrank by.
+*/
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Compaction {
+    pub dropped: usize,
+    pub reclaimed: u64,
+}
+
+/*
+Struct:     -Archive
+Purpose:    -A room's history as kept by an --archive peer, so members who
+             were away can catch up from it.
+
+Fields:
+            - PathBuf path:  <profile>/store/archive/<topic>.json, the
+              messages as base64, oldest first; <topic>.sealed when sealed.
+            - Option<Sealing> sealing:  The key the file is sealed with, if
+              it is.
+            - Arc<Mutex<State>> state:  The messages with their slots,
+              shared with the receive loop and the protocol handler.
+
+Details:
+            - Keeps chat messages and pins as sent, still encrypted: the
+              archive never needs to read them. Also keeps each member's
+              latest name and every sender key members handed each other
+              (each wrapped for its recipient), without which the chat
+              messages could not be read later.
+            - A delete removes the message (and its pin) instead of being
+              kept itself.
+            - At most MAX_ARCHIVED messages are kept. Changes are saved every
+              SAVE_INTERVAL, so a crash loses at most the last few, and the
+              archive compacts itself first once COMPACT_AT messages have
+              been replaced or deleted.
+            - Names, sender IDs and who handed keys to whom are readable in
+              a plain file, as are public rooms' messages to anyone who
+              learns the topic from its name. A sealed file (see StoreKey)
+              gives away nothing but its size.
+*/
+#[derive(Debug, Clone)]
+pub struct Archive {
+    path: PathBuf,
+    sealing: Option<Sealing>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Archive {
+    /*
+    Function:   -open
+    Purpose:    -The archive for a room, loaded from the profile's store,
+                 saving itself in the background from now on.
+
+    Parameters:
+                - &Profile profile:  Whose store.
+                - &TopicId topic:  The room.
+                - &StoreKey store_key:  How the file is (to be) kept.
+
+    Details:
+                - A plain archive is sealed when opened with a key, and the
+                  plain file removed; a sealed one cannot be opened without.
+                - Opening with a passphrase is slow; call from a blocking
+                  context.
+    */
+    pub fn open(profile: &Profile, topic: &TopicId, store_key: &StoreKey) -> Result<Self> {
+        let dir = profile.store_dir().join("archive");
+        std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let plain_path = dir.join(format!("{}.json", topic));
+        let sealed_path = dir.join(format!("{}.sealed", topic));
+        let sealed = std::fs::read(&sealed_path).ok();
+        let sealing = Sealing::for_file(store_key, sealed.as_deref(), profile, topic)?;
+        let data = match (&sealing, sealed) {
+            (Some(sealing), Some(blob)) => Some(
+                open_with_key(&blob, &sealing.key).context("cannot open the archive – wrong passphrase?")?,
+            ),
+            (None, Some(_)) => bail!("the archive is sealed; open it with its passphrase or keychain key"),
+            (_, None) => std::fs::read(&plain_path).ok(),
+        };
+        let mut state = State::default();
+        if let Some(data) = data {
+            let encoded: Vec<String> = serde_json::from_slice(&data).context("archive file is corrupt")?;
+            for line in encoded {
+                let Ok(bytes) = BASE64.decode(line.as_bytes()) else {
+                    continue;
+                };
+                if let Some(slot) = Message::from_bytes(&bytes).ok().as_ref().and_then(Slot::of) {
+                    state.push(slot, bytes);
+                }
+            }
+            state.dirty = false;
+        }
+        let path = if sealing.is_some() { sealed_path } else { plain_path.clone() };
+        debug!(messages = state.index.len(), path = %path.display(), "archive loaded");
+        // Seal what was kept plain before, and never leave it lying around.
+        if sealing.is_some() && plain_path.exists() {
+            state.dirty = true;
+        }
+        let archive = Self {
+            path,
+            sealing,
+            state: Arc::new(Mutex::new(state)),
+        };
+        archive.save()?;
+        if archive.sealing.is_some() && plain_path.exists() {
+            std::fs::remove_file(&plain_path).with_context(|| format!("removing {}", plain_path.display()))?;
+        }
+        let saver = archive.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SAVE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                {
+                    let mut state = saver.lock();
+                    if state.tombstones() >= COMPACT_AT {
+                        let dropped = state.compact();
+                        debug!(dropped, "archive compacted");
+                    }
+                }
+                if let Err(e) = saver.save() {
+                    warn!(error = %e, "could not save the archive");
+                }
+            }
+        });
+        Ok(archive)
+    }
+
+    /// Whether the profile keeps an archive of this room, plain or sealed.
+    pub fn exists(profile: &Profile, topic: &TopicId) -> bool {
+        let dir = profile.store_dir().join("archive");
+        dir.join(format!("{}.json", topic)).exists() || dir.join(format!("{}.sealed", topic)).exists()
+    }
+
+    /// The rooms the profile keeps an archive of.
+    pub fn topics(profile: &Profile) -> Result<Vec<TopicId>> {
+        let dir = profile.store_dir().join("archive");
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return Ok(Vec::new());
+        };
+        let mut topics = Vec::new();
+        for entry in entries {
+            let path = entry.with_context(|| format!("reading {}", dir.display()))?.path();
+            if let Some(topic) = path.file_stem().and_then(|stem| stem.to_str()?.parse().ok())
+                && !topics.contains(&topic)
+            {
+                topics.push(topic);
+            }
+        }
+        Ok(topics)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("archive lock poisoned")
+    }
+
+    fn save(&self) -> Result<()> {
+        let encoded: Vec<String> = {
+            let mut state = self.lock();
+            if !state.dirty {
+                return Ok(());
+            }
+            state.dirty = false;
+            state.iter().map(|(_, bytes)| BASE64.encode(bytes)).collect()
+        };
+        let data = serde_json::to_vec(&encoded)?;
+        match &self.sealing {
+            Some(sealing) => write_private(&self.path, &seal_with_key(&data, &sealing.key, sealing.salt)?),
+            None => write_private(&self.path, &data),
+        }
+    }
+
+    /// Keep a message the room accepted, if it is the kind worth keeping.
+    pub fn record(&self, message: &Message) {
+        let Some(slot) = Slot::of(message) else {
+            return;
+        };
+        self.lock().push(slot, message.to_vec());
+    }
+
+    /// Everything kept, oldest first, as sent.
+    pub fn messages(&self) -> Vec<Vec<u8>> {
+        self.lock().iter().map(|(_, bytes)| bytes.clone()).collect()
+    }
+
+    /// Keep messages from another copy of the room's archive, e.g. an
+    /// imported one, and save at once. Returns how many were worth keeping.
+    pub fn restore(&self, messages: &[Vec<u8>]) -> Result<usize> {
+        let mut kept = 0;
+        for message in messages.iter().filter_map(|bytes| Message::from_bytes(bytes).ok()) {
+            if Slot::of(&message).is_some() {
+                self.record(&message);
+                kept += 1;
+            }
+        }
+        self.save()?;
+        Ok(kept)
+    }
+
+    /// Drop a deleted message and its pin.
+    pub fn forget(&self, id: u64) {
+        let mut state = self.lock();
+        state.remove(&Slot::Chat(id));
+        state.remove(&Slot::Pin(id));
+    }
+
+    /*
+    Function:   -compact
+    Purpose:    -Compact the archive now and write it out afresh, as it
+                 otherwise does by itself once enough has been replaced or
+                 deleted (see State::compact).
+
+    Details:
+                - Another process with the same archive open (a running
+                  daemon) would write its own copy back over it; stop it
+                  first.
+    */
+    pub fn compact(&self) -> Result<Compaction> {
+        let size = || std::fs::metadata(&self.path).map_or(0, |meta| meta.len());
+        let before = size();
+        let dropped = self.lock().compact();
+        self.save()?;
+        Ok(Compaction {
+            dropped,
+            reclaimed: before.saturating_sub(size()),
+        })
+    }
+
+    /// Everything kept, oldest first, as length-prefixed messages.
+    fn encoded(&self) -> Vec<u8> {
+        let state = self.lock();
+        let mut out = Vec::new();
+        for (_, bytes) in state.iter() {
+            out.extend((bytes.len() as u32).to_be_bytes());
+            out.extend(bytes);
+        }
+        out
+    }
+}
+
+/*
+Struct:     -ArchiveHost
+Purpose:    -Protocol handler that hands a room's archive to whoever asks.
+
+Details:
+            - A request is the 32-byte topic; the reply is every archived
+              message, each as a 4-byte big-endian length and the message.
+              A request for another room gets an empty reply.
+            - Anyone who can reach us may ask, but what they get is what the
+              room gossips to anyone with the topic anyway, still encrypted.
+*/
+#[derive(Debug, Clone)]
+pub struct ArchiveHost {
+    archive: Archive,
+    topic: TopicId,
+}
+
+impl ArchiveHost {
+    pub fn new(archive: Archive, topic: TopicId) -> Self {
+        Self { archive, topic }
+    }
+
+    async fn handle(&self, conn: Connection) -> Result<()> {
+        let (mut send, mut recv) = conn.accept_bi().await?;
+        let request = recv.read_to_end(32).await?;
+        let reply = if request == self.topic.as_bytes() {
+            self.archive.encoded()
+        } else {
+            Vec::new()
+        };
+        send.write_all(&reply).await?;
+        send.finish()?;
+        conn.closed().await;
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for ArchiveHost {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        self.handle(conn)
+            .await
+            .map_err(|e| AcceptError::from_err(std::io::Error::other(e.to_string())))
+    }
+}
+
+/// Download a room's history from an archive peer, oldest message first.
+pub async fn fetch(endpoint: &Endpoint, archive: EndpointId, topic: &TopicId) -> Result<Vec<Vec<u8>>> {
+    let conn = endpoint.connect(archive, ARCHIVE_ALPN).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    send.write_all(topic.as_bytes()).await?;
+    send.finish()?;
+    let reply = recv.read_to_end(MAX_HISTORY).await?;
+    conn.close(0u32.into(), b"done");
+    let mut messages = Vec::new();
+    let mut rest = reply.as_slice();
+    while !rest.is_empty() {
+        let Some((len, tail)) = rest.split_first_chunk::<4>() else {
+            bail!("truncated archive reply");
+        };
+        let len = u32::from_be_bytes(*len) as usize;
+        if tail.len() < len {
+            bail!("truncated archive reply");
+        }
+        let (message, tail) = tail.split_at(len);
+        messages.push(message.to_vec());
+        rest = tail;
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    fn chat(from: &SecretKey, id: u64, key_id: u64) -> Message {
+        Message::new(MessageBody::EncryptedMessage {
+            from: from.public(),
+            id,
+            key_id,
+            step: 0,
+            ciphertext: vec![0; 64],
+            nonce: [0; 12],
+            pow: None,
+            signature: None,
+        })
+    }
+
+    fn pin(from: &SecretKey, id: u64) -> Message {
+        Message::new(MessageBody::Pin {
+            from: from.public(),
+            id,
+            pinned: true,
+            signature: from.sign(b"pin"),
+        })
+    }
+
+    fn key(from: &SecretKey, to: &SecretKey, key_id: u64) -> Message {
+        Message::new(MessageBody::SenderKey {
+            from: from.public(),
+            to: to.public(),
+            room_epoch: 0,
+            room_step: 0,
+            key_id,
+            step: 0,
+            wrapped: vec![0; 48],
+            nonce: [0; 12],
+        })
+    }
+
+    #[tokio::test]
+    async fn compaction_drops_what_nobody_needs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let profile = Profile::at(dir.path().join("archive"))?;
+        let topic = TopicId::from_bytes(rand::random());
+        let archive = Archive::open(&profile, &topic, &StoreKey::Plain)?;
+        let (alice, bob) = (SecretKey::from_bytes(&rand::random()), SecretKey::from_bytes(&rand::random()));
+        // Alice's first key is only needed while "1" is kept, her second
+        // for messages still to come; "2" was deleted with its pin.
+        for message in [key(&alice, &bob, 1), chat(&alice, 1, 1), pin(&alice, 1), key(&alice, &bob, 2)] {
+            archive.record(&message);
+        }
+        archive.record(&chat(&alice, 2, 2));
+        archive.record(&pin(&alice, 2));
+        archive.forget(2);
+        assert_eq!(archive.lock().tombstones(), 2);
+        assert_eq!(archive.compact()?.dropped, 0);
+        assert_eq!(archive.messages().len(), 4);
+
+        archive.forget(1);
+        archive.record(&pin(&alice, 1));
+        let compaction = archive.compact()?;
+        assert_eq!(compaction.dropped, 2);
+        assert!(compaction.reclaimed > 0);
+        assert_eq!(archive.messages(), vec![key(&alice, &bob, 2).to_vec()]);
+        assert_eq!(archive.lock().tombstones(), 0);
+
+        let reopened = Archive::open(&profile, &topic, &StoreKey::Plain)?;
+        assert_eq!(reopened.messages(), archive.messages());
+        Ok(())
+    }
+
+    #[test]
+    fn the_oldest_message_goes_first() {
+        let alice = SecretKey::from_bytes(&rand::random());
+        let mut state = State::default();
+        for id in 0..MAX_ARCHIVED as u64 {
+            let message = chat(&alice, id, 0);
+            state.push(Slot::of(&message).unwrap(), message.to_vec());
+        }
+        // Replacing a message frees its place; a new one takes the oldest's.
+        let again = chat(&alice, 0, 0);
+        state.push(Slot::Chat(0), again.to_vec());
+        assert_eq!(state.index.len(), MAX_ARCHIVED);
+        state.push(Slot::Chat(u64::MAX), chat(&alice, u64::MAX, 0).to_vec());
+        assert_eq!(state.index.len(), MAX_ARCHIVED);
+        assert!(!state.index.contains_key(&Slot::Chat(1)));
+        assert!(state.index.contains_key(&Slot::Chat(0)));
+        assert_eq!(state.iter().last().map(|(slot, _)| *slot), Some(Slot::Chat(u64::MAX)));
+    }
+}
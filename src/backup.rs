@@ -0,0 +1,330 @@
+use std::{
+    path::{Component as PathComponent, Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, EndpointId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::app::ChatEvent;
+use crate::bus::Events;
+use crate::crypto::{open_with_passphrase, passphrase_key, seal_with_passphrase};
+use crate::profile::{write_private, Profile};
+
+// ── Encrypted backups ─────────────────────────────────────────────────────────
+
+/// ALPN for the backup protocol, served by peers with `backup.host = true`.
+pub const BACKUP_ALPN: &[u8] = b"p2p-chat/backup/0";
+
+/// Largest encrypted snapshot a host accepts or a client downloads.
+const MAX_BLOB: usize = 64 * 1024 * 1024;
+
+/// Salt prefix for the slot ID. The profile name is appended, so one
+/// passphrase can back up several profiles to the same host.
+const SLOT_SALT: &[u8] = b"p2p-chat/backup-slot/v1/";
+
+const OP_STORE: u8 = 1;
+const OP_FETCH: u8 = 2;
+const STATUS_OK: u8 = 0;
+const STATUS_MISSING: u8 = 1;
+
+/// Profile files included in a snapshot. Everything under store/ is added.
+const SNAPSHOT_FILES: &[&str] = &["config.toml", "identity.key"];
+
+/// Plaintext of a backup: profile-relative paths and their contents.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+/*
+Function:   -slot_id
+Purpose:    -Name under which a host stores a profile's backup.
+
+Parameters:
+            - &str passphrase:  The master passphrase.
+            - &str profile:  Profile name.
+
+Details:
+            - Derived from the passphrase, so a new machine with no identity
+              yet can find its backup, and the host learns nothing it could
+              use to guess the passphrase cheaply.
+*/
+fn slot_id(passphrase: &str, profile: &str) -> Result<[u8; 32]> {
+    let mut salt = SLOT_SALT.to_vec();
+    salt.extend_from_slice(profile.as_bytes());
+    passphrase_key(passphrase, &salt)
+}
+
+/// Collect the profile's identity, config and store into a snapshot.
+fn snapshot(profile: &Profile) -> Result<Snapshot> {
+    let mut files = Vec::new();
+    for name in SNAPSHOT_FILES {
+        let path = profile.dir.join(name);
+        if path.exists() {
+            let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+            files.push((name.to_string(), data));
+        }
+    }
+    // An identity kept in the keychain is restored as a file, and moved
+    // back into the keychain on first use (see Profile::secret_key).
+    if !profile.identity_path().exists() {
+        let key = profile.secret_key()?;
+        files.push(("identity.key".to_string(), hex::encode(key.to_bytes()).into_bytes()));
+    }
+    let mut dirs = vec![profile.store_dir()];
+    while let Some(dir) = dirs.pop() {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(&profile.dir)?;
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+            files.push((name, data));
+        }
+    }
+    Ok(Snapshot { files })
+}
+
+/*
+Function:   -seal
+Purpose:    -Encrypt a profile snapshot under the master passphrase.
+
+Details:
+            - See crypto::seal_with_passphrase. A fresh salt per backup means
+              the host never sees two backups under the same key.
+*/
+fn seal(profile: &Profile, passphrase: &str) -> Result<Vec<u8>> {
+    let plaintext = serde_json::to_vec(&snapshot(profile)?)?;
+    seal_with_passphrase(&plaintext, passphrase)
+}
+
+/// Reverse of `seal`. A wrong passphrase shows up as a decryption failure.
+fn open(blob: &[u8], passphrase: &str) -> Result<Snapshot> {
+    let plaintext = open_with_passphrase(blob, passphrase).context("could not open the backup")?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Send one request on a fresh stream and wait for the host's reply.
+async fn request(endpoint: &Endpoint, host: EndpointId, request: Vec<u8>) -> Result<Vec<u8>> {
+    let conn = endpoint.connect(host, BACKUP_ALPN).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    send.write_all(&request).await?;
+    send.finish()?;
+    let reply = recv.read_to_end(MAX_BLOB + 1).await?;
+    conn.close(0u32.into(), b"done");
+    Ok(reply)
+}
+
+/*
+Function:   -upload
+Purpose:    -Encrypt the profile and store it on a backup host.
+
+Parameters:
+            - &Endpoint endpoint:  Any bound endpoint; the host does not care
+              who we are.
+            - EndpointId host:  The backup target.
+            - &Profile profile:  Profile to back up.
+            - &str passphrase:  Master passphrase.
+
+Details:
+            - Replaces the previous backup in the same slot.
+*/
+pub async fn upload(endpoint: &Endpoint, host: EndpointId, profile: &Profile, passphrase: &str) -> Result<()> {
+    let (slot, blob) = {
+        let profile = profile.clone();
+        let passphrase = passphrase.to_string();
+        tokio::task::spawn_blocking(move || -> Result<_> {
+            Ok((slot_id(&passphrase, &profile.name)?, seal(&profile, &passphrase)?))
+        })
+        .await??
+    };
+    if blob.len() > MAX_BLOB {
+        bail!("profile is too large to back up ({} bytes)", blob.len());
+    }
+    let mut req = vec![OP_STORE];
+    req.extend_from_slice(&slot);
+    req.extend(blob);
+    match request(endpoint, host, req).await?.first() {
+        Some(&STATUS_OK) => Ok(()),
+        _ => bail!("backup host refused the backup"),
+    }
+}
+
+/*
+Function:   -restore
+Purpose:    -Fetch a profile's backup from a host and unpack it as a new
+             profile.
+
+Parameters:
+            - &Endpoint endpoint:  Any bound endpoint.
+            - EndpointId host:  The backup host.
+            - &str name:  Profile name the backup was made under; it is
+              restored under the same name.
+            - &str passphrase:  Master passphrase.
+
+Details:
+            - Refuses to overwrite an existing profile.
+            - Paths in the snapshot are checked so a tampered backup cannot
+              write outside the profile directory.
+*/
+pub async fn restore(endpoint: &Endpoint, host: EndpointId, name: &str, passphrase: &str) -> Result<Profile> {
+    let dir = Profile::root()?.join(name);
+    if dir.exists() {
+        bail!("profile '{}' already exists – delete it first to restore over it", name);
+    }
+
+    let slot = {
+        let (passphrase, name) = (passphrase.to_string(), name.to_string());
+        tokio::task::spawn_blocking(move || slot_id(&passphrase, &name)).await??
+    };
+    let mut req = vec![OP_FETCH];
+    req.extend_from_slice(&slot);
+    let reply = request(endpoint, host, req).await?;
+    let blob = match reply.split_first() {
+        Some((&STATUS_OK, blob)) => blob.to_vec(),
+        Some((&STATUS_MISSING, _)) => bail!("the host has no backup for this profile and passphrase"),
+        _ => bail!("backup host sent an invalid reply"),
+    };
+    let snapshot = {
+        let passphrase = passphrase.to_string();
+        tokio::task::spawn_blocking(move || open(&blob, &passphrase)).await??
+    };
+
+    for (file, data) in &snapshot.files {
+        let relative = Path::new(file);
+        if !relative.components().all(|c| matches!(c, PathComponent::Normal(_))) {
+            bail!("backup contains an invalid path: {}", file);
+        }
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_private(&path, data)?;
+    }
+    std::fs::create_dir_all(dir.join("store"))?;
+    Ok(Profile {
+        name: name.to_string(),
+        dir,
+    })
+}
+
+/*
+Function:   -spawn_periodic
+Purpose:    -Back the profile up to its configured host at a fixed interval.
+
+Parameters:
+            - Endpoint endpoint:  The session's endpoint.
+            - EndpointId host:  The backup target.
+            - Profile profile:  Profile to back up.
+            - String passphrase:  Master passphrase, kept in memory only.
+            - Duration interval:  Time between backups; the first runs at once.
+            - Events ui_tx:  Where to report the first success and
+              any failure.
+*/
+pub fn spawn_periodic(
+    endpoint: Endpoint,
+    host: EndpointId,
+    profile: Profile,
+    passphrase: String,
+    interval: Duration,
+    ui_tx: Events,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut reported = false;
+        loop {
+            ticker.tick().await;
+            let note = match upload(&endpoint, host, &profile, &passphrase).await {
+                Ok(()) if !reported => {
+                    reported = true;
+                    format!("Backed up profile '{}' to {}", profile.name, host.fmt_short())
+                }
+                Ok(()) => continue,
+                Err(e) => format!("Backup to {} failed: {}", host.fmt_short(), e),
+            };
+            if ui_tx.send(ChatEvent::System(note)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/*
+Struct:     -BackupHost
+Purpose:    -Protocol handler that stores other peers' encrypted backups.
+
+Fields:
+            - PathBuf dir:  Where blobs are kept, one file per slot.
+
+Details:
+            - Blobs are opaque to the host: it cannot read them, and slots do
+              not reveal which peer or profile they belong to.
+            - Anyone who knows a slot ID can overwrite or fetch that slot;
+              slot IDs are derived from the passphrase, so that means anyone
+              who knows the passphrase.
+*/
+#[derive(Debug, Clone)]
+pub struct BackupHost {
+    dir: PathBuf,
+}
+
+impl BackupHost {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    async fn handle(&self, conn: Connection) -> Result<()> {
+        let (mut send, mut recv) = conn.accept_bi().await?;
+        let req = recv.read_to_end(1 + 32 + MAX_BLOB).await?;
+        if req.len() < 33 {
+            bail!("short backup request");
+        }
+        let (op, rest) = (req[0], &req[1..]);
+        let (slot, blob) = rest.split_at(32);
+        let path = self.dir.join(format!("{}.bin", hex::encode(slot)));
+        let reply = match op {
+            OP_STORE => {
+                std::fs::create_dir_all(&self.dir)?;
+                write_private(&path, blob)?;
+                vec![STATUS_OK]
+            }
+            OP_FETCH => match std::fs::read(&path) {
+                Ok(data) => {
+                    let mut reply = vec![STATUS_OK];
+                    reply.extend(data);
+                    reply
+                }
+                Err(_) => vec![STATUS_MISSING],
+            },
+            _ => bail!("unknown backup op {}", op),
+        };
+        send.write_all(&reply).await?;
+        send.finish()?;
+        conn.closed().await;
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for BackupHost {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        self.handle(conn)
+            .await
+            .map_err(|e| AcceptError::from_err(std::io::Error::other(e.to_string())))
+    }
+}
@@ -0,0 +1,41 @@
+use unicode_bidi::{get_base_direction, BidiInfo, Direction, Level};
+
+// ── Right-to-left text ────────────────────────────────────────────────────────
+
+/// Whether a message reads right to left: its first strong character is
+/// from an RTL script such as Arabic or Hebrew. Text without any strong
+/// characters (numbers, emoji) counts as left to right.
+pub fn is_rtl(text: &str) -> bool {
+    get_base_direction(text) == Direction::Rtl
+}
+
+/*
+Function:   -visual
+Purpose:    -Reorder one displayed line from logical to visual order.
+
+Parameters:
+            - &str text:  A single line, already wrapped; not a whole message.
+            - bool rtl:  Base direction of the message the line belongs to.
+
+Details:
+            - Most terminals draw cells left to right and never reorder, so
+              an RTL run would appear backwards. This applies the Unicode
+              Bidirectional Algorithm to the line ourselves.
+            - Call it per span: each span is reordered on its own, which
+              isolates a message's text from the nickname and markers around
+              it, so neither can rearrange the other.
+            - Explicit direction overrides in the text only affect the span
+              they are in.
+            - Lines without RTL characters are returned unchanged.
+*/
+pub fn visual(text: &str, rtl: bool) -> String {
+    let level = if rtl { Level::rtl() } else { Level::ltr() };
+    let info = BidiInfo::new(text, Some(level));
+    if !info.has_rtl() {
+        return text.to_string();
+    }
+    info.paragraphs
+        .iter()
+        .map(|para| info.reorder_line(para, para.range.clone()))
+        .collect()
+}
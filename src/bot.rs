@@ -0,0 +1,356 @@
+use std::{collections::VecDeque, future::Future, pin::Pin, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{debug, info, warn};
+
+use crate::app::{ChatEvent, ChatMessage, Trust};
+use crate::bus::ChatCommand;
+use crate::client::ChatClientBuilder;
+use crate::config::{BotConfig, HostedBot};
+use crate::profile::Profile;
+use crate::protocol::{ChatPayload, Ticket};
+use crate::session::Session;
+use crate::tui::quit_signals;
+
+// ── Bots ──────────────────────────────────────────────────────────────────────
+
+/// What a handler comes back with: text to say in the room, or None to stay
+/// quiet.
+pub type Reply = Pin<Box<dyn Future<Output = Option<String>> + Send>>;
+
+type Responder = Box<dyn Fn(ChatMessage) -> Reply + Send + Sync>;
+type Announcer = Arc<dyn Fn() -> Reply + Send + Sync>;
+
+/// The span a bot's per-minute limit counts over.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/*
+Struct:     -Bot
+Purpose:    -A room bot made of async handlers, run on a Session in place
+             of the TUI.
+
+Fields:
+            - Vec<(String, Responder)> responders:  Patterns and what to do
+              with a message that matches, tried in the order added.
+            - Vec<(Duration, Announcer)> announcements:  Handlers run on a
+              fixed period, e.g. reminders.
+            - u32 per_minute:  Most messages said per minute; 0 for no
+              limit (see `limit`).
+
+Details:
+            - Built up with `on` and `every`, then started with `run`:
+
+                  Bot::default()
+                      .on("!ping", |_| async { Some("pong".to_string()) })
+                      .on("*deploy*", |msg| async move {
+                          Some(format!("{}: deploys are frozen", msg.sender))
+                      })
+                      .every(Duration::from_secs(3600), || async {
+                          Some("Standup in the main room at 10".to_string())
+                      })
+                      .run(&mut session)
+                      .await
+
+            - Patterns are matched against the whole message, ignoring case;
+              `*` stands for any text. Only the first matching responder
+              runs.
+            - Handlers run as their own tasks, so a slow one does not hold
+              up the room. Replies go through the profile's filters like
+              anything else we send.
+            - Our own messages and ones that did not decrypt are never
+              answered, so two bots cannot talk each other into a loop
+              through their own echo.
+*/
+#[derive(Default)]
+pub struct Bot {
+    responders: Vec<(String, Responder)>,
+    announcements: Vec<(Duration, Announcer)>,
+    per_minute: u32,
+}
+
+impl Bot {
+    /// Answer messages matching `pattern` with whatever `handler` returns.
+    pub fn on<F, Fut>(mut self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(ChatMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        self.responders
+            .push((pattern.to_lowercase(), Box::new(move |msg| Box::pin(handler(msg)))));
+        self
+    }
+
+    /// Say whatever `handler` returns every `period`, starting one period
+    /// after the bot starts.
+    pub fn every<F, Fut>(mut self, period: Duration, handler: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        self.announcements.push((period, Arc::new(move || Box::pin(handler()))));
+        self
+    }
+
+    /// Say at most `per_minute` messages a minute, dropping the rest with a
+    /// warning; 0 (the default) for no limit.
+    pub fn limit(mut self, per_minute: u32) -> Self {
+        self.per_minute = per_minute;
+        self
+    }
+
+    /// The bot a profile's [bot] table describes: fixed replies, where
+    /// "{name}" is the sender's name, and periodic announcements.
+    pub fn from_config(config: &BotConfig) -> Result<Self> {
+        if config.replies.is_empty() && config.announcements.is_empty() {
+            bail!("nothing for the bot to do: add replies or announcements to the [bot] table of the config");
+        }
+        let mut bot = Bot::default();
+        for reply in &config.replies {
+            let text = reply.reply.clone();
+            bot = bot.on(&reply.pattern, move |msg| {
+                let text = text.replace("{name}", &msg.sender);
+                async move { Some(text) }
+            });
+        }
+        for announcement in &config.announcements {
+            let text = announcement.text.clone();
+            let period = Duration::from_secs(announcement.every_mins.max(1) * 60);
+            bot = bot.every(period, move || {
+                let text = text.clone();
+                async move { Some(text) }
+            });
+        }
+        Ok(bot)
+    }
+
+    /*
+    Function:   -run
+    Purpose:    -Answer and announce in a room until the process is told to
+                 quit.
+
+    Parameters:
+                - &mut Session session:  The room, already joined.
+
+    Details:
+                - Replies are dropped, with a warning, until we are admitted
+                  to the room.
+    */
+    pub async fn run(self, session: &mut Session) -> Result<()> {
+        let mut quit = quit_signals()?;
+        self.run_until(session, async move {
+            quit.recv().await;
+        })
+        .await
+    }
+
+    /// Like run, until `stop` completes instead.
+    pub async fn run_until(self, session: &mut Session, stop: impl Future<Output = ()>) -> Result<()> {
+        tokio::pin!(stop);
+        let per_minute = self.per_minute as usize;
+        // When each message of the last RATE_WINDOW was said.
+        let mut said: VecDeque<Instant> = VecDeque::new();
+        let (reply_tx, mut reply_rx) = mpsc::channel::<String>(64);
+        let timers: Vec<_> = self
+            .announcements
+            .into_iter()
+            .map(|(period, announce)| {
+                let reply_tx = reply_tx.clone();
+                tokio::spawn(async move {
+                    let mut ticker = tokio::time::interval(period);
+                    // The first tick is immediate.
+                    ticker.tick().await;
+                    loop {
+                        ticker.tick().await;
+                        if let Some(text) = announce().await
+                            && reply_tx.send(text).await.is_err()
+                        {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        loop {
+            tokio::select! {
+                _ = &mut stop => break,
+                Some(ui) = session.events.recv() => {
+                    let ChatEvent::Chat(msg) = ui else { continue };
+                    if msg.from == session.my_id || msg.trust == Trust::Failed {
+                        continue;
+                    }
+                    let text = msg.content.to_lowercase();
+                    let Some((pattern, respond)) = self.responders.iter().find(|(pattern, _)| matches(pattern, &text)) else {
+                        continue;
+                    };
+                    debug!(id = msg.id, pattern = %pattern, "bot answering");
+                    let reply = respond(msg);
+                    let reply_tx = reply_tx.clone();
+                    tokio::spawn(async move {
+                        if let Some(text) = reply.await {
+                            let _ = reply_tx.send(text).await;
+                        }
+                    });
+                }
+                Some(text) = reply_rx.recv() => {
+                    if per_minute > 0 {
+                        while said.front().is_some_and(|at| at.elapsed() >= RATE_WINDOW) {
+                            said.pop_front();
+                        }
+                        if said.len() >= per_minute {
+                            warn!(per_minute, "over the bot's limit; dropping a message");
+                            continue;
+                        }
+                        said.push_back(Instant::now());
+                    }
+                    say(session, text).await;
+                }
+            }
+        }
+        for timer in timers {
+            timer.abort();
+        }
+        Ok(())
+    }
+}
+
+/*
+Struct:     -Hosted
+Purpose:    -The bots a daemon runs beside its own room ([[bots]] in its
+             config), each room in a session of its own.
+
+Fields:
+            - watch::Sender<bool> stop:  Set to tell every bot to leave.
+            - Vec<JoinHandle<()>> tasks:  One per bot and room.
+
+Details:
+            - Each bot runs as its own profile, with that profile's identity
+              key and [bot] and [filters] tables (see HostedBot), so one
+              daemon can run a feed bot and a reminder bot that share
+              neither keys nor plugins, nor the daemon's own identity.
+            - A bot that cannot start stops the ones started before it.
+*/
+pub struct Hosted {
+    stop: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Hosted {
+    /*
+    Function:   -start
+    Purpose:    -Join every bot to its rooms.
+
+    Parameters:
+                - &[HostedBot] bots:  The daemon's [[bots]] tables.
+                - &Profile daemon:  The daemon's own profile, which no bot
+                  may run as.
+                - impl Fn(&str) -> Result<Profile> open:  Opens a bot's
+                  profile by name (Profile::open outside tests).
+                - impl Fn() -> ChatClientBuilder client:  A builder with the
+                  daemon's network options, e.g. its relays; not its bound
+                  port, which it already holds.
+    */
+    pub async fn start(
+        bots: &[HostedBot],
+        daemon: &Profile,
+        open: impl Fn(&str) -> Result<Profile>,
+        client: impl Fn() -> ChatClientBuilder,
+    ) -> Result<Self> {
+        let (stop, _) = watch::channel(false);
+        let mut hosted = Self { stop, tasks: Vec::new() };
+        let mut profiles: Vec<Profile> = Vec::new();
+        for bot in bots {
+            let started = async {
+                let profile = open(&bot.profile)?;
+                if profile.dir == daemon.dir || profiles.iter().any(|other| other.dir == profile.dir) {
+                    bail!("bot profile '{}' would share an identity with the daemon or another bot", bot.profile);
+                }
+                for room in &bot.rooms {
+                    let ticket = Ticket::from_str(room).with_context(|| format!("bot '{}': invalid ticket", bot.profile))?;
+                    let session = client().profile(profile.clone()).join(ticket).build().await?;
+                    hosted.run(session, bot.per_minute).await?;
+                }
+                profiles.push(profile);
+                Ok(())
+            };
+            if let Err(e) = started.await {
+                hosted.shutdown().await;
+                return Err(e);
+            }
+        }
+        if !hosted.tasks.is_empty() {
+            info!(bots = profiles.len(), rooms = hosted.tasks.len(), "hosting bots");
+        }
+        Ok(hosted)
+    }
+
+    /// Run the bot a session's profile describes until told to stop.
+    async fn run(&mut self, mut session: Session, per_minute: u32) -> Result<()> {
+        let profile = session.profile.clone();
+        let bot = match Bot::from_config(&session.config.bot) {
+            Ok(bot) => bot.limit(per_minute),
+            Err(e) => {
+                session.shutdown().await?;
+                return Err(e.context(format!("bot profile '{}'", profile)));
+            }
+        };
+        let mut stop = self.stop.subscribe();
+        self.tasks.push(tokio::spawn(async move {
+            let stopped = async move {
+                let _ = stop.wait_for(|stop| *stop).await;
+            };
+            if let Err(e) = bot.run_until(&mut session, stopped).await {
+                warn!(profile = %profile, error = %e, "bot stopped");
+            }
+            if let Err(e) = session.shutdown().await {
+                warn!(profile = %profile, error = %e, "could not shut the bot down");
+            }
+        }));
+        Ok(())
+    }
+
+    /// Tell every bot to leave its rooms, and wait until they have.
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        for task in self.tasks {
+            let _ = task.await;
+        }
+    }
+}
+
+/// Send a reply or announcement to the room.
+async fn say(session: &Session, text: String) {
+    if session.room_key.current().is_none() {
+        warn!("not admitted to the room yet; dropping a bot message");
+        return;
+    }
+    if let Some(payload) = session.filters.outbound(ChatPayload::text(text)) {
+        let _ = session.commands.send(ChatCommand::Send { payload, id: rand::random() }).await;
+    }
+}
+
+/// Whether `text` matches `pattern` as a whole, where `*` in the pattern
+/// stands for any run of characters. Both are already lowercase.
+fn matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`: the pattern is the whole text.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use futures_lite::StreamExt;
+use iroh::EndpointId;
+use irc::client::prelude::{Client, Command, Config, Message};
+use matrix_sdk::{
+    config::SyncSettings,
+    ruma::{
+        events::room::{
+            message::{MessageType, OriginalSyncRoomMessageEvent, Relation, RoomMessageEventContent},
+            redaction::OriginalSyncRoomRedactionEvent,
+        },
+        OwnedEventId, OwnedUserId, RoomOrAliasId,
+    },
+    Client as MatrixClient, Room as MatrixRoom,
+};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::app::{ChatEvent, ChatMessage};
+use crate::bus::ChatCommand;
+use crate::protocol::{ChatPayload, MAX_TEXT};
+use crate::session::Session;
+use crate::tui::quit_signals;
+
+// ── IRC bridge ────────────────────────────────────────────────────────────────
+
+/// Longest line we send to IRC; servers cut messages at 512 bytes including
+/// the command and prefix.
+const MAX_IRC_LINE: usize = 400;
+
+/// Where the IRC side of a bridge connects to.
+pub struct IrcOptions {
+    pub server: String,
+    pub port: u16,
+    pub tls: bool,
+    pub channel: String,
+    pub nick: String,
+}
+
+/*
+Function:   -run_irc
+Purpose:    -Mirror a room to an IRC channel until the connection drops or
+             the process is told to quit.
+
+Parameters:
+            - &mut Session session:  The room, already joined.
+            - &IrcOptions options:  IRC server, channel and the bridge's nick.
+
+Details:
+            - IRC messages are sent to the room as "<nick> text", with the
+              IRC nick also in the payload's meta ({"irc": {"nick": …}}) so
+              bots can tell bridged speakers apart. Text too long for one
+              room message is sent as several.
+            - Room messages go to the channel as "<name> text", one PRIVMSG
+              per line.
+            - Joins, parts, quits and nick changes are relayed both ways.
+              Room members are named by their announced name, looked up from
+              the Name messages the session passes on.
+            - Runs on the Session the TUI would use, so everything the room
+              does (admission, encryption, retries) works unchanged.
+*/
+pub async fn run_irc(session: &mut Session, options: &IrcOptions) -> Result<()> {
+    // Both rustls backends end up enabled through our dependencies, so
+    // rustls cannot pick one itself; use ring, as iroh does.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let config = Config {
+        nickname: Some(options.nick.clone()),
+        server: Some(options.server.clone()),
+        port: Some(options.port),
+        use_tls: Some(options.tls),
+        channels: vec![options.channel.clone()],
+        realname: Some("p2p-chat bridge".to_string()),
+        ..Config::default()
+    };
+    let mut client = Client::from_config(config)
+        .await
+        .with_context(|| format!("could not connect to {}", options.server))?;
+    client.identify()?;
+    let mut stream = client.stream()?;
+    let mut quit = quit_signals()?;
+    info!(server = %options.server, channel = %options.channel, "irc bridge up");
+
+    // Announced names of room members, to name them when they leave.
+    let mut names: HashMap<EndpointId, String> = HashMap::new();
+    loop {
+        tokio::select! {
+            _ = quit.recv() => break,
+            message = stream.next() => {
+                let Some(message) = message else {
+                    warn!("irc connection closed");
+                    break;
+                };
+                let message = message.context("irc connection failed")?;
+                if let Some(text) = from_irc(&message, &options.channel, client.current_nickname()) {
+                    let meta = message.source_nickname().map(|nick| json!({ "irc": { "nick": nick } }));
+                    send_to_room(session, text, meta).await;
+                }
+            }
+            Some(ui) = session.events.recv() => {
+                let lines = match ui {
+                    ChatEvent::Chat(msg) => to_irc(&msg),
+                    ChatEvent::System(text) => {
+                        info!(%text, "room");
+                        Vec::new()
+                    }
+                    ui => member_change(&mut names, &ui).into_iter().collect(),
+                };
+                for line in lines {
+                    client.send_privmsg(&options.channel, line)?;
+                }
+            }
+        }
+    }
+    let _ = client.send_quit("bridge shutting down");
+    Ok(())
+}
+
+/// What to tell the other network about a room member joining, leaving
+/// or renaming, tracking announced names in `names`.
+fn member_change(names: &mut HashMap<EndpointId, String>, ui: &ChatEvent) -> Option<String> {
+    match ui {
+        ChatEvent::Name { from, name } => match names.insert(*from, name.clone()) {
+            None => Some(format!("{} joined the room", name)),
+            Some(old) if old != *name => Some(format!("{} is now known as {}", old, name)),
+            Some(_) => None,
+        },
+        ChatEvent::Left(from) => names.remove(from).map(|name| format!("{} left the room", name)),
+        _ => None,
+    }
+}
+
+/// Room text for an IRC message worth relaying: channel messages and
+/// actions, and joins, parts, quits and nick changes other than our own.
+fn from_irc(message: &Message, channel: &str, me: &str) -> Option<String> {
+    let nick = message.source_nickname()?;
+    if nick == me {
+        return None;
+    }
+    match &message.command {
+        Command::PRIVMSG(target, text) if target.eq_ignore_ascii_case(channel) => {
+            match text.strip_prefix("\u{1}ACTION ") {
+                Some(action) => Some(format!("* {} {}", nick, action.trim_end_matches('\u{1}'))),
+                // Other CTCP requests are answered by the client.
+                None if text.starts_with('\u{1}') => None,
+                None => Some(format!("<{}> {}", nick, text)),
+            }
+        }
+        Command::JOIN(target, ..) if target.eq_ignore_ascii_case(channel) => {
+            Some(format!("{} joined {}", nick, channel))
+        }
+        Command::PART(target, reason) if target.eq_ignore_ascii_case(channel) => Some(match reason {
+            Some(reason) if !reason.is_empty() => format!("{} left {} ({})", nick, channel, reason),
+            _ => format!("{} left {}", nick, channel),
+        }),
+        Command::QUIT(reason) => Some(match reason {
+            Some(reason) if !reason.is_empty() => format!("{} quit IRC ({})", nick, reason),
+            _ => format!("{} quit IRC", nick),
+        }),
+        Command::NICK(new) => Some(format!("{} is now known as {} on IRC", nick, new)),
+        _ => None,
+    }
+}
+
+/// IRC lines for a room message, prefixed with the sender's name.
+fn to_irc(msg: &ChatMessage) -> Vec<String> {
+    chat_text(msg)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .flat_map(|line| split_line(&format!("<{}> {}", msg.sender, line)))
+        .collect()
+}
+
+/// Break a line into pieces of at most MAX_IRC_LINE bytes, on character
+/// boundaries.
+fn split_line(line: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    for c in line.chars() {
+        if piece.len() + c.len_utf8() > MAX_IRC_LINE {
+            pieces.push(std::mem::take(&mut piece));
+        }
+        piece.push(c);
+    }
+    pieces.push(piece);
+    pieces
+}
+
+// ── Matrix bridge ─────────────────────────────────────────────────────────────
+
+/// Where the Matrix side of a bridge logs in.
+pub struct MatrixOptions {
+    pub homeserver: String,
+    pub user: String,
+    pub password: String,
+    /// Room ID or alias, e.g. "#p2p:example.org".
+    pub room: String,
+}
+
+/// What the sync task hands the bridge loop from the Matrix room.
+enum MatrixEvent {
+    /// A message, or with `replaces` an edit of an earlier one.
+    Message {
+        event_id: OwnedEventId,
+        replaces: Option<OwnedEventId>,
+        text: String,
+        sender: OwnedUserId,
+    },
+    Redaction(OwnedEventId),
+}
+
+/*
+Function:   -run_matrix
+Purpose:    -Mirror a room to a Matrix room until the sync fails or the
+             process is told to quit.
+
+Parameters:
+            - &mut Session session:  The room, already joined.
+            - &MatrixOptions options:  Homeserver, bot account and Matrix room.
+
+Details:
+            - Logs in as an ordinary user (bot login) and joins the Matrix
+              room if it has not already. The Matrix room must not be
+              end-to-end encrypted: the bridge keeps no crypto store.
+            - Matrix messages are sent to the room as "<display name> text",
+              with the Matrix user in the payload's meta; room messages go
+              to Matrix as "<name> text". A Matrix message too long for one
+              room message is sent as several, and deleting it deletes all
+              of them.
+            - Deletes go both ways: deleting a bridged message in the room
+              redacts it on Matrix, and a redaction on Matrix deletes it in
+              the room. The room has no edits, so a Matrix edit deletes the
+              old message and posts the new text.
+            - Only events after the bridge starts are relayed, not history.
+*/
+pub async fn run_matrix(session: &mut Session, options: &MatrixOptions) -> Result<()> {
+    let client = MatrixClient::builder()
+        .server_name_or_homeserver_url(&options.homeserver)
+        .build()
+        .await
+        .with_context(|| format!("could not reach {}", options.homeserver))?;
+    client
+        .matrix_auth()
+        .login_username(&options.user, &options.password)
+        .initial_device_display_name("p2p-chat bridge")
+        .send()
+        .await
+        .context("Matrix login failed")?;
+    let room_id = RoomOrAliasId::parse(&options.room).context("invalid Matrix room")?;
+    let room = client.join_room_by_id_or_alias(&room_id, &[]).await?;
+    // Skip the backlog; only relay what is said from now on.
+    let first = client.sync_once(SyncSettings::default()).await?;
+    if room.encryption_state().is_encrypted() {
+        bail!("{} is end-to-end encrypted; the bridge needs an unencrypted room", options.room);
+    }
+
+    let me = client.user_id().context("not logged in")?.to_owned();
+    let (event_tx, mut event_rx) = mpsc::channel(64);
+    let tx = event_tx.clone();
+    let bridged = room.room_id().to_owned();
+    client.add_event_handler(move |ev: OriginalSyncRoomMessageEvent, from: MatrixRoom| {
+        let (tx, me, bridged) = (tx.clone(), me.clone(), bridged.clone());
+        async move {
+            if from.room_id() != bridged || ev.sender == me {
+                return;
+            }
+            let name = match from.get_member_no_sync(&ev.sender).await {
+                Ok(Some(member)) => member.name().to_string(),
+                _ => ev.sender.localpart().to_string(),
+            };
+            let (replaces, msgtype) = match ev.content.relates_to {
+                Some(Relation::Replacement(edit)) => (Some(edit.event_id), edit.new_content.msgtype),
+                _ => (None, ev.content.msgtype),
+            };
+            let text = match &msgtype {
+                MessageType::Emote(emote) => format!("* {} {}", name, emote.body),
+                MessageType::Text(_) | MessageType::Notice(_) => format!("<{}> {}", name, msgtype.body()),
+                other => format!("<{}> [{}] {}", name, other.msgtype(), other.body()),
+            };
+            let _ = tx
+                .send(MatrixEvent::Message { event_id: ev.event_id, replaces, text, sender: ev.sender })
+                .await;
+        }
+    });
+    let bridged = room.room_id().to_owned();
+    client.add_event_handler(move |ev: OriginalSyncRoomRedactionEvent, from: MatrixRoom| {
+        let (tx, bridged) = (event_tx.clone(), bridged.clone());
+        async move {
+            if from.room_id() != bridged {
+                return;
+            }
+            if let Some(id) = ev.redacts.or(ev.content.redacts) {
+                let _ = tx.send(MatrixEvent::Redaction(id)).await;
+            }
+        }
+    });
+    let mut sync = tokio::spawn({
+        let client = client.clone();
+        async move { client.sync(SyncSettings::default().token(first.next_batch)).await }
+    });
+    let mut quit = quit_signals()?;
+    info!(homeserver = %options.homeserver, room = %options.room, "matrix bridge up");
+
+    let mut names: HashMap<EndpointId, String> = HashMap::new();
+    // Room message IDs for each relayed Matrix event (keyed by the original
+    // event, which later edits refer to), and the other way round.
+    let mut from_matrix: HashMap<OwnedEventId, Vec<u64>> = HashMap::new();
+    let mut to_matrix: HashMap<u64, OwnedEventId> = HashMap::new();
+    let result = loop {
+        tokio::select! {
+            _ = quit.recv() => break Ok(()),
+            result = &mut sync => break match result {
+                Ok(Err(e)) => Err(e).context("Matrix sync failed"),
+                Ok(Ok(())) => Ok(()),
+                Err(e) => Err(e.into()),
+            },
+            Some(event) = event_rx.recv() => match event {
+                MatrixEvent::Message { event_id, replaces, text, sender } => {
+                    let original = replaces.unwrap_or(event_id);
+                    for old in from_matrix.remove(&original).unwrap_or_default() {
+                        let _ = session.commands.send(ChatCommand::Delete(old)).await;
+                    }
+                    let meta = json!({ "matrix": { "sender": sender.as_str() } });
+                    let ids = send_to_room(session, text, Some(meta)).await;
+                    if !ids.is_empty() {
+                        from_matrix.insert(original, ids);
+                    }
+                }
+                MatrixEvent::Redaction(event_id) => {
+                    for id in from_matrix.remove(&event_id).unwrap_or_default() {
+                        let _ = session.commands.send(ChatCommand::Delete(id)).await;
+                    }
+                }
+            },
+            Some(ui) = session.events.recv() => match ui {
+                ChatEvent::Chat(msg) => {
+                    let text = format!("<{}> {}", msg.sender, chat_text(&msg));
+                    match room.send(RoomMessageEventContent::text_plain(text)).await {
+                        Ok(sent) => {
+                            to_matrix.insert(msg.id, sent.response.event_id);
+                        }
+                        Err(e) => warn!(error = %e, "could not send to Matrix"),
+                    }
+                }
+                ChatEvent::Delete(id) => {
+                    if let Some(event_id) = to_matrix.remove(&id)
+                        && let Err(e) = room.redact(&event_id, Some("deleted in the p2p room"), None).await
+                    {
+                        warn!(error = %e, "could not redact on Matrix");
+                    }
+                }
+                ChatEvent::System(text) => info!(%text, "room"),
+                ui => {
+                    if let Some(notice) = member_change(&mut names, &ui)
+                        && let Err(e) = room.send(RoomMessageEventContent::notice_plain(notice)).await
+                    {
+                        warn!(error = %e, "could not send to Matrix");
+                    }
+                }
+            },
+        }
+    };
+    sync.abort();
+    result
+}
+
+// ── Common ────────────────────────────────────────────────────────────────────
+
+/// A room message as plain text; voice messages are announced, not relayed.
+fn chat_text(msg: &ChatMessage) -> String {
+    match &msg.voice {
+        Some(clip) => format!("[voice message, {}]", clip.duration()),
+        None => msg.content.clone(),
+    }
+}
+
+/// Send text from the other network to the room, in pieces of at most
+/// MAX_TEXT characters, and return their message IDs; none if we are not
+/// allowed to speak yet.
+async fn send_to_room(session: &Session, text: String, meta: Option<Value>) -> Vec<u64> {
+    if session.room_key.current().is_none() {
+        warn!("not admitted to the room yet; dropping a bridged message");
+        return Vec::new();
+    }
+    let mut ids = Vec::new();
+    for piece in split_text(&text) {
+        let mut payload = ChatPayload::text(piece);
+        payload.meta = meta.clone();
+        let Some(payload) = session.filters.outbound(payload) else {
+            continue;
+        };
+        let id = rand::random();
+        if session.commands.send(ChatCommand::Send { payload, id }).await.is_err() {
+            break;
+        }
+        ids.push(id);
+    }
+    ids
+}
+
+/// Break text into pieces of at most MAX_TEXT characters, the most one room
+/// message may hold.
+fn split_text(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.chunks(MAX_TEXT).map(String::from_iter).collect()
+}
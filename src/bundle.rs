@@ -0,0 +1,259 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use data_encoding::BASE64;
+use iroh::SecretKey;
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{Archive, StoreKey};
+use crate::config::Config;
+use crate::crypto::{open_with_passphrase, seal_with_passphrase, MessageKey};
+use crate::drafts::Drafts;
+use crate::names::{KnownName, NameCache};
+use crate::profile::Profile;
+use crate::protocol::Ticket;
+use crate::recent::RecentRooms;
+use crate::roomkeys::CreatorKeys;
+
+// ── Room bundles ──────────────────────────────────────────────────────────────
+
+/*
+Struct:     -Bundle
+Purpose:    -Everything a profile keeps about one room, for moving it to
+             another machine (room export / room import).
+
+Fields:
+            - String topic:  The room.
+            - Option<String> ticket:  The ticket it was last joined with, if
+              it was joined by ticket (see RecentRooms).
+            - Option<String> nickname:  Our name in this room, if it has its
+              own (see Config::room_names).
+            - String draft:  The unsent message, if any.
+            - Vec<String> history:  The room's archive, base64, oldest
+              first; empty unless we keep one (--archive).
+            - BTreeMap<String, KnownName> names:  The members we know, by
+              endpoint ID (see NameCache); absent from older bundles.
+            - Option<RoomKeyCopy> room_key:  The key of a private room we
+              created, and whom we let in (see CreatorKeys).
+            - String identity:  The profile's identity key, hex-encoded.
+
+Details:
+            - The identity goes along because the room knows us by it: a
+              private room's creator admitted that endpoint, and the sender
+              keys in the archive are wrapped for it. It is only taken over
+              on import when asked (see `import`).
+            - Public rooms' keys come from the topic, and named rooms' from
+              their name and passphrase, so neither needs key material of
+              its own. A private room's key only goes along for its
+              creator; members are handed it again when they rejoin.
+            - Only ever stored or moved sealed (see `seal`).
+*/
+#[derive(Serialize, Deserialize)]
+pub struct Bundle {
+    topic: String,
+    ticket: Option<String>,
+    nickname: Option<String>,
+    draft: String,
+    history: Vec<String>,
+    #[serde(default)]
+    names: BTreeMap<String, KnownName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    room_key: Option<RoomKeyCopy>,
+    identity: String,
+}
+
+/*
+Struct:     -RoomKeyCopy
+Purpose:    -A private room's key as it goes in a Bundle.
+
+Fields:
+            - u64 epoch:  Its /rekey epoch.
+            - u64 step:  The ratchet step `key` belongs to.
+            - String key:  Hex-encoded.
+            - Vec<String> admitted:  Endpoint IDs of the members let in.
+*/
+#[derive(Serialize, Deserialize)]
+struct RoomKeyCopy {
+    epoch: u64,
+    step: u64,
+    key: String,
+    admitted: Vec<String>,
+}
+
+/*
+Struct:     -Imported
+Purpose:    -What `Bundle::import` did, for the caller to report.
+
+Fields:
+            - String topic:  The room.
+            - usize messages:  Archived messages added to the profile's
+              archive of the room.
+            - bool other_identity:  The room knows us by an identity other
+              than the profile's, which was kept.
+*/
+pub struct Imported {
+    pub topic: String,
+    pub messages: usize,
+    pub other_identity: bool,
+}
+
+impl Bundle {
+    /*
+    Function:   -collect
+    Purpose:    -Gather what a profile keeps about a room.
+
+    Parameters:
+                - &Profile profile:  Whose room.
+                - &TopicId topic:  The room.
+                - &StoreKey store_key:  How the profile's archive of it is
+                  sealed; unused if there is none (see Archive::exists).
+
+    Details:
+                - Fails for a room the profile knows nothing about.
+                - Opening a passphrase-sealed archive is slow; call from a
+                  blocking context.
+    */
+    pub fn collect(profile: &Profile, topic: &TopicId, store_key: &StoreKey) -> Result<Self> {
+        let config = Config::load(&profile.config_path())?;
+        let ticket = RecentRooms::new(profile)
+            .list()
+            .into_iter()
+            .find(|room| room.topic == topic.to_string())
+            .map(|room| room.ticket);
+        let nickname = config.room_names.get(&topic.to_string()).cloned();
+        let draft = Drafts::new(profile).load(topic);
+        let history = if Archive::exists(profile, topic) {
+            let archive = Archive::open(profile, topic, store_key)?;
+            archive.messages().iter().map(|bytes| BASE64.encode(bytes)).collect()
+        } else {
+            Vec::new()
+        };
+        let names: BTreeMap<String, KnownName> = NameCache::new(profile)
+            .load(topic)
+            .into_iter()
+            .map(|(id, known)| (id.to_string(), known))
+            .collect();
+        let room_key = CreatorKeys::new(profile)?.load(topic)?.map(|(key, admitted)| RoomKeyCopy {
+            epoch: key.epoch,
+            step: key.step,
+            key: hex::encode(key.key),
+            admitted: admitted.iter().map(ToString::to_string).collect(),
+        });
+        if ticket.is_none()
+            && nickname.is_none()
+            && draft.is_empty()
+            && history.is_empty()
+            && names.is_empty()
+            && room_key.is_none()
+        {
+            bail!("profile '{}' keeps nothing about room {}", profile.name, topic);
+        }
+        Ok(Self {
+            topic: topic.to_string(),
+            ticket,
+            nickname,
+            draft,
+            history,
+            names,
+            room_key,
+            identity: hex::encode(profile.secret_key()?.to_bytes()),
+        })
+    }
+
+    /// Encrypt the bundle under a passphrase (see
+    /// crypto::seal_with_passphrase).
+    pub fn seal(&self, passphrase: &str) -> Result<Vec<u8>> {
+        seal_with_passphrase(&serde_json::to_vec(self)?, passphrase)
+    }
+
+    /// Reverse of `seal`. A wrong passphrase shows up as a decryption
+    /// failure.
+    pub fn open(blob: &[u8], passphrase: &str) -> Result<Self> {
+        let plaintext = open_with_passphrase(blob, passphrase).context("could not open the bundle")?;
+        serde_json::from_slice(&plaintext).context("the bundle is corrupt")
+    }
+
+    /// Whether importing writes to the profile's archive, which then needs
+    /// its store key.
+    pub fn has_history(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /*
+    Function:   -import
+    Purpose:    -Add the room to a profile, as if it had been there all
+                 along.
+
+    Parameters:
+                - &Profile profile:  The profile to add it to.
+                - &StoreKey store_key:  How the profile's archive of the room
+                  is, or is to be, sealed.
+                - bool identity:  Replace the profile's identity key with the
+                  bundle's if they differ.
+
+    Details:
+                - The ticket becomes the profile's most recent room, the
+                  nickname and draft replace any the profile had for it, the
+                  members' names are added to its name cache, the history
+                  is merged into its archive, and a private room's key
+                  replaces the one the profile had saved.
+                - Without `identity` a differing identity is reported, not
+                  taken over: the profile may be in other rooms under its own.
+    */
+    pub fn import(&self, profile: &Profile, store_key: &StoreKey, identity: bool) -> Result<Imported> {
+        let topic = TopicId::from_str(&self.topic).context("the bundle names an invalid room")?;
+        let key: [u8; 32] = hex::decode(&self.identity)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .context("the bundle's identity key is invalid")?;
+        let key = SecretKey::from_bytes(&key);
+        let other_identity = profile.secret_key()?.public() != key.public();
+        if other_identity && identity {
+            profile.set_secret_key(&key)?;
+        }
+
+        if let Some(ticket) = &self.ticket {
+            RecentRooms::new(profile).record(&Ticket::from_str(ticket).context("the bundle's ticket is invalid")?)?;
+        }
+        if let Some(nickname) = &self.nickname {
+            let mut config = Config::load(&profile.config_path())?;
+            config.room_names.insert(self.topic.clone(), nickname.clone());
+            config.save(&profile.config_path())?;
+        }
+        if !self.draft.is_empty() {
+            Drafts::new(profile).save(&topic, &self.draft)?;
+        }
+        let cache = NameCache::new(profile);
+        for (id, known) in &self.names {
+            let Ok(id) = id.parse() else {
+                continue;
+            };
+            cache.update(&topic, id, &known.name, |entry| *entry = known.clone())?;
+        }
+        if let Some(copy) = &self.room_key {
+            let key = hex::decode(&copy.key)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .context("the bundle's room key is invalid")?;
+            let key = MessageKey { epoch: copy.epoch, step: copy.step, key };
+            let admitted = copy.admitted.iter().filter_map(|id| id.parse().ok()).collect();
+            CreatorKeys::new(profile)?.save(&topic, &key, &admitted)?;
+        }
+        let mut messages = 0;
+        if self.has_history() {
+            let history = self
+                .history
+                .iter()
+                .map(|line| BASE64.decode(line.as_bytes()))
+                .collect::<Result<Vec<_>, _>>()
+                .context("the bundle's history is corrupt")?;
+            messages = Archive::open(profile, &topic, store_key)?.restore(&history)?;
+        }
+        Ok(Imported {
+            topic: self.topic.clone(),
+            messages,
+            other_identity: other_identity && !identity,
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use iroh::EndpointId;
+use tokio::sync::broadcast;
+
+use crate::app::ChatEvent;
+use crate::i18n::t;
+use crate::protocol::{ChatPayload, Interaction, Membership, ModAction, Presence};
+
+// ── Commands ──────────────────────────────────────────────────────────────────
+
+/*
+Enum:       -ChatCommand
+Purpose:    -What a front end asks of its session; sent on Session::commands
+             and carried out in order by the send loop.
+
+Variants:
+            - Send { payload, id }:  Encrypt and broadcast a chat message
+              under this ID.
+            - Delete(u64):  Delete one of our messages everywhere.
+            - Pin { id, pinned }:  Pin (or unpin) a message for everyone.
+            - Interact { to, interaction }:  A click on a component of a
+              message `to` wrote.
+            - Moderate(ModAction):  Sign and broadcast a kick or mute. Only
+              meaningful when we are the moderator.
+            - Membership(Membership):  Sign and broadcast new role
+              assignments. Only meaningful when we are the creator.
+            - Admit(EndpointId):  Let a member into our private room by
+              sending them the wrapped room key.
+            - Revoke { token, admitted }:  Sign and broadcast the revocation
+              of one of our invites, and stop counting the members admitted
+              with it as members (Session::revoke).
+            - Rekey:  Rotate our private room's key and hand the new one to
+              every member we admitted and did not kick.
+            - AskSwarm:  Ask every member for their gossip neighbours, for
+              /swarm.
+            - Ping(EndpointId):  /ping a member through the room; the pong
+              (or its absence after PING_TIMEOUT) is reported as a system
+              message.
+            - Nick(String):  Announce our new nickname (Session::rename).
+            - Presence(Presence):  Announce our presence
+              (Session::set_presence).
+            - Leave:  Send what is still queued, say goodbye and stop
+              (Session::shutdown).
+
+Details:
+            - One channel for everything, so commands are carried out in the
+              order they were given: a kick is applied before the rekey
+              that follows it, and nothing queued is lost to Leave.
+*/
+#[derive(Debug)]
+pub enum ChatCommand {
+    Send { payload: ChatPayload, id: u64 },
+    Delete(u64),
+    Pin { id: u64, pinned: bool },
+    Interact { to: EndpointId, interaction: Interaction },
+    Moderate(ModAction),
+    Membership(Membership),
+    Admit(EndpointId),
+    Revoke { token: u64, admitted: Vec<EndpointId> },
+    Rekey,
+    AskSwarm,
+    Ping(EndpointId),
+    Nick(String),
+    Presence(Presence),
+    Leave,
+}
+
+// ── Events ────────────────────────────────────────────────────────────────────
+
+/// Events a subscriber may fall behind by before it starts missing some.
+/// Generous, since the room does not wait for slow front ends: an archive
+/// catch-up arrives all at once.
+pub const EVENT_CAPACITY: usize = 8192;
+
+/// Where a session publishes its ChatEvents.
+pub type Events = broadcast::Sender<ChatEvent>;
+
+/// A new event bus with no subscribers yet.
+pub fn events() -> Events {
+    broadcast::channel(EVENT_CAPACITY).0
+}
+
+/*
+Struct:     -Subscription
+Purpose:    -One front end's view of a session's events.
+
+Fields:
+            - Receiver<ChatEvent> rx:  Our place on the bus.
+
+Details:
+            - Every subscription sees every event published after it was
+              made. One that falls more than EVENT_CAPACITY behind loses the
+              oldest and is told so with a System event.
+*/
+pub struct Subscription {
+    rx: broadcast::Receiver<ChatEvent>,
+}
+
+impl Subscription {
+    pub fn new(events: &Events) -> Self {
+        Self { rx: events.subscribe() }
+    }
+
+    /// The next event; None once the session is gone.
+    pub async fn recv(&mut self) -> Option<ChatEvent> {
+        match self.rx.recv().await {
+            Ok(event) => Some(event),
+            Err(broadcast::error::RecvError::Lagged(missed)) => Some(missed_events(missed)),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+
+    /// The next event if one is waiting.
+    pub fn try_recv(&mut self) -> Option<ChatEvent> {
+        match self.rx.try_recv() {
+            Ok(event) => Some(event),
+            Err(broadcast::error::TryRecvError::Lagged(missed)) => Some(missed_events(missed)),
+            Err(_) => None,
+        }
+    }
+}
+
+fn missed_events(missed: u64) -> ChatEvent {
+    ChatEvent::System(t!("Fell behind the room; {} events were missed.", missed))
+}
@@ -0,0 +1,212 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use anyhow::{bail, Result};
+use iroh::RelayUrl;
+
+use crate::archive::StoreKey;
+use crate::config::LanConfig;
+use crate::profile::{Profile, DEFAULT_PROFILE};
+use crate::protocol::Ticket;
+use crate::session::{Entry, NetOptions, Session};
+
+// ── Options ───────────────────────────────────────────────────────────────────
+
+/*
+Enum:       -Discovery
+Purpose:    -How a client finds the room's members besides the peers its
+             ticket lists.
+
+Variants:
+            - Internet:  Relays and n0 address lookup only.
+            - Lan:  Also mDNS on the local network ([lan] discover).
+            - LanOnly:  The local network and nothing else ([lan] offline).
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discovery {
+    Internet,
+    Lan,
+    LanOnly,
+}
+
+impl Discovery {
+    /// The [lan] settings that mean this.
+    pub fn lan(self) -> LanConfig {
+        LanConfig {
+            discover: self == Discovery::Lan,
+            offline: self == Discovery::LanOnly,
+        }
+    }
+}
+
+/*
+Enum:       -HistoryBackend
+Purpose:    -Where a client keeps the room's messages.
+
+Variants:
+            - Memory:  Only in the front end's scrollback, as usual.
+            - Archive:  Also on disk, handed to members who were away (see
+              archive::Archive). Meant for an always-on peer.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryBackend {
+    Memory,
+    Archive,
+}
+
+/*
+Enum:       -CryptoMode
+Purpose:    -How a room we open keys its messages.
+
+Variants:
+            - Public:  The key is derived from the topic; anyone with the
+              ticket reads the room.
+            - Private:  The key is random and handed to each member we admit
+              (see MessageBody::Knock).
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoMode {
+    Public,
+    Private,
+}
+
+// ── Builder ───────────────────────────────────────────────────────────────────
+
+/*
+Struct:     -ChatClientBuilder
+Purpose:    -Everything needed to start a Session, gathered one option at a
+             time, for the binary and for apps embedding the client.
+
+Fields:
+            - Option<Profile> profile:  Identity and config to run as; the
+              default profile if not set.
+            - Option<String> nickname:  Overrides the profile's nickname.
+            - Option<Entry> entry:  The room to open or join; a new public
+              room if not set.
+            - Option<CryptoMode> crypto:  How a room we open is keyed.
+            - NetOptions net:  Sockets, relays, discovery and history.
+
+Details:
+            - Options left unset fall back to the profile's config, as on the
+              command line.
+*/
+#[derive(Default)]
+pub struct ChatClientBuilder {
+    profile: Option<Profile>,
+    nickname: Option<String>,
+    entry: Option<Entry>,
+    crypto: Option<CryptoMode>,
+    net: NetOptions,
+}
+
+impl ChatClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run as this profile.
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Run as the profile kept in this directory, created if missing: its
+    /// identity key, config and store all live there.
+    pub fn identity_dir(mut self, dir: impl Into<PathBuf>) -> Result<Self> {
+        self.profile = Some(Profile::at(dir.into())?);
+        Ok(self)
+    }
+
+    pub fn nickname(mut self, name: impl Into<String>) -> Self {
+        self.nickname = Some(name.into());
+        self
+    }
+
+    /// Open a new room as its creator.
+    pub fn open(mut self) -> Self {
+        self.entry = Some(Entry::Open { private: false, title: None });
+        self
+    }
+
+    /// Join the room a ticket names.
+    pub fn join(mut self, ticket: Ticket) -> Self {
+        self.entry = Some(Entry::Join(ticket));
+        self
+    }
+
+    /// Meet in a room named by a name and passphrase, running its
+    /// rendezvous endpoint if `host`.
+    pub fn named(mut self, name: impl Into<String>, passphrase: impl Into<String>, host: bool) -> Self {
+        self.entry = Some(Entry::Named {
+            name: name.into(),
+            passphrase: passphrase.into(),
+            host,
+        });
+        self
+    }
+
+    /// Open, join or meet as `entry` says.
+    pub fn entry(mut self, entry: Entry) -> Self {
+        self.entry = Some(entry);
+        self
+    }
+
+    /// How a room we open is keyed. A joined room's ticket decides for
+    /// itself, and named rooms are always public.
+    pub fn crypto(mut self, crypto: CryptoMode) -> Self {
+        self.crypto = Some(crypto);
+        self
+    }
+
+    /// Listen on this UDP socket; repeat for several. The defaults (every
+    /// interface, random ports) if never called.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.net.bind.push(addr);
+        self
+    }
+
+    /// Use this relay instead of the defaults; repeat for several.
+    pub fn relay(mut self, url: RelayUrl) -> Self {
+        self.net.relays.push(url);
+        self
+    }
+
+    /// Never use a relay; connect to peers directly only.
+    pub fn no_relay(mut self) -> Self {
+        self.net.no_relay = true;
+        self
+    }
+
+    /// Find members this way instead of as the config's [lan] table says.
+    pub fn discovery(mut self, discovery: Discovery) -> Self {
+        self.net.discovery = Some(discovery);
+        self
+    }
+
+    pub fn history(mut self, history: HistoryBackend) -> Self {
+        self.net.archive = history == HistoryBackend::Archive;
+        self
+    }
+
+    /// Seal the archive on disk with this key; plain if never called.
+    pub fn archive_key(mut self, key: StoreKey) -> Self {
+        self.net.archive_key = key;
+        self
+    }
+
+    /// Start the session: bind the endpoint and open or join the room.
+    pub async fn build(self) -> Result<Session> {
+        if !self.net.relays.is_empty() && self.net.no_relay {
+            bail!("relays were given but relays are turned off");
+        }
+        let entry = match (self.entry.unwrap_or(Entry::Open { private: false, title: None }), self.crypto) {
+            (Entry::Open { title, .. }, Some(crypto)) => Entry::Open { private: crypto == CryptoMode::Private, title },
+            (Entry::Named { .. }, Some(CryptoMode::Private)) => bail!("named rooms are always public"),
+            (entry, _) => entry,
+        };
+        let profile = match self.profile {
+            Some(profile) => profile,
+            None => Profile::open(DEFAULT_PROFILE)?,
+        };
+        Session::start(&profile, self.nickname, entry, self.net).await
+    }
+}
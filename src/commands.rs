@@ -0,0 +1,455 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Result};
+
+use crate::i18n::t;
+use crate::notify;
+use crate::protocol::{Presence, Role, Ticket, MAX_POLL_OPTIONS, MAX_VOICE_SECONDS};
+
+// ── Slash commands ────────────────────────────────────────────────────────────
+
+/*
+Enum:       -SlashCommand
+Purpose:    -Represents a local command typed into the input box with a leading '/'.
+
+Variants:
+            - Dnd:  Toggle "do not disturb", suppressing desktop notifications
+              and the terminal bell.
+            - TabOpen { profile }:  Open a new room as another profile, in a
+              new tab. (/tab open <profile>)
+            - TabJoin { profile, ticket }:  Join a room as another profile, in a
+              new tab. (/tab join <profile> <ticket>)
+            - TabClose:  Leave the room shown in the current tab. (/tab close)
+            - Export { path }:  Write the chat history to a file; .json gives
+              JSON, anything else plain text. (/export <path>)
+            - Topic { subject }:  Show the room topic, or set it for everyone;
+              "-" clears it. (/topic [<text> | -])
+            - Ephemeral { ttl, text }:  Send a message that every peer removes
+              after `ttl` seconds. (/ephemeral <duration> <text>, e.g. 5m)
+            - Kick { name }:  Moderator only: make everyone ignore a member
+              from now on. (/kick <name>)
+            - Mute { name }:  Moderator only: make everyone ignore a member's
+              chat messages. (/mute <name>)
+            - Approve { name }:  Creator of a private room only: let a member
+              who knocked in. (/approve <name>)
+            - Deny { name }:  Creator of a private room only: turn a knocking
+              member away. (/deny <name>)
+            - Rekey:  Creator of a private room only: switch to a fresh room
+              key, handed only to the members still let in. (/rekey)
+            - SetRole { name, role }:  Creator only: make a member an admin,
+              a regular member or read-only. (/role <name> admin|member|readonly)
+            - Members:  List known members and their roles. (/members)
+            - Block { peer }:  Drop everything a peer sends, in every room,
+              from now on; saved in the profile. (/block <name|endpoint ID>)
+            - Unblock { peer }:  Undo a block; the number is the one /blocks
+              shows. (/unblock <number|name|endpoint ID>)
+            - Blocks:  List blocked peers. (/blocks)
+            - Network:  Show the relay in use and the measured latency to each
+              relay. (/network)
+            - Net:  Open a panel showing, per connected peer, whether the
+              path is direct or relayed, its latency and the bytes sent and
+              received. Esc closes it. (/net)
+            - Ping { peer }:  Measure the round trip to a member through the
+              room, and show our connection's own if we have one.
+              (/ping <name|endpoint ID>)
+            - Whois { peer }:  Show a peer's endpoint ID, addresses, relay,
+              connection, key status, when we first and last heard from them
+              and how many messages they sent. (/whois <name|short ID>)
+            - Swarm:  Open a panel drawing our gossip neighbours and, as they
+              answer, theirs, with how each pair is connected. Esc closes it.
+              (/swarm)
+            - Pins:  Open a panel listing every pinned message. Esc closes
+              it. (/pins)
+            - Ticket { qr }:  Show this room's ticket, as text or as a QR code
+              to scan. (/ticket [qr])
+            - Invite { ttl, once }:  Creator of a private room only: make a
+              ticket that stops working after `ttl` seconds and/or after one
+              use. Knocks with it are let in without asking.
+              (/invite [duration] [once], e.g. /invite 1h once)
+            - Revoke { ticket }:  Creator of a private room only: stop
+              accepting an invite ticket and rotate the key past whoever got
+              in with it. (/revoke <ticket>)
+            - Directory { query }:  Search the configured rooms directory; no
+              query lists every room. (/directory [query])
+            - DirectoryJoin { index }:  Join a room from the last search, in a
+              new tab. (/directory join <number>)
+            - Publish { name, description }:  Creator only: list this room in
+              the directory and keep the listing fresh while the tab is open.
+              (/publish <name> <description>)
+            - Voice { seconds }:  Record a voice message from the microphone
+              for `seconds` (default 10, at most 30) and send it.
+              (/voice [duration])
+            - Nick { name, room }:  Change our nickname in this room. With
+              --room it is also kept for the next time we join it; "-" then
+              forgets that instead, going back to the profile's name.
+              (/nick [--room] <name|->)
+            - Status { presence }:  Tell the room we are online, away or
+              busy; shown next to our name in /members.
+              (/status online|away|busy)
+            - Notify { rule }:  Show the notification rules, or change one
+              and save it in the profile (see NotifyRule).
+              (/notify [mentions-only on|off | keyword add|remove <word> |
+              mute|unmute <name|endpoint ID> | quiet <HH:MM-HH:MM>|off])
+            - Poll { question, options }:  Ask the room a question with 2 to 9
+              options; members vote with the number keys.
+              (/poll "Question?" "A" "B" ...)
+            - Clear:  Empty the message pane. Nothing is deleted from disk
+              or from anyone else's view. (/clear)
+            - Wipe { confirmed, identity }:  Securely delete this profile's
+              history, drafts and remembered rooms, and its identity key with
+              `identity`, then quit. Without "yes" it only says what would go.
+              (/wipe [yes] [identity])
+
+Details:
+            - Slash commands are never broadcast; they are handled by the TUI.
+            - Input that does not start with '/' is a regular chat message.
+*/
+#[derive(Debug)]
+pub enum SlashCommand {
+    Dnd,
+    TabOpen { profile: String },
+    TabJoin { profile: String, ticket: Ticket },
+    TabClose,
+    Export { path: PathBuf },
+    Topic { subject: Option<String> },
+    Ephemeral { ttl: u64, text: String },
+    Kick { name: String },
+    Mute { name: String },
+    Approve { name: String },
+    Deny { name: String },
+    Rekey,
+    SetRole { name: String, role: Role },
+    Members,
+    Block { peer: String },
+    Unblock { peer: String },
+    Blocks,
+    Network,
+    Net,
+    Ping { peer: String },
+    Whois { peer: String },
+    Swarm,
+    Pins,
+    Ticket { qr: bool },
+    Invite { ttl: Option<u64>, once: bool },
+    Revoke { ticket: Ticket },
+    Directory { query: String },
+    DirectoryJoin { index: usize },
+    Publish { name: String, description: String },
+    Voice { seconds: u64 },
+    Poll { question: String, options: Vec<String> },
+    Nick { name: Option<String>, room: bool },
+    Status { presence: Presence },
+    Notify { rule: Option<NotifyRule> },
+    Clear,
+    Wipe { confirmed: bool, identity: bool },
+}
+
+/// One change to the notification rules (see config::NotifyConfig).
+#[derive(Debug)]
+pub enum NotifyRule {
+    MentionsOnly(bool),
+    Keyword { word: String, add: bool },
+    Mute { peer: String, muted: bool },
+    Quiet(Option<String>),
+}
+
+impl SlashCommand {
+    /*
+    Function:   -parse
+    Purpose:    -Parse a line of input into a SlashCommand.
+
+    Parameters:
+                - &str input:  The raw input buffer, including the leading '/'.
+
+    Details:
+                - The first whitespace-separated word selects the command;
+                  the remainder is passed to the command as arguments.
+                - Returns an error describing the problem for unknown commands
+                  or bad arguments, suitable for display as a system message.
+    */
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut words = input.split_whitespace();
+        let name = words.next().unwrap_or("/");
+        match name {
+            "/dnd" => Ok(SlashCommand::Dnd),
+            "/tab" => match (words.next(), words.next(), words.next()) {
+                (Some("open"), Some(profile), None) => Ok(SlashCommand::TabOpen {
+                    profile: profile.to_string(),
+                }),
+                (Some("join"), Some(profile), Some(ticket)) => Ok(SlashCommand::TabJoin {
+                    profile: profile.to_string(),
+                    ticket: Ticket::from_str(ticket)?,
+                }),
+                (Some("close"), None, None) => Ok(SlashCommand::TabClose),
+                _ => bail!(t!("Usage: /tab open <profile> | /tab join <profile> <ticket> | /tab close")),
+            },
+            "/export" => match words.next() {
+                Some(path) => Ok(SlashCommand::Export {
+                    path: PathBuf::from(path),
+                }),
+                None => bail!(t!("Usage: /export <path>")),
+            },
+            "/topic" => {
+                let rest = input.trim_start()[name.len()..].trim();
+                Ok(SlashCommand::Topic {
+                    subject: match rest {
+                        "" => None,
+                        "-" => Some(String::new()),
+                        text => Some(text.to_string()),
+                    },
+                })
+            }
+            "/nick" => {
+                let rest = input.trim_start()[name.len()..].trim();
+                let (room, rest) = match rest.strip_prefix("--room") {
+                    Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => (true, rest.trim()),
+                    _ => (false, rest),
+                };
+                match rest {
+                    "" => bail!(t!("Usage: /nick [--room] <name>  (/nick --room - forgets this room's name)")),
+                    "-" if room => Ok(SlashCommand::Nick { name: None, room }),
+                    "-" => bail!(t!("Only /nick --room - forgets a name")),
+                    text => Ok(SlashCommand::Nick {
+                        name: Some(text.to_string()),
+                        room,
+                    }),
+                }
+            }
+            "/status" => match (words.next().and_then(Presence::parse), words.next()) {
+                (Some(presence), None) => Ok(SlashCommand::Status { presence }),
+                _ => bail!(t!("Usage: /status online|away|busy")),
+            },
+            "/notify" => {
+                let rule = match (words.next(), words.next(), words.next(), words.next()) {
+                    (None, ..) => None,
+                    (Some("mentions-only"), Some("on"), None, _) => Some(NotifyRule::MentionsOnly(true)),
+                    (Some("mentions-only"), Some("off"), None, _) => Some(NotifyRule::MentionsOnly(false)),
+                    (Some("keyword"), Some(op @ ("add" | "remove")), Some(word), None) => Some(NotifyRule::Keyword {
+                        word: word.to_string(),
+                        add: op == "add",
+                    }),
+                    (Some(op @ ("mute" | "unmute")), Some(peer), None, _) => Some(NotifyRule::Mute {
+                        peer: peer.to_string(),
+                        muted: op == "mute",
+                    }),
+                    (Some("quiet"), Some("off"), None, _) => Some(NotifyRule::Quiet(None)),
+                    (Some("quiet"), Some(hours), None, _) => {
+                        notify::quiet_hours(hours)?;
+                        Some(NotifyRule::Quiet(Some(hours.to_string())))
+                    }
+                    _ => bail!(t!(
+                        "Usage: /notify [mentions-only on|off | keyword add|remove <word> | mute|unmute <name|endpoint ID> | quiet <HH:MM-HH:MM>|off]"
+                    )),
+                };
+                Ok(SlashCommand::Notify { rule })
+            }
+            "/ephemeral" => {
+                // Keep the message text exactly as typed, spacing included.
+                let rest = input.trim_start()[name.len()..].trim_start();
+                let Some((duration, text)) = rest.split_once(char::is_whitespace) else {
+                    bail!(t!("Usage: /ephemeral <duration> <text>  (e.g. 30s, 5m, 2h, 1d)"));
+                };
+                let text = text.trim_start();
+                if text.is_empty() {
+                    bail!(t!("Usage: /ephemeral <duration> <text>  (e.g. 30s, 5m, 2h, 1d)"));
+                }
+                Ok(SlashCommand::Ephemeral {
+                    ttl: parse_duration(duration)?,
+                    text: text.to_string(),
+                })
+            }
+            "/kick" | "/mute" => match (words.next(), words.next()) {
+                (Some(target), None) if name == "/kick" => Ok(SlashCommand::Kick {
+                    name: target.to_string(),
+                }),
+                (Some(target), None) => Ok(SlashCommand::Mute {
+                    name: target.to_string(),
+                }),
+                _ => bail!(t!("Usage: {} <name>", name)),
+            },
+            "/approve" | "/deny" => match (words.next(), words.next()) {
+                (Some(target), None) if name == "/approve" => Ok(SlashCommand::Approve {
+                    name: target.to_string(),
+                }),
+                (Some(target), None) => Ok(SlashCommand::Deny {
+                    name: target.to_string(),
+                }),
+                _ => bail!(t!("Usage: {} <name>", name)),
+            },
+            "/role" => match (words.next(), words.next().map(Role::parse), words.next()) {
+                (Some(target), Some(Some(role)), None) => Ok(SlashCommand::SetRole {
+                    name: target.to_string(),
+                    role,
+                }),
+                _ => bail!(t!("Usage: /role <name> admin|member|readonly")),
+            },
+            "/rekey" => Ok(SlashCommand::Rekey),
+            "/members" => Ok(SlashCommand::Members),
+            "/block" | "/unblock" => match (words.next(), words.next()) {
+                (Some(peer), None) if name == "/block" => Ok(SlashCommand::Block {
+                    peer: peer.to_string(),
+                }),
+                (Some(peer), None) => Ok(SlashCommand::Unblock {
+                    peer: peer.to_string(),
+                }),
+                _ if name == "/block" => bail!(t!("Usage: /block <name|endpoint ID>")),
+                _ => bail!(t!("Usage: /unblock <number|name|endpoint ID>")),
+            },
+            "/blocks" => Ok(SlashCommand::Blocks),
+            "/network" => Ok(SlashCommand::Network),
+            "/net" => Ok(SlashCommand::Net),
+            "/swarm" => Ok(SlashCommand::Swarm),
+            "/ping" | "/whois" => match (words.next(), words.next()) {
+                (Some(peer), None) if name == "/ping" => Ok(SlashCommand::Ping {
+                    peer: peer.to_string(),
+                }),
+                (Some(peer), None) => Ok(SlashCommand::Whois {
+                    peer: peer.to_string(),
+                }),
+                _ if name == "/ping" => bail!(t!("Usage: /ping <name|endpoint ID>")),
+                _ => bail!(t!("Usage: /whois <name|short ID>")),
+            },
+            "/pins" => Ok(SlashCommand::Pins),
+            "/ticket" => match (words.next(), words.next()) {
+                (None, None) => Ok(SlashCommand::Ticket { qr: false }),
+                (Some("qr"), None) => Ok(SlashCommand::Ticket { qr: true }),
+                _ => bail!(t!("Usage: /ticket [qr]")),
+            },
+            "/invite" => {
+                let mut ttl = None;
+                let mut once = false;
+                for word in words {
+                    match word {
+                        "once" if !once => once = true,
+                        _ if ttl.is_none() => ttl = Some(parse_duration(word)?),
+                        _ => bail!(t!("Usage: /invite [duration] [once]  (e.g. /invite 1h once)")),
+                    }
+                }
+                if ttl.is_none() && !once {
+                    bail!(t!("Usage: /invite [duration] [once]  (e.g. /invite 1h once)"));
+                }
+                Ok(SlashCommand::Invite { ttl, once })
+            }
+            "/revoke" => match (words.next(), words.next()) {
+                (Some(ticket), None) => Ok(SlashCommand::Revoke {
+                    ticket: Ticket::from_str(ticket)?,
+                }),
+                _ => bail!(t!("Usage: /revoke <ticket>")),
+            },
+            "/directory" => {
+                let rest: Vec<&str> = words.collect();
+                match rest.as_slice() {
+                    ["join", n] => match n.parse::<usize>() {
+                        Ok(index) if index >= 1 => Ok(SlashCommand::DirectoryJoin { index }),
+                        _ => bail!(t!("Usage: /directory join <number>")),
+                    },
+                    ["join", ..] => bail!(t!("Usage: /directory join <number>")),
+                    query => Ok(SlashCommand::Directory {
+                        query: query.join(" "),
+                    }),
+                }
+            }
+            "/publish" => {
+                let rest = input.trim_start()[name.len()..].trim();
+                match rest.split_once(char::is_whitespace) {
+                    Some((room, description)) => Ok(SlashCommand::Publish {
+                        name: room.to_string(),
+                        description: description.trim().to_string(),
+                    }),
+                    None => bail!(t!("Usage: /publish <name> <description>")),
+                }
+            }
+            "/voice" => match (words.next(), words.next()) {
+                (None, None) => Ok(SlashCommand::Voice { seconds: 10 }),
+                (Some(duration), None) => match parse_duration(duration)? {
+                    seconds if seconds <= MAX_VOICE_SECONDS => Ok(SlashCommand::Voice { seconds }),
+                    _ => bail!(t!("Voice messages can be at most {} seconds long", MAX_VOICE_SECONDS)),
+                },
+                _ => bail!(t!("Usage: /voice [duration]  (e.g. 15s)")),
+            },
+            "/poll" => {
+                let usage = t!("Usage: /poll \"Question?\" \"Option 1\" \"Option 2\" ...");
+                let mut args = quoted_words(&input.trim_start()[name.len()..])?.into_iter();
+                let Some(question) = args.next() else {
+                    bail!(usage);
+                };
+                let options: Vec<String> = args.collect();
+                if options.len() < 2 {
+                    bail!(usage);
+                }
+                if options.len() > MAX_POLL_OPTIONS {
+                    bail!(t!("A poll can have at most {} options", MAX_POLL_OPTIONS));
+                }
+                Ok(SlashCommand::Poll { question, options })
+            }
+            "/clear" => Ok(SlashCommand::Clear),
+            "/wipe" => match (words.next(), words.next(), words.next()) {
+                (None, ..) => Ok(SlashCommand::Wipe { confirmed: false, identity: false }),
+                (Some("identity"), None, _) => Ok(SlashCommand::Wipe { confirmed: false, identity: true }),
+                (Some("yes"), None, _) => Ok(SlashCommand::Wipe { confirmed: true, identity: false }),
+                (Some("yes"), Some("identity"), None) => Ok(SlashCommand::Wipe { confirmed: true, identity: true }),
+                _ => bail!(t!("Usage: /wipe [yes] [identity]")),
+            },
+            _ => bail!(t!("Unknown command: {}", name)),
+        }
+    }
+}
+
+/// Parse a duration like `45`, `30s`, `5m`, `2h` or `1d` into seconds.
+pub fn parse_duration(text: &str) -> Result<u64> {
+    let (number, unit) = match text.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&text[..i], c),
+        _ => (text, 's'),
+    };
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => bail!(t!("Unknown duration unit '{}': use s, m, h or d", unit)),
+    };
+    let Ok(n) = number.parse::<u64>() else {
+        bail!(t!("Invalid duration '{}'", text));
+    };
+    if n == 0 {
+        bail!(t!("Duration must be greater than zero"));
+    }
+    Ok(n.saturating_mul(scale))
+}
+
+/// Split arguments on whitespace, except inside double quotes, which are
+/// removed: `"Lunch?" Pizza "Thai food"` is three words.
+fn quoted_words(text: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(words);
+        };
+        let mut word = String::new();
+        if first == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => word.push(c),
+                    None => bail!(t!("Missing closing quote")),
+                }
+            }
+        } else {
+            word.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+}
+
+/// Returns true if the input should be treated as a slash command rather than
+/// sent as a chat message.
+pub fn is_command(input: &str) -> bool {
+    input.starts_with('/')
+}
@@ -0,0 +1,465 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::history::DEFAULT_HISTORY;
+
+// ── Config file ───────────────────────────────────────────────────────────────
+
+/*
+Struct:     -Config
+Purpose:    -User settings loaded from a profile's config.toml.
+
+Fields:
+            - Option<String> name:  Default nickname for this profile. The
+              --name flag takes precedence when given.
+            - BTreeMap<String, String> room_names:  Nickname to use in a
+              room instead of `name`, keyed by topic ID (/nick --room).
+            - Option<String> locale:  Language of the user interface, "en"
+              or "de". When unset, LC_ALL, LC_MESSAGES or LANG decides (see
+              i18n::init).
+            - bool notify_on_mention:  Notify (or ring the bell) when a message
+              mentions our nickname, even if the terminal is focused.
+            - NotifyConfig notify:  More notification rules ([notify]
+              table, /notify).
+            - u64 away_after_mins:  Minutes without a key press after which
+              the TUI sets us away, and back to online on the next one. 0
+              turns it off; a /status of busy or away is left alone.
+            - BTreeMap<String, String> relay_pins:  Relay URL to use for a room,
+              keyed by topic ID (as shown by /network). Rooms without a pin use
+              the lowest-latency default relay.
+            - BTreeMap<String, Vec<String>> keys:  TUI key bindings to change,
+              action → keys, e.g. quit = ["ctrl+q"] ([keys] table; see
+              keymap::Keymap for the actions and their defaults).
+            - BackupConfig backup:  Encrypted profile backups ([backup] table).
+            - DisplayConfig display:  Presentation settings ([display] table).
+            - DirectoryConfig directory:  Public rooms directory ([directory]
+              table).
+            - LanConfig lan:  Local network discovery ([lan] table).
+            - FiltersConfig filters:  Message filters ([filters] table).
+            - BotConfig bot:  What the `bot` subcommand says ([bot] table).
+            - Vec<HostedBot> bots:  Bots the `daemon` subcommand runs too,
+              each as a profile of its own ([[bots]] tables).
+            - WebhookConfig webhook:  Where to post received messages
+              ([webhook] table).
+            - LoadConfig load:  When to cut back presence broadcasts
+              ([load] table).
+            - Vec<String> blocked:  Endpoint IDs of peers whose messages are
+              dropped unseen in every room (/block, /unblock).
+            - u32 pow_bits:  Proof-of-work difficulty, in leading zero bits,
+              for chat messages in public rooms: ours are stamped to it and
+              others' with less are dropped. 0 (the default) turns it off;
+              capped at 24. Everyone in a room should use the same value.
+            - bool legacy_compat:  Also send our chat messages in public
+              rooms as legacy plaintext (MessageBody::Message), for clients
+              of the original unencrypted protocol while a room moves off
+              it. Their plaintext is shown, marked unencrypted, either way.
+            - bool keychain:  Keep the identity key and named rooms'
+              passphrases in the OS keychain rather than in files or typing
+              them each time (`profile keychain`).
+            - bool seal_archive:  Encrypt the --archive store on disk, with
+              a key kept in the keychain if `keychain` is on, else with a
+              passphrase asked for at startup.
+
+Details:
+            - Every field is optional; a missing or empty file yields defaults.
+            - Unknown keys are ignored so older builds can read newer files.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub name: Option<String>,
+    pub room_names: BTreeMap<String, String>,
+    pub locale: Option<String>,
+    pub notify_on_mention: bool,
+    pub notify: NotifyConfig,
+    pub away_after_mins: u64,
+    pub relay_pins: BTreeMap<String, String>,
+    pub keys: BTreeMap<String, Vec<String>>,
+    pub backup: BackupConfig,
+    pub display: DisplayConfig,
+    pub directory: DirectoryConfig,
+    pub lan: LanConfig,
+    pub filters: FiltersConfig,
+    pub bot: BotConfig,
+    pub bots: Vec<HostedBot>,
+    pub webhook: WebhookConfig,
+    pub load: LoadConfig,
+    pub blocked: Vec<String>,
+    pub pow_bits: u32,
+    pub legacy_compat: bool,
+    pub keychain: bool,
+    pub seal_archive: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: None,
+            room_names: BTreeMap::new(),
+            locale: None,
+            notify_on_mention: true,
+            notify: NotifyConfig::default(),
+            away_after_mins: 10,
+            relay_pins: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            backup: BackupConfig::default(),
+            display: DisplayConfig::default(),
+            directory: DirectoryConfig::default(),
+            lan: LanConfig::default(),
+            filters: FiltersConfig::default(),
+            bot: BotConfig::default(),
+            bots: Vec::new(),
+            webhook: WebhookConfig::default(),
+            load: LoadConfig::default(),
+            blocked: Vec::new(),
+            pow_bits: 0,
+            legacy_compat: false,
+            keychain: false,
+            seal_archive: false,
+        }
+    }
+}
+
+/*
+Struct:     -NotifyConfig
+Purpose:    -When a received message raises a notification, beyond
+             notify_on_mention (see notify::Rules).
+
+Fields:
+            - bool mentions_only:  Notify for mentions and keywords only, not
+              for every message that arrives while we are not looking.
+            - Vec<String> keywords:  Words (any case) that notify like a
+              mention, even while the terminal is focused.
+            - Vec<String> muted:  Endpoint IDs of peers who never notify.
+            - Option<String> quiet_hours:  Local times with no notifications
+              at all, as "HH:MM-HH:MM"; may wrap past midnight.
+*/
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub mentions_only: bool,
+    pub keywords: Vec<String>,
+    pub muted: Vec<String>,
+    pub quiet_hours: Option<String>,
+}
+
+/*
+Struct:     -BackupConfig
+Purpose:    -Settings for encrypted backups to a trusted peer.
+
+Fields:
+            - Option<String> target:  Endpoint ID of the peer to back up to.
+              When set, the master passphrase is asked for at startup.
+            - u64 interval_mins:  Minutes between backups while running.
+            - bool host:  Accept and store other peers' backups.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub target: Option<String>,
+    pub interval_mins: u64,
+    pub host: bool,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            target: None,
+            interval_mins: 60,
+            host: false,
+        }
+    }
+}
+
+/*
+Struct:     -DirectoryConfig
+Purpose:    -Settings for the opt-in public rooms directory.
+
+Fields:
+            - Option<String> server:  Endpoint ID of the directory peer that
+              /directory searches and /publish lists rooms on.
+            - bool host:  Run a directory for other peers.
+            - bool gossip:  Also search and list on the serverless directory
+              topic (see directory::GossipDirectory), and `browse` it.
+            - Vec<String> peers:  Endpoint IDs of peers on the directory
+              topic to enter it by; the server, if set, is tried too.
+
+Details:
+            - Nothing is sent anywhere unless a server or gossip is
+              configured and a /directory or /publish command (or `browse`)
+              is used. A host with gossip on stays on the topic, so others
+              can enter by it.
+*/
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirectoryConfig {
+    pub server: Option<String>,
+    pub host: bool,
+    pub gossip: bool,
+    pub peers: Vec<String>,
+}
+
+/*
+Struct:     -LanConfig
+Purpose:    -Settings for finding room members on the local network.
+
+Fields:
+            - bool discover:  Announce ourselves and browse for members of the
+              same room over mDNS, and connect to any we find.
+            - bool offline:  Use only the local network: no relays and no n0
+              address lookup. Implies discover. For air-gapped LAN parties.
+
+Details:
+            - Announcements name a hash of the room's topic, not the topic
+              itself, so others on the network only learn that some room is
+              in use.
+*/
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanConfig {
+    pub discover: bool,
+    pub offline: bool,
+}
+
+impl LanConfig {
+    /// Whether to run mDNS discovery at all.
+    pub fn enabled(&self) -> bool {
+        self.discover || self.offline
+    }
+}
+
+/*
+Struct:     -FiltersConfig
+Purpose:    -Which message filters to run (see filters::Filter), and their
+             settings.
+
+Fields:
+            - Vec<String> enabled:  Filter names, run in this order: "mask",
+              "highlight" and "log".
+            - Vec<String> mask_words:  Words "mask" replaces with asterisks in
+              messages sent and received.
+            - Vec<String> highlight_words:  Keywords that make "highlight"
+              show and notify a received message like a mention.
+*/
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FiltersConfig {
+    pub enabled: Vec<String>,
+    pub mask_words: Vec<String>,
+    pub highlight_words: Vec<String>,
+}
+
+/*
+Struct:     -BotConfig
+Purpose:    -A simple room bot, run with the `bot` subcommand (see bot::Bot).
+
+Fields:
+            - Vec<BotReply> replies:  [[bot.replies]] entries; the first
+              whose pattern matches a message answers it.
+            - Vec<BotAnnouncement> announcements:  [[bot.announcements]]
+              entries, each said on its own schedule.
+*/
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BotConfig {
+    pub replies: Vec<BotReply>,
+    pub announcements: Vec<BotAnnouncement>,
+}
+
+/// Answer messages matching `pattern` (whole message, any case, `*` for any
+/// text) with `reply`, where "{name}" becomes the sender's name.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BotReply {
+    pub pattern: String,
+    pub reply: String,
+}
+
+/// Say `text` every `every_mins` minutes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BotAnnouncement {
+    pub every_mins: u64,
+    pub text: String,
+}
+
+/*
+Struct:     -HostedBot
+Purpose:    -A bot a daemon runs beside its own room (see bot::Hosted).
+
+Fields:
+            - String profile:  The profile the bot runs as. Its identity key
+              and the [bot] and [filters] tables of its config are the bot's
+              own, so bots sharing a daemon share neither keys nor plugins.
+            - Vec<String> rooms:  Tickets of the rooms it joins.
+            - u32 per_minute:  Most messages it says per minute in each room;
+              more are dropped. 0 for no limit.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostedBot {
+    pub profile: String,
+    pub rooms: Vec<String>,
+    pub per_minute: u32,
+}
+
+impl Default for HostedBot {
+    fn default() -> Self {
+        Self {
+            profile: String::new(),
+            rooms: Vec::new(),
+            per_minute: 10,
+        }
+    }
+}
+
+/*
+Struct:     -WebhookConfig
+Purpose:    -Posting received messages to an HTTP endpoint (see
+             webhook::Webhook).
+
+Fields:
+            - Option<String> url:  Where to POST each message as JSON; no
+              webhook when unset.
+            - u32 retries:  How many times to retry a failed POST before
+              giving up on that message.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub url: Option<String>,
+    pub retries: u32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self { url: None, retries: 5 }
+    }
+}
+
+/*
+Struct:     -LoadConfig
+Purpose:    -When presence changes and the periodic repeat of our name are
+             cut back (see netstats::Load).
+
+Fields:
+            - usize crowded_members:  Members heard from lately at which
+              the room counts as large: our name is repeated less often
+              and our status is no longer repeated to each newcomer.
+            - usize congested_queue:  Broadcasts waiting to go out at which
+              our connection counts as congested: status changes and
+              repeats are held back until the queue drains.
+
+Details:
+            - 0 turns either limit off.
+            - Chat messages, deletes, keys and moderation are never held
+              back; only broadcasts a later one replaces are.
+*/
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadConfig {
+    pub crowded_members: usize,
+    pub congested_queue: usize,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            crowded_members: 50,
+            congested_queue: 20,
+        }
+    }
+}
+
+/*
+Struct:     -DisplayConfig
+Purpose:    -Readability settings for the TUI.
+
+Fields:
+            - u16 scale:  1 (the default) is the dense layout. 2 and 3 put
+              blank lines between messages and widen the margins, and hide
+              secondary markers and the key help panel. Values outside 1–3
+              are clamped.
+            - bool reorder_rtl:  Lay out Arabic, Hebrew and other
+              right-to-left text ourselves. Turn off on terminals that do
+              their own bidi (Konsole, mlterm), or it is reversed twice.
+            - Vec<String> nick_colors:  Palette sender names are coloured
+              from, as colour names ("light-green") or "#rrggbb". Empty (the
+              default) uses the terminal's own named colours.
+            - usize history:  Entries each room keeps in its scrollback;
+              older ones are dropped as new ones arrive. At least 1.
+
+Details:
+            - Terminals cannot change their font size for us; this is the
+              closest we get to a larger font on high-DPI screens.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub scale: u16,
+    pub reorder_rtl: bool,
+    pub nick_colors: Vec<String>,
+    pub history: usize,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            reorder_rtl: true,
+            nick_colors: Vec::new(),
+            history: DEFAULT_HISTORY,
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Steps above the default scale: 0, 1 or 2.
+    fn level(&self) -> u16 {
+        self.scale.clamp(1, 3) - 1
+    }
+
+    /// Blank lines between messages.
+    pub fn line_spacing(&self) -> usize {
+        self.level() as usize
+    }
+
+    /// Columns of padding inside each pane, on both sides.
+    pub fn gutter(&self) -> u16 {
+        2 * self.level()
+    }
+
+    /// Whether to show secondary detail: key hints, the key help panel
+    /// and the bot-message marker.
+    pub fn detailed(&self) -> bool {
+        self.level() == 0
+    }
+}
+
+impl Config {
+    /*
+    Function:   -load
+    Purpose:    -Read a Config from a TOML file.
+
+    Parameters:
+                - &Path path:  Location of the config file.
+
+    Details:
+                - A missing file is not an error and returns the default Config.
+                - Parse errors are reported with the offending path.
+    */
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Write this Config to `path` as TOML, replacing any existing file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+    }
+}
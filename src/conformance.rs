@@ -0,0 +1,375 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use data_encoding::HEXLOWER;
+use iroh::{EndpointId, SecretKey, Signature};
+use iroh_gossip::proto::TopicId;
+use serde::Deserialize;
+
+use crate::crypto::{
+    decrypt_message, get_encryption_key, pow_bits, ratchet, step_key, unwrap_sender_key,
+    verify_delete, verify_about_me, verify_key_request, verify_message, verify_moderation,
+};
+use crate::protocol::{ChatPayload, Message, ModAction, Ticket};
+
+// ── Conformance vectors ───────────────────────────────────────────────────────
+
+/*
+Enum:       -Vector
+Purpose:    -One protocol test vector, as stored in the conformance directory.
+
+Variants:
+            - Message:  A wire message; it must parse and serialize back to the
+              same JSON.
+            - Ticket:  A ticket string and the fields it must decode to.
+            - ProtectedTicket:  A passphrase-protected ticket, its passphrase
+              and the topic it must decode to.
+            - TopicKey:  The room key derived from a topic (public rooms).
+            - StepKey:  A public room's key at a ratchet step.
+            - Ratchet:  One ratchet step of a private room or sender key.
+            - Decrypt:  An EncryptedMessage ciphertext and the payload it
+              decrypts to under a known key.
+            - Moderation:  A signed moderation action and whether the creator's
+              signature must verify.
+            - Delete:  A signed delete request and whether the signer's
+              signature must verify.
+            - KeyRequest:  A signed key request and whether the requester's
+              signature must verify.
+            - AboutMe:  A signed name announcement and whether the announcer's
+              signature must verify.
+            - Pow:  A proof-of-work stamp on an encrypted message and the
+              leading zero bits it is worth.
+            - SenderKey:  A wrapped sender key, the recipient's secret key, and
+              the key it must unwrap to.
+
+Details:
+            - Binary values are lowercase hex. Endpoint IDs use their usual
+              string form.
+            - Vectors are published for alternative clients; the secret keys in
+              them are test keys and must never be used for anything else.
+*/
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Vector {
+    Message {
+        json: serde_json::Value,
+    },
+    Ticket {
+        ticket: String,
+        topic: String,
+        creator: Option<EndpointId>,
+        private: bool,
+    },
+    ProtectedTicket {
+        ticket: String,
+        passphrase: String,
+        topic: String,
+    },
+    TopicKey {
+        topic: String,
+        key: String,
+    },
+    StepKey {
+        topic_key: String,
+        step: u64,
+        key: String,
+    },
+    Ratchet {
+        key: String,
+        next: String,
+    },
+    Decrypt {
+        key: String,
+        nonce: String,
+        ciphertext: String,
+        payload: serde_json::Value,
+    },
+    Moderation {
+        creator: EndpointId,
+        topic: String,
+        action: ModAction,
+        signature: String,
+        valid: bool,
+    },
+    Delete {
+        signer: EndpointId,
+        topic: String,
+        id: u64,
+        signature: String,
+        valid: bool,
+    },
+    KeyRequest {
+        signer: EndpointId,
+        topic: String,
+        sent: u64,
+        nonce: u64,
+        signature: String,
+        valid: bool,
+    },
+    AboutMe {
+        signer: EndpointId,
+        topic: String,
+        nickname: String,
+        nonce: u64,
+        signature: String,
+        valid: bool,
+    },
+    SignedMessage {
+        json: serde_json::Value,
+        topic: String,
+        valid: bool,
+    },
+    Pow {
+        from: EndpointId,
+        id: u64,
+        nonce: String,
+        ciphertext: String,
+        stamp: u64,
+        bits: u32,
+    },
+    SenderKey {
+        recipient_secret: String,
+        from: EndpointId,
+        topic: String,
+        room_key: String,
+        key_id: u64,
+        step: u64,
+        wrapped: String,
+        nonce: String,
+        key: String,
+    },
+}
+
+/// A named vector. Each file in the directory holds a JSON array of these.
+#[derive(Deserialize)]
+struct Case {
+    name: String,
+    #[serde(flatten)]
+    vector: Vector,
+}
+
+/// Outcome of a conformance run: how many vectors passed, and for each
+/// failure the file, vector name and reason.
+pub struct Report {
+    pub passed: usize,
+    pub failures: Vec<(PathBuf, String, String)>,
+}
+
+/// Decode a fixed-size hex field.
+fn hex<const N: usize>(text: &str) -> Result<[u8; N]> {
+    let bytes = HEXLOWER
+        .decode(text.as_bytes())
+        .with_context(|| format!("invalid hex '{}'", text))?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| anyhow::anyhow!("expected {} bytes, got {}", N, bytes.len()))
+}
+
+fn check(vector: &Vector) -> Result<()> {
+    match vector {
+        Vector::Message { json } => {
+            let message = Message::from_bytes(json.to_string().as_bytes())?;
+            let back: serde_json::Value = serde_json::from_slice(&message.to_vec())?;
+            ensure!(&back == json, "re-serialized as {}", back);
+        }
+        Vector::Ticket {
+            ticket,
+            topic,
+            creator,
+            private,
+        } => {
+            let parsed: Ticket = ticket.parse()?;
+            ensure!(parsed.topic == TopicId::from_bytes(hex(topic)?), "wrong topic");
+            ensure!(parsed.creator == *creator, "wrong creator");
+            ensure!(parsed.private == *private, "wrong private flag");
+        }
+        Vector::ProtectedTicket {
+            ticket,
+            passphrase,
+            topic,
+        } => {
+            ensure!(Ticket::is_protected(ticket), "not recognised as protected");
+            let parsed = Ticket::unprotect(ticket, passphrase)?;
+            ensure!(parsed.topic == TopicId::from_bytes(hex(topic)?), "wrong topic");
+            ensure!(Ticket::unprotect(ticket, "wrong").is_err(), "opened with the wrong passphrase");
+        }
+        Vector::TopicKey { topic, key } => {
+            let derived = get_encryption_key(&TopicId::from_bytes(hex(topic)?));
+            ensure!(derived == hex(key)?, "derived {}", HEXLOWER.encode(&derived));
+        }
+        Vector::StepKey {
+            topic_key,
+            step,
+            key,
+        } => {
+            let derived = step_key(&hex(topic_key)?, *step);
+            ensure!(derived == hex(key)?, "derived {}", HEXLOWER.encode(&derived));
+        }
+        Vector::Ratchet { key, next } => {
+            let derived = ratchet(&hex(key)?);
+            ensure!(derived == hex(next)?, "derived {}", HEXLOWER.encode(&derived));
+        }
+        Vector::Decrypt {
+            key,
+            nonce,
+            ciphertext,
+            payload,
+        } => {
+            let ciphertext = HEXLOWER.decode(ciphertext.as_bytes())?;
+            let decrypted = decrypt_message(&ciphertext, &hex(nonce)?, &hex(key)?)?;
+            let expected: ChatPayload = serde_json::from_value(payload.clone())?;
+            ensure!(
+                decrypted.to_vec() == expected.to_vec(),
+                "decrypted to {}",
+                String::from_utf8_lossy(&decrypted.to_vec())
+            );
+        }
+        Vector::Moderation {
+            creator,
+            topic,
+            action,
+            signature,
+            valid,
+        } => {
+            let signature = Signature::from_bytes(&hex(signature)?);
+            let topic = TopicId::from_bytes(hex(topic)?);
+            let verified = verify_moderation(action, &signature, *creator, &topic).is_ok();
+            ensure!(verified == *valid, "signature verified: {}", verified);
+        }
+        Vector::Delete {
+            signer,
+            topic,
+            id,
+            signature,
+            valid,
+        } => {
+            let signature = Signature::from_bytes(&hex(signature)?);
+            let topic = TopicId::from_bytes(hex(topic)?);
+            let verified = verify_delete(*id, &signature, *signer, &topic).is_ok();
+            ensure!(verified == *valid, "signature verified: {}", verified);
+        }
+        Vector::KeyRequest {
+            signer,
+            topic,
+            sent,
+            nonce,
+            signature,
+            valid,
+        } => {
+            let signature = Signature::from_bytes(&hex(signature)?);
+            let topic = TopicId::from_bytes(hex(topic)?);
+            let verified = verify_key_request((*sent, *nonce), &signature, *signer, &topic).is_ok();
+            ensure!(verified == *valid, "signature verified: {}", verified);
+        }
+        Vector::AboutMe {
+            signer,
+            topic,
+            nickname,
+            nonce,
+            signature,
+            valid,
+        } => {
+            let signature = Signature::from_bytes(&hex(signature)?);
+            let topic = TopicId::from_bytes(hex(topic)?);
+            let verified = verify_about_me((nickname, *nonce), Some(&signature), *signer, &topic).is_ok_and(|signed| signed);
+            ensure!(verified == *valid, "signature verified: {}", verified);
+        }
+        Vector::SignedMessage { json, topic, valid } => {
+            let message = Message::from_bytes(json.to_string().as_bytes())?;
+            let topic = TopicId::from_bytes(hex(topic)?);
+            let verified = verify_message(&message.body, &topic).is_ok_and(|signed| signed);
+            ensure!(verified == *valid, "signature verified: {}", verified);
+        }
+        Vector::Pow {
+            from,
+            id,
+            nonce,
+            ciphertext,
+            stamp,
+            bits,
+        } => {
+            let ciphertext = HEXLOWER.decode(ciphertext.as_bytes())?;
+            let worth = pow_bits(*from, *id, &hex(nonce)?, &ciphertext, *stamp);
+            ensure!(worth == *bits, "stamp is worth {} bits", worth);
+        }
+        Vector::SenderKey {
+            recipient_secret,
+            from,
+            topic,
+            room_key,
+            key_id,
+            step,
+            wrapped,
+            nonce,
+            key,
+        } => {
+            let unwrapped = unwrap_sender_key(
+                &HEXLOWER.decode(wrapped.as_bytes())?,
+                &hex(nonce)?,
+                (*key_id, *step),
+                &hex(room_key)?,
+                &SecretKey::from_bytes(&hex(recipient_secret)?),
+                *from,
+                &TopicId::from_bytes(hex(topic)?),
+            )?;
+            ensure!(unwrapped.key == hex(key)?, "unwrapped {}", HEXLOWER.encode(&unwrapped.key));
+        }
+    }
+    Ok(())
+}
+
+/*
+Function:   -run
+Purpose:    -Check every vector in a directory against this implementation.
+
+Parameters:
+            - &Path dir:  Directory of vector files (*.json), e.g. the
+              conformance/ directory shipped with the source.
+
+Details:
+            - Files are read in name order. A file that cannot be parsed is an
+              error; a vector that fails is recorded in the report and the run
+              carries on.
+*/
+pub fn run(dir: &Path) -> Result<Report> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        bail!("no vector files (*.json) in {}", dir.display());
+    }
+
+    let mut report = Report {
+        passed: 0,
+        failures: Vec::new(),
+    };
+    for file in files {
+        let text = std::fs::read_to_string(&file)
+            .with_context(|| format!("reading {}", file.display()))?;
+        let cases: Vec<Case> =
+            serde_json::from_str(&text).with_context(|| format!("parsing {}", file.display()))?;
+        for case in cases {
+            match check(&case.vector) {
+                Ok(()) => report.passed += 1,
+                Err(e) => report.failures.push((file.clone(), case.name, e.to_string())),
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_vectors_pass() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("conformance");
+        let report = run(&dir).unwrap();
+        assert!(report.passed > 0);
+        assert!(report.failures.is_empty(), "{:?}", report.failures);
+    }
+}
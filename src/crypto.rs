@@ -1,17 +1,55 @@
-use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{bail, Result};
 use chacha20poly1305::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     ChaCha20Poly1305, Key, Nonce,
 };
+use argon2::Argon2;
 use hkdf::Hkdf;
-use iroh::EndpointId;
+use iroh::{EndpointId, SecretKey, Signature};
 use iroh_gossip::proto::TopicId;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
+use tracing::{debug, instrument, warn};
 
-use crate::protocol::{Message, MessageBody};
+#[cfg(not(target_family = "wasm"))]
+use crate::directory::Listing;
+use crate::protocol::{ChatPayload, Membership, Message, MessageBody, ModAction};
 
 // ── Encryption helpers ──────────────────────────────────────────────────────────
 
+/// Prefix of the bytes signed for a moderation action, so the signature
+/// cannot be mistaken for one over any other kind of data.
+const MODERATION_CONTEXT: &[u8] = b"encrypted-chat/moderation/v1";
+const DELETE_CONTEXT: &[u8] = b"encrypted-chat/delete/v1";
+const PIN_CONTEXT: &[u8] = b"encrypted-chat/pin/v1";
+const REVOKE_CONTEXT: &[u8] = b"encrypted-chat/revoke/v1";
+const KEY_REQUEST_CONTEXT: &[u8] = b"encrypted-chat/key-request/v1";
+const ABOUT_ME_CONTEXT: &[u8] = b"encrypted-chat/about-me/v1";
+const POW_CONTEXT: &[u8] = b"encrypted-chat/pow/v1";
+const MESSAGE_CONTEXT: &[u8] = b"encrypted-chat/message/v1";
+
+/// Highest proof-of-work difficulty a profile may ask for: about 16 million
+/// hashes, a few seconds per message.
+pub const MAX_POW_BITS: u32 = 24;
+
+/// Signing context for membership documents.
+const MEMBERSHIP_CONTEXT: &[u8] = b"encrypted-chat/membership/v1";
+
+/// Signing context for rooms directory listings.
+#[cfg(not(target_family = "wasm"))]
+const LISTING_CONTEXT: &[u8] = b"encrypted-chat/directory/v1";
+
+/// HKDF info string for the key that wraps a private room's key for one
+/// member.
+const WRAP_INFO: &[u8] = b"encrypted-chat/room-key-wrap/v1";
+
+/// HKDF info string for the key that wraps a sender key for one peer.
+const SENDER_WRAP_INFO: &[u8] = b"encrypted-chat/sender-key-wrap/v1";
+
 /// Application-specific salt for HKDF.
 /// Public and fixed — exists purely for domain separation, not secrecy.
 const HKDF_SALT: &[u8] = b"encrypted-chat-v1-salt";
@@ -27,10 +65,10 @@ const HKDF_INFO: &[u8] = b"encrypted-chat/message-key/v1";
    Parameters:
    - &TopicId topic: Reference to the topic identifier used as input key material.
    Details:
-   - The topic acts as the IKM (input key material). Security depends on keeping
-     the ticket private — anyone who intercepts the ticket can derive this key.
-     There is no forward secrecy; this is acceptable for a gossip-based system
-     where the topic is the shared secret.
+   - The topic acts as the IKM (input key material), so anyone who intercepts
+     the ticket can derive this key. Messages are encrypted with per-sender
+     keys (SenderKeys); this key only gates public rooms' sender key
+     exchange and reads messages from older clients.
    - The fixed salt provides domain separation from bare SHA-256 and binds the
      key to this application.
    - The info string ("encrypted-chat/message-key/v1") ensures keys derived here
@@ -47,62 +85,1120 @@ pub fn get_encryption_key(topic: &TopicId) -> [u8; 32] {
     okm
 }
 
+/// HKDF info string for one step of a room key's ratchet.
+const RATCHET_INFO: &[u8] = b"encrypted-chat/ratchet/v1";
+
+/// HKDF info string for a public room's key at a given step.
+const STEP_INFO: &[u8] = b"encrypted-chat/step-key/v1";
+
+/// Length of one ratchet step. Keys older than the previous step are
+/// forgotten, so this bounds how much past traffic a leaked key exposes.
+pub const RATCHET_SECS: u64 = 60 * 60;
+
+/// How many steps ahead of our clock a message may be and still decrypt.
+const MAX_SKEW_STEPS: u64 = 1;
+
+/// Most steps a chain is ratcheted forward in one go, about a month's
+/// worth. Each is a hash under a lock the receive loop needs, and the
+/// starting step of a handed-over key is the sender's to pick.
+const MAX_CATCH_UP: u64 = 31 * 24 * 60 * 60 / RATCHET_SECS;
+
+/// How far behind our clock a handed-over key may start. Keys are handed
+/// over at the sender's current step, so anything older is stale or an
+/// attempt to make us ratchet.
+const MAX_KEY_LAG: u64 = 2;
+
+/// Sender keys replayed from an archive kept per peer, newest last. An
+/// archive keeps every key handed to us, one per renewal.
+const MAX_ARCHIVED_KEYS: usize = 8;
+
+/// The ratchet step for the current time.
+pub fn current_step() -> u64 {
+    crate::invites::now() / RATCHET_SECS
+}
+
+/// One-way step from a key to the next: knowing the new key reveals
+/// nothing about the old one.
+pub fn ratchet(key: &[u8; 32]) -> [u8; 32] {
+    let hk = Hkdf::<Sha256>::new(None, key);
+    let mut okm = [0u8; 32];
+    hk.expand(RATCHET_INFO, &mut okm)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    okm
+}
+
+/// A public room's key at `step`. Step 0 is the topic key itself, which is
+/// what clients without the ratchet use.
+pub fn step_key(topic_key: &[u8; 32], step: u64) -> [u8; 32] {
+    if step == 0 {
+        return *topic_key;
+    }
+    let hk = Hkdf::<Sha256>::new(Some(&step.to_be_bytes()), topic_key);
+    let mut okm = [0u8; 32];
+    hk.expand(STEP_INFO, &mut okm)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    okm
+}
+
+/// A room or sender key and where it sits: the /rekey epoch (for a sender
+/// key, its ID) and the ratchet step within it.
+#[derive(Clone, Copy)]
+pub struct MessageKey {
+    pub epoch: u64,
+    pub step: u64,
+    pub key: [u8; 32],
+}
+
+/* Struct: -Chain
+   Purpose:
+   -The ratchet state for one epoch: the key for `step` and the one before.
+   Details:
+   - Public rooms keep the topic key instead and derive each step's key
+     from it directly, since anyone with the ticket can do the same.
+*/
+struct Chain {
+    epoch: u64,
+    step: u64,
+    key: [u8; 32],
+    previous: Option<[u8; 32]>,
+    topic_key: Option<[u8; 32]>,
+}
+
+impl Chain {
+    fn private(epoch: u64, step: u64, key: [u8; 32]) -> Self {
+        Self { epoch, step, key, previous: None, topic_key: None }
+    }
+
+    /// Ratchet forward to `step`, dropping every key older than its
+    /// predecessor. Returns false, changing nothing, if that is more than
+    /// MAX_CATCH_UP steps away.
+    fn advance_to(&mut self, step: u64) -> bool {
+        if let Some(topic_key) = &self.topic_key {
+            self.key = step_key(topic_key, step);
+            self.step = step;
+            return true;
+        }
+        if step.saturating_sub(self.step) > MAX_CATCH_UP {
+            warn!(epoch = self.epoch, from = self.step, to = step, "key too far behind to ratchet");
+            return false;
+        }
+        while self.step < step {
+            self.previous = Some(self.key);
+            self.key = ratchet(&self.key);
+            self.step += 1;
+        }
+        true
+    }
+
+    fn key_at(&self, step: u64) -> Option<[u8; 32]> {
+        if let Some(topic_key) = &self.topic_key {
+            return Some(step_key(topic_key, step));
+        }
+        if step == self.step {
+            Some(self.key)
+        } else if step + 1 == self.step {
+            self.previous
+        } else if step > self.step && step - self.step <= MAX_SKEW_STEPS {
+            // A sender whose clock runs a little ahead of ours.
+            let mut key = self.key;
+            for _ in self.step..step {
+                key = ratchet(&key);
+            }
+            Some(key)
+        } else {
+            None
+        }
+    }
+}
+
+/* Struct: -RoomKey
+   Purpose:
+   -The symmetric keys a room's messages are encrypted with, shared between
+    a session's send and receive loops.
+   Details:
+   - Public rooms use keys derived from the topic (get_encryption_key), one
+     per step, known from the start. The ticket is the key there, so they
+     get no forward secrecy.
+   - Private rooms use a random key chosen by the creator. Members start
+     without it and receive it wrapped to their endpoint key when the
+     creator admits them (wrap_room_key / unwrap_room_key).
+   - A private room's key is ratcheted forward every RATCHET_SECS and the
+     old key discarded, so a key leaked today does not decrypt traffic
+     from earlier steps. Messages carry their step so receivers whose
+     ratchet is behind catch up.
+   - Keys are also numbered by epoch; /rekey moves to the next one, which
+     also locks out future traffic. Only the current and the previous
+     epoch are kept, so messages sent during a rotation still decrypt
+     while anything older is gone for good.
+*/
+#[derive(Clone)]
+pub struct RoomKey(Arc<RwLock<EpochKeys>>);
+
+#[derive(Default)]
+struct EpochKeys {
+    current: Option<Chain>,
+    previous: Option<Chain>,
+}
+
+impl EpochKeys {
+    /// Bring both chains up to the current time.
+    fn tick(&mut self) {
+        let step = current_step();
+        for chain in [&mut self.current, &mut self.previous].into_iter().flatten() {
+            // One left that far behind has nothing current to decrypt.
+            let _ = chain.advance_to(step);
+        }
+    }
+}
+
+impl RoomKey {
+    /// A public room's keys, derived from its topic key.
+    pub fn public(topic_key: [u8; 32]) -> Self {
+        let step = current_step();
+        Self::from_chain(Some(Chain {
+            epoch: 0,
+            step,
+            key: step_key(&topic_key, step),
+            previous: None,
+            topic_key: Some(topic_key),
+        }))
+    }
+
+    /// A private room's keys: a fresh random key for its creator, or
+    /// nothing while a member waits to be admitted.
+    pub fn private(creator: bool) -> Self {
+        Self::from_chain(creator.then(|| Chain::private(0, current_step(), rand::random())))
+    }
+
+    /// A private room's keys picked up again from `key`, as saved by its
+    /// creator. None if it is too far behind to ratchet to now.
+    pub fn restore(key: MessageKey) -> Option<Self> {
+        let mut chain = Chain::private(key.epoch, key.step, key.key);
+        chain.advance_to(current_step()).then(|| Self::from_chain(Some(chain)))
+    }
+
+    fn from_chain(chain: Option<Chain>) -> Self {
+        Self(Arc::new(RwLock::new(EpochKeys {
+            current: chain,
+            previous: None,
+        })))
+    }
+
+    /// The key to encrypt with now, or None while waiting to be admitted.
+    pub fn current(&self) -> Option<MessageKey> {
+        let mut keys = self.0.write().expect("room key lock poisoned");
+        keys.tick();
+        keys.current.as_ref().map(|chain| MessageKey {
+            epoch: chain.epoch,
+            step: chain.step,
+            key: chain.key,
+        })
+    }
+
+    /// The key for a message sent at `epoch` and `step`, if we still have
+    /// (or can ratchet to) it.
+    pub fn for_message(&self, epoch: u64, step: u64) -> Option<[u8; 32]> {
+        let mut keys = self.0.write().expect("room key lock poisoned");
+        keys.tick();
+        [&keys.current, &keys.previous]
+            .into_iter()
+            .flatten()
+            .find(|chain| chain.epoch == epoch)
+            .and_then(|chain| chain.key_at(step))
+    }
+
+    /// Whether a message from `epoch` may become readable later: we have no
+    /// key yet, or it belongs to an epoch we have not received.
+    pub fn is_ahead(&self, epoch: u64) -> bool {
+        let keys = self.0.read().expect("room key lock poisoned");
+        keys.current.as_ref().is_none_or(|chain| epoch > chain.epoch)
+    }
+
+    /// Move to a newer epoch, starting from `key`. Returns false (and
+    /// changes nothing) if its epoch is not newer than the current one.
+    pub fn advance(&self, key: MessageKey) -> bool {
+        let mut keys = self.0.write().expect("room key lock poisoned");
+        if keys.current.as_ref().is_some_and(|chain| key.epoch <= chain.epoch) {
+            return false;
+        }
+        let mut chain = Chain::private(key.epoch, key.step, key.key);
+        if !chain.advance_to(current_step()) {
+            return false;
+        }
+        debug!(epoch = key.epoch, step = key.step, "room key advanced");
+        keys.previous = keys.current.replace(chain);
+        keys.tick();
+        true
+    }
+}
+
+/* Function: -passphrase_key
+   Purpose:
+   -Stretch a passphrase into a 256-bit key with Argon2id.
+   Parameters:
+   - &str passphrase: A backup master passphrase or ticket passphrase.
+   - &[u8] salt: At least 8 bytes.
+   Details:
+   - Deliberately slow; call from a blocking context.
+*/
+pub fn passphrase_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// HKDF info string for the mDNS service name of a room.
+const LAN_SERVICE_INFO: &[u8] = b"encrypted-chat/lan-service/v1";
+
+/* Function: -lan_service_name
+   Purpose:
+   -The mDNS service name members of a room announce and browse under.
+   Details:
+   - Derived one-way from the topic so the announcement does not give the
+     topic (and with it a public room's key) to everyone on the network.
+   - 12 hex characters after a 'c': DNS-SD service names are at most 15
+     characters.
+*/
+pub fn lan_service_name(topic: &TopicId) -> String {
+    let hk = Hkdf::<Sha256>::new(Some(HKDF_SALT), topic.as_bytes());
+    let mut okm = [0u8; 6];
+    hk.expand(LAN_SERVICE_INFO, &mut okm)
+        .expect("6 bytes is a valid HKDF-SHA256 output length");
+    format!("c{}", hex::encode(okm))
+}
+
+/// Salt prefix for a named room; the room name follows it.
+const NAMED_ROOM_SALT: &[u8] = b"encrypted-chat/named-room/v1";
+
+/// HKDF info strings for the two values derived from a named room.
+const NAMED_TOPIC_INFO: &[u8] = b"encrypted-chat/named-room/topic/v1";
+const NAMED_RENDEZVOUS_INFO: &[u8] = b"encrypted-chat/named-room/rendezvous/v1";
+
+/* Function: -named_room
+   Purpose:
+   -Derive a room's topic and its rendezvous key from a name and passphrase.
+   Parameters:
+   - &str name: The room name everyone types, e.g. "friday-games".
+   - &str passphrase: Shared separately; without it the name is useless.
+   Details:
+   - Argon2id over the passphrase, salted with the name, then HKDF for each
+     output. The name alone is guessable, so the stretching is what keeps
+     outsiders from finding the topic (and with it the public room key).
+   - The rendezvous key is an endpoint identity the opener runs alongside
+     their own, so joiners can find the room knowing only the name.
+   - Deliberately slow; call from a blocking context.
+*/
+pub fn named_room(name: &str, passphrase: &str) -> Result<(TopicId, SecretKey)> {
+    let mut salt = NAMED_ROOM_SALT.to_vec();
+    salt.extend_from_slice(name.as_bytes());
+    let seed = passphrase_key(passphrase, &salt)?;
+    let hk = Hkdf::<Sha256>::new(None, &seed);
+    let mut topic = [0u8; 32];
+    let mut rendezvous = [0u8; 32];
+    hk.expand(NAMED_TOPIC_INFO, &mut topic)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    hk.expand(NAMED_RENDEZVOUS_INFO, &mut rendezvous)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    Ok((TopicId::from_bytes(topic), SecretKey::from_bytes(&rendezvous)))
+}
+
+/* Function: -seal_with_passphrase
+   Purpose:
+   -Encrypt arbitrary bytes under a passphrase.
+   Details:
+   - Layout: 16-byte Argon2 salt, 12-byte nonce, ChaCha20-Poly1305
+     ciphertext. The salt is fresh each time, so sealing the same data
+     twice gives unrelated output.
+   - Used for profile backups, protected tickets and sealed archives.
+*/
+pub fn seal_with_passphrase(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let salt: [u8; 16] = rand::random();
+    seal_with_key(plaintext, &passphrase_key(passphrase, &salt)?, salt)
+}
+
+/// seal_with_passphrase with the key already stretched from `salt`, for
+/// sealing many times under one passphrase without paying for Argon2 each
+/// time. Salt and key must belong together for open_with_passphrase.
+pub fn seal_with_key(plaintext: &[u8], key: &[u8; 32], salt: [u8; 16]) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+    let mut blob = salt.to_vec();
+    blob.extend_from_slice(&nonce);
+    blob.extend(ciphertext);
+    Ok(blob)
+}
+
+/// Reverse of seal_with_passphrase. A wrong passphrase shows up as a
+/// decryption failure.
+pub fn open_with_passphrase(blob: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    open_with_key(blob, &passphrase_key(passphrase, sealed_salt(blob)?)?)
+}
+
+/// The salt a sealed blob's key was stretched with.
+pub fn sealed_salt(blob: &[u8]) -> Result<&[u8; 16]> {
+    if blob.len() < 28 {
+        bail!("data is truncated");
+    }
+    Ok(blob.first_chunk().expect("checked above"))
+}
+
+/// Reverse of seal_with_key.
+pub fn open_with_key(blob: &[u8], key: &[u8; 32]) -> Result<Vec<u8>> {
+    sealed_salt(blob)?;
+    let (nonce, ciphertext) = blob[16..].split_at(12);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("decryption failed – wrong passphrase?"))
+}
+
+/* Struct: -SenderKeys
+   Purpose:
+   -Per-sender message keys: our own, and those other peers handed us.
+   Details:
+   - Every member encrypts with a random key of its own, handed to each
+     peer separately (MessageBody::SenderKey) wrapped with X25519 between
+     the two endpoint keys and bound to the room key. Holding the ticket
+     is no longer enough to read a room: keys only go to peers who ask for
+     them, and a key that unwraps proves which endpoint it came from.
+   - A sender key's `epoch` is a random ID; `step` ratchets like RoomKey.
+   - Our key is replaced when the room key's epoch changes or someone is
+     kicked, so removed members cannot read what follows. For each peer
+     the two most recent keys are kept.
+   - Keys replayed from an archive (insert_archived) are kept apart, as
+     handed over, so that history from while we were away can be read:
+     up to MAX_CATCH_UP steps after each key's start. They are as old as
+     the history they open, so they give away nothing the archive did not.
+*/
+#[derive(Clone, Default)]
+pub struct SenderKeys(Arc<RwLock<SenderState>>);
+
+#[derive(Default)]
+struct SenderState {
+    /// Our key, and the room epoch it was made under.
+    own: Option<(u64, Chain)>,
+    peers: HashMap<EndpointId, Vec<Chain>>,
+    archived: HashMap<EndpointId, Vec<MessageKey>>,
+}
+
+impl SenderKeys {
+    /// Our key for room epoch `room_epoch`; a fresh one if we have none
+    /// yet or ours predates that epoch.
+    pub fn own(&self, room_epoch: u64) -> MessageKey {
+        let mut state = self.0.write().expect("sender key lock poisoned");
+        if state.own.as_ref().is_none_or(|(epoch, _)| *epoch != room_epoch) {
+            state.own = Some((room_epoch, Self::fresh()));
+        }
+        let (_, chain) = state.own.as_mut().expect("set above");
+        if !chain.advance_to(current_step()) {
+            *chain = Self::fresh();
+        }
+        MessageKey {
+            epoch: chain.epoch,
+            step: chain.step,
+            key: chain.key,
+        }
+    }
+
+    /// Replace our key, e.g. after a kick.
+    pub fn renew(&self, room_epoch: u64) {
+        debug!(room_epoch, "renewing our sender key");
+        self.0.write().expect("sender key lock poisoned").own = Some((room_epoch, Self::fresh()));
+    }
+
+    fn fresh() -> Chain {
+        // ID 0 is left for clients that encrypt with the topic key.
+        Chain::private(rand::random::<u64>().max(1), current_step(), rand::random())
+    }
+
+    /// Store a key `from` handed us. Returns false if we already had it,
+    /// or it starts more than MAX_KEY_LAG steps behind our clock or ahead
+    /// of it by more than clock skew explains.
+    pub fn insert(&self, from: EndpointId, key: MessageKey) -> bool {
+        let now = current_step();
+        if key.step.saturating_add(MAX_KEY_LAG) < now || key.step > now + MAX_SKEW_STEPS {
+            debug!(from = %from.fmt_short(), step = key.step, "ignoring sender key at a stale step");
+            return false;
+        }
+        let mut state = self.0.write().expect("sender key lock poisoned");
+        let chains = state.peers.entry(from).or_default();
+        if chains.iter().any(|chain| chain.epoch == key.epoch) {
+            return false;
+        }
+        let mut chain = Chain::private(key.epoch, key.step, key.key);
+        chain.advance_to(now);
+        chains.push(chain);
+        if chains.len() > 2 {
+            chains.remove(0);
+        }
+        true
+    }
+
+    /// Store a key `from` handed us that an archive replayed. Unlike
+    /// insert, it may start as far back as the archive's history goes
+    /// (MAX_CATCH_UP steps). Returns false if we already had it.
+    pub fn insert_archived(&self, from: EndpointId, key: MessageKey) -> bool {
+        let now = current_step();
+        if key.step.saturating_add(MAX_CATCH_UP) < now || key.step > now + MAX_SKEW_STEPS {
+            debug!(from = %from.fmt_short(), step = key.step, "ignoring archived sender key at a stale step");
+            return false;
+        }
+        let mut state = self.0.write().expect("sender key lock poisoned");
+        let keys = state.archived.entry(from).or_default();
+        if keys.iter().any(|known| known.epoch == key.epoch) {
+            return false;
+        }
+        keys.push(key);
+        if keys.len() > MAX_ARCHIVED_KEYS {
+            keys.remove(0);
+        }
+        true
+    }
+
+    /// Whether we hold the key with this ID from `from`.
+    pub fn knows(&self, from: EndpointId, id: u64) -> bool {
+        let state = self.0.read().expect("sender key lock poisoned");
+        state
+            .peers
+            .get(&from)
+            .is_some_and(|chains| chains.iter().any(|chain| chain.epoch == id))
+            || state
+                .archived
+                .get(&from)
+                .is_some_and(|keys| keys.iter().any(|key| key.epoch == id))
+    }
+
+    /// The key for a message `from` sent with key `id` at `step`, if we
+    /// hold it and have not ratcheted past that step, or an archive
+    /// replayed it from before that step.
+    pub fn for_message(&self, from: EndpointId, id: u64, step: u64) -> Option<[u8; 32]> {
+        let mut state = self.0.write().expect("sender key lock poisoned");
+        let live = state
+            .peers
+            .get_mut(&from)
+            .and_then(|chains| chains.iter_mut().find(|chain| chain.epoch == id))
+            .and_then(|chain| chain.advance_to(current_step()).then(|| chain.key_at(step))?);
+        if live.is_some() {
+            return live;
+        }
+        let start = state.archived.get(&from)?.iter().find(|key| key.epoch == id)?;
+        if step < start.step || step - start.step > MAX_CATCH_UP || step > current_step() + MAX_SKEW_STEPS {
+            return None;
+        }
+        let mut key = start.key;
+        for _ in start.step..step {
+            key = ratchet(&key);
+        }
+        Some(key)
+    }
+}
+
 /* Function: -encrypt_message
    Purpose:
-   -Encrypt a plaintext message using ChaCha20-Poly1305 authenticated encryption.
+   -Encrypt a chat payload using ChaCha20-Poly1305 authenticated encryption.
    Parameters:
-   - &str text: The plaintext message to be encrypted.
-   - EndpointId from: Identifier of the sender endpoint.
-   - &TopicId topic: The topic used to derive the symmetric encryption key.
+   - &ChatPayload payload: The text (and optional bot metadata) to be encrypted.
+   - &SecretKey secret_key: Our endpoint's secret key, to sign with.
+   - &TopicId topic: The room it is for.
+   - MessageKey key: Our sender key, with its ID and step (see SenderKeys).
    - u64 id: A unique identifier for the message.
    Details:
-   - Derives a 256-bit encryption key from the topic via HKDF-SHA256.
    - A secure random 96-bit nonce is generated per message using OsRng.
+   - The payload is serialized to JSON, so metadata stays inside the
+     ciphertext and is only visible to room members.
    - The plaintext is encrypted with AEAD — ciphertext includes an
      authentication tag ensuring integrity and authenticity.
-   - Returns a Message struct containing the sender ID, message ID,
-     ciphertext, and nonce.
+   - Returns a Message struct containing the sender ID, message ID, key
+     ID and step, ciphertext, and nonce, signed with our endpoint key (see
+     verify_message).
    - Returns Result<Message>, propagating encryption errors if they occur.
 */
-pub fn encrypt_message(text: &str, from: EndpointId, topic: &TopicId, id: u64) -> Result<Message> {
-    let key = get_encryption_key(topic);
+#[instrument(level = "trace", skip_all, fields(id, key_id = key.epoch, step = key.step))]
+pub fn encrypt_message(
+    payload: &ChatPayload,
+    secret_key: &SecretKey,
+    topic: &TopicId,
+    key: MessageKey,
+    id: u64,
+) -> Result<Message> {
+    let MessageKey { epoch: key_id, step, key } = key;
     let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
     let nonce_bytes = ChaCha20Poly1305::generate_nonce(&mut OsRng);
     let ciphertext = cipher
-        .encrypt(&nonce_bytes, text.as_bytes())
+        .encrypt(&nonce_bytes, payload.to_vec().as_slice())
         .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+    let nonce: [u8; 12] = nonce_bytes.into();
+    let from = secret_key.public();
+    let signature = secret_key.sign(&message_bytes(topic, from, id, (key_id, step), &nonce, &ciphertext));
 
-    Ok(Message {
-        body: MessageBody::EncryptedMessage {
-            from,
-            id,
-            ciphertext,
-            nonce: nonce_bytes.into(),
-        },
-    })
+    Ok(Message::new(MessageBody::EncryptedMessage {
+        from,
+        id,
+        key_id,
+        step,
+        ciphertext,
+        nonce,
+        pow: None,
+        signature: Some(signature),
+    }))
+}
+
+/// What the sender's signature on an EncryptedMessage covers: everything
+/// but the proof of work, which is added after signing.
+fn message_bytes(
+    topic: &TopicId,
+    from: EndpointId,
+    id: u64,
+    (key_id, step): (u64, u64),
+    nonce: &[u8; 12],
+    ciphertext: &[u8],
+) -> Vec<u8> {
+    let mut bytes = MESSAGE_CONTEXT.to_vec();
+    bytes.extend_from_slice(topic.as_bytes());
+    bytes.extend_from_slice(from.as_bytes());
+    bytes.extend_from_slice(&id.to_be_bytes());
+    bytes.extend_from_slice(&key_id.to_be_bytes());
+    bytes.extend_from_slice(&step.to_be_bytes());
+    bytes.extend_from_slice(nonce);
+    bytes.extend_from_slice(ciphertext);
+    bytes
+}
+
+/* Function: -verify_message
+   Purpose:
+   -Check who wrote an EncryptedMessage.
+   Details:
+   - Ok(true) if it is signed by the endpoint it claims to be from, Ok(false)
+     if it is not signed at all (older clients), and an error for a bad
+     signature or a message of another kind.
+   - Decrypting under a sender's key proves nothing about authorship, as
+     every member holds that key; only a signature does.
+*/
+pub fn verify_message(body: &MessageBody, topic: &TopicId) -> Result<bool> {
+    let MessageBody::EncryptedMessage { from, id, key_id, step, ciphertext, nonce, signature, .. } = body else {
+        bail!("not a chat message");
+    };
+    let Some(signature) = signature else {
+        return Ok(false);
+    };
+    from.verify(&message_bytes(topic, *from, *id, (*key_id, *step), nonce, ciphertext), signature)
+        .map_err(|_| anyhow::anyhow!("Bad message signature"))?;
+    Ok(true)
+}
+
+// ── Proof of work ─────────────────────────────────────────────────────────────
+
+/* Function: -pow_hasher
+   Purpose:
+   -SHA-256 state over everything a proof-of-work stamp covers but the
+    stamp itself, so minting only hashes the stamp per attempt.
+   Details:
+   - Covers the sender, message ID, nonce and ciphertext: a stamp cannot be
+     reused for another message, or by another sender.
+*/
+fn pow_hasher(from: EndpointId, id: u64, nonce: &[u8; 12], ciphertext: &[u8]) -> Sha256 {
+    let mut hasher = Sha256::new();
+    hasher.update(POW_CONTEXT);
+    hasher.update(from.as_bytes());
+    hasher.update(id.to_be_bytes());
+    hasher.update(nonce);
+    hasher.update(ciphertext);
+    hasher
+}
+
+fn leading_zero_bits(hasher: &Sha256, stamp: u64) -> u32 {
+    let digest = hasher.clone().chain_update(stamp.to_be_bytes()).finalize();
+    let mut bits = 0;
+    for byte in digest {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Proof-of-work a stamp carries for an EncryptedMessage: the leading zero
+/// bits of its hash (see pow_hasher).
+pub fn pow_bits(from: EndpointId, id: u64, nonce: &[u8; 12], ciphertext: &[u8], stamp: u64) -> u32 {
+    leading_zero_bits(&pow_hasher(from, id, nonce, ciphertext), stamp)
+}
+
+/* Function: -mint_pow
+   Purpose:
+   -Find a hashcash-style stamp worth at least `bits` for a message.
+   Parameters:
+   - u32 bits: Difficulty, in leading zero bits; each one doubles the work.
+   Details:
+   - CPU-bound (about 2^bits hashes); run it off the async runtime.
+*/
+fn mint_pow(from: EndpointId, id: u64, nonce: &[u8; 12], ciphertext: &[u8], bits: u32) -> u64 {
+    let hasher = pow_hasher(from, id, nonce, ciphertext);
+    (0..)
+        .find(|stamp| leading_zero_bits(&hasher, *stamp) >= bits)
+        .expect("a stamp exists for any difficulty up to 256 bits")
+}
+
+/// Stamp an EncryptedMessage with proof of work worth `bits`; other
+/// messages are left alone.
+pub fn stamp_pow(msg: &mut Message, bits: u32) {
+    if let MessageBody::EncryptedMessage { from, id, ciphertext, nonce, pow, .. } = &mut msg.body {
+        *pow = Some(mint_pow(*from, *id, nonce, ciphertext, bits));
+    }
 }
 
 /* Function: -decrypt_message
    Purpose:
-   -Decrypt a ChaCha20-Poly1305 encrypted message and return the plaintext string.
+   -Decrypt a ChaCha20-Poly1305 encrypted message and return its payload.
    Parameters:
    - &[u8] ciphertext: The encrypted message bytes to be decrypted.
    - &[u8; 12] nonce: The 96-bit nonce used during encryption.
-   - &TopicId topic: The topic used to derive the symmetric decryption key.
+   - &[u8; 32] key: The sender's key (see SenderKeys).
    Details:
-   - Derives the same 256-bit key from the topic via HKDF-SHA256.
    - Authenticated decryption — fails explicitly if the key, nonce, or
      ciphertext have been tampered with.
-   - Decrypted bytes are validated as UTF-8 before being returned.
-   - Returns Result<String>, propagating decryption or UTF-8 errors.
+   - Decrypted bytes are decoded as a ChatPayload; bare UTF-8 text from
+     older clients is accepted as a payload without metadata.
+   - Returns Result<ChatPayload>, propagating decryption or UTF-8 errors.
 */
-pub fn decrypt_message(ciphertext: &[u8], nonce: &[u8; 12], topic: &TopicId) -> Result<String> {
-    let key = get_encryption_key(topic);
-    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+#[instrument(level = "trace", skip_all, err(level = "debug"))]
+pub fn decrypt_message(ciphertext: &[u8], nonce: &[u8; 12], key: &[u8; 32]) -> Result<ChatPayload> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
     let nonce_obj = Nonce::from_slice(nonce);
     let plaintext = cipher
         .decrypt(nonce_obj, ciphertext)
         .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
 
-    String::from_utf8(plaintext).map_err(Into::into)
+    ChatPayload::from_bytes(&plaintext)
+}
+
+/* Function: -signed_bytes
+   Purpose:
+   -Build the exact byte string a creator signature covers.
+   Details:
+   - Context string, then the topic, then the JSON-encoded value. Including
+     the topic stops a value signed for one room being replayed in another
+     room with the same creator; the context keeps a signed action from
+     passing as a membership document and vice versa.
+*/
+fn signed_bytes(context: &[u8], topic: &TopicId, value: &impl serde::Serialize) -> Vec<u8> {
+    let mut bytes = context.to_vec();
+    bytes.extend_from_slice(topic.as_bytes());
+    bytes.extend(serde_json::to_vec(value).expect("serde_json::to_vec is infallible"));
+    bytes
+}
+
+/* Function: -sign_moderation
+   Purpose:
+   -Wrap a moderation action in a Message signed with our secret key.
+   Parameters:
+   - ModAction action: The kick or mute to announce.
+   - &SecretKey key: Our endpoint's secret key; must be the room creator's.
+   - &TopicId topic: The room the action applies to.
+*/
+pub fn sign_moderation(action: ModAction, key: &SecretKey, topic: &TopicId) -> Message {
+    let signature = key.sign(&signed_bytes(MODERATION_CONTEXT, topic, &action));
+    Message::new(MessageBody::Moderation {
+        from: key.public(),
+        action,
+        signature,
+    })
+}
+
+/* Function: -verify_moderation
+   Purpose:
+   -Check that a moderation action was signed by the room creator.
+   Parameters:
+   - &ModAction action: The received action.
+   - &Signature signature: The received signature.
+   - EndpointId creator: The creator's key, from the ticket.
+   - &TopicId topic: The room the action must have been signed for.
+   Details:
+   - The claimed `from` of the message is irrelevant; only the creator's
+     key is tried.
+*/
+pub fn verify_moderation(
+    action: &ModAction,
+    signature: &Signature,
+    creator: EndpointId,
+    topic: &TopicId,
+) -> Result<()> {
+    creator
+        .verify(&signed_bytes(MODERATION_CONTEXT, topic, action), signature)
+        .map_err(|_| anyhow::anyhow!("Bad moderation signature"))
+}
+
+/* Function: -sign_delete
+   Purpose:
+   -Ask the room to delete one of our messages, signed with our secret key.
+   Parameters:
+   - u64 id: The message to delete.
+   - &SecretKey key: Our endpoint's secret key.
+   - &TopicId topic: The room the message was sent in.
+*/
+pub fn sign_delete(id: u64, key: &SecretKey, topic: &TopicId) -> Message {
+    let signature = key.sign(&signed_bytes(DELETE_CONTEXT, topic, &id));
+    Message::new(MessageBody::DeleteMessage {
+        from: key.public(),
+        id,
+        signature,
+    })
+}
+
+/* Function: -verify_delete
+   Purpose:
+   -Check that a delete request was signed by the endpoint it claims to be
+    from.
+   Details:
+   - Says nothing about whether that endpoint may delete the message; the
+     caller checks ownership (or admin rights) against the verified signer.
+*/
+pub fn verify_delete(id: u64, signature: &Signature, signer: EndpointId, topic: &TopicId) -> Result<()> {
+    signer
+        .verify(&signed_bytes(DELETE_CONTEXT, topic, &id), signature)
+        .map_err(|_| anyhow::anyhow!("Bad delete signature"))
+}
+
+/* Function: -sign_pin
+   Purpose:
+   -Pin or unpin a message for the room, signed with our secret key.
+   Details:
+   - Like sign_delete; the signature covers the ID and whether it is pinned,
+     so an unpin cannot be replayed as a pin.
+*/
+pub fn sign_pin(id: u64, pinned: bool, key: &SecretKey, topic: &TopicId) -> Message {
+    let signature = key.sign(&signed_bytes(PIN_CONTEXT, topic, &(id, pinned)));
+    Message::new(MessageBody::Pin {
+        from: key.public(),
+        id,
+        pinned,
+        signature,
+    })
+}
+
+/// Check that a pin or unpin was signed by the endpoint it claims to be from.
+pub fn verify_pin(
+    id: u64,
+    pinned: bool,
+    signature: &Signature,
+    signer: EndpointId,
+    topic: &TopicId,
+) -> Result<()> {
+    signer
+        .verify(&signed_bytes(PIN_CONTEXT, topic, &(id, pinned)), signature)
+        .map_err(|_| anyhow::anyhow!("Bad pin signature"))
+}
+
+/* Function: -sign_revoke
+   Purpose:
+   -Withdraw an invite token of our private room, signed with our secret key.
+   Details:
+   - Like sign_moderation; only the room creator's signature is accepted.
+*/
+pub fn sign_revoke(token: u64, key: &SecretKey, topic: &TopicId) -> Message {
+    let signature = key.sign(&signed_bytes(REVOKE_CONTEXT, topic, &token));
+    Message::new(MessageBody::Revoke {
+        from: key.public(),
+        token,
+        signature,
+    })
+}
+
+/// Check that an invite was revoked by the room creator.
+pub fn verify_revoke(token: u64, signature: &Signature, creator: EndpointId, topic: &TopicId) -> Result<()> {
+    creator
+        .verify(&signed_bytes(REVOKE_CONTEXT, topic, &token), signature)
+        .map_err(|_| anyhow::anyhow!("Bad revocation signature"))
+}
+
+/* Function: -sign_key_request
+   Purpose:
+   -Ask the room for its members' sender keys, signed with our secret key.
+   Details:
+   - Like sign_delete; the signature covers when it was sent and a random
+     nonce, so an old request replayed later can be told apart from a
+     fresh one.
+*/
+pub fn sign_key_request(key: &SecretKey, topic: &TopicId) -> Message {
+    let (sent, nonce) = (crate::invites::now(), rand::random());
+    let signature = key.sign(&signed_bytes(KEY_REQUEST_CONTEXT, topic, &(sent, nonce)));
+    Message::new(MessageBody::KeyRequest {
+        from: key.public(),
+        sent,
+        nonce,
+        signature,
+    })
+}
+
+/// Check that a key request was signed by the endpoint it claims to be from.
+pub fn verify_key_request(
+    (sent, nonce): (u64, u64),
+    signature: &Signature,
+    signer: EndpointId,
+    topic: &TopicId,
+) -> Result<()> {
+    signer
+        .verify(&signed_bytes(KEY_REQUEST_CONTEXT, topic, &(sent, nonce)), signature)
+        .map_err(|_| anyhow::anyhow!("Bad key request signature"))
+}
+
+/* Function: -sign_about_me
+   Purpose:
+   -Announce our nickname to the room, signed with our secret key.
+   Details:
+   - Like sign_delete; the signature covers the name and the nonce that
+     sets each announcement apart.
+*/
+pub fn sign_about_me(name: String, key: &SecretKey, topic: &TopicId) -> Message {
+    let nonce = rand::random();
+    let signature = key.sign(&signed_bytes(ABOUT_ME_CONTEXT, topic, &(&name, nonce)));
+    Message::new(MessageBody::AboutMe {
+        from: key.public(),
+        name,
+        nonce,
+        signature: Some(signature),
+    })
+}
+
+/// Check who announced a name: Ok(true) if it is signed by the endpoint it
+/// claims to be from, Ok(false) if it is not signed at all (older clients),
+/// and an error for a bad signature. Like verify_message.
+pub fn verify_about_me(
+    (name, nonce): (&str, u64),
+    signature: Option<&Signature>,
+    signer: EndpointId,
+    topic: &TopicId,
+) -> Result<bool> {
+    let Some(signature) = signature else {
+        return Ok(false);
+    };
+    signer
+        .verify(&signed_bytes(ABOUT_ME_CONTEXT, topic, &(name, nonce)), signature)
+        .map_err(|_| anyhow::anyhow!("Bad name signature"))?;
+    Ok(true)
+}
+
+/* Function: -sign_membership
+   Purpose:
+   -Wrap a membership document in a Message signed with our secret key.
+   Details:
+   - Like sign_moderation; only the room creator's signature is accepted.
+*/
+pub fn sign_membership(membership: Membership, key: &SecretKey, topic: &TopicId) -> Message {
+    let signature = key.sign(&signed_bytes(MEMBERSHIP_CONTEXT, topic, &membership));
+    Message::new(MessageBody::Membership {
+        from: key.public(),
+        membership,
+        signature,
+    })
+}
+
+/// Check that a membership document was signed by the room creator.
+pub fn verify_membership(
+    membership: &Membership,
+    signature: &Signature,
+    creator: EndpointId,
+    topic: &TopicId,
+) -> Result<()> {
+    creator
+        .verify(&signed_bytes(MEMBERSHIP_CONTEXT, topic, membership), signature)
+        .map_err(|_| anyhow::anyhow!("Bad membership signature"))
+}
+
+/// Sign a rooms directory listing as the room's creator.
+#[cfg(not(target_family = "wasm"))]
+pub fn sign_listing(listing: &Listing, key: &SecretKey, topic: &TopicId) -> Signature {
+    key.sign(&signed_bytes(LISTING_CONTEXT, topic, listing))
+}
+
+/// Check that a directory listing was signed by the room creator.
+#[cfg(not(target_family = "wasm"))]
+pub fn verify_listing(
+    listing: &Listing,
+    signature: &Signature,
+    creator: EndpointId,
+    topic: &TopicId,
+) -> Result<()> {
+    creator
+        .verify(&signed_bytes(LISTING_CONTEXT, topic, listing), signature)
+        .map_err(|_| anyhow::anyhow!("Bad listing signature"))
+}
+
+/* Function: -wrap_key
+   Purpose:
+   -Derive the key that protects a room or sender key in transit between
+    two endpoints.
+   Parameters:
+   - &SecretKey ours: Our endpoint secret key.
+   - EndpointId theirs: The other endpoint's public key.
+   - &TopicId topic: The room, mixed in as HKDF salt.
+   - &[u8] info: WRAP_INFO or SENDER_WRAP_INFO, keeping the two apart.
+   - &[u8] bound: Extra secret input appended to the shared secret, or
+     empty. Sender keys are bound to the room key this way.
+   Details:
+   - Both Ed25519 keys are converted to X25519 (Montgomery form) and
+     combined with Diffie-Hellman, so either side computes the same value
+     and nobody else can.
+   - Fails on a low-order peer key, which would give an all-zero secret.
+*/
+fn wrap_key(
+    ours: &SecretKey,
+    theirs: EndpointId,
+    topic: &TopicId,
+    info: &[u8],
+    bound: &[u8],
+) -> Result<[u8; 32]> {
+    let shared = theirs
+        .as_verifying_key()
+        .to_montgomery()
+        .mul_clamped(ours.as_signing_key().to_scalar_bytes())
+        .to_bytes();
+    if shared == [0u8; 32] {
+        bail!("Invalid peer key");
+    }
+    let ikm = [shared.as_slice(), bound].concat();
+    let hk = Hkdf::<Sha256>::new(Some(topic.as_bytes()), &ikm);
+    let mut okm = [0u8; 32];
+    hk.expand(info, &mut okm)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    Ok(okm)
+}
+
+/// Encrypt `key` under a wrapping key from wrap_key, authenticating its
+/// epoch and step alongside it.
+fn seal_key(key: &MessageKey, wrap: &[u8; 32]) -> Result<(Vec<u8>, [u8; 12])> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(wrap));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: key.key.as_slice(),
+        aad: &wrap_aad(key.epoch, key.step),
+    };
+    let wrapped = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+    Ok((wrapped, nonce.into()))
+}
+
+/// Reverse of seal_key. `epoch` and `step` come from the carrying message
+/// and must match what was sealed.
+fn open_key(
+    wrapped: &[u8],
+    nonce: &[u8; 12],
+    (epoch, step): (u64, u64),
+    wrap: &[u8; 32],
+) -> Result<MessageKey> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(wrap));
+    let payload = Payload {
+        msg: wrapped,
+        aad: &wrap_aad(epoch, step),
+    };
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
+    let key = plaintext
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Wrapped key has the wrong length"))?;
+    Ok(MessageKey { epoch, step, key })
+}
+
+/// Associated data for a wrapped key: its epoch (or ID) and step.
+fn wrap_aad(epoch: u64, step: u64) -> [u8; 16] {
+    let mut aad = [0u8; 16];
+    aad[..8].copy_from_slice(&epoch.to_be_bytes());
+    aad[8..].copy_from_slice(&step.to_be_bytes());
+    aad
+}
+
+/* Function: -wrap_room_key
+   Purpose:
+   -Encrypt a private room's key so only one member can read it.
+   Parameters:
+   - &MessageKey room_key: The key to hand over. Its epoch and step are
+     authenticated alongside it so an old wrapped key cannot be replayed
+     as a newer one.
+   - &SecretKey ours: The creator's secret key.
+   - EndpointId to: The admitted member.
+   - &TopicId topic: The room.
+   Details:
+   - Returns the ciphertext and its nonce, for MessageBody::Admit and
+     MessageBody::Rekey.
+*/
+pub fn wrap_room_key(
+    room_key: &MessageKey,
+    ours: &SecretKey,
+    to: EndpointId,
+    topic: &TopicId,
+) -> Result<(Vec<u8>, [u8; 12])> {
+    seal_key(room_key, &wrap_key(ours, to, topic, WRAP_INFO, &[])?)
+}
+
+/* Function: -unwrap_room_key
+   Purpose:
+   -Recover a room key wrapped for us by the creator.
+   Details:
+   - Decryption only succeeds if the wrap was made with the creator's
+     secret key, so a successful unwrap also proves who admitted us.
+   - `epoch` and `step` come from the carrying message and must match
+     what the creator wrapped.
+*/
+#[instrument(level = "trace", skip_all, fields(creator = %creator.fmt_short(), ?position), err(level = "debug"))]
+pub fn unwrap_room_key(
+    wrapped: &[u8],
+    nonce: &[u8; 12],
+    position: (u64, u64),
+    ours: &SecretKey,
+    creator: EndpointId,
+    topic: &TopicId,
+) -> Result<MessageKey> {
+    open_key(wrapped, nonce, position, &wrap_key(ours, creator, topic, WRAP_INFO, &[])?)
+}
+
+/* Function: -wrap_sender_key
+   Purpose:
+   -Encrypt our sender key so only one peer can read it.
+   Parameters:
+   - &MessageKey sender_key: Our key (see SenderKeys).
+   - &[u8; 32] room_key: The room key in use, which the peer must also
+     hold to unwrap; a kicked or not yet admitted peer cannot.
+   - &SecretKey ours: Our endpoint secret key.
+   - EndpointId to: The peer.
+   - &TopicId topic: The room.
+   Details:
+   - Returns the ciphertext and its nonce, for MessageBody::SenderKey.
+*/
+pub fn wrap_sender_key(
+    sender_key: &MessageKey,
+    room_key: &[u8; 32],
+    ours: &SecretKey,
+    to: EndpointId,
+    topic: &TopicId,
+) -> Result<(Vec<u8>, [u8; 12])> {
+    seal_key(sender_key, &wrap_key(ours, to, topic, SENDER_WRAP_INFO, room_key)?)
+}
+
+/* Function: -unwrap_sender_key
+   Purpose:
+   -Recover a peer's sender key wrapped for us.
+   Details:
+   - Only `from` could have made a wrap that opens with our secret key, so
+     whatever decrypts under the recovered key was sent by `from` (or by
+     another member who was handed the same key).
+*/
+#[instrument(level = "trace", skip_all, fields(from = %from.fmt_short(), ?position), err(level = "debug"))]
+pub fn unwrap_sender_key(
+    wrapped: &[u8],
+    nonce: &[u8; 12],
+    position: (u64, u64),
+    room_key: &[u8; 32],
+    ours: &SecretKey,
+    from: EndpointId,
+    topic: &TopicId,
+) -> Result<MessageKey> {
+    open_key(wrapped, nonce, position, &wrap_key(ours, from, topic, SENDER_WRAP_INFO, room_key)?)
 }
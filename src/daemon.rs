@@ -0,0 +1,200 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::gateway::{Event, Request};
+use crate::session::Session;
+
+#[cfg(unix)]
+use {
+    crate::gateway::{drive, Requests, EVENT_BACKLOG},
+    anyhow::{bail, Context},
+    std::os::unix::fs::PermissionsExt,
+    tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{UnixListener, UnixStream},
+        sync::{broadcast, mpsc},
+    },
+    tracing::{debug, info, warn},
+};
+
+// ── Daemon ────────────────────────────────────────────────────────────────────
+
+#[cfg(not(unix))]
+const NO_DAEMON: &str = "daemon mode needs Unix domain sockets, which this platform lacks";
+
+/*
+Function:   -run
+Purpose:    -Keep a room open in the background, controlled over a Unix
+             socket, until the process is told to quit.
+
+Parameters:
+            - &mut Session session:  The room, already joined.
+            - &Path path:  Socket to listen on (Profile::socket_path).
+
+Details:
+            - Speaks the gateway's JSON (Request / Event), one object per
+              line. A connection gets events only after sending
+              {"type": "subscribe"}, so one-shot commands see just their
+              reply.
+            - The socket is only accessible to its owner, and is removed on
+              the way out. A socket left by a crashed daemon is replaced; one
+              with a live daemon behind it is an error.
+*/
+#[cfg(unix)]
+pub async fn run(session: &mut Session, path: &Path) -> Result<()> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            bail!("a daemon is already running for this profile ({})", path.display());
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("could not listen on {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    let (event_tx, _) = broadcast::channel(EVENT_BACKLOG);
+    let (request_tx, request_rx) = mpsc::channel(64);
+    info!(path = %path.display(), "daemon up");
+    let events = event_tx.clone();
+    let accept = tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            debug!("control client connected");
+            tokio::spawn(client(stream, events.subscribe(), request_tx.clone()));
+        }
+    });
+    let result = drive(session, request_rx, &event_tx).await;
+    accept.abort();
+    let _ = std::fs::remove_file(path);
+    result
+}
+
+#[cfg(not(unix))]
+pub async fn run(_session: &mut Session, _path: &Path) -> Result<()> {
+    anyhow::bail!(NO_DAEMON)
+}
+
+/// One control connection: forward its requests, write back replies and,
+/// once subscribed, events.
+#[cfg(unix)]
+async fn client(stream: UnixStream, mut events: broadcast::Receiver<String>, requests: Requests) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let (reply_tx, mut reply_rx) = mpsc::channel(16);
+    let mut subscribed = false;
+    loop {
+        let out = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => match serde_json::from_str(&line) {
+                    Ok(Request::Subscribe) => {
+                        subscribed = true;
+                        continue;
+                    }
+                    Ok(request) => {
+                        if requests.send((request, reply_tx.clone())).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(e) => Event::Error { message: format!("invalid request: {}", e) }.to_json(),
+                },
+                Ok(None) | Err(_) => break,
+            },
+            event = events.recv(), if subscribed => match event {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!(missed, "control client fell behind");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            Some(reply) = reply_rx.recv() => reply,
+        };
+        if write.write_all(format!("{}\n", out).as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+// ── Control commands ──────────────────────────────────────────────────────────
+
+#[cfg(unix)]
+async fn connect(path: &Path) -> Result<UnixStream> {
+    UnixStream::connect(path)
+        .await
+        .context("no daemon is running for this profile (start one with `daemon`)")
+}
+
+#[cfg(unix)]
+async fn write_request(stream: &mut UnixStream, request: &Request) -> Result<()> {
+    let line = format!("{}\n", serde_json::to_string(request)?);
+    stream.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// Send one request to the daemon and wait for its reply.
+#[cfg(unix)]
+pub async fn request(path: &Path, request: &Request) -> Result<Event> {
+    let mut stream = connect(path).await?;
+    write_request(&mut stream, request).await?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).await?;
+    if line.is_empty() {
+        bail!("the daemon closed the connection");
+    }
+    match serde_json::from_str(&line)? {
+        Event::Error { message } => bail!("{}", message),
+        event => Ok(event),
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn request(_path: &Path, _request: &Request) -> Result<Event> {
+    anyhow::bail!(NO_DAEMON)
+}
+
+/*
+Function:   -tail
+Purpose:    -Print what happens in the daemon's room until it stops or the
+             process is interrupted.
+
+Parameters:
+            - &Path path:  The daemon's socket.
+            - bool json:  Print the raw JSON events instead of chat lines.
+*/
+#[cfg(unix)]
+pub async fn tail(path: &Path, json: bool) -> Result<()> {
+    let mut stream = connect(path).await?;
+    write_request(&mut stream, &Request::Subscribe).await?;
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(line) = lines.next_line().await? {
+        if json {
+            println!("{}", line);
+        } else if let Some(text) = serde_json::from_str(&line).ok().and_then(|event| describe(&event)) {
+            println!("{}", text);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn tail(_path: &Path, _json: bool) -> Result<()> {
+    anyhow::bail!(NO_DAEMON)
+}
+
+/// One line of `tail` output for an event, like the TUI's history.
+#[cfg(unix)]
+fn describe(event: &Event) -> Option<String> {
+    Some(match event {
+        Event::Chat { sender, content, at, .. } => {
+            let time = chrono::DateTime::parse_from_rfc3339(at)
+                .map(|at| at.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+                .unwrap_or_default();
+            format!("[{}] {}: {}", time, sender, content)
+        }
+        Event::System { text } => format!("* {}", text),
+        Event::Name { name, .. } => format!("* {} is here", name),
+        Event::Left { from } => format!("* {} left", &from[..from.len().min(10)]),
+        Event::Delete { id } => format!("* message {} was deleted", id),
+        Event::SendFailed { id } => format!("* message {} could not be sent", id),
+        _ => return None,
+    })
+}
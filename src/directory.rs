@@ -0,0 +1,435 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use futures_lite::StreamExt;
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler, Router},
+    Endpoint, EndpointId, Signature,
+};
+use iroh_gossip::{
+    api::{Event, GossipReceiver, GossipSender},
+    net::Gossip,
+    proto::TopicId,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+use crate::config::DirectoryConfig;
+use crate::crypto::verify_listing;
+use crate::i18n::t;
+use crate::protocol::Ticket;
+
+// ── Rooms directory ───────────────────────────────────────────────────────────
+
+/// ALPN for the directory protocol, served by peers with `directory.host = true`.
+pub const DIRECTORY_ALPN: &[u8] = b"p2p-chat/directory/0";
+
+/// Largest request or reply on a directory stream.
+const MAX_MESSAGE: usize = 256 * 1024;
+
+/// A listing that is not refreshed within this time is dropped.
+const LISTING_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How often a published room refreshes its listing.
+pub const REPUBLISH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Most listings returned for one search.
+const MAX_RESULTS: usize = 50;
+
+/*
+Struct:     -Listing
+Purpose:    -What a directory shows about one room.
+
+Fields:
+            - String name:  Short room name.
+            - String description:  One line about the room.
+            - usize members:  Members the creator saw when publishing.
+            - String ticket:  Ticket to join with. Its `private` flag is the
+              room's join policy.
+
+Details:
+            - Listings are signed by the room's creator (SignedListing), so a
+              directory cannot be fed listings for rooms the publisher does
+              not own.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Listing {
+    pub name: String,
+    pub description: String,
+    pub members: usize,
+    pub ticket: String,
+}
+
+impl Listing {
+    pub fn ticket(&self) -> Result<Ticket> {
+        self.ticket.parse()
+    }
+
+    /// Join policy as shown to people browsing.
+    pub fn policy(&self) -> &'static str {
+        match self.ticket() {
+            Ok(ticket) if ticket.private => t!("invite-only"),
+            _ => t!("open"),
+        }
+    }
+}
+
+/// A listing and its creator's signature (crypto::sign_listing).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedListing {
+    pub listing: Listing,
+    pub signature: Signature,
+}
+
+impl SignedListing {
+    /// The room's topic, once the signature checks out against the creator
+    /// named in the ticket.
+    fn verify(&self) -> Result<TopicId> {
+        let ticket = self.listing.ticket()?;
+        let Some(creator) = ticket.creator else {
+            bail!("ticket has no creator to vouch for it");
+        };
+        verify_listing(&self.listing, &self.signature, creator, &ticket.topic)?;
+        Ok(ticket.topic)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Publish(SignedListing),
+    Search { query: String },
+}
+
+#[derive(Serialize, Deserialize)]
+enum Reply {
+    Published,
+    Listings(Vec<Listing>),
+    Refused(String),
+}
+
+/// Send one request on a fresh stream and wait for the directory's reply.
+async fn request(endpoint: &Endpoint, server: EndpointId, request: &Request) -> Result<Reply> {
+    let conn = endpoint
+        .connect(server, DIRECTORY_ALPN)
+        .await
+        .context("could not reach the directory")?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    send.write_all(&serde_json::to_vec(request)?).await?;
+    send.finish()?;
+    let reply = recv.read_to_end(MAX_MESSAGE).await?;
+    conn.close(0u32.into(), b"done");
+    Ok(serde_json::from_slice(&reply)?)
+}
+
+/// Add or refresh a room's listing on a directory.
+pub async fn publish(endpoint: &Endpoint, server: EndpointId, listing: SignedListing) -> Result<()> {
+    match request(endpoint, server, &Request::Publish(listing)).await? {
+        Reply::Published => Ok(()),
+        Reply::Refused(reason) => bail!("directory refused the listing: {}", reason),
+        Reply::Listings(_) => bail!("directory sent an invalid reply"),
+    }
+}
+
+/// Listings whose name or description contain `query` (all if empty).
+pub async fn search(endpoint: &Endpoint, server: EndpointId, query: &str) -> Result<Vec<Listing>> {
+    let query = query.to_string();
+    match request(endpoint, server, &Request::Search { query }).await? {
+        Reply::Listings(listings) => Ok(listings),
+        Reply::Refused(reason) => bail!("directory refused the search: {}", reason),
+        Reply::Published => bail!("directory sent an invalid reply"),
+    }
+}
+
+/*
+Struct:     -Listings
+Purpose:    -Signed listings by room, as a directory host or a peer on the
+             directory topic has collected them.
+
+Details:
+            - Listings that were not refreshed within LISTING_TTL are dropped;
+              rooms re-publish every REPUBLISH_INTERVAL.
+            - Only the creator can list a room or change its listing.
+*/
+#[derive(Debug, Clone, Default)]
+struct Listings(Arc<Mutex<HashMap<TopicId, (SignedListing, Instant)>>>);
+
+impl Listings {
+    /// Add or refresh a listing once its signature checks out.
+    fn add(&self, signed: SignedListing) -> Result<()> {
+        let topic = signed.verify()?;
+        let mut listings = self.0.lock().expect("directory lock poisoned");
+        listings.retain(|_, (_, at)| at.elapsed() < LISTING_TTL);
+        listings.insert(topic, (signed, Instant::now()));
+        Ok(())
+    }
+
+    /// Listings whose name or description contain `query` (all if empty),
+    /// the busiest rooms first.
+    fn search(&self, query: &str) -> Vec<Listing> {
+        let mut listings = self.0.lock().expect("directory lock poisoned");
+        listings.retain(|_, (_, at)| at.elapsed() < LISTING_TTL);
+        let query = query.to_lowercase();
+        let mut found: Vec<Listing> = listings
+            .values()
+            .map(|(signed, _)| signed.listing.clone())
+            .filter(|listing| {
+                listing.name.to_lowercase().contains(&query) || listing.description.to_lowercase().contains(&query)
+            })
+            .collect();
+        found.sort_by(|a, b| b.members.cmp(&a.members).then_with(|| a.name.cmp(&b.name)));
+        found.truncate(MAX_RESULTS);
+        found
+    }
+}
+
+/*
+Struct:     -DirectoryHost
+Purpose:    -Protocol handler that keeps a searchable list of rooms.
+
+Fields:
+            - Listings listings:  Latest signed listing per topic.
+
+Details:
+            - Listings live in memory only, so a restarted host fills up
+              again as rooms re-publish.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryHost {
+    listings: Listings,
+}
+
+impl DirectoryHost {
+    fn answer(&self, request: Request) -> Reply {
+        match request {
+            Request::Publish(signed) => match self.listings.add(signed) {
+                Ok(()) => Reply::Published,
+                Err(e) => Reply::Refused(e.to_string()),
+            },
+            Request::Search { query } => Reply::Listings(self.listings.search(&query)),
+        }
+    }
+
+    async fn handle(&self, conn: Connection) -> Result<()> {
+        let (mut send, mut recv) = conn.accept_bi().await?;
+        let request: Request = serde_json::from_slice(&recv.read_to_end(MAX_MESSAGE).await?)?;
+        let reply = self.answer(request);
+        send.write_all(&serde_json::to_vec(&reply)?).await?;
+        send.finish()?;
+        conn.closed().await;
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for DirectoryHost {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        self.handle(conn)
+            .await
+            .map_err(|e| AcceptError::from_err(std::io::Error::other(e.to_string())))
+    }
+}
+
+// ── Gossip directory ──────────────────────────────────────────────────────────
+
+/// Longest we wait to meet a peer on the directory topic before going on
+/// without one (we may be the first).
+const JOIN_WAIT: Duration = Duration::from_secs(10);
+
+/// How long a search listens for creators answering it.
+pub const ANSWER_WAIT: Duration = Duration::from_secs(3);
+
+/// Least time between two answers of ours to searches, so a burst of them
+/// costs one announcement.
+const ANSWER_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// The well-known topic of the serverless directory.
+pub fn directory_topic() -> TopicId {
+    TopicId::from_bytes(Sha256::digest(b"p2p-chat/directory/v1").into())
+}
+
+/// What peers say on the directory topic. The nonce tells repeats apart,
+/// as gossip drops messages it has already delivered.
+#[derive(Serialize, Deserialize)]
+enum Announcement {
+    /// A room's listing, from its creator.
+    Listing { signed: SignedListing, nonce: u64 },
+    /// Someone is searching: creators announce their rooms again.
+    Query { nonce: u64 },
+}
+
+/*
+Struct:     -GossipDirectory
+Purpose:    -Our place on the directory topic, where creators announce their
+             rooms and every peer there collects the announcements: a
+             directory without a host.
+
+Fields:
+            - GossipSender sender:  Broadcasts to the topic.
+            - Listings listings:  What we have heard announced.
+            - Arc<Mutex<Option<SignedListing>>> own:  Our room's listing, if
+              we published one; announced again to searches and to new
+              neighbours.
+
+Details:
+            - Like any gossip swarm, the topic is entered through a peer
+              already on it: the bootstrap peers in the [directory] config.
+            - Listings are signed and checked as for a host, so nobody can
+              announce a room they did not create.
+*/
+#[derive(Clone)]
+pub struct GossipDirectory {
+    sender: GossipSender,
+    listings: Listings,
+    own: Arc<Mutex<Option<SignedListing>>>,
+}
+
+impl GossipDirectory {
+    /// Subscribe to the directory topic through `bootstrap` and start
+    /// collecting listings. Without bootstrap peers we wait for others to
+    /// come to us.
+    pub async fn join(gossip: &Gossip, bootstrap: Vec<EndpointId>) -> Result<Self> {
+        let alone = bootstrap.is_empty();
+        let (sender, mut receiver) = gossip.subscribe(directory_topic(), bootstrap).await?.split();
+        if !alone {
+            let _ = tokio::time::timeout(JOIN_WAIT, receiver.joined()).await;
+        }
+        let directory = Self {
+            sender,
+            listings: Listings::default(),
+            own: Arc::default(),
+        };
+        tokio::spawn(directory.clone().receive(receiver));
+        Ok(directory)
+    }
+
+    async fn receive(self, mut receiver: GossipReceiver) {
+        let mut answered: Option<Instant> = None;
+        while let Ok(Some(event)) = receiver.try_next().await {
+            match event {
+                Event::Received(msg) => match serde_json::from_slice(&msg.content) {
+                    Ok(Announcement::Listing { signed, .. }) => {
+                        if let Err(e) = self.listings.add(signed) {
+                            debug!(error = %e, "dropping directory listing");
+                        }
+                    }
+                    Ok(Announcement::Query { .. }) if answered.is_none_or(|at| at.elapsed() >= ANSWER_COOLDOWN) => {
+                        answered = Some(Instant::now());
+                        self.announce().await;
+                    }
+                    _ => {}
+                },
+                // Newcomers hear of our room without asking.
+                Event::NeighborUp(_) => self.announce().await,
+                _ => {}
+            }
+        }
+    }
+
+    /// Broadcast our listing, if we have one.
+    async fn announce(&self) {
+        let own = self.own.lock().expect("directory lock poisoned").clone();
+        if let Some(signed) = own {
+            let announcement = Announcement::Listing { signed, nonce: rand::random() };
+            self.broadcast(&announcement).await;
+        }
+    }
+
+    async fn broadcast(&self, announcement: &Announcement) {
+        let bytes = serde_json::to_vec(announcement).expect("serde_json::to_vec is infallible");
+        if let Err(e) = self.sender.broadcast(bytes.into()).await {
+            debug!(error = %e, "directory broadcast failed");
+        }
+    }
+
+    /// Announce our room, and keep announcing it when asked.
+    pub async fn publish(&self, signed: SignedListing) -> Result<()> {
+        self.listings.add(signed.clone())?;
+        *self.own.lock().expect("directory lock poisoned") = Some(signed);
+        self.announce().await;
+        Ok(())
+    }
+
+    /// Ask the topic for its rooms and, after ANSWER_WAIT, return those
+    /// matching `query` (all if empty).
+    pub async fn search(&self, query: &str) -> Vec<Listing> {
+        self.broadcast(&Announcement::Query { nonce: rand::random() }).await;
+        tokio::time::sleep(ANSWER_WAIT).await;
+        self.listings.search(query)
+    }
+}
+
+/*
+Struct:     -DirectoryTopic
+Purpose:    -The directory topic as a session sees it: joined on first use,
+             then shared by its searches and listings.
+
+Fields:
+            - Gossip gossip:  The session's gossip instance.
+            - Vec<EndpointId> bootstrap:  Peers to enter the topic by.
+            - Arc<OnceCell<GossipDirectory>> joined:  Set once joined.
+*/
+#[derive(Clone)]
+pub struct DirectoryTopic {
+    gossip: Gossip,
+    bootstrap: Vec<EndpointId>,
+    joined: Arc<OnceCell<GossipDirectory>>,
+}
+
+impl DirectoryTopic {
+    pub fn new(gossip: Gossip, bootstrap: Vec<EndpointId>) -> Self {
+        Self {
+            gossip,
+            bootstrap,
+            joined: Arc::default(),
+        }
+    }
+
+    pub async fn get(&self) -> Result<&GossipDirectory> {
+        self.joined
+            .get_or_try_init(|| GossipDirectory::join(&self.gossip, self.bootstrap.clone()))
+            .await
+    }
+}
+
+/// The peers a profile's [directory] config says to enter the directory
+/// topic by.
+pub fn bootstrap(config: &DirectoryConfig) -> Result<Vec<EndpointId>> {
+    config
+        .peers
+        .iter()
+        .chain(&config.server)
+        .map(|peer| {
+            peer.parse()
+                .with_context(|| format!("invalid directory peer '{}'", peer))
+        })
+        .collect()
+}
+
+/*
+Function:   -browse
+Purpose:    -Search the directory topic from a throwaway endpoint, for the
+             `browse` command.
+
+Parameters:
+            - Vec<EndpointId> bootstrap:  Peers on the topic to enter by.
+            - &str query:  Text to find in room names and descriptions;
+              empty lists everything.
+
+Details:
+            - A fresh identity each time: browsing is not tied to the
+              profile, and may run next to its daemon.
+*/
+pub async fn browse(bootstrap: Vec<EndpointId>, query: &str) -> Result<Vec<Listing>> {
+    let endpoint = Endpoint::builder().bind().await?;
+    let gossip = Gossip::builder().spawn(endpoint.clone());
+    let router = Router::builder(endpoint).accept(iroh_gossip::ALPN, gossip.clone()).spawn();
+    let listings = GossipDirectory::join(&gossip, bootstrap).await?.search(query).await;
+    router.shutdown().await?;
+    Ok(listings)
+}
@@ -0,0 +1,57 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use iroh_gossip::proto::TopicId;
+
+use crate::profile::{write_private, Profile};
+
+// ── Drafts ────────────────────────────────────────────────────────────────────
+
+/*
+Struct:     -Drafts
+Purpose:    -Unsent input, per room, kept in the profile so it survives a
+             quit or a crash.
+
+Fields:
+            - PathBuf path:  <profile>/drafts.json, a map from room topic to
+              the draft text.
+
+Details:
+            - Every save re-reads the file, so tabs of the same profile do
+              not overwrite each other's drafts.
+            - Empty drafts are removed rather than stored.
+*/
+pub struct Drafts {
+    path: PathBuf,
+}
+
+impl Drafts {
+    pub fn new(profile: &Profile) -> Self {
+        Self {
+            path: profile.drafts_path(),
+        }
+    }
+
+    fn read(&self) -> BTreeMap<String, String> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// The saved draft for a room, empty if there is none.
+    pub fn load(&self, topic: &TopicId) -> String {
+        self.read().remove(&topic.to_string()).unwrap_or_default()
+    }
+
+    /// Replace the saved draft for a room.
+    pub fn save(&self, topic: &TopicId, text: &str) -> Result<()> {
+        let mut drafts = self.read();
+        if text.is_empty() {
+            drafts.remove(&topic.to_string());
+        } else {
+            drafts.insert(topic.to_string(), text.to_string());
+        }
+        write_private(&self.path, &serde_json::to_vec_pretty(&drafts)?)
+    }
+}
@@ -0,0 +1,272 @@
+//! C API for embedding the client in apps written in other languages.
+//!
+//! Built with `--features ffi`; `cargo rustc --lib --release --features ffi
+//! --crate-type cdylib` (or `staticlib`) gives the library to link against,
+//! and include/p2p_chat.h declares what is here. The header is generated:
+//! after changing this file, run `cbindgen --config cbindgen.toml --output
+//! include/p2p_chat.h`. The comments on each function end up in it, which is
+//! why they are doc comments.
+
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, c_int, CStr, CString},
+    path::PathBuf,
+    ptr,
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tokio::runtime::Runtime;
+
+use crate::client::ChatClientBuilder;
+use crate::gateway::{self, Event};
+use crate::protocol::{ChatPayload, Ticket};
+use crate::session::Session;
+
+// ── Client ────────────────────────────────────────────────────────────────────
+
+/*
+Struct:     -P2pChatClient
+Purpose:    -What a C caller holds: a session and the runtime it runs on.
+             Opaque on the C side.
+
+Fields:
+            - Runtime runtime:  Drives the session's tasks in the background
+              and blocks on the calls that wait.
+            - PathBuf profile_dir:  The profile to run as (see Profile::at).
+            - Option<String> nickname:  Overrides the profile's nickname.
+            - Option<Session> session:  The room, once joined.
+            - VecDeque<String> echoes:  Our own messages, as Chat events for
+              the next polls: the room does not send them back to us.
+            - CString last_error:  Why the last call failed, for
+              p2p_chat_last_error.
+*/
+pub struct P2pChatClient {
+    runtime: Runtime,
+    profile_dir: PathBuf,
+    nickname: Option<String>,
+    session: Option<Session>,
+    echoes: VecDeque<String>,
+    last_error: CString,
+}
+
+impl P2pChatClient {
+    fn join(&mut self, ticket: &str) -> Result<()> {
+        if self.session.is_some() {
+            bail!("already in a room");
+        }
+        let ticket = Ticket::from_str(ticket)?;
+        let mut client = ChatClientBuilder::new().identity_dir(self.profile_dir.clone())?.join(ticket);
+        if let Some(name) = &self.nickname {
+            client = client.nickname(name);
+        }
+        self.session = Some(self.runtime.block_on(client.build())?);
+        Ok(())
+    }
+
+    fn send(&mut self, text: &str) -> Result<()> {
+        let session = self.session.as_ref().context("not in a room")?;
+        let sent = self.runtime.block_on(gateway::send(session, ChatPayload::text(text)));
+        let msg = sent.map_err(anyhow::Error::msg)?;
+        self.echoes.push_back(Event::chat(&msg).to_json());
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Option<String> {
+        if let Some(echo) = self.echoes.pop_front() {
+            return Some(echo);
+        }
+        let session = self.session.as_mut()?;
+        self.runtime.block_on(async {
+            let deadline = tokio::time::Instant::now() + timeout;
+            // Skip what gateway clients are not told about, without waiting
+            // past the deadline for something they are.
+            while let Ok(Some(ui)) = tokio::time::timeout_at(deadline, session.events.recv()).await {
+                if let Some(event) = Event::from_ui(&ui) {
+                    return Some(event.to_json());
+                }
+            }
+            None
+        })
+    }
+
+    /// Record why a call failed and return the code C callers check.
+    fn fail(&mut self, e: anyhow::Error) -> c_int {
+        self.last_error = CString::new(format!("{:#}", e).replace('\0', " ")).unwrap_or_default();
+        -1
+    }
+}
+
+/// A C string as a &str, or why not.
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string.
+unsafe fn text<'a>(text: *const c_char) -> Result<&'a str> {
+    if text.is_null() {
+        bail!("unexpected null string");
+    }
+    // SAFETY: not null, and NUL-terminated as the caller promised.
+    let text = unsafe { CStr::from_ptr(text) };
+    text.to_str().context("strings must be UTF-8")
+}
+
+// ── Functions ─────────────────────────────────────────────────────────────────
+
+/// Create a client running as the profile kept in `profile_dir` (created if
+/// missing), with `nickname`, or the profile's nickname if null. Returns
+/// null on failure. Free with p2p_chat_client_free.
+///
+/// # Safety
+///
+/// `profile_dir` must point to a NUL-terminated string, `nickname` too or
+/// be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_client_new(profile_dir: *const c_char, nickname: *const c_char) -> *mut P2pChatClient {
+    // SAFETY: as the caller promised.
+    let Ok(profile_dir) = (unsafe { text(profile_dir) }) else {
+        return ptr::null_mut();
+    };
+    let nickname = if nickname.is_null() {
+        None
+    } else {
+        // SAFETY: not null, so NUL-terminated as the caller promised.
+        match unsafe { text(nickname) } {
+            Ok(nickname) => Some(nickname.to_string()),
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+    let Ok(runtime) = Runtime::new() else {
+        return ptr::null_mut();
+    };
+    Box::into_raw(Box::new(P2pChatClient {
+        runtime,
+        profile_dir: PathBuf::from(profile_dir),
+        nickname,
+        session: None,
+        echoes: VecDeque::new(),
+        last_error: CString::default(),
+    }))
+}
+
+/// Join the room a ticket names; blocks until the session is up. Returns 0,
+/// or -1 on failure (see p2p_chat_last_error).
+///
+/// # Safety
+///
+/// `client` must come from p2p_chat_client_new and not be freed; `ticket`
+/// must point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_join(client: *mut P2pChatClient, ticket: *const c_char) -> c_int {
+    // SAFETY: a live client, as the caller promised.
+    let Some(client) = (unsafe { client.as_mut() }) else {
+        return -1;
+    };
+    // SAFETY: as the caller promised.
+    match unsafe { text(ticket) }.and_then(|ticket| client.join(ticket)) {
+        Ok(()) => 0,
+        Err(e) => client.fail(e),
+    }
+}
+
+/// The ticket others can join the room with, or null before joining. Free
+/// with p2p_chat_string_free.
+///
+/// # Safety
+///
+/// `client` must come from p2p_chat_client_new and not be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_ticket(client: *const P2pChatClient) -> *mut c_char {
+    // SAFETY: a live client, as the caller promised.
+    let Some(session) = (unsafe { client.as_ref() }).and_then(|client| client.session.as_ref()) else {
+        return ptr::null_mut();
+    };
+    CString::new(session.ticket.to_string()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Send a text message to the room. Returns 0 once it is queued, or -1 on
+/// failure (see p2p_chat_last_error). It comes back as a "chat" event.
+///
+/// # Safety
+///
+/// `client` must come from p2p_chat_client_new and not be freed; `text`
+/// must point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_send(client: *mut P2pChatClient, text: *const c_char) -> c_int {
+    // SAFETY: a live client, as the caller promised.
+    let Some(client) = (unsafe { client.as_mut() }) else {
+        return -1;
+    };
+    // SAFETY: as the caller promised.
+    match unsafe { self::text(text) }.and_then(|text| client.send(text)) {
+        Ok(()) => 0,
+        Err(e) => client.fail(e),
+    }
+}
+
+/// The next event, as the JSON the gateway sends (see gateway::Event),
+/// waiting up to `timeout_ms` for one. Null if none came or the client is
+/// not in a room. Free with p2p_chat_string_free.
+///
+/// # Safety
+///
+/// `client` must come from p2p_chat_client_new and not be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_poll_event(client: *mut P2pChatClient, timeout_ms: u64) -> *mut c_char {
+    // SAFETY: a live client, as the caller promised.
+    let Some(client) = (unsafe { client.as_mut() }) else {
+        return ptr::null_mut();
+    };
+    match client.poll(Duration::from_millis(timeout_ms)) {
+        Some(json) => CString::new(json).map_or(ptr::null_mut(), CString::into_raw),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Why the last call on this client failed; empty if none has. Valid until
+/// the next call on the client; do not free.
+///
+/// # Safety
+///
+/// `client` must come from p2p_chat_client_new and not be freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_last_error(client: *const P2pChatClient) -> *const c_char {
+    // SAFETY: a live client, as the caller promised.
+    match unsafe { client.as_ref() } {
+        Some(client) => client.last_error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Free a string this library returned.
+///
+/// # Safety
+///
+/// `text` must come from this library and not be freed already, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        // SAFETY: made by CString::into_raw, as the caller promised.
+        drop(unsafe { CString::from_raw(text) });
+    }
+}
+
+/// Leave the room, sending what is still queued, and free the client.
+///
+/// # Safety
+///
+/// `client` must come from p2p_chat_client_new and not be freed already,
+/// or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn p2p_chat_client_free(client: *mut P2pChatClient) {
+    if client.is_null() {
+        return;
+    }
+    // SAFETY: made by Box::into_raw, as the caller promised.
+    let client = unsafe { Box::from_raw(client) };
+    let P2pChatClient { runtime, session, .. } = *client;
+    if let Some(session) = session {
+        let _ = runtime.block_on(session.shutdown());
+    }
+}
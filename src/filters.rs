@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use tracing::info;
+
+use crate::app::ChatMessage;
+use crate::config::FiltersConfig;
+use crate::protocol::ChatPayload;
+
+// ── Message filters ───────────────────────────────────────────────────────────
+
+/// What a filter decided about a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Keep,
+    Drop,
+}
+
+/*
+Trait:      -Filter
+Purpose:    -A hook on every chat message in or out of a room, to rewrite,
+             mark or drop it.
+
+Details:
+            - inbound sees messages from others after decryption, before
+              they are shown; outbound sees ours before they are encrypted,
+              so every peer sees the result.
+            - Both default to keeping the message untouched; implement
+              whichever side the filter cares about.
+            - To add one, implement this trait and give it a name in
+              `builtin`; profiles then turn it on in their config.
+*/
+pub trait Filter: Send + Sync {
+    fn inbound(&self, _msg: &mut ChatMessage) -> Verdict {
+        Verdict::Keep
+    }
+
+    fn outbound(&self, _payload: &mut ChatPayload) -> Verdict {
+        Verdict::Keep
+    }
+}
+
+/// The filter called `name`, set up from the config, if there is one.
+fn builtin(name: &str, config: &FiltersConfig) -> Option<Box<dyn Filter>> {
+    Some(match name {
+        "mask" => Box::new(Mask::new(&config.mask_words)),
+        "highlight" => Box::new(Highlight::new(&config.highlight_words)),
+        "log" => Box::new(Log),
+        _ => return None,
+    })
+}
+
+/// Names `builtin` knows, for error messages.
+const BUILTIN: &str = "mask, highlight, log";
+
+/*
+Struct:     -Filters
+Purpose:    -The filters a profile turned on, run in the order configured.
+
+Details:
+            - The first filter to drop a message stops the chain.
+            - With no filters configured every message passes untouched.
+*/
+#[derive(Default)]
+pub struct Filters {
+    chain: Vec<Box<dyn Filter>>,
+}
+
+impl Filters {
+    /// Build the chain from a profile's [filters] table. An unknown name is
+    /// an error, so a typo does not silently turn a filter off.
+    pub fn from_config(config: &FiltersConfig) -> Result<Self> {
+        let mut chain = Vec::new();
+        for name in &config.enabled {
+            match builtin(name, config) {
+                Some(filter) => chain.push(filter),
+                None => bail!("unknown filter '{}' (built in: {})", name, BUILTIN),
+            }
+        }
+        Ok(Self { chain })
+    }
+
+    /// A received message as it should be shown, or None to drop it.
+    pub fn inbound(&self, mut msg: ChatMessage) -> Option<ChatMessage> {
+        for filter in &self.chain {
+            if filter.inbound(&mut msg) == Verdict::Drop {
+                return None;
+            }
+        }
+        Some(msg)
+    }
+
+    /// One of our messages as it should be sent, or None to not send it.
+    pub fn outbound(&self, mut payload: ChatPayload) -> Option<ChatPayload> {
+        for filter in &self.chain {
+            if filter.outbound(&mut payload) == Verdict::Drop {
+                return None;
+            }
+        }
+        Some(payload)
+    }
+}
+
+fn lowercase_set(words: &[String]) -> HashSet<String> {
+    words.iter().map(|w| w.to_lowercase()).collect()
+}
+
+/// Whole words of `text`, case-insensitively in `words`, as byte ranges.
+fn matching_words<'a>(text: &'a str, words: &'a HashSet<String>) -> impl Iterator<Item = (usize, usize)> + 'a {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && words.contains(&word.to_lowercase()))
+        .map(move |word| {
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            (start, start + word.len())
+        })
+}
+
+// ── Built-in filters ──────────────────────────────────────────────────────────
+
+/// Replaces listed words with asterisks, in both directions ("mask").
+struct Mask {
+    words: HashSet<String>,
+}
+
+impl Mask {
+    fn new(words: &[String]) -> Self {
+        Self {
+            words: lowercase_set(words),
+        }
+    }
+
+    fn apply(&self, text: &mut String) {
+        let ranges: Vec<(usize, usize)> = matching_words(text, &self.words).collect();
+        for (start, end) in ranges.into_iter().rev() {
+            let stars = "*".repeat(text[start..end].chars().count());
+            text.replace_range(start..end, &stars);
+        }
+    }
+}
+
+impl Filter for Mask {
+    fn inbound(&self, msg: &mut ChatMessage) -> Verdict {
+        self.apply(&mut msg.content);
+        Verdict::Keep
+    }
+
+    fn outbound(&self, payload: &mut ChatPayload) -> Verdict {
+        self.apply(&mut payload.text);
+        Verdict::Keep
+    }
+}
+
+/// Marks received messages containing a keyword, which are then shown and
+/// notified like mentions ("highlight").
+struct Highlight {
+    words: HashSet<String>,
+}
+
+impl Highlight {
+    fn new(words: &[String]) -> Self {
+        Self {
+            words: lowercase_set(words),
+        }
+    }
+}
+
+impl Filter for Highlight {
+    fn inbound(&self, msg: &mut ChatMessage) -> Verdict {
+        if matching_words(&msg.content, &self.words).next().is_some() {
+            msg.highlight = true;
+        }
+        Verdict::Keep
+    }
+}
+
+/// Logs every message in and out at info level, under the "filters" target
+/// ("log"). Note that this writes decrypted text to the log.
+struct Log;
+
+impl Filter for Log {
+    fn inbound(&self, msg: &mut ChatMessage) -> Verdict {
+        info!(target: "filters", id = msg.id, from = %msg.from.fmt_short(), text = %msg.content, "received");
+        Verdict::Keep
+    }
+
+    fn outbound(&self, payload: &mut ChatPayload) -> Verdict {
+        info!(target: "filters", text = %payload.text, "sending");
+        Verdict::Keep
+    }
+}
@@ -0,0 +1,333 @@
+use std::{collections::HashMap, net::SocketAddr};
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use iroh::EndpointId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc},
+};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+use crate::app::{ChatEvent, ChatMessage};
+use crate::bus::ChatCommand;
+use crate::protocol::ChatPayload;
+use crate::session::Session;
+use crate::tui::quit_signals;
+
+// ── Local API ─────────────────────────────────────────────────────────────────
+
+/// Events a slow client may fall behind by before it starts missing some.
+pub const EVENT_BACKLOG: usize = 256;
+
+/// Where transports hand requests to `drive`, each with a channel for the
+/// reply.
+pub type Requests = mpsc::Sender<(Request, mpsc::Sender<String>)>;
+
+/*
+Enum:       -Event
+Purpose:    -What local clients (WebSocket or daemon socket) are sent, one
+             JSON object per frame or line, tagged by "type".
+
+Variants:
+            - Chat:  A message in the room, including ones sent through the
+              gateway.
+            - System:  A notice the TUI would show in the history.
+            - Delete:  A message was deleted.
+            - Name:  A member announced (or changed) their name.
+            - Left:  A member quit the room.
+            - SendFailed:  A message sent through the gateway never made it out.
+            - Sent:  Reply to "send", with the new message's ID.
+            - Members:  Reply to "members".
+            - Rooms:  Reply to "rooms".
+            - Error:  Reply to a request that could not be carried out.
+
+Details:
+            - Message and endpoint IDs are strings: JavaScript numbers cannot
+              hold every u64.
+*/
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Chat {
+        id: String,
+        from: String,
+        sender: String,
+        content: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        meta: Option<Value>,
+        at: String,
+    },
+    System { text: String },
+    Delete { id: String },
+    Name { from: String, name: String },
+    Left { from: String },
+    SendFailed { id: String },
+    Sent { id: String },
+    Members { members: Vec<Member> },
+    Rooms { rooms: Vec<RoomInfo> },
+    Error { message: String },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Member {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub topic: String,
+    pub members: usize,
+    pub ticket: String,
+}
+
+/// What local clients may ask for.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Send {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        meta: Option<Value>,
+    },
+    Delete { id: String },
+    Members,
+    Rooms,
+    /// Start receiving events. Handled by the transport; WebSocket clients
+    /// are subscribed from the start.
+    Subscribe,
+}
+
+impl Event {
+    pub fn chat(msg: &ChatMessage) -> Self {
+        Event::Chat {
+            id: msg.id.to_string(),
+            from: msg.from.to_string(),
+            sender: msg.sender.clone(),
+            content: msg.content.clone(),
+            meta: msg.meta.clone(),
+            at: msg.at.to_rfc3339(),
+        }
+    }
+
+    /// The event for something the session reports, if clients care.
+    pub fn from_ui(ui: &ChatEvent) -> Option<Self> {
+        Some(match ui {
+            ChatEvent::Chat(msg) => Event::chat(msg),
+            ChatEvent::System(text) => Event::System { text: text.clone() },
+            ChatEvent::Delete(id) => Event::Delete { id: id.to_string() },
+            ChatEvent::Name { from, name } => Event::Name {
+                from: from.to_string(),
+                name: name.clone(),
+            },
+            ChatEvent::Left(from) => Event::Left { from: from.to_string() },
+            ChatEvent::SendFailed(id) => Event::SendFailed { id: id.to_string() },
+            _ => return None,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("gateway events always serialize")
+    }
+}
+
+/*
+Function:   -send
+Purpose:    -Send a message on behalf of a local client.
+
+Parameters:
+            - &Session session:  The room.
+            - ChatPayload payload:  The message, before filters.
+
+Details:
+            - Returns the message as the room will see it, for the client to
+              echo, or why it was not sent.
+*/
+pub async fn send(session: &Session, payload: ChatPayload) -> Result<ChatMessage, &'static str> {
+    if session.room_key.current().is_none() {
+        return Err("not admitted to the room yet");
+    }
+    let Some(payload) = session.filters.outbound(payload) else {
+        return Err("a filter stopped the message");
+    };
+    let id: u64 = rand::random();
+    let msg = ChatMessage::new(id, session.my_id, session.my_name.clone(), payload.clone());
+    let _ = session.commands.send(ChatCommand::Send { payload, id }).await;
+    Ok(msg)
+}
+
+/*
+Function:   -drive
+Purpose:    -Run a room on behalf of local clients until the process is
+             told to quit.
+
+Parameters:
+            - &mut Session session:  The room, already joined.
+            - Receiver requests:  Requests from every client, with where to
+              send each reply.
+            - &Sender<String> events:  Where events go, as JSON, for every
+              subscribed client.
+
+Details:
+            - Transport-agnostic: the WebSocket gateway (serve) and the
+              daemon's Unix socket both feed it.
+            - Clients share one peer: a message sent by one shows up as a
+              Chat event for all of them, as it would in the TUI.
+*/
+pub async fn drive(
+    session: &mut Session,
+    mut requests: mpsc::Receiver<(Request, mpsc::Sender<String>)>,
+    events: &broadcast::Sender<String>,
+) -> Result<()> {
+    let mut quit = quit_signals()?;
+    // Announced names of room members, for "members".
+    let mut names: HashMap<EndpointId, String> = HashMap::new();
+    loop {
+        tokio::select! {
+            _ = quit.recv() => break,
+            Some((request, reply)) = requests.recv() => {
+                let answer = match request {
+                    Request::Send { text, meta } => {
+                        let mut payload = ChatPayload::text(text);
+                        payload.meta = meta;
+                        match send(session, payload).await {
+                            Ok(msg) => {
+                                let _ = events.send(Event::chat(&msg).to_json());
+                                Event::Sent { id: msg.id.to_string() }
+                            }
+                            Err(message) => Event::Error { message: message.to_string() },
+                        }
+                    }
+                    Request::Delete { id } => match id.parse::<u64>() {
+                        Ok(id) => {
+                            let _ = session.commands.send(ChatCommand::Delete(id)).await;
+                            Event::Delete { id: id.to_string() }
+                        }
+                        Err(_) => Event::Error { message: format!("invalid message ID {:?}", id) },
+                    },
+                    Request::Members => {
+                        let me = Member { id: session.my_id.to_string(), name: session.my_name.clone() };
+                        let others = names.iter().map(|(id, name)| Member { id: id.to_string(), name: name.clone() });
+                        Event::Members { members: std::iter::once(me).chain(others).collect() }
+                    }
+                    Request::Rooms => Event::Rooms {
+                        rooms: vec![RoomInfo {
+                            topic: session.ticket.topic.to_string(),
+                            members: names.len() + 1,
+                            ticket: session.ticket.to_string(),
+                        }],
+                    },
+                    Request::Subscribe => continue,
+                };
+                if let Event::Delete { .. } = answer {
+                    // Everyone should drop the message, not just the asker.
+                    let _ = events.send(answer.to_json());
+                } else {
+                    let _ = reply.send(answer.to_json()).await;
+                }
+            }
+            Some(ui) = session.events.recv() => {
+                match &ui {
+                    ChatEvent::Name { from, name } => {
+                        names.insert(*from, name.clone());
+                    }
+                    ChatEvent::Left(from) => {
+                        names.remove(from);
+                    }
+                    _ => {}
+                }
+                if let Some(event) = Event::from_ui(&ui) {
+                    let _ = events.send(event.to_json());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// ── WebSocket gateway ─────────────────────────────────────────────────────────
+
+/*
+Function:   -serve
+Purpose:    -Drive a room over a local WebSocket + JSON API until the
+             process is told to quit.
+
+Parameters:
+            - &mut Session session:  The room, already joined.
+            - SocketAddr addr:  Where to listen, normally on localhost.
+
+Details:
+            - Every client gets every event (see Event); requests are
+              {"type": "send", "text": …, "meta": …}, {"type": "delete",
+              "id": …}, {"type": "members"} and {"type": "rooms"}.
+            - There is no authentication; anyone who can connect speaks for
+              this peer, so keep it on localhost.
+*/
+pub async fn serve(session: &mut Session, addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("could not listen on {}", addr))?;
+    let (event_tx, _) = broadcast::channel(EVENT_BACKLOG);
+    let (request_tx, request_rx) = mpsc::channel(64);
+    info!(%addr, "gateway up");
+    let events = event_tx.clone();
+    let accept = tokio::spawn(async move {
+        while let Ok((stream, peer)) = listener.accept().await {
+            debug!(%peer, "gateway client connected");
+            tokio::spawn(client(stream, events.subscribe(), request_tx.clone()));
+        }
+    });
+    let result = drive(session, request_rx, &event_tx).await;
+    accept.abort();
+    result
+}
+
+/// One WebSocket client: forward events to it and its requests to `serve`.
+async fn client(
+    stream: TcpStream,
+    mut events: broadcast::Receiver<String>,
+    requests: Requests,
+) {
+    let mut ws = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            debug!(error = %e, "gateway handshake failed");
+            return;
+        }
+    };
+    let (reply_tx, mut reply_rx) = mpsc::channel(16);
+    loop {
+        let out = tokio::select! {
+            frame = ws.next() => match frame {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(request) => {
+                        if requests.send((request, reply_tx.clone())).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(e) => Event::Error { message: format!("invalid request: {}", e) }.to_json(),
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by tungstenite; binary frames are ignored.
+                Some(Ok(_)) => continue,
+            },
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!(missed, "gateway client fell behind");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            Some(reply) = reply_rx.recv() => reply,
+        };
+        if ws.send(Message::text(out)).await.is_err() {
+            break;
+        }
+    }
+}
@@ -1,135 +1,1613 @@
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map, HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::Result;
 use futures_lite::StreamExt;
-use iroh::EndpointId;
+use iroh::{Endpoint, EndpointId, SecretKey};
 use iroh_gossip::{
-    api::{Event, GossipReceiver, GossipSender},
+    api::{ApiError, Event, GossipReceiver, GossipSender},
+    net::Gossip,
     proto::TopicId,
 };
-use tokio::sync::mpsc;
+use tokio::{
+    sync::{mpsc, watch},
+    time::Instant,
+};
+use tracing::{debug, info, trace, warn};
 
-use crate::app::{ChatMessage, UiMessage};
-use crate::crypto::decrypt_message;
-use crate::protocol::{Message, MessageBody};
+use crate::app::{ChatEvent, ChatMessage, Trust};
+use crate::archive::{self, Archive};
+use crate::bus::{ChatCommand, Events};
+use crate::crypto::{
+    decrypt_message, pow_bits, unwrap_room_key, unwrap_sender_key, verify_delete, verify_membership, verify_message,
+    sign_about_me, sign_key_request, verify_about_me, verify_key_request, verify_moderation, verify_pin, verify_revoke,
+    wrap_sender_key, RoomKey, SenderKeys,
+};
+use crate::filters::Filters;
+use crate::history::DEFAULT_HISTORY;
+use crate::i18n::t;
+use crate::invites::{self, Invites};
+use crate::names::NameCache;
+use crate::netstats::{Load, Pings, Sightings, Swarm, Traffic};
+use crate::protocol::{
+    fragment, ChatPayload, Membership, Message, MessageBody, ModAction, Presence, Reassembly, Role, UnknownKind, MAX_TEXT,
+};
+use crate::webhook::Webhook;
 
 // ── Gossip receive loop ───────────────────────────────────────────────────────
+
+/// First wait before retrying known peers after losing the room; doubles up
+/// to RETRY_MAX.
+const RETRY_MIN: Duration = Duration::from_secs(2);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// How often we repeat our name, for members who missed it; less often in
+/// a crowded room (see Load).
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const CROWDED_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(20 * 60);
+
+/// Members heard from this recently count towards the room's size (see
+/// Load). Longer than CROWDED_ANNOUNCE_INTERVAL, so members who only repeat
+/// their name still count.
+const ACTIVE_WINDOW: Duration = Duration::from_secs(30 * 60);
+
+/// Least time between two announcements of our name when neighbours
+/// connect, so a burst of them costs one.
+const ANNOUNCE_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Most key requests answered for any one peer per KEY_REQUEST_WINDOW:
+/// enough for a join, a kick and a rekey in quick succession, while a peer
+/// asking over and over cannot make us flood the room with keys.
+const KEY_REQUESTS_PER_WINDOW: usize = 3;
+const KEY_REQUEST_WINDOW: Duration = Duration::from_secs(60);
+
+/// How far a key request's send time may be from our clock; older ones
+/// are replays.
+const KEY_REQUEST_MAX_AGE: u64 = 5 * 60;
+
+/// Deletes remembered for messages that have not arrived yet; the oldest go
+/// first.
+const MAX_EARLY_DELETES: usize = 256;
+
+/// Messages remembered as already acknowledged to their sender, by anyone;
+/// the oldest go first.
+const MAX_ACKED: usize = 256;
+
+/// Longest we wait before acknowledging a message. Each member waits a
+/// random time up to this, and stays quiet if another's ack comes first,
+/// so a large room does not answer every message with an ack apiece.
+const ACK_DELAY: Duration = Duration::from_millis(1500);
+
+/// Most messages held back at once (see Inbox), and from any one sender.
+/// Each can be a reassembled message of up to MAX_MESSAGE bytes.
+const MAX_PENDING: usize = 64;
+const MAX_PENDING_PER_PEER: usize = 16;
+
+/// How long a held-back message waits for its sender's name or key.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Message authors remembered for deletes; the oldest go first. As many
+/// as the front end keeps messages.
+const MAX_OWNERS: usize = DEFAULT_HISTORY;
+
+/*
+Struct:     -RoomSender
+Purpose:    -Handle for sending to the room that outlives a resubscribe.
+
+Details:
+            - The receive loop swaps in a new GossipSender when it has to
+              join the topic again; every clone sees the new one.
+            - Splits messages too large for gossip into chunks
+              (protocol::fragment), sent as our endpoint.
+            - Also tracks whether we have any neighbours, as the receive
+              loop sees them come and go: a broadcast with none reaches
+              nobody, so the send queue holds messages until one turns up.
+            - Carries the room's Traffic counters; everything broadcast is
+              counted as sent. Also carries its Load, for deciding what
+              can wait.
+*/
+#[derive(Debug, Clone)]
+pub struct RoomSender {
+    sender: Arc<Mutex<GossipSender>>,
+    my_id: EndpointId,
+    joined: Arc<watch::Sender<bool>>,
+    traffic: Traffic,
+    load: Load,
+}
+
+impl RoomSender {
+    pub fn new(sender: GossipSender, my_id: EndpointId, joined: bool, traffic: Traffic, load: Load) -> Self {
+        Self {
+            sender: Arc::new(Mutex::new(sender)),
+            my_id,
+            joined: Arc::new(watch::Sender::new(joined)),
+            traffic,
+            load,
+        }
+    }
+
+    pub fn traffic(&self) -> &Traffic {
+        &self.traffic
+    }
+
+    pub fn load(&self) -> &Load {
+        &self.load
+    }
+
+    fn set_joined(&self, joined: bool) {
+        self.joined.send_replace(joined);
+    }
+
+    /// Whether at least one neighbour is connected.
+    pub fn is_joined(&self) -> bool {
+        *self.joined.borrow()
+    }
+
+    /// Wait until at least one neighbour is connected.
+    pub async fn wait_joined(&self) {
+        let _ = self.joined.subscribe().wait_for(|joined| *joined).await;
+    }
+
+    fn current(&self) -> GossipSender {
+        self.sender.lock().expect("room sender lock poisoned").clone()
+    }
+
+    fn replace(&self, sender: GossipSender) {
+        *self.sender.lock().expect("room sender lock poisoned") = sender;
+    }
+
+    pub async fn broadcast(&self, bytes: Vec<u8>) -> Result<()> {
+        let sender = self.current();
+        for piece in fragment(bytes, self.my_id)? {
+            let len = piece.len();
+            sender.broadcast(piece.into()).await?;
+            self.traffic.sent(len);
+        }
+        Ok(())
+    }
+
+    pub async fn join_peers(&self, peers: Vec<EndpointId>) -> Result<(), ApiError> {
+        self.current().join_peers(peers).await
+    }
+}
+
+/*
+Struct:     -Room
+Purpose:    -What the receive loop knows about its room and about us.
+
+Fields:
+            - TopicId topic:  The gossip topic.
+            - EndpointId my_id:  Our endpoint.
+            - String my_name:  Our nickname, re-announced to newcomers; follows /nick.
+            - SecretKey secret_key:  Our endpoint key, to unwrap the room key
+              when admitted to a private room.
+            - Option<EndpointId> creator:  Room creator and moderator.
+            - bool private:  Invite-only room; see MessageBody::Knock.
+            - RoomKey key:  Shared with the send loop; empty until admitted.
+            - SenderKeys sender_keys:  Ours, shared with the send loop, and
+              those peers hand us.
+            - Option<u64> token:  Invite token from our ticket, sent with
+              every knock.
+            - Invites invites:  Tokens we issued, as the creator, to check
+              knocks against.
+            - Gossip gossip:  To join the topic again if our subscription ends.
+            - Endpoint endpoint:  Ours, to fetch history from archive peers.
+            - Vec<EndpointId> bootstrap:  Peers from the ticket, tried along
+              with everyone we have met when reconnecting.
+            - Arc<Filters> filters:  The profile's message filters, run on
+              every chat message before it is shown.
+            - Option<Webhook> webhook:  Where received messages are also
+              posted, if the profile has a webhook.
+            - Arc<Mutex<HashSet<EndpointId>>> blocked:  Peers we blocked;
+              shared with the session, which edits it for /block.
+            - u32 pow_bits:  Proof-of-work chat messages must carry; 0 when
+              not required (always so in private rooms).
+            - Option<Archive> archive:  Where we keep the room's history, when
+              running as an archive peer (--archive).
+            - Swarm swarm:  Our neighbours and the ones members report, for
+              /swarm; shared with the session.
+            - Pings pings:  Our pings awaiting a pong; the send loop adds
+              them.
+            - Sightings sightings:  When we first and last heard from each
+              peer and how much, for /whois; shared with the session.
+            - NameCache known:  Members' names from earlier sessions, to
+              start from and keep up to date.
+*/
+pub struct Room {
+    pub topic: TopicId,
+    pub my_id: EndpointId,
+    pub my_name: String,
+    pub secret_key: SecretKey,
+    pub creator: Option<EndpointId>,
+    pub private: bool,
+    pub key: RoomKey,
+    pub sender_keys: SenderKeys,
+    pub token: Option<u64>,
+    pub invites: Invites,
+    pub gossip: Gossip,
+    pub endpoint: Endpoint,
+    pub bootstrap: Vec<EndpointId>,
+    pub filters: Arc<Filters>,
+    pub webhook: Option<Webhook>,
+    pub blocked: Arc<Mutex<HashSet<EndpointId>>>,
+    pub pow_bits: u32,
+    pub archive: Option<Archive>,
+    pub swarm: Swarm,
+    pub pings: Pings,
+    pub sightings: Sightings,
+    pub known: NameCache,
+}
+
+/// An encrypted message held back until we know its sender's name and
+/// have the key for it.
+struct Pending {
+    from: EndpointId,
+    id: u64,
+    /// Key ID and step.
+    position: (u64, u64),
+    ciphertext: Vec<u8>,
+    nonce: [u8; 12],
+    /// Whether the sender signed it (see crypto::verify_message).
+    signed: bool,
+    /// Whether it came from an archive's history rather than the room.
+    replayed: bool,
+    since: Instant,
+}
+
+/*
+Struct:     -Inbox
+Purpose:    -What the receive loop keeps about chat messages: the ones held
+             back, and who wrote the ones shown.
+
+Fields:
+            - VecDeque<Pending> pending:  Messages waiting for their sender's
+              name or key, oldest first.
+            - HashMap<u64, EndpointId> owners:  Who wrote each message, by
+              ID, so only they (or an admin) may delete it. The first to
+              send an ID under their own sender key keeps it.
+            - VecDeque<u64> owned:  The IDs in `owners`, oldest first.
+            - usize unreadable:  Messages from an archive's history that
+              could not be read since the user was last told.
+            - VecDeque<(Instant, EndpointId, u64)> acks:  Acks due for
+              messages that decrypted: when, to whom, for which ID.
+
+Details:
+            - Peers decide how much of this there is, so it is bounded like
+              Reassembly: held-back messages expire after PENDING_TIMEOUT,
+              and past MAX_PENDING_PER_PEER from one sender or MAX_PENDING
+              in all further ones are dropped. Authors are forgotten oldest
+              first past MAX_OWNERS. At most MAX_ACKED acks wait at once.
+*/
+#[derive(Default)]
+struct Inbox {
+    pending: VecDeque<Pending>,
+    owners: HashMap<u64, EndpointId>,
+    owned: VecDeque<u64>,
+    unreadable: usize,
+    acks: VecDeque<(Instant, EndpointId, u64)>,
+}
+
+impl Inbox {
+    /// Hold a message back; false if it was dropped instead.
+    fn hold(&mut self, pending: Pending) -> bool {
+        self.pending.retain(|held| held.since.elapsed() < PENDING_TIMEOUT);
+        let from_peer = self.pending.iter().filter(|held| held.from == pending.from).count();
+        if from_peer >= MAX_PENDING_PER_PEER || self.pending.len() >= MAX_PENDING {
+            return false;
+        }
+        self.pending.push_back(pending);
+        true
+    }
+
+    fn owner(&self, id: u64) -> Option<EndpointId> {
+        self.owners.get(&id).copied()
+    }
+
+    /// Note that `from` wrote message `id`, unless someone already has.
+    /// Only called once it decrypts under their own sender key.
+    fn claim(&mut self, id: u64, from: EndpointId) {
+        let hash_map::Entry::Vacant(entry) = self.owners.entry(id) else {
+            return;
+        };
+        entry.insert(from);
+        self.owned.push_back(id);
+        if self.owned.len() > MAX_OWNERS
+            && let Some(oldest) = self.owned.pop_front()
+        {
+            self.owners.remove(&oldest);
+        }
+    }
+
+    /// Acknowledge message `id` to `from` after a random delay of up to
+    /// ACK_DELAY, unless someone else does first (see `acked`).
+    fn ack_later(&mut self, from: EndpointId, id: u64) {
+        if self.acks.len() == MAX_ACKED {
+            self.acks.pop_front();
+        }
+        let at = Instant::now() + ACK_DELAY.mul_f64(rand::random());
+        self.acks.push_back((at, from, id));
+    }
+
+    /// When the next ack is due.
+    fn next_ack(&self) -> Option<Instant> {
+        self.acks.iter().map(|(at, _, _)| *at).min()
+    }
+
+    /// Someone acknowledged message `id`: ours is not needed.
+    fn acked(&mut self, id: u64) {
+        self.acks.retain(|(_, _, waiting)| *waiting != id);
+    }
+
+    /// Forget a deleted message, held back or shown.
+    fn forget(&mut self, id: u64) {
+        if self.owners.remove(&id).is_some() {
+            self.owned.retain(|owned| *owned != id);
+        }
+        self.pending.retain(|held| held.id != id);
+    }
+}
+
+/// The key to decrypt a message from `from` with: their sender key, or the
+/// topic key for older clients that send key ID 0 at step 0.
+fn message_key(
+    key: &RoomKey,
+    sender_keys: &SenderKeys,
+    from: EndpointId,
+    (key_id, step): (u64, u64),
+) -> Option<[u8; 32]> {
+    if (key_id, step) == (0, 0) {
+        return key.for_message(0, 0);
+    }
+    sender_keys.for_message(from, key_id, step)
+}
+
+/// How far a message decrypted with the key at `(key_id, step)` can be
+/// attributed to its sender (see message_key). Only their signature says
+/// they wrote it: every member holds their sender key.
+fn trust((key_id, step): (u64, u64), signed: bool) -> Trust {
+    if (key_id, step) == (0, 0) || !signed {
+        Trust::SharedKey
+    } else {
+        Trust::Verified
+    }
+}
+
+/// What to show for a decrypted (or undecryptable) message: a chat line as
+/// the filters leave it, for a topic change the new topic, or for a vote
+/// the pick. None when a
+/// filter dropped it. Chat lines also go to the webhook, if there is one.
+fn shown(
+    id: u64,
+    from: EndpointId,
+    name: String,
+    decrypted: Result<ChatPayload>,
+    trust: Trust,
+    filters: &Filters,
+    webhook: Option<&Webhook>,
+) -> Option<ChatEvent> {
+    Some(match decrypted {
+        Ok(ChatPayload { subject: Some(subject), .. }) => ChatEvent::Subject { name, subject },
+        // Only a signed vote is theirs; any other could be anyone
+        // stuffing the ballot.
+        Ok(ChatPayload { vote: Some(vote), .. }) => match trust {
+            Trust::Verified => ChatEvent::Vote {
+                poll: vote.poll,
+                from,
+                choice: vote.choice,
+            },
+            _ => return None,
+        },
+        Ok(payload) => {
+            let msg = ChatMessage::new(id, from, name, payload).with_trust(trust);
+            let msg = filters.inbound(msg)?;
+            if let Some(webhook) = webhook {
+                webhook.post(&msg);
+            }
+            ChatEvent::Chat(msg)
+        }
+        Err(e) => ChatEvent::Chat(ChatMessage::undecryptable(id, from, name, e)),
+    })
+}
+
+/// Decrypt and show every pending message whose sender is now known and
+/// whose key we have. Messages for a key we have yet to receive stay
+/// pending; those for a step already ratcheted past are dropped.
+fn flush_pending(
+    inbox: &mut Inbox,
+    names: &HashMap<EndpointId, String>,
+    key: &RoomKey,
+    sender_keys: &SenderKeys,
+    ui_tx: &Events,
+    filters: &Filters,
+    webhook: Option<&Webhook>,
+) {
+    for held in std::mem::take(&mut inbox.pending) {
+        if held.since.elapsed() >= PENDING_TIMEOUT {
+            inbox.unreadable += usize::from(held.replayed);
+            continue;
+        }
+        let Some(name) = names.get(&held.from) else {
+            inbox.pending.push_back(held); // keep — sender still unknown
+            continue;
+        };
+        let Some(key) = message_key(key, sender_keys, held.from, held.position) else {
+            if !sender_keys.knows(held.from, held.position.0) {
+                inbox.pending.push_back(held);
+            } else {
+                inbox.unreadable += usize::from(held.replayed);
+            }
+            continue;
+        };
+        // Someone else's message took the ID while this one waited.
+        if inbox.owner(held.id).is_some_and(|owner| owner != held.from) {
+            continue;
+        }
+        let decrypted = decrypt_message(&held.ciphertext, &held.nonce, &key);
+        if decrypted.is_ok() && held.position != (0, 0) {
+            inbox.claim(held.id, held.from);
+        }
+        if decrypted.is_ok() && !held.replayed {
+            inbox.ack_later(held.from, held.id);
+        }
+        let trust = trust(held.position, held.signed);
+        if let Some(msg) = shown(held.id, held.from, name.clone(), decrypted, trust, filters, webhook) {
+            let _ = ui_tx.send(msg);
+        }
+    }
+}
+
+/*
+Function:   -hand_over
+Purpose:    -Send our sender key to one peer, wrapped for them alone.
+
+Details:
+            - Bound to the current room key, so it is useless to a peer that
+              was never admitted or has been rekeyed out.
+            - Does nothing while we have no room key ourselves.
+*/
+async fn hand_over(
+    sender: &RoomSender,
+    secret_key: &SecretKey,
+    topic: &TopicId,
+    key: &RoomKey,
+    sender_keys: &SenderKeys,
+    to: EndpointId,
+) {
+    let Some(room) = key.current() else {
+        return;
+    };
+    let own = sender_keys.own(room.epoch);
+    let Ok((wrapped, nonce)) = wrap_sender_key(&own, &room.key, secret_key, to, topic) else {
+        return;
+    };
+    let msg = Message::new(MessageBody::SenderKey {
+        from: secret_key.public(),
+        to,
+        room_epoch: room.epoch,
+        room_step: room.step,
+        key_id: own.epoch,
+        step: own.step,
+        wrapped,
+        nonce,
+    });
+    broadcast(sender, msg.to_vec()).await;
+}
+
+/// Broadcast to the room. A failure means the gossip actor has stopped, which
+/// ends the session anyway, so it is only logged.
+pub async fn broadcast(sender: &RoomSender, bytes: Vec<u8>) {
+    if let Err(e) = sender.broadcast(bytes).await {
+        warn!(error = %e, "broadcast failed");
+    }
+}
+
+/// Announce our nickname to the room, signed.
+async fn announce(sender: &RoomSender, secret_key: &SecretKey, topic: &TopicId, name: &str) {
+    broadcast(sender, sign_about_me(name.to_string(), secret_key, topic).to_vec()).await;
+}
+
+/// Note that message `id` has been acknowledged.
+fn remember_ack(acked: &mut VecDeque<u64>, id: u64) {
+    if acked.contains(&id) {
+        return;
+    }
+    if acked.len() == MAX_ACKED {
+        acked.pop_front();
+    }
+    acked.push_back(id);
+}
+
+/// Peers to try when reconnecting: the ticket's, and everyone we have met.
+fn known_peers(
+    bootstrap: &[EndpointId],
+    names: &HashMap<EndpointId, String>,
+    my_id: EndpointId,
+) -> Vec<EndpointId> {
+    let mut peers = bootstrap.to_vec();
+    peers.extend(names.keys().filter(|id| **id != my_id && !bootstrap.contains(id)));
+    peers
+}
+
+/*
+Function:   -catch_up
+Purpose:    -Fetch the history an archive peer keeps, in the background,
+             and hand it to the receive loop.
+
+Details:
+            - The receive loop treats it like gossip (`replayed`), so it goes
+              through the same checks and keys. Messages we saw live are
+              skipped.
+            - Names and sender keys go first, so the messages after them can
+              be read straight away. Sender keys from history are accepted
+              however old (SenderKeys::insert_archived). Messages under a
+              key that was never handed to us, such as a private room's
+              from before we were let in, stay unreadable.
+            - An empty message marks the end, after which the receive loop
+              says how many could not be read.
+*/
+fn catch_up(
+    endpoint: &Endpoint,
+    from: EndpointId,
+    topic: TopicId,
+    history: &mpsc::Sender<Vec<u8>>,
+    ui_tx: &Events,
+) {
+    let (endpoint, history, ui_tx) = (endpoint.clone(), history.clone(), ui_tx.clone());
+    tokio::spawn(async move {
+        match archive::fetch(&endpoint, from, &topic).await {
+            Ok(mut messages) => {
+                debug!(from = %from.fmt_short(), count = messages.len(), "fetched history");
+                messages.sort_by_key(|bytes| {
+                    !Message::from_bytes(bytes).is_ok_and(|message| {
+                        matches!(message.body, MessageBody::AboutMe { .. } | MessageBody::SenderKey { .. })
+                    })
+                });
+                for message in messages.into_iter().chain([Vec::new()]) {
+                    if history.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            Err(e) => {
+                warn!(from = %from.fmt_short(), error = %e, "could not fetch history");
+                let _ = ui_tx
+                    .send(ChatEvent::System(t!(
+                        "Could not fetch missed messages from the archive at {}.",
+                        from.fmt_short()
+                    )));
+            }
+        }
+    });
+}
+
+/// Account for a message that did not decode. One of a kind we do not know
+/// is from a newer client and skipped; the first of each such kind gets a
+/// notice, so the user knows something was not shown.
+fn undecodable(e: &anyhow::Error, len: usize, unknown: &mut HashSet<String>, ui_tx: &Events) {
+    let Some(UnknownKind { kind, version }) = e.downcast_ref::<UnknownKind>() else {
+        warn!(error = %e, len, "dropping malformed message");
+        return;
+    };
+    debug!(kind, version, "skipping a message of an unknown kind");
+    if unknown.insert(kind.clone()) {
+        let _ = ui_tx
+            .send(ChatEvent::System(t!(
+                "Someone sent a kind of message this version does not understand ({}); it was skipped. Updating may show it.",
+                kind
+            )));
+    }
+}
+
+/// Ask every peer for their sender key again, e.g. once ours has changed.
+async fn request_keys(sender: &RoomSender, secret_key: &SecretKey, topic: &TopicId) {
+    broadcast(sender, sign_key_request(secret_key, topic).to_vec()).await;
+}
+
+/*
+Function:   -subscribe_loop
+Purpose:    -Receive and act on everything sent to the room, for as long as
+             the session runs.
+
+Details:
+            - Supervises the subscription: when the last neighbour goes
+              (sleep, network switch) it retries the ticket's peers and
+              everyone we have met, backing off up to RETRY_MAX; when the
+              event stream itself ends it joins the topic again. The user
+              sees "Reconnecting…" and "Reconnected" notices.
+            - Once back, announces us again and asks for sender keys (or
+              knocks), since messages sent meanwhile are lost.
+            - Ends when the TUI has gone or gossip has shut down.
+*/
 pub async fn subscribe_loop(
     mut receiver: GossipReceiver,
-    sender: GossipSender,
-    topic: TopicId,
-    ui_tx: mpsc::Sender<UiMessage>,
-    my_id: EndpointId,
-    my_name: String,
+    sender: RoomSender,
+    ui_tx: Events,
+    mut loopback: mpsc::Receiver<Vec<u8>>,
+    commands: mpsc::Sender<ChatCommand>,
+    room: Room,
 ) -> Result<()> {
+    let Room {
+        topic,
+        my_id,
+        mut my_name,
+        secret_key,
+        creator: moderator,
+        private,
+        key,
+        sender_keys,
+        token,
+        invites,
+        gossip,
+        endpoint,
+        bootstrap,
+        filters,
+        webhook,
+        blocked,
+        pow_bits: required_pow,
+        archive,
+        swarm,
+        pings,
+        sightings,
+        known,
+    } = room;
+    swarm.reset(receiver.neighbors());
     let mut names: HashMap<EndpointId, String> = HashMap::new();
-    let mut message_owners: HashMap<u64, EndpointId> = HashMap::new();
-    // Messages that arrived before we knew the sender's name.
-    let mut pending: Vec<(EndpointId, u64, Vec<u8>, [u8; 12])> = Vec::new();
+    // Messages that arrived before we knew the sender's name or the key,
+    // and who wrote the ones we have seen.
+    let mut inbox = Inbox::default();
+    // Signed deletes for messages we have not seen yet, and who sent them.
+    let mut early_deletes: VecDeque<(u64, EndpointId)> = VecDeque::new();
+    // Messages someone has told the sender about, so we need not too.
+    let mut acked: VecDeque<u64> = VecDeque::new();
+    // Peers the moderator removed, and the signed actions that did it (as
+    // received, so the moderator can repeat them to newcomers).
+    let mut kicked: HashSet<EndpointId> = HashSet::new();
+    let mut muted: HashSet<EndpointId> = HashSet::new();
+    let mut actions: Vec<Vec<u8>> = Vec::new();
+    // Invite tokens the creator revoked, so each is announced once (the
+    // Revokes themselves go in `actions`).
+    let mut revoked: HashSet<u64> = HashSet::new();
+    // Latest signed membership document, and the message that carried it.
+    let mut membership = Membership::default();
+    let mut membership_msg: Option<Vec<u8>> = None;
+    // Peers we have handed our current sender key to, and when we last
+    // answered each one's key requests.
+    let mut handed: HashSet<EndpointId> = HashSet::new();
+    let mut answered: HashMap<EndpointId, VecDeque<Instant>> = HashMap::new();
+    // Pieces of chunked messages still coming in.
+    let mut chunks = Reassembly::default();
+    // While cut off from the room: when to retry next, and the wait after.
+    let mut retry: Option<(Instant, Duration)> = None;
+    // Archive peers we know of, the history they hand us, and the members
+    // we only know from that history.
+    let mut archives: HashSet<EndpointId> = HashSet::new();
+    let (history_tx, mut history) = mpsc::channel::<Vec<u8>>(64);
+    let mut archived_names: HashSet<EndpointId> = HashSet::new();
+    // Message kinds from newer clients we have told the user we skip.
+    let mut unknown_kinds: HashSet<String> = HashSet::new();
 
     names.insert(my_id, my_name.clone());
+    // Members from earlier sessions, named from the start but, like those
+    // learned from history, not taken to be here until they say so.
+    let cached = known.load(&topic);
+    // Whether each member's messages last came under their own key, as
+    // saved, so the cache is only written when that changes; and whose
+    // names are saved at all, which only signed ones are.
+    let mut verified: HashMap<EndpointId, bool> = HashMap::new();
+    let mut saved: HashSet<EndpointId> = HashSet::new();
+    for (id, entry) in cached {
+        if id != my_id {
+            names.insert(id, entry.name);
+            archived_names.insert(id);
+            verified.insert(id, entry.verified);
+            saved.insert(id);
+        }
+    }
+    // Ours, as last set with /status or auto-away, for newcomers.
+    let mut my_presence = Presence::Online;
+    // When we last announced our name (the session did on joining), and
+    // when we next will unless something comes first.
+    let mut announced = Instant::now();
+    let mut announce_at = announced + ANNOUNCE_INTERVAL;
 
-    while let Some(event) = receiver.try_next().await? {
-        if let Event::Received(msg) = event {
-            let message = Message::from_bytes(&msg.content)?;
-            match message.body {
-                MessageBody::AboutMe { from, name } => {
-                    let is_new = !names.contains_key(&from);
-                    names.insert(from, name.clone());
-
-                    if from != my_id {
-                        if is_new {
-                            // Re-announce ourselves so the newcomer learns our name.
-                            let announce = Message::new(MessageBody::AboutMe {
+    loop {
+        // Ourselves and whoever spoke up lately; names stay cached long
+        // after their owners left.
+        sender.load().set_members(sightings.active(ACTIVE_WINDOW) + 1);
+        // Gossip from the room, or our own signed moderation actions and
+        // membership documents, which gossip does not deliver back to us, or
+        // history from an archive peer (`replayed`).
+        let (content, replayed): (Vec<u8>, bool) = tokio::select! {
+            event = receiver.try_next() => match event {
+                Ok(Some(Event::Received(msg))) => {
+                    sender.traffic().received(msg.content.len());
+                    (msg.content.to_vec(), false)
+                }
+                Ok(Some(Event::NeighborUp(peer))) => {
+                    debug!(peer = %peer.fmt_short(), "neighbour up");
+                    swarm.neighbour_up(peer);
+                    sender.set_joined(true);
+                    if retry.take().is_some() {
+                        info!("reconnected");
+                        let _ = ui_tx.send(ChatEvent::System(t!("Reconnected.").to_string()));
+                        announce(&sender, &secret_key, &topic, &my_name).await;
+                        announced = Instant::now();
+                        if my_presence != Presence::Online {
+                            let presence = Message::new(MessageBody::Presence { from: my_id, presence: my_presence });
+                            broadcast(&sender, presence.to_vec()).await;
+                        }
+                        if archive.is_some() {
+                            broadcast(&sender, Message::new(MessageBody::Archive { from: my_id }).to_vec()).await;
+                        }
+                        // Catch up on what we missed while away.
+                        for archive in &archives {
+                            catch_up(&endpoint, *archive, topic, &history_tx, &ui_tx);
+                        }
+                        if key.current().is_some() {
+                            handed.clear();
+                            request_keys(&sender, &secret_key, &topic).await;
+                        } else {
+                            let knock = Message::new(MessageBody::Knock {
                                 from: my_id,
                                 name: my_name.clone(),
+                                token,
                             });
-                            let _ = sender.broadcast(announce.to_vec().into()).await;
+                            broadcast(&sender, knock.to_vec()).await;
                         }
-
+                    } else if announced.elapsed() >= ANNOUNCE_COOLDOWN {
+                        // Perhaps a newcomer, who has not heard our name.
+                        announce(&sender, &secret_key, &topic, &my_name).await;
+                        announced = Instant::now();
+                    }
+                    continue;
+                }
+                Ok(Some(Event::NeighborDown(peer))) => {
+                    debug!(peer = %peer.fmt_short(), "neighbour down");
+                    swarm.neighbour_down(peer);
+                    sender.set_joined(receiver.is_joined());
+                    if !receiver.is_joined() && retry.is_none() {
+                        info!("lost every neighbour, reconnecting");
                         let _ = ui_tx
-                            .send(UiMessage::System(format!("{} joined the chat", name)))
-                            .await;
+                            .send(ChatEvent::System(t!("Lost the room. Reconnecting…").to_string()));
+                        retry = Some((Instant::now() + RETRY_MIN, RETRY_MIN));
+                    }
+                    continue;
+                }
+                Ok(Some(Event::Lagged)) => {
+                    warn!("fell behind the room; some messages were missed");
+                    continue;
+                }
+                Ok(None) | Err(_) => {
+                    if ui_tx.receiver_count() == 0 {
+                        break;
+                    }
+                    if let Err(e) = &event {
+                        warn!(error = %e, "gossip subscription failed");
+                    }
+                    // Already rejoined once without a neighbour turning up:
+                    // don't spin if gossip keeps closing on us.
+                    if retry.is_some() {
+                        tokio::time::sleep(RETRY_MIN).await;
+                    }
+                    let peers = known_peers(&bootstrap, &names, my_id);
+                    let topic = match gossip.subscribe(topic, peers).await {
+                        Ok(topic) => topic,
+                        Err(e) => {
+                            warn!(error = %e, "could not join the topic again");
+                            break;
+                        }
+                    };
+                    let (new_sender, new_receiver) = topic.split();
+                    sender.replace(new_sender);
+                    receiver = new_receiver;
+                    sender.set_joined(receiver.is_joined());
+                    swarm.reset(receiver.neighbors());
+                    if retry.is_none() {
+                        info!("subscription ended, rejoined the topic");
+                        let _ = ui_tx.send(ChatEvent::System(t!("Reconnecting…").to_string()));
+                        retry = Some((Instant::now() + RETRY_MIN, RETRY_MIN));
+                    }
+                    continue;
+                }
+            },
+            Some(content) = loopback.recv() => (content, false),
+            Some(content) = history.recv() => {
+                if content.is_empty() {
+                    // The end of an archive's history: whatever is still
+                    // held back from it is not going to be read.
+                    let held = inbox.pending.len();
+                    inbox.pending.retain(|held| !held.replayed);
+                    let unreadable = std::mem::take(&mut inbox.unreadable) + held - inbox.pending.len();
+                    if unreadable > 0 {
+                        let _ = ui_tx.send(ChatEvent::System(t!(
+                            "{} missed messages from the archive could not be read: their keys were never handed to you.",
+                            unreadable
+                        )));
+                    }
+                    continue;
+                }
+                (content, true)
+            }
+            _ = tokio::time::sleep_until(inbox.next_ack().unwrap_or_else(Instant::now)), if !inbox.acks.is_empty() => {
+                let now = Instant::now();
+                let (due, waiting) = std::mem::take(&mut inbox.acks).into_iter().partition(|(at, _, _)| *at <= now);
+                inbox.acks = waiting;
+                for (_, to, id) in due {
+                    if acked.contains(&id) {
+                        continue;
+                    }
+                    remember_ack(&mut acked, id);
+                    broadcast(&sender, Message::new(MessageBody::Ack { from: my_id, to, id }).to_vec()).await;
+                }
+                continue;
+            }
+            _ = tokio::time::sleep_until(retry.map_or_else(Instant::now, |(at, _)| at)), if retry.is_some() => {
+                let peers = known_peers(&bootstrap, &names, my_id);
+                debug!(peers = peers.len(), "retrying known peers");
+                if let Err(e) = sender.join_peers(peers).await {
+                    warn!(error = %e, "could not retry peers");
+                }
+                if let Some((at, wait)) = &mut retry {
+                    *wait = (*wait * 2).min(RETRY_MAX);
+                    *at = Instant::now() + *wait;
+                }
+                continue;
+            }
+            _ = tokio::time::sleep_until(announce_at) => {
+                let load = sender.load();
+                if load.congested() {
+                    debug!("sending is backed up; skipping the repeat of our name");
+                } else {
+                    announce(&sender, &secret_key, &topic, &my_name).await;
+                    announced = Instant::now();
+                    if load.take_held_presence() {
+                        let presence = Message::new(MessageBody::Presence { from: my_id, presence: my_presence });
+                        broadcast(&sender, presence.to_vec()).await;
+                    }
+                }
+                let interval = if load.crowded() { CROWDED_ANNOUNCE_INTERVAL } else { ANNOUNCE_INTERVAL };
+                announce_at = Instant::now() + interval;
+                continue;
+            }
+        };
+        let mut message = match Message::from_bytes(&content) {
+            Ok(message) => message,
+            Err(e) => {
+                undecodable(&e, content.len(), &mut unknown_kinds, &ui_tx);
+                continue;
+            }
+        };
+        // A piece of a larger message: wait for the rest, then handle the
+        // whole as if it had arrived in one go.
+        if let MessageBody::Chunk { from, id, index, total, ref data } = message.body {
+            let whole = match chunks.add(from, id, index, total, data) {
+                Ok(Some(whole)) => whole,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(from = %from.fmt_short(), error = %e, "dropping chunk");
+                    continue;
+                }
+            };
+            message = match Message::from_bytes(&whole) {
+                Ok(message) if message.body.from() == from => message,
+                Ok(_) => {
+                    warn!(from = %from.fmt_short(), "chunked message claims another sender");
+                    continue;
+                }
+                Err(e) => {
+                    undecodable(&e, whole.len(), &mut unknown_kinds, &ui_tx);
+                    continue;
+                }
+            };
+        }
+        let sender_id = message.body.from();
+        if blocked.lock().expect("blocklist lock poisoned").contains(&sender_id) {
+            trace!(from = %sender_id.fmt_short(), "ignoring blocked peer");
+            continue;
+        }
+        if kicked.contains(&sender_id) {
+            trace!(from = %sender_id.fmt_short(), "ignoring kicked peer");
+            continue;
+        }
+        if !replayed && sender_id != my_id {
+            sightings.seen(sender_id);
+        }
+        let silenced = muted.contains(&sender_id)
+            || membership.role(sender_id, moderator) == Role::ReadOnly;
+        if silenced
+            && matches!(
+                message.body,
+                MessageBody::EncryptedMessage { .. }
+                    | MessageBody::Message { .. }
+                    | MessageBody::Interaction { .. }
+                    | MessageBody::Pin { .. }
+            )
+        {
+            continue;
+        }
+        if let Some(archive) = &archive
+            && !replayed
+            && !matches!(message.body, MessageBody::EncryptedMessage { .. } | MessageBody::Pin { .. })
+        {
+            // Chat messages and pins are kept once they pass their checks.
+            archive.record(&message);
+        }
+        match message.body {
+            MessageBody::AboutMe { from, ref name, nonce, ref signature }
+                if verify_about_me((name, nonce), signature.as_ref(), from, &topic).is_err() =>
+            {
+                warn!(from = %from.fmt_short(), "dropping name with a bad signature");
+            }
 
-                        // Flush any messages that arrived before we knew this peer's name.
-                        pending.retain(|(msg_from, id, ciphertext, nonce)| {
-                            if *msg_from != from {
-                                return true; // keep — belongs to a different unknown peer
-                            }
-                            match decrypt_message(ciphertext, nonce, &topic) {
-                                Ok(text) => {
-                                    let _ = ui_tx.try_send(UiMessage::Chat(ChatMessage {
-                                        id: *id,
-                                        sender: name.clone(),
-                                        content: text,
-                                    }));
-                                }
-                                Err(e) => {
-                                    let _ = ui_tx.try_send(UiMessage::System(format!(
-                                        "Failed to decrypt message from {}: {}",
-                                        name, e
-                                    )));
-                                }
-                            }
-                            false // remove from pending after flushing
+            // Learned from history: good for naming old messages, but they
+            // may well be gone, so neither shown as present nor greeted.
+            MessageBody::AboutMe { from, name, .. } if replayed => {
+                if let hash_map::Entry::Vacant(entry) = names.entry(from) {
+                    entry.insert(name);
+                    archived_names.insert(from);
+                    flush_pending(&mut inbox, &names, &key, &sender_keys, &ui_tx, &filters, webhook.as_ref());
+                }
+            }
+
+            MessageBody::AboutMe { from, name, signature, .. } => {
+                let is_new = !names.contains_key(&from) || archived_names.remove(&from);
+                // A repeat of a name we know (see ANNOUNCE_INTERVAL) shows
+                // nothing. It still tells us the creator is there, which a
+                // knock still waiting to be answered cares about.
+                if !is_new && names.get(&from) == Some(&name) {
+                    if from != my_id && private && key.current().is_none() && Some(from) == moderator {
+                        let knock = Message::new(MessageBody::Knock {
+                            from: my_id,
+                            name: my_name.clone(),
+                            token,
                         });
+                        broadcast(&sender, knock.to_vec()).await;
+                    }
+                    continue;
+                }
+                let renamed_from = names.insert(from, name.clone()).filter(|old| *old != name && !is_new);
+                if from == my_id {
+                    // Our own rename, looped back by the send loop.
+                    my_name = name.clone();
+                } else if signature.is_some() {
+                    // Checked above. Anyone can claim an unsigned name, so
+                    // those are shown but not kept.
+                    saved.insert(from);
+                    let own = verified.get(&from).copied();
+                    if let Err(e) = known.update(&topic, from, &name, |entry| {
+                        entry.name = name.clone();
+                        entry.verified = own.unwrap_or(entry.verified);
+                    }) {
+                        warn!(error = %e, "could not save the name cache");
                     }
                 }
+                let _ = ui_tx
+                    .send(ChatEvent::Name {
+                        from,
+                        name: name.clone(),
+                    });
 
-                MessageBody::EncryptedMessage {
-                    from,
-                    id,
-                    ref ciphertext,
-                    ref nonce,
-                } => {
-                    message_owners.insert(id, from);
+                if from != my_id {
+                    if is_new {
+                        // Re-announce ourselves so the newcomer learns our name.
+                        announce(&sender, &secret_key, &topic, &my_name).await;
+                        announced = Instant::now();
+                        let load = sender.load();
+                        if my_presence != Presence::Online && load.greets_with_presence() {
+                            let presence = Message::new(MessageBody::Presence { from: my_id, presence: my_presence });
+                            broadcast(&sender, presence.to_vec()).await;
+                        }
+                        if archive.is_some() {
+                            broadcast(&sender, Message::new(MessageBody::Archive { from: my_id }).to_vec()).await;
+                        }
 
-                    if from == my_id {
+                        // The moderator repeats past actions so the
+                        // newcomer enforces them as well.
+                        if moderator == Some(my_id) {
+                            for action in actions.iter().chain(&membership_msg) {
+                                broadcast(&sender, action.clone()).await;
+                            }
+                        }
+                    }
+
+                    let note = match renamed_from {
+                        Some(old) => t!("{} is now known as {}", old, name),
+                        None => t!("{} joined the chat", name),
+                    };
+                    let _ = ui_tx.send(ChatEvent::System(note));
+
+                    // Flush any messages that arrived before we knew this peer's name.
+                    flush_pending(&mut inbox, &names, &key, &sender_keys, &ui_tx, &filters, webhook.as_ref());
+
+                    // Still waiting to get into a private room: knock again
+                    // now that the creator is (back) online.
+                    if private && key.current().is_none() && Some(from) == moderator {
+                        let knock = Message::new(MessageBody::Knock {
+                            from: my_id,
+                            name: my_name.clone(),
+                            token,
+                        });
+                        broadcast(&sender, knock.to_vec()).await;
+                    }
+                }
+            }
+
+            MessageBody::EncryptedMessage {
+                from,
+                id,
+                key_id,
+                step,
+                ref ciphertext,
+                ref nonce,
+                pow,
+                ..
+            } => {
+                // Flood protection for public rooms: no work, no display.
+                if required_pow > 0
+                    && pow.is_none_or(|stamp| pow_bits(from, id, nonce, ciphertext, stamp) < required_pow)
+                {
+                    debug!(from = %from.fmt_short(), id, "dropping message without enough proof of work");
+                    continue;
+                }
+                // Signed, but not by who it claims to be from: forged.
+                let signed = match verify_message(&message.body, &topic) {
+                    Ok(signed) => signed,
+                    Err(e) => {
+                        warn!(from = %from.fmt_short(), id, error = %e, "dropping message");
                         continue;
                     }
+                };
 
-                    // If we don't know this peer's name yet, buffer the message.
-                    if !names.contains_key(&from) {
-                        pending.push((from, id, ciphertext.clone(), *nonce));
+                if let Some(archive) = &archive
+                    && !replayed
+                {
+                    archive.record(&message);
+                }
+                // An ID belongs to whoever first sent it under their own
+                // key: a later message with it is history we already saw
+                // live, or someone after the right to delete it.
+                match inbox.owner(id) {
+                    Some(owner) if owner != from => {
+                        debug!(from = %from.fmt_short(), id, "dropping message reusing another's ID");
                         continue;
                     }
+                    Some(_) if replayed => continue,
+                    _ => {}
+                }
+                if !replayed {
+                    sender.traffic().message();
+                    if from != my_id {
+                        sightings.message(from);
+                    }
+                }
 
-                    let name = names
-                        .get(&from)
-                        .cloned()
-                        .unwrap_or_else(|| from.fmt_short().to_string());
+                if from == my_id {
+                    continue;
+                }
+
+                // Deleted before it got here.
+                if let Some(index) = early_deletes.iter().position(|(deleted, by)| {
+                    *deleted == id && (*by == from || membership.role(*by, moderator) == Role::Admin)
+                }) {
+                    early_deletes.remove(index);
+                    continue;
+                }
+
+                // Sent at a step we have already ratcheted past: unreadable.
+                let message_key = message_key(&key, &sender_keys, from, (key_id, step));
+                if message_key.is_none() && sender_keys.knows(from, key_id) {
+                    inbox.unreadable += usize::from(replayed);
+                    continue;
+                }
+
+                // If we don't know this peer's name (or their key) yet,
+                // buffer the message.
+                let Some(message_key) = message_key.filter(|_| names.contains_key(&from)) else {
+                    let held = Pending {
+                        from,
+                        id,
+                        position: (key_id, step),
+                        ciphertext: ciphertext.clone(),
+                        nonce: *nonce,
+                        signed,
+                        replayed,
+                        since: Instant::now(),
+                    };
+                    if !inbox.hold(held) {
+                        debug!(from = %from.fmt_short(), id, "too many messages held back; dropping one");
+                        inbox.unreadable += usize::from(replayed);
+                    }
+                    continue;
+                };
+
+                let name = names
+                    .get(&from)
+                    .cloned()
+                    .unwrap_or_else(|| from.fmt_short().to_string());
 
-                    match decrypt_message(ciphertext, nonce, &topic) {
-                        Ok(text) => {
+                let decrypted = decrypt_message(ciphertext, nonce, &message_key);
+                let trust = trust((key_id, step), signed);
+                match &decrypted {
+                    Ok(_) => {
+                        if (key_id, step) != (0, 0) {
+                            inbox.claim(id, from);
+                        }
+                        // Tell the sender it arrived, once we could read it.
+                        // One ack is all it needs, so skip it when another
+                        // member's got here first.
+                        if !replayed && !acked.contains(&id) {
+                            inbox.ack_later(from, id);
+                        }
+                        let own = trust == Trust::Verified;
+                        sightings.decrypted(from, own);
+                        if verified.insert(from, own) != Some(own)
+                            && saved.contains(&from)
+                            && let Err(e) = known.update(&topic, from, &name, |entry| entry.verified = own)
+                        {
+                            warn!(error = %e, "could not save the name cache");
+                        }
+                    }
+                    Err(e) => warn!(from = %from.fmt_short(), key_id, step, error = %e, "decryption failed"),
+                }
+                if let Some(msg) = shown(id, from, name, decrypted, trust, &filters, webhook.as_ref()) {
+                    let _ = ui_tx.send(msg);
+                }
+            }
+
+            // Legacy plaintext. A copy with an ID is one a current client
+            // sent next to the encrypted message (see Config::legacy_compat),
+            // which is the one we show.
+            MessageBody::Message { from, ref text, id } => {
+                if id != 0 || from == my_id || replayed {
+                    continue;
+                }
+                // Private rooms are encrypted for their members alone, and
+                // legacy clients cannot mint proof of work.
+                if private || required_pow > 0 {
+                    debug!(from = %from.fmt_short(), "dropping legacy plaintext message");
+                    continue;
+                }
+                sender.traffic().message();
+                sightings.message(from);
+                let name = names
+                    .get(&from)
+                    .cloned()
+                    .unwrap_or_else(|| from.fmt_short().to_string());
+                let text: String = text.chars().take(MAX_TEXT).collect();
+                let msg = ChatMessage::new(rand::random(), from, name, ChatPayload::text(text))
+                    .with_trust(Trust::Unencrypted);
+                if let Some(msg) = filters.inbound(msg) {
+                    if let Some(webhook) = &webhook {
+                        webhook.post(&msg);
+                    }
+                    let _ = ui_tx.send(ChatEvent::Chat(msg));
+                }
+            }
+
+            MessageBody::DeleteMessage { from, id, ref signature } => {
+                if let Err(e) = verify_delete(id, signature, from, &topic) {
+                    warn!(from = %from.fmt_short(), id, error = %e, "ignoring delete");
+                    continue;
+                }
+                // Authors may delete their own messages, admins anyone's.
+                // One held back is dropped, and one we have not seen yet is
+                // deleted when it arrives.
+                let admin = membership.role(from, moderator) == Role::Admin;
+                let Some(owner) = inbox.owner(id) else {
+                    inbox.pending.retain(|held| held.id != id || (held.from != from && !admin));
+                    if early_deletes.len() == MAX_EARLY_DELETES {
+                        early_deletes.pop_front();
+                    }
+                    early_deletes.push_back((id, from));
+                    continue;
+                };
+                if owner == from || admin {
+                    if let Some(archive) = &archive {
+                        archive.forget(id);
+                    }
+                    inbox.forget(id);
+                    let _ = ui_tx.send(ChatEvent::Delete(id));
+                }
+            }
+
+            MessageBody::Pin { from, id, pinned, ref signature } => {
+                if let Err(e) = verify_pin(id, pinned, signature, from, &topic) {
+                    warn!(from = %from.fmt_short(), id, error = %e, "ignoring pin");
+                    continue;
+                }
+                // Rooms without a creator have no admins; anyone may pin.
+                if moderator.is_some() && membership.role(from, moderator) != Role::Admin {
+                    debug!(from = %from.fmt_short(), id, "ignoring pin from non-admin");
+                    continue;
+                }
+                if let Some(archive) = &archive
+                    && !replayed
+                {
+                    archive.record(&message);
+                }
+                let name = names
+                    .get(&from)
+                    .cloned()
+                    .unwrap_or_else(|| from.fmt_short().to_string());
+                let _ = ui_tx.send(ChatEvent::Pin { id, name, pinned });
+            }
+
+            MessageBody::Knock { from, name, token } => {
+                // Only the creator of a private room decides who gets in.
+                // Knocking with an invite we issued lets them straight in;
+                // without one, the creator is asked.
+                if private && moderator == Some(my_id) && from != my_id {
+                    match token.map(|token| invites.redeem(token, from)) {
+                        None => {
+                            let _ = ui_tx.send(ChatEvent::Knock { from, name });
+                        }
+                        Some(Ok(())) => {
+                            let _ = commands.send(ChatCommand::Admit(from)).await;
                             let _ = ui_tx
-                                .send(UiMessage::Chat(ChatMessage {
-                                    id,
-                                    sender: name,
-                                    content: text,
-                                }))
-                                .await;
+                                .send(ChatEvent::System(t!("Let {} in with their invite.", name)));
                         }
-                        Err(e) => {
+                        Some(Err(e)) => {
                             let _ = ui_tx
-                                .send(UiMessage::System(format!(
-                                    "Failed to decrypt message from {}: {}",
-                                    name, e
-                                )))
-                                .await;
+                                .send(ChatEvent::System(t!("Turned {} away: {}.", name, e)));
+                        }
+                    }
+                }
+            }
+
+            MessageBody::Admit {
+                from,
+                to,
+                epoch,
+                step,
+                wrapped,
+                nonce,
+            } => {
+                if to != my_id || Some(from) != moderator || key.current().is_some() {
+                    continue;
+                }
+                match unwrap_room_key(&wrapped, &nonce, (epoch, step), &secret_key, from, &topic) {
+                    Ok(room_key) => {
+                        key.advance(room_key);
+                        let _ = ui_tx
+                            .send(ChatEvent::System(
+                                t!("The room's creator let you in.").to_string(),
+                            ));
+                        handed.clear();
+                        request_keys(&sender, &secret_key, &topic).await;
+                    }
+                    Err(e) => {
+                        warn!(error = %e, epoch, step, "could not unwrap room key from admission");
+                        let _ = ui_tx
+                            .send(ChatEvent::System(t!("Could not read the room key: {}", e)));
+                    }
+                }
+            }
+
+            MessageBody::Rekey {
+                from,
+                epoch,
+                step,
+                keys,
+            } => {
+                // Our own rotation, looped back: hand out our new sender key.
+                if from == my_id {
+                    handed.clear();
+                    request_keys(&sender, &secret_key, &topic).await;
+                    continue;
+                }
+                // Only the creator rotates the key, and only forwards.
+                if Some(from) != moderator || key.current().is_none() || !key.is_ahead(epoch) {
+                    continue;
+                }
+                let Some((_, wrapped, nonce)) = keys.iter().find(|(to, _, _)| *to == my_id) else {
+                    let _ = ui_tx
+                        .send(ChatEvent::System(
+                            t!("The room's key was rotated without you; new messages are unreadable.")
+                                .to_string(),
+                        ));
+                    continue;
+                };
+                match unwrap_room_key(wrapped, nonce, (epoch, step), &secret_key, from, &topic) {
+                    Ok(room_key) => {
+                        key.advance(room_key);
+                        let _ = ui_tx
+                            .send(ChatEvent::System(t!(
+                                "The room's creator rotated the key (epoch {}).",
+                                epoch
+                            )));
+                        handed.clear();
+                        request_keys(&sender, &secret_key, &topic).await;
+                    }
+                    Err(e) => {
+                        warn!(error = %e, epoch, step, "could not unwrap rotated room key");
+                        let _ = ui_tx
+                            .send(ChatEvent::System(t!("Could not read the new room key: {}", e)));
+                    }
+                }
+            }
+
+            MessageBody::KeyRequest { from, sent, nonce, signature } => {
+                if from == my_id || sent.abs_diff(invites::now()) > KEY_REQUEST_MAX_AGE {
+                    continue;
+                }
+                if let Err(e) = verify_key_request((sent, nonce), &signature, from, &topic) {
+                    warn!(from = %from.fmt_short(), error = %e, "ignoring key request");
+                    continue;
+                }
+                answered.retain(|_, times| {
+                    times.retain(|at| at.elapsed() < KEY_REQUEST_WINDOW);
+                    !times.is_empty()
+                });
+                let times = answered.entry(from).or_default();
+                if times.len() >= KEY_REQUESTS_PER_WINDOW {
+                    debug!(from = %from.fmt_short(), "too many key requests; not answering");
+                    continue;
+                }
+                times.push_back(Instant::now());
+                hand_over(&sender, &secret_key, &topic, &key, &sender_keys, from).await;
+                handed.insert(from);
+            }
+
+            MessageBody::SenderKey {
+                from,
+                to,
+                room_epoch,
+                room_step,
+                key_id,
+                step,
+                wrapped,
+                nonce,
+            } => {
+                if to != my_id {
+                    continue;
+                }
+                // Without the room key it was bound to we cannot open it;
+                // we ask again once admitted or rekeyed.
+                let Some(room_key) = key.for_message(room_epoch, room_step) else {
+                    debug!(from = %from.fmt_short(), room_epoch, room_step, "sender key for a room key we lack");
+                    continue;
+                };
+                let sender_key =
+                    match unwrap_sender_key(&wrapped, &nonce, (key_id, step), &room_key, &secret_key, from, &topic) {
+                        Ok(sender_key) => sender_key,
+                        Err(e) => {
+                            warn!(from = %from.fmt_short(), key_id, error = %e, "could not unwrap sender key");
+                            continue;
                         }
+                    };
+                debug!(from = %from.fmt_short(), key_id, step, "received sender key");
+                // One from history may be older than a live one could be:
+                // it opens the history sent under it.
+                let inserted = if replayed {
+                    sender_keys.insert_archived(from, sender_key)
+                } else {
+                    sender_keys.insert(from, sender_key)
+                };
+                if inserted {
+                    flush_pending(&mut inbox, &names, &key, &sender_keys, &ui_tx, &filters, webhook.as_ref());
+                }
+                // Return the favour if they do not have ours yet (and may
+                // still be around).
+                if !replayed && handed.insert(from) {
+                    hand_over(&sender, &secret_key, &topic, &key, &sender_keys, from).await;
+                }
+            }
+
+            MessageBody::Membership {
+                from: _,
+                membership: doc,
+                signature,
+            } => {
+                let Some(creator) = moderator else {
+                    continue;
+                };
+                if doc.version <= membership.version {
+                    continue;
+                }
+                if verify_membership(&doc, &signature, creator, &topic).is_err() {
+                    warn!(version = doc.version, "dropping membership document with a bad signature");
+                    continue;
+                }
+                // Announce whose role changed.
+                let mut changed: Vec<EndpointId> = doc.roles.iter().map(|(id, _)| *id).collect();
+                changed.extend(membership.roles.iter().map(|(id, _)| *id));
+                changed.sort();
+                changed.dedup();
+                for id in changed {
+                    let role = doc.role(id, moderator);
+                    if role == membership.role(id, moderator) {
+                        continue;
                     }
+                    let text = if id == my_id {
+                        t!("You are now {}.", role.label())
+                    } else {
+                        let name = names.get(&id).cloned().unwrap_or_else(|| id.fmt_short().to_string());
+                        t!("{} is now {}.", name, role.label())
+                    };
+                    let _ = ui_tx.send(ChatEvent::System(text));
+                }
+                membership = doc;
+                membership_msg = Some(content.clone());
+                let _ = ui_tx.send(ChatEvent::Membership(membership.clone()));
+            }
+
+            MessageBody::Moderation {
+                from: _,
+                action,
+                signature,
+            } => {
+                let Some(creator) = moderator else {
+                    continue;
+                };
+                if verify_moderation(&action, &signature, creator, &topic).is_err() {
+                    warn!(target = %action.target().fmt_short(), "dropping moderation action with a bad signature");
+                    continue;
+                }
+                let target = action.target();
+                if target == creator {
+                    continue;
+                }
+                // Repeats (including our own re-broadcasts) change nothing.
+                let new = match action {
+                    ModAction::Kick { .. } => kicked.insert(target),
+                    ModAction::Mute { .. } => muted.insert(target),
+                };
+                if !new {
+                    continue;
                 }
+                actions.push(content.clone());
+                inbox.pending.retain(|held| held.from != target);
 
-                MessageBody::DeleteMessage { from, id } => {
-                    let authorised = message_owners
-                        .get(&id)
-                        .map(|owner| *owner == from)
-                        .unwrap_or(false);
+                // A kicked peer still holds our sender key: switch to a new
+                // one and hand it to everyone else.
+                if matches!(action, ModAction::Kick { .. })
+                    && target != my_id
+                    && let Some(room) = key.current()
+                {
+                    sender_keys.renew(room.epoch);
+                    handed.clear();
+                    request_keys(&sender, &secret_key, &topic).await;
+                }
 
-                    if authorised {
-                        message_owners.remove(&id);
-                        let _ = ui_tx.send(UiMessage::Delete(id)).await;
+                let name = names
+                    .get(&target)
+                    .cloned()
+                    .unwrap_or_else(|| target.fmt_short().to_string());
+                let notice = match (&action, target == my_id) {
+                    (ModAction::Kick { .. }, true) => {
+                        t!("You were kicked from the room by the moderator.").to_string()
+                    }
+                    (ModAction::Mute { .. }, true) => {
+                        t!("You were muted by the moderator; nobody will see your messages.").to_string()
+                    }
+                    (ModAction::Kick { .. }, false) => {
+                        t!("{} was kicked by the moderator.", name)
                     }
+                    (ModAction::Mute { .. }, false) => {
+                        t!("{} was muted by the moderator.", name)
+                    }
+                };
+                let _ = ui_tx.send(ChatEvent::System(notice));
+            }
+
+            MessageBody::Revoke {
+                from: _,
+                token,
+                signature,
+            } => {
+                let Some(creator) = moderator else {
+                    continue;
+                };
+                if verify_revoke(token, &signature, creator, &topic).is_err() {
+                    warn!("dropping invite revocation with a bad signature");
+                    continue;
+                }
+                if !revoked.insert(token) {
+                    continue;
+                }
+                actions.push(content.clone());
+                // The creator hears how it went from Session::revoke.
+                if creator != my_id {
+                    let _ = ui_tx.send(ChatEvent::System(
+                        t!("The room's creator revoked an invite; knocks with it are turned away.").to_string(),
+                    ));
+                }
+            }
+
+            MessageBody::Interaction {
+                from,
+                to,
+                interaction,
+            } => {
+                // Only the author of the message cares about clicks on it.
+                if to != my_id {
+                    continue;
+                }
+                let name = names
+                    .get(&from)
+                    .cloned()
+                    .unwrap_or_else(|| from.fmt_short().to_string());
+                let choice = interaction.value.unwrap_or(interaction.component_id);
+                let _ = ui_tx
+                    .send(ChatEvent::System(t!(
+                        "{} chose '{}' on your message",
+                        name, choice
+                    )));
+            }
+
+            // Only reached by a chunk nested in a chunked message; never sent.
+            MessageBody::Chunk { .. } => {}
+
+            MessageBody::Archive { from } => {
+                if from != my_id && !replayed && archives.insert(from) {
+                    catch_up(&endpoint, from, topic, &history_tx, &ui_tx);
+                }
+            }
+
+            MessageBody::Ack { from, to, id } => {
+                remember_ack(&mut acked, id);
+                inbox.acked(id);
+                if to == my_id && from != my_id {
+                    let _ = ui_tx.send(ChatEvent::Delivered(id));
+                }
+            }
+
+            MessageBody::Neighbours { from, neighbours, ask } => {
+                if from == my_id || replayed {
+                    continue;
+                }
+                swarm.report(from, neighbours);
+                if ask {
+                    let answer = Message::new(MessageBody::Neighbours {
+                        from: my_id,
+                        neighbours: swarm.links(),
+                        ask: false,
+                    });
+                    broadcast(&sender, answer.to_vec()).await;
+                }
+            }
+
+            MessageBody::Ping { from, to, nonce } => {
+                if to == my_id && from != my_id && !replayed {
+                    let pong = Message::new(MessageBody::Pong { from: my_id, to: from, nonce });
+                    broadcast(&sender, pong.to_vec()).await;
+                }
+            }
+
+            MessageBody::Pong { from, to, nonce } => {
+                if to != my_id {
+                    continue;
+                }
+                if let Some(rtt) = pings.finish(nonce, from) {
+                    let name = names
+                        .get(&from)
+                        .cloned()
+                        .unwrap_or_else(|| from.fmt_short().to_string());
+                    let _ = ui_tx
+                        .send(ChatEvent::System(t!(
+                            "Pong from {}: {} ms through the room.",
+                            name,
+                            rtt.as_millis()
+                        )));
+                }
+            }
+
+            MessageBody::Presence { from, presence } => {
+                if from == my_id {
+                    // Ours, looped back by the send loop.
+                    my_presence = presence;
+                }
+                let _ = ui_tx.send(ChatEvent::Presence { from, presence });
+            }
+
+            MessageBody::Leave { from } => {
+                // Forget them, so coming back counts as joining again.
+                if from != my_id && names.remove(&from).is_some() {
+                    handed.remove(&from);
+                    let _ = ui_tx.send(ChatEvent::Left(from));
                 }
             }
         }
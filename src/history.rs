@@ -0,0 +1,178 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::app::{ChatEvent, ChatMessage};
+
+// ── History ───────────────────────────────────────────────────────────────────
+
+/// Entries a room keeps by default ([display] history).
+pub const DEFAULT_HISTORY: usize = 5000;
+
+/*
+Struct:     -History
+Purpose:    -A room's scrollback: the most recent entries, up to a
+             capacity, with chat messages indexed by ID.
+
+Fields:
+            - VecDeque<Option<ChatEvent>> entries:  Oldest first. None marks
+              a removed entry (a tombstone), left in place so positions do
+              not shift.
+            - usize first:  Sequence number of entries[0]. Entry n of all
+              those ever pushed sits at entries[n - first] until trimmed.
+            - HashMap<u64, usize> index:  Sequence number of each chat
+              message still held, by message ID.
+            - usize capacity:  Most entries kept, tombstones included; the
+              oldest go first.
+
+Details:
+            - Pushing, trimming and finding or removing a chat message by ID
+              are O(1). Iterating skips tombstones.
+            - Tombstones at the front are dropped at once, so those left are
+              between live entries and soon trimmed with them.
+            - A message ID seen twice indexes the later entry.
+*/
+pub struct History {
+    entries: VecDeque<Option<ChatEvent>>,
+    first: usize,
+    index: HashMap<u64, usize>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            first: 0,
+            index: HashMap::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Change the capacity, trimming the oldest entries if it shrank.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.trim();
+    }
+
+    /// How many entries have ever been pushed, trimmed ones included: the
+    /// sequence number the next one gets.
+    pub fn added(&self) -> usize {
+        self.first + self.entries.len()
+    }
+
+    /// Append an entry, dropping the oldest if over capacity.
+    pub fn push(&mut self, msg: ChatEvent) {
+        if let ChatEvent::Chat(chat) = &msg {
+            self.index.insert(chat.id, self.added());
+        }
+        self.entries.push_back(Some(msg));
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.entries.len() > self.capacity || matches!(self.entries.front(), Some(None)) {
+            if let Some(Some(ChatEvent::Chat(chat))) = self.entries.pop_front()
+                && self.index.get(&chat.id) == Some(&self.first)
+            {
+                self.index.remove(&chat.id);
+            }
+            self.first += 1;
+        }
+    }
+
+    pub fn chat(&self, id: u64) -> Option<&ChatMessage> {
+        match self.entries.get(self.index.get(&id)? - self.first)? {
+            Some(ChatEvent::Chat(chat)) => Some(chat),
+            _ => None,
+        }
+    }
+
+    pub fn chat_mut(&mut self, id: u64) -> Option<&mut ChatMessage> {
+        match self.entries.get_mut(self.index.get(&id)? - self.first)? {
+            Some(ChatEvent::Chat(chat)) => Some(chat),
+            _ => None,
+        }
+    }
+
+    /// Remove the chat message with this ID; false if it is not held.
+    pub fn remove(&mut self, id: u64) -> bool {
+        let Some(seq) = self.index.remove(&id) else {
+            return false;
+        };
+        if let Some(entry) = self.entries.get_mut(seq - self.first) {
+            *entry = None;
+        }
+        self.trim();
+        true
+    }
+
+    /// Remove every entry `keep` says no to.
+    pub fn retain(&mut self, mut keep: impl FnMut(&ChatEvent) -> bool) {
+        for (n, entry) in self.entries.iter_mut().enumerate() {
+            if let Some(msg) = entry
+                && !keep(msg)
+            {
+                if let ChatEvent::Chat(chat) = msg
+                    && self.index.get(&chat.id) == Some(&(self.first + n))
+                {
+                    self.index.remove(&chat.id);
+                }
+                *entry = None;
+            }
+        }
+        self.trim();
+    }
+
+    /// Drop every entry. Sequence numbers go on from where they were, so
+    /// `since` still finds only what is pushed after.
+    pub fn clear(&mut self) {
+        self.first = self.added();
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    /// Entries held, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &ChatEvent> {
+        self.entries.iter().flatten()
+    }
+
+    /// Entries pushed at or after sequence number `seq` (see `added`) and
+    /// still held.
+    pub fn since(&self, seq: usize) -> impl Iterator<Item = &ChatEvent> {
+        self.entries.iter().skip(seq.saturating_sub(self.first)).flatten()
+    }
+}
+
+impl<'a> IntoIterator for &'a History {
+    type Item = &'a ChatEvent;
+    type IntoIter = std::iter::Flatten<std::collections::vec_deque::Iter<'a, Option<ChatEvent>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleared_history_counts_on() {
+        // Trimmed before the clear or not, and refilled past capacity or not.
+        for (capacity, before, after) in [(1, 0, 0), (3, 2, 1), (3, 10, 2), (5, 7, 12), (50, 99, 30)] {
+            let mut history = History::new(capacity);
+            for n in 0..before {
+                history.push(ChatEvent::System(format!("before {}", n)));
+            }
+            let cleared = history.added();
+            assert_eq!(cleared, before);
+            history.clear();
+            assert_eq!(history.iter().count(), 0);
+            for n in 0..after {
+                history.push(ChatEvent::System(format!("after {}", n)));
+            }
+            assert_eq!(history.added(), before + after);
+            assert!(history.iter().all(|msg| matches!(msg, ChatEvent::System(text) if text.starts_with("after"))));
+            assert_eq!(history.since(cleared).count(), after.min(capacity));
+        }
+    }
+}
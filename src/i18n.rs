@@ -0,0 +1,449 @@
+use std::{
+    fmt::{Display, Write},
+    sync::OnceLock,
+};
+
+// ── Localization ──────────────────────────────────────────────────────────────
+
+/*
+Enum:       -Locale
+Purpose:    -A language the user interface can be shown in.
+
+Variants:
+            - En:  English, the language the strings are written in.
+            - De:  German.
+
+Details:
+            - Chosen once at startup (see init) and used for every string
+              passed through t!.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+impl Locale {
+    /// The locale for a tag such as "de", "de-AT" or "de_DE.UTF-8" (as in
+    /// LANG), or None for languages we do not have.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let language = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            _ => None,
+        }
+    }
+
+    /// The locale the environment asks for: LC_ALL, then LC_MESSAGES, then
+    /// LANG, like gettext. The first one that is set decides.
+    fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+    }
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/*
+Function:   -init
+Purpose:    -Pick the language of the user interface.
+
+Parameters:
+            - Option<&str> configured:  `locale` from the profile's config,
+              if set.
+
+Details:
+            - The config wins over the environment; a language we do not
+              have falls back to the next choice, and in the end to English.
+            - Only the first call counts. Strings translated before it are
+              in English.
+*/
+pub fn init(configured: Option<&str>) {
+    let locale = configured
+        .and_then(Locale::parse)
+        .or_else(Locale::from_env)
+        .unwrap_or_default();
+    let _ = LOCALE.set(locale);
+}
+
+/// The language chosen by init, English until then.
+pub fn current() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+/// `msgid` in the current language; the English text itself when there is
+/// no translation.
+pub fn tr(msgid: &'static str) -> &'static str {
+    match current() {
+        Locale::En => msgid,
+        Locale::De => german(msgid).unwrap_or(msgid),
+    }
+}
+
+/*
+Function:   -fill
+Purpose:    -Put arguments into a translated template.
+
+Parameters:
+            - &str template:  Text with `{}` for the next argument, or `{0}`,
+              `{1}`, … for a given one, so a translation can reorder them.
+            - &[&dyn Display] args:  The arguments, in the English order.
+
+Details:
+            - Anything else in braces, and a placeholder without an argument,
+              is left as it is.
+*/
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let arg = after.find('}').and_then(|close| {
+            let inner = &after[..close];
+            let index = if inner.is_empty() {
+                next += 1;
+                next - 1
+            } else {
+                inner.parse().ok()?
+            };
+            Some((args.get(index)?, close))
+        });
+        match arg {
+            Some((arg, close)) => {
+                let _ = write!(out, "{}", arg);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Translate a user-facing string. With only the English text this is a
+/// `&'static str`; with arguments it is a String, filled in like format!.
+#[macro_export]
+macro_rules! t {
+    ($msgid:literal) => {
+        $crate::i18n::tr($msgid)
+    };
+    // The block drops the borrowed arguments before the caller goes on, so
+    // t! can be used in a statement that awaits.
+    ($msgid:literal, $($arg:expr),+ $(,)?) => {{
+        let text = $crate::i18n::fill($crate::i18n::tr($msgid), &[$(&$arg as &dyn std::fmt::Display),+]);
+        text
+    }};
+}
+pub use t;
+
+// ── Catalogs ──────────────────────────────────────────────────────────────────
+
+/// German translations, by English text.
+fn german(msgid: &str) -> Option<&'static str> {
+    Some(match msgid {
+        "(could not decrypt: {})" => "(konnte nicht entschlüsselt werden: {})",
+        "A message was deleted." => "Eine Nachricht wurde gelöscht.",
+        "{} left the chat" => "{} hat den Chat verlassen",
+        "A message could not be sent. Select it and press Ctrl+R (NORMAL mode) to retry." => "Eine Nachricht konnte nicht gesendet werden. Wähle sie aus und drücke Strg+R (NORMAL-Modus), um es erneut zu versuchen.",
+        "No one is connected. Messages will be sent when someone is." => "Niemand ist verbunden. Nachrichten werden gesendet, sobald jemand da ist.",
+        "(a message you have not seen)" => "(eine Nachricht, die du nicht gesehen hast)",
+        "{} pinned a message." => "{} hat eine Nachricht angeheftet.",
+        "{} unpinned a message." => "{} hat eine Nachricht losgelöst.",
+        "{} cleared the topic." => "{} hat das Thema entfernt.",
+        "{} set the topic to: {}" => "{} hat das Thema geändert: {}",
+        "No rooms found." => "Keine Räume gefunden.",
+        "{}. {} – {} ({} members, {})" => "{}. {} – {} ({} Mitglieder, {})",
+        "Join one with /directory join <number>." => "Tritt einem mit /directory join <Nummer> bei.",
+        "{} ({}) wants to join – /approve {} or /deny {}" => "{} ({}) möchte beitreten – /approve {} oder /deny {}",
+        "Transcript logging stopped: {}" => "Protokollierung gestoppt: {}",
+        "No one called '{}' is in the room" => "Niemand namens '{}' ist im Raum",
+        "Several members are called '{}'" => "Mehrere Mitglieder heißen '{}'",
+        "No one called '{}' is waiting to join" => "Niemand namens '{}' wartet auf Einlass",
+        "Usage: /tab open <profile> | /tab join <profile> <ticket> | /tab close" => "Aufruf: /tab open <Profil> | /tab join <Profil> <Ticket> | /tab close",
+        "Usage: /export <path>" => "Aufruf: /export <Pfad>",
+        "Usage: /nick [--room] <name>  (/nick --room - forgets this room's name)" => "Aufruf: /nick [--room] <Name>  (/nick --room - vergisst den Namen für diesen Raum)",
+        "Only /nick --room - forgets a name" => "Nur /nick --room - vergisst einen Namen",
+        "Usage: /status online|away|busy" => "Aufruf: /status online|away|busy",
+        "Usage: /notify [mentions-only on|off | keyword add|remove <word> | mute|unmute <name|endpoint ID> | quiet <HH:MM-HH:MM>|off]" => "Aufruf: /notify [mentions-only on|off | keyword add|remove <Wort> | mute|unmute <Name|Endpunkt-ID> | quiet <HH:MM-HH:MM>|off]",
+        "Usage: /ephemeral <duration> <text>  (e.g. 30s, 5m, 2h, 1d)" => "Aufruf: /ephemeral <Dauer> <Text>  (z. B. 30s, 5m, 2h, 1d)",
+        "Usage: {} <name>" => "Aufruf: {} <Name>",
+        "Usage: /role <name> admin|member|readonly" => "Aufruf: /role <Name> admin|member|readonly",
+        "Usage: /block <name|endpoint ID>" => "Aufruf: /block <Name|Endpunkt-ID>",
+        "Usage: /unblock <number|name|endpoint ID>" => "Aufruf: /unblock <Nummer|Name|Endpunkt-ID>",
+        "Usage: /ping <name|endpoint ID>" => "Aufruf: /ping <Name|Endpunkt-ID>",
+        "Usage: /whois <name|short ID>" => "Aufruf: /whois <Name|kurze ID>",
+        "Usage: /ticket [qr]" => "Aufruf: /ticket [qr]",
+        "Usage: /invite [duration] [once]  (e.g. /invite 1h once)" => "Aufruf: /invite [Dauer] [once]  (z. B. /invite 1h once)",
+        "Usage: /directory join <number>" => "Aufruf: /directory join <Nummer>",
+        "Usage: /publish <name> <description>" => "Aufruf: /publish <Name> <Beschreibung>",
+        "Voice messages can be at most {} seconds long" => "Sprachnachrichten dürfen höchstens {} Sekunden lang sein",
+        "Usage: /voice [duration]  (e.g. 15s)" => "Aufruf: /voice [Dauer]  (z. B. 15s)",
+        "Usage: /poll \"Question?\" \"Option 1\" \"Option 2\" ..." => "Aufruf: /poll \"Frage?\" \"Option 1\" \"Option 2\" ...",
+        "A poll can have at most {} options" => "Eine Umfrage kann höchstens {} Optionen haben",
+        "Unknown command: {}" => "Unbekannter Befehl: {}",
+        "Unknown duration unit '{}': use s, m, h or d" => "Unbekannte Zeiteinheit '{}': s, m, h oder d verwenden",
+        "Invalid duration '{}'" => "Ungültige Dauer '{}'",
+        "Duration must be greater than zero" => "Die Dauer muss größer als null sein",
+        "Missing closing quote" => "Schließendes Anführungszeichen fehlt",
+        "invite-only" => "nur auf Einladung",
+        "open" => "offen",
+        "Could not fetch missed messages from the archive at {}." => "Verpasste Nachrichten konnten nicht vom Archiv bei {} geholt werden.",
+        "{} missed messages from the archive could not be read: their keys were never handed to you." => "{} verpasste Nachrichten aus dem Archiv konnten nicht gelesen werden: ihre Schlüssel wurden dir nie übergeben.",
+        "Reconnected." => "Wieder verbunden.",
+        "Lost the room. Reconnecting…" => "Verbindung zum Raum verloren. Verbinde neu…",
+        "Reconnecting…" => "Verbinde neu…",
+        "{} is now known as {}" => "{} heißt jetzt {}",
+        "{} joined the chat" => "{} ist dem Chat beigetreten",
+        "Let {} in with their invite." => "{} wurde mit der Einladung hereingelassen.",
+        "Turned {} away: {}." => "{} wurde abgewiesen: {}.",
+        "The room's creator let you in." => "Der Ersteller des Raums hat dich hereingelassen.",
+        "Could not read the room key: {}" => "Der Raumschlüssel konnte nicht gelesen werden: {}",
+        "The room's key was rotated without you; new messages are unreadable." => "Der Raumschlüssel wurde ohne dich erneuert; neue Nachrichten sind unlesbar.",
+        "The room's creator rotated the key (epoch {})." => "Der Ersteller des Raums hat den Schlüssel erneuert (Epoche {}).",
+        "Could not read the new room key: {}" => "Der neue Raumschlüssel konnte nicht gelesen werden: {}",
+        "You are now {}." => "Du bist jetzt {}.",
+        "{} is now {}." => "{} ist jetzt {}.",
+        "You were kicked from the room by the moderator." => "Du wurdest vom Moderator aus dem Raum geworfen.",
+        "You were muted by the moderator; nobody will see your messages." => "Du wurdest vom Moderator stummgeschaltet; niemand sieht deine Nachrichten.",
+        "{} was kicked by the moderator." => "{} wurde vom Moderator hinausgeworfen.",
+        "{} was muted by the moderator." => "{} wurde vom Moderator stummgeschaltet.",
+        "{} chose '{}' on your message" => "{} hat '{}' bei deiner Nachricht gewählt",
+        "Pong from {}: {} ms through the room." => "Pong von {}: {} ms über den Raum.",
+        "Room passphrase:" => "Passphrase des Raums:",
+        "Paste your ticket and press Enter:" => "Füge dein Ticket ein und drücke Enter:",
+        "This ticket is protected. Passphrase:" => "Dieses Ticket ist geschützt. Passphrase:",
+        "Listening on {}" => "Lausche auf {}",
+        "Master passphrase for backups:" => "Master-Passphrase für Sicherungen:",
+        "Passphrase to protect the ticket with:" => "Passphrase zum Schutz des Tickets:",
+        "Others can join with: join --room {}" => "Andere können so beitreten: join --room {}",
+        "(Give them the passphrase separately.) Or share this ticket:" => "(Gib ihnen die Passphrase getrennt.) Oder teile dieses Ticket:",
+        "Share this ticket with others to join:" => "Teile dieses Ticket, damit andere beitreten können:",
+        "(Give them the passphrase separately.)" => "(Gib ihnen die Passphrase getrennt.)",
+        "(Valid until {}.)" => "(Gültig bis {}.)",
+        "(Works for one person only.)" => "(Gilt nur für eine Person.)",
+        "Daemon running on {}. Use send, tail and rooms to talk to it." => "Daemon läuft auf {}. Mit send, tail und rooms sprichst du mit ihm.",
+        "Serving the room on ws://{}. Press Ctrl+C to stop." => "Stelle den Raum auf ws://{} bereit. Strg+C zum Beenden.",
+        "Bot running. Press Ctrl+C to stop." => "Bot läuft. Strg+C zum Beenden.",
+        "Bridging to {} on {}. Press Ctrl+C to stop." => "Verbinde mit {} auf {}. Strg+C zum Beenden.",
+        "Matrix password:" => "Matrix-Passwort:",
+        "none" => "keine",
+        "Mentions: {}" => "Erwähnungen: {}",
+        "always notify" => "immer benachrichtigen",
+        "like any message" => "wie jede Nachricht",
+        "Other messages: {}" => "Andere Nachrichten: {}",
+        "never" => "nie",
+        "when not looking" => "wenn du nicht hinsiehst",
+        "Keywords: {}" => "Stichwörter: {}",
+        "Muted: {}" => "Stummgeschaltet: {}",
+        "Quiet hours: {}" => "Ruhezeiten: {}",
+        "away" => "abwesend",
+        "busy" => "beschäftigt",
+        "admin" => "Admin",
+        "member" => "Mitglied",
+        "read-only" => "nur lesend",
+        "You joined as {}" => "Du bist als {} beigetreten",
+        "INSERT mode – type & Enter to send. ESC for NORMAL mode." => "INSERT-Modus – tippen & Enter zum Senden. ESC für den NORMAL-Modus.",
+        "This room is invite-only. Waiting for its creator to let you in…" => "Dieser Raum ist nur auf Einladung. Warte, bis der Ersteller dich hereinlässt…",
+        "Using pinned relay {}" => "Verwende das festgelegte Relay {}",
+        "Using relay {} ({} ms)" => "Verwende Relay {} ({} ms)",
+        "Using relay {}" => "Verwende Relay {}",
+        "No relay reachable; direct connections only." => "Kein Relay erreichbar; nur direkte Verbindungen.",
+        "Rotated the room key (epoch {}) for {} member(s)." => "Raumschlüssel erneuert (Epoche {}) für {} Mitglied(er).",
+        "No pong from {} within {} s." => "Kein Pong von {} innerhalb von {} s.",
+        "Only the room's creator can issue invites." => "Nur der Ersteller des Raums kann Einladungen ausstellen.",
+        "Expiring and single-use tickets need an invite-only room (open --private)." => "Ablaufende und einmalige Tickets brauchen einen Raum nur auf Einladung (open --private).",
+        "No directory configured – set server or gossip in the [directory] section of config.toml." => "Kein Verzeichnis eingerichtet – setze server oder gossip im Abschnitt [directory] der config.toml.",
+        "Directory search failed: {}" => "Suche im Verzeichnis fehlgeschlagen: {}",
+        "Only the room's creator can list it in the directory." => "Nur der Ersteller des Raums kann ihn im Verzeichnis eintragen.",
+        "Directory listing failed: {}" => "Eintrag im Verzeichnis fehlgeschlagen: {}",
+        "Could not record: {}" => "Aufnahme nicht möglich: {}",
+        "Could not play: {}" => "Wiedergabe nicht möglich: {}",
+        "direct only" => "nur direkt",
+        "relay" => "Relay",
+        "no relay" => "kein Relay",
+        "Room: {}" => "Raum: {}",
+        "Bound: {}" => "Gebunden: {}",
+        "Relays disabled; direct connections only." => "Relays deaktiviert; nur direkte Verbindungen.",
+        "Relay probe still running…" => "Relay-Messung läuft noch…",
+        "Relay: {} (pinned)" => "Relay: {} (festgelegt)",
+        "Relay: {} (lowest latency)" => "Relay: {} (geringste Latenz)",
+        "Relay: none reachable" => "Relay: keines erreichbar",
+        " (in use)" => " (in Benutzung)",
+        "none known" => "keine bekannt",
+        "Addresses: {}" => "Adressen: {}",
+        "Connection: {}, {} ms" => "Verbindung: {}, {} ms",
+        "relayed" => "über Relay",
+        "direct" => "direkt",
+        "Connection: none open" => "Verbindung: keine offen",
+        "Not heard from in this session." => "In dieser Sitzung noch nichts gehört.",
+        "Key: signed by them 🔒 (verified)" => "Schlüssel: von ihnen signiert 🔒 (verifiziert)",
+        "Key: not signed, shared key 🔓 (not verified)" => "Schlüssel: nicht signiert, gemeinsamer Schlüssel 🔓 (nicht verifiziert)",
+        "Key: no readable message yet" => "Schlüssel: noch keine lesbare Nachricht",
+        "First seen: {}, last seen: {}" => "Zuerst gesehen: {}, zuletzt gesehen: {}",
+        "Chat messages received: {}" => "Empfangene Chatnachrichten: {}",
+        "Found {} on the local network." => "{} im lokalen Netzwerk gefunden.",
+        "Ignoring [keys] in the config: {}" => "[keys] in der Konfiguration wird ignoriert: {}",
+        "Share this ticket with others to join: {}" => "Teile dieses Ticket, damit andere beitreten können: {}",
+        "Today" => "Heute",
+        "Yesterday" => "Gestern",
+        "%A %-d %B %Y" => "%d.%m.%Y",
+        " ✗ not sent" => " ✗ nicht gesendet",
+        " … pending" => " … ausstehend",
+        " p to play " => " p zum Abspielen ",
+        "📌 {} pinned  (z to expand)" => "📌 {} angeheftet  (z zum Aufklappen)",
+        "   +{} more  (/pins)" => "   +{} weitere  (/pins)",
+        "No pinned messages." => "Keine angehefteten Nachrichten.",
+        "Path" => "Weg",
+        "Sent" => "Gesendet",
+        "Received" => "Empfangen",
+        "No open connections." => "Keine offenen Verbindungen.",
+        "you" => "du",
+        "You" => "Du",
+        "└─ no neighbours" => "└─ keine Nachbarn",
+        "└─ no answer yet" => "└─ noch keine Antwort",
+        "Further away" => "Weiter entfernt",
+        "Not seen in the swarm: {}" => "Nicht im Schwarm gesehen: {}",
+        "You can't send messages until the room's creator lets you in." => "Du kannst erst Nachrichten senden, wenn der Ersteller des Raums dich hereinlässt.",
+        "You are read-only in this room; nobody would see your message." => "Du kannst in diesem Raum nur lesen; niemand würde deine Nachricht sehen.",
+        "A filter stopped that message." => "Ein Filter hat diese Nachricht aufgehalten.",
+        "Only the room's creator can kick or mute members." => "Nur der Ersteller des Raums kann Mitglieder hinauswerfen oder stummschalten.",
+        "You cannot moderate yourself." => "Du kannst dich nicht selbst moderieren.",
+        "You can't change the topic in this room." => "Du kannst das Thema in diesem Raum nicht ändern.",
+        "You can't vote in this room." => "Du kannst in diesem Raum nicht abstimmen.",
+        "You cannot block yourself." => "Du kannst dich nicht selbst blockieren.",
+        "Blocked {}; nothing they send will be shown." => "{} blockiert; nichts davon wird mehr angezeigt.",
+        "Unblocked {}." => "{} nicht mehr blockiert.",
+        "Could not save the block list: {}" => "Die Sperrliste konnte nicht gespeichert werden: {}",
+        "You are now {} in this room, also next time." => "Du bist in diesem Raum jetzt {}, auch beim nächsten Mal.",
+        "You are now {} in this room." => "Du bist in diesem Raum jetzt {}.",
+        "Could not save the name: {}" => "Der Name konnte nicht gespeichert werden: {}",
+        "Only mentions and keywords will notify." => "Nur Erwähnungen und Stichwörter benachrichtigen.",
+        "Messages will notify while you are not looking." => "Nachrichten benachrichtigen, wenn du nicht hinsiehst.",
+        "\"{}\" will notify like a mention." => "\"{}\" benachrichtigt wie eine Erwähnung.",
+        "\"{}\" is no longer a keyword." => "\"{}\" ist kein Stichwort mehr.",
+        "{} will not notify." => "{} benachrichtigt nicht mehr.",
+        "{} will notify again." => "{} benachrichtigt wieder.",
+        "No notifications between {}." => "Keine Benachrichtigungen zwischen {}.",
+        " and " => " und ",
+        "Quiet hours are off." => "Ruhezeiten sind aus.",
+        "{} (Could not save it: {})" => "{} (Speichern fehlgeschlagen: {})",
+        "You cannot ping yourself." => "Du kannst dich nicht selbst anpingen.",
+        "Pinging {}… Our {} connection to them measures {} ms." => "Pinge {}… Unsere Verbindung ({}) misst {} ms.",
+        "Pinging {}…" => "Pinge {}…",
+        "  {} → normal mode" => "  {} → Normalmodus",
+        "  {} → insert  |  {} {} {} {} → select  |  {} → delete  |  {} → copy  |  {} → quit" => "  {} → Eingabe  |  {} {} {} {} → Auswahl  |  {} → Löschen  |  {} → Kopieren  |  {} → Beenden",
+        "  ↓{}/s ↑{}/s {} msg/min" => "  ↓{}/s ↑{}/s {} Nachr./min",
+        "Messages" => "Nachrichten",
+        "  ({} unread, {} to jump)" => "  ({} ungelesen, {} zum Springen)",
+        "  ↑ scrolled" => "  ↑ gescrollt",
+        "Connections  (Esc to close)" => "Verbindungen  (Esc zum Schließen)",
+        "Gossip swarm  (Esc to close)" => "Gossip-Schwarm  (Esc zum Schließen)",
+        "Pinned messages  (Esc to close)" => "Angeheftete Nachrichten  (Esc zum Schließen)",
+        "Input" => "Eingabe",
+        "Input (press {} to type)" => "Eingabe ({} zum Tippen)",
+        "  send message    " => "  Nachricht senden    ",
+        "  delete char    " => "  Zeichen löschen    ",
+        "  complete @name    " => "  @Name ergänzen    ",
+        "  normal mode" => "  Normalmodus",
+        "  insert mode    " => "  Eingabemodus    ",
+        "  select msg    " => "  Nachricht wählen    ",
+        "  scroll    " => "  scrollen    ",
+        "  oldest/newest    " => "  älteste/neueste    ",
+        "  first unread/bottom    " => "  erste ungelesene/Ende    ",
+        "  delete selected    " => "  Auswahl löschen    ",
+        "  copy selected    " => "  Auswahl kopieren    ",
+        "  pin/unpin    " => "  anheften/lösen    ",
+        "  fold pins    " => "  Angeheftete einklappen    ",
+        "  delete last msg    " => "  letzte Nachricht löschen    ",
+        "  resend failed msg    " => "  fehlgeschlagene erneut senden    ",
+        "  quit" => "  beenden",
+        "  switch tab    " => "  Tab wechseln    ",
+        "  manage tabs    " => "  Tabs verwalten    ",
+        "  press button / vote on selected msg    " => "  Knopf drücken / abstimmen    ",
+        "  play voice msg" => "  Sprachnachricht abspielen",
+        "Controls" => "Steuerung",
+        "Do not disturb enabled." => "Nicht stören aktiviert.",
+        "Do not disturb disabled." => "Nicht stören deaktiviert.",
+        "Exported {} messages to {}" => "{} Nachrichten nach {} exportiert",
+        "Export failed: {}" => "Export fehlgeschlagen: {}",
+        "Topic: {}" => "Thema: {}",
+        "No topic set. Set one with /topic <text>." => "Kein Thema gesetzt. Setze eines mit /topic <Text>.",
+        "Only the room's creator can let people in." => "Nur der Ersteller des Raums kann Leute hereinlassen.",
+        "Let {} in." => "{} hereingelassen.",
+        "Only the room's creator can turn people away." => "Nur der Ersteller des Raums kann Leute abweisen.",
+        "Only the room's creator can rotate its key." => "Nur der Ersteller des Raums kann den Schlüssel erneuern.",
+        "Only invite-only rooms have a key to rotate; in open rooms the ticket is the key." => "Nur Räume auf Einladung haben einen Schlüssel zum Erneuern; in offenen Räumen ist das Ticket der Schlüssel.",
+        "Only the room's creator can assign roles." => "Nur der Ersteller des Raums kann Rollen vergeben.",
+        "The creator is always an admin." => "Der Ersteller ist immer Admin.",
+        "No one is blocked." => "Niemand ist blockiert.",
+        "Invite ticket{}{}: {}" => "Einladungsticket{}{}: {}",
+        ", valid for {}s" => ", gültig für {}s",
+        ", single use" => ", einmalig",
+        "Searching the directory…" => "Durchsuche das Verzeichnis…",
+        "Bad ticket in listing: {}" => "Ungültiges Ticket im Eintrag: {}",
+        "No such room – run /directory first." => "Kein solcher Raum – zuerst /directory ausführen.",
+        "Listed as {} in the directory." => "Als {} im Verzeichnis eingetragen.",
+        "Recording for {} s…" => "Nehme {} s auf…",
+        "No unread messages." => "Keine ungelesenen Nachrichten.",
+        "Only admins can pin messages." => "Nur Admins können Nachrichten anheften.",
+        "Select a message with j/k first." => "Wähle zuerst eine Nachricht mit j/k aus.",
+        "Copied to the clipboard." => "In die Zwischenablage kopiert.",
+        "Could not copy: {}" => "Kopieren fehlgeschlagen: {}",
+        "No voice messages to play." => "Keine Sprachnachrichten zum Abspielen.",
+        "No failed messages to resend." => "Keine fehlgeschlagenen Nachrichten zum erneuten Senden.",
+        "You can only delete your own messages." => "Du kannst nur deine eigenen Nachrichten löschen.",
+        "No messages to delete." => "Keine Nachrichten zum Löschen.",
+        "Could not open tab: {}" => "Tab konnte nicht geöffnet werden: {}",
+        "Could not join: {}" => "Beitritt fehlgeschlagen: {}",
+        "This is the last tab – use Ctrl+C in NORMAL mode to quit." => "Das ist der letzte Tab – Strg+C im NORMAL-Modus zum Beenden.",
+        "That message is {} characters long; the limit is {}." => "Die Nachricht ist {} Zeichen lang; erlaubt sind {}.",
+        "Someone sent a kind of message this version does not understand ({}); it was skipped. Updating may show it." => "Jemand hat eine Nachrichtenart gesendet, die diese Version nicht versteht ({}); sie wurde übersprungen. Ein Update zeigt sie vielleicht an.",
+        "Fell behind the room; {} events were missed." => "Mit dem Raum ins Hintertreffen geraten; {} Ereignisse sind verloren gegangen.",
+        "Archive passphrase:" => "Passphrase für das Archiv:",
+        "Usage: /wipe [yes] [identity]" => "Verwendung: /wipe [yes] [identity]",
+        "This securely deletes the chat history, drafts, remembered rooms and identity key of this profile, then quits. Type /wipe yes identity to go ahead." => "Das löscht Verlauf, Entwürfe, gemerkte Räume und Identitätsschlüssel dieses Profils sicher und beendet das Programm. Zum Fortfahren /wipe yes identity eingeben.",
+        "This securely deletes the chat history, drafts and remembered rooms of this profile, then quits. Type /wipe yes to go ahead, or /wipe yes identity to delete the identity key too." => "Das löscht Verlauf, Entwürfe und gemerkte Räume dieses Profils sicher und beendet das Programm. Zum Fortfahren /wipe yes eingeben, oder /wipe yes identity, um auch den Identitätsschlüssel zu löschen.",
+        "Wiped {} files of profile '{}'." => "{} Dateien des Profils '{}' gelöscht.",
+        "Only the room's creator can revoke invites." => "Nur der Ersteller des Raums kann Einladungen widerrufen.",
+        "That is not an invite ticket for this room." => "Das ist kein Einladungsticket für diesen Raum.",
+        "Usage: /revoke <ticket>" => "Aufruf: /revoke <Ticket>",
+        "Revoked the invite; the key rotation that follows leaves out the {} member(s) who joined with it." => "Einladung widerrufen; der folgende Schlüsselwechsel schließt die {} Mitglied(er) aus, die damit beigetreten sind.",
+        "The room's creator revoked an invite; knocks with it are turned away." => "Der Ersteller des Raums hat eine Einladung widerrufen; wer damit anklopft, wird abgewiesen.",
+        "a room" => "ein Raum",
+        " created by {}" => " von {} erstellt",
+        "just now" => "gerade eben",
+        "1 minute ago" => "vor 1 Minute",
+        "{} minutes ago" => "vor {} Minuten",
+        "1 hour ago" => "vor 1 Stunde",
+        "{} hours ago" => "vor {} Stunden",
+        "1 day ago" => "vor 1 Tag",
+        "{} days ago" => "vor {} Tagen",
+        "Joining {} — continue? [y/N]" => "Beitritt zu {} – fortfahren? [y/N]",
+        "Not joining." => "Kein Beitritt.",
+        "No directory peers configured – set peers or server in the [directory] section of config.toml." => "Keine Verzeichnis-Peers eingerichtet – setze peers oder server im Abschnitt [directory] der config.toml.",
+        "Looking for rooms…" => "Suche nach Räumen…",
+        "Number of the room to join (Enter to quit):" => "Nummer des Raums zum Beitreten (Enter zum Beenden):",
+        "Tabs are not available in plain mode." => "Tabs gibt es im einfachen Modus nicht.",
+        " (unverified)" => " (nicht verifiziert)",
+        " (unencrypted)" => " (unverschlüsselt)",
+        " [unencrypted]" => " [unverschlüsselt]",
+        " (disappearing)" => " (verschwindet)",
+        "(voice message, {})" => "(Sprachnachricht, {})",
+        "Poll: {}" => "Umfrage: {}",
+        _ => return None,
+    })
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+#[cfg(not(target_family = "wasm"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(target_family = "wasm")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use iroh::EndpointId;
+
+// ── Invites ───────────────────────────────────────────────────────────────────
+
+/// Seconds since the Unix epoch, the unit of `Ticket::expires`.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// One issued invite: when it stops working, for single-use invites who
+/// used it, who got in with it, and whether it was revoked.
+struct Invite {
+    expires: Option<u64>,
+    once: bool,
+    used_by: Option<EndpointId>,
+    admitted: Vec<EndpointId>,
+    revoked: bool,
+}
+
+/*
+Struct:     -Invites
+Purpose:    -Tokens the creator of a private room has put in tickets, and
+             what each one still allows.
+
+Details:
+            - Shared between the session, which issues tokens, and the
+              receive loop, which redeems them when a knock presents one.
+            - The expiry that counts is the one recorded here, not the copy in
+              the ticket, so editing a ticket cannot extend it.
+            - A single-use token is bound to the first endpoint that knocks
+              with it; that endpoint may knock again (it does whenever we come
+              back online), anyone else is turned away.
+            - A revoked token stays on record, so knocks with it are told
+              why they are turned away.
+            - Kept in memory only: invites end when the creator's session does.
+*/
+#[derive(Clone, Default)]
+pub struct Invites(Arc<Mutex<HashMap<u64, Invite>>>);
+
+impl Invites {
+    /// Record a new invite and return its token.
+    pub fn issue(&self, expires: Option<u64>, once: bool) -> u64 {
+        let token = rand::random();
+        let invite = Invite {
+            expires,
+            once,
+            used_by: None,
+            admitted: Vec::new(),
+            revoked: false,
+        };
+        self.0.lock().expect("invites lock poisoned").insert(token, invite);
+        token
+    }
+
+    /// Check a token presented by `from` and use it up if single-use.
+    pub fn redeem(&self, token: u64, from: EndpointId) -> Result<()> {
+        let mut invites = self.0.lock().expect("invites lock poisoned");
+        let Some(invite) = invites.get_mut(&token) else {
+            bail!("their ticket is not one we issued (or we restarted since)");
+        };
+        if invite.revoked {
+            bail!("their ticket was revoked");
+        }
+        if invite.expires.is_some_and(|expires| now() >= expires) {
+            bail!("their ticket has expired");
+        }
+        if invite.once {
+            match invite.used_by {
+                Some(user) if user != from => bail!("their single-use ticket was already used"),
+                _ => invite.used_by = Some(from),
+            }
+        }
+        if !invite.admitted.contains(&from) {
+            invite.admitted.push(from);
+        }
+        Ok(())
+    }
+
+    /// Stop accepting a token; returns the endpoints that got in with it.
+    pub fn revoke(&self, token: u64) -> Result<Vec<EndpointId>> {
+        let mut invites = self.0.lock().expect("invites lock poisoned");
+        let Some(invite) = invites.get_mut(&token) else {
+            bail!("that ticket is not one we issued (or we restarted since)");
+        };
+        if invite.revoked {
+            bail!("that ticket was already revoked");
+        }
+        invite.revoked = true;
+        Ok(std::mem::take(&mut invite.admitted))
+    }
+}
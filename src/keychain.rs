@@ -0,0 +1,108 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+
+// ── OS keychain ───────────────────────────────────────────────────────────────
+
+/// Service our secrets are filed under, as the keychain shows it.
+const SERVICE: &str = "p2p-chat";
+
+/*
+Function:   -get
+Purpose:    -Read a secret from the platform's secret store.
+
+Parameters:
+            - &str account:  Which secret, e.g. "work/identity".
+
+Details:
+            - Returns None if there is no such secret, and an error if the
+              store's tool cannot be run.
+            - Goes through the store's own command-line tool: `security` on
+              macOS (the login keychain), `secret-tool` elsewhere (the Secret
+              Service, i.e. GNOME Keyring or KWallet). Secrets are passed on
+              stdin and stdout, never as arguments others could see.
+*/
+pub fn get(account: &str) -> Result<Option<String>> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+            .stderr(Stdio::null())
+            .output()
+    } else {
+        Command::new("secret-tool")
+            .args(["lookup", "service", SERVICE, "account", account])
+            .stderr(Stdio::null())
+            .output()
+    };
+    let output = output.context(unavailable())?;
+    if !output.status.success() {
+        // A store the user would not unlock looks the same: either way
+        // there is no secret to use.
+        return Ok(None);
+    }
+    let secret = String::from_utf8(output.stdout).context("the keychain returned a secret that is not UTF-8")?;
+    Ok(Some(secret.trim_end_matches('\n').to_string()))
+}
+
+/// Store a secret, replacing any already filed under `account`.
+pub fn set(account: &str, secret: &str) -> Result<()> {
+    let label = format!("{} {}", SERVICE, account);
+    let (mut command, input) = if cfg!(target_os = "macos") {
+        // A trailing -w with no value makes it ask for the secret (twice)
+        // on stdin, keeping it out of the process list; the other values
+        // are separate arguments, so nothing in them is interpreted.
+        if secret.contains(['\n', '\r']) {
+            bail!("the keychain cannot hold secrets with line breaks");
+        }
+        let mut command = Command::new("security");
+        command.args(["add-generic-password", "-U", "-l", &label, "-s", SERVICE, "-a", account, "-w"]);
+        (command, format!("{}\n{}\n", secret, secret))
+    } else {
+        let mut command = Command::new("secret-tool");
+        command.args(["store", "--label", &label, "service", SERVICE, "account", account]);
+        (command, secret.to_string())
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(unavailable())?;
+    child.stdin.take().expect("piped").write_all(input.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("the keychain refused the secret: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Remove a secret; one that is not there is not an error.
+pub fn delete(account: &str) -> Result<()> {
+    let status = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(["delete-generic-password", "-s", SERVICE, "-a", account])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    } else {
+        Command::new("secret-tool")
+            .args(["clear", "service", SERVICE, "account", account])
+            .stderr(Stdio::null())
+            .status()
+    };
+    status.context(unavailable())?;
+    Ok(())
+}
+
+fn unavailable() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "could not run `security` to reach the keychain"
+    } else if cfg!(windows) {
+        "the OS keychain is not supported on Windows yet"
+    } else {
+        "could not run `secret-tool` to reach the keychain (install libsecret-tools)"
+    }
+}
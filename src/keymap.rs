@@ -0,0 +1,385 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::app::Mode;
+
+// ── Key bindings ──────────────────────────────────────────────────────────────
+
+/*
+Enum:       -Action
+Purpose:    -What a key can be bound to, by its name in the [keys] table.
+
+Variants:
+            - Normal:  INSERT mode: close an open panel, else go to NORMAL mode.
+            - Complete:  INSERT mode: complete the @name being typed.
+            - Insert:  Go back to typing.
+            - Back:  Close an open panel, else clear the selection.
+            - ScrollUp / ScrollDown:  Scroll the history by ten lines.
+            - HalfPageUp / HalfPageDown:  Scroll the history by half the
+              messages pane.
+            - SelectOlder / SelectNewer:  Move the selection by one message.
+            - SelectOldest / SelectNewest:  Select the first or last message.
+            - FirstUnread:  Select the first message that arrived while we
+              were away, scrolling back to it.
+            - Bottom:  Back to the newest messages, clearing the selection.
+            - Pin:  Pin or unpin the selected message for everyone.
+            - FoldPins:  Fold or unfold the pinned strip.
+            - Copy:  Copy the selected message to the clipboard.
+            - Play:  Play the selected (or latest) voice message.
+            - NextTab / PrevTab:  Switch tabs.
+            - Quit:  Leave every room and exit.
+            - Retry:  Send a message that failed again.
+            - Delete:  Delete the selected message everywhere.
+            - DeleteLast:  Delete our latest message everywhere (admins: the
+              selected one).
+
+Details:
+            - Every action but Normal and Complete is a NORMAL mode one.
+              Enter, Backspace and the digits (which press buttons, vote or
+              count) are not remappable.
+            - A count before Select*, Scroll* and HalfPage* repeats them.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Normal,
+    Complete,
+    Insert,
+    Back,
+    ScrollUp,
+    ScrollDown,
+    HalfPageUp,
+    HalfPageDown,
+    SelectOlder,
+    SelectNewer,
+    SelectOldest,
+    SelectNewest,
+    FirstUnread,
+    Bottom,
+    Pin,
+    FoldPins,
+    Copy,
+    Play,
+    NextTab,
+    PrevTab,
+    Quit,
+    Retry,
+    Delete,
+    DeleteLast,
+}
+
+/// Every action with its name in the config and its default keys.
+const ACTIONS: &[(Action, &str, &[&str])] = &[
+    (Action::Normal, "normal", &["Esc"]),
+    (Action::Complete, "complete", &["Tab"]),
+    (Action::Insert, "insert", &["i"]),
+    (Action::Back, "back", &["Esc"]),
+    (Action::ScrollUp, "scroll_up", &["PageUp"]),
+    (Action::ScrollDown, "scroll_down", &["PageDown"]),
+    (Action::HalfPageUp, "half_page_up", &["ctrl+u"]),
+    (Action::HalfPageDown, "half_page_down", &["ctrl+d"]),
+    (Action::SelectOlder, "select_older", &["k", "Up"]),
+    (Action::SelectNewer, "select_newer", &["j", "Down"]),
+    (Action::SelectOldest, "select_oldest", &["g g"]),
+    (Action::SelectNewest, "select_newest", &["G"]),
+    (Action::FirstUnread, "first_unread", &["u"]),
+    (Action::Bottom, "bottom", &["b"]),
+    (Action::Pin, "pin", &["P"]),
+    (Action::FoldPins, "fold_pins", &["z"]),
+    (Action::Copy, "copy", &["y"]),
+    (Action::Play, "play", &["p"]),
+    (Action::NextTab, "next_tab", &["]"]),
+    (Action::PrevTab, "prev_tab", &["["]),
+    (Action::Quit, "quit", &["ctrl+c"]),
+    (Action::Retry, "retry", &["ctrl+r"]),
+    (Action::Delete, "delete", &["d d"]),
+    (Action::DeleteLast, "delete_last", &["D"]),
+];
+
+impl Action {
+    fn mode(self) -> Mode {
+        match self {
+            Action::Normal | Action::Complete => Mode::Insert,
+            _ => Mode::Normal,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(action, ..)| *action == self)
+            .map(|(_, name, _)| *name)
+            .expect("every action is listed")
+    }
+}
+
+/*
+Struct:     -Chord
+Purpose:    -One key press with its modifiers, as in "ctrl+d", "G" or
+             "PageUp".
+
+Details:
+            - Shift is implied by the character ("G", "?"), so it is
+              ignored for characters; other keys take "shift+".
+            - Names of other keys are not case-sensitive: Up, Down, Left,
+              Right, PageUp, PageDown, Home, End, Enter, Esc, Tab, BackTab,
+              Backspace, Delete, Insert, Space and F1 to F12.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Chord {
+    pub fn of(key: &KeyEvent) -> Self {
+        let mut modifiers = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(_) = key.code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code: key.code, modifiers }
+    }
+
+    /// A character typed without Ctrl or Alt, which INSERT mode needs.
+    fn is_typing(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_)) && self.modifiers.is_empty()
+    }
+}
+
+impl FromStr for Chord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s.trim();
+        while let Some((modifier, tail)) = rest.split_once('+').filter(|(m, tail)| !m.is_empty() && !tail.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("unknown modifier {:?} in {:?}", modifier, s),
+            };
+            rest = tail;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            // Ctrl+C arrives as lowercase c whatever the binding says.
+            (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char(c.to_ascii_lowercase()),
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "enter" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => bail!("unknown key {:?}", rest),
+                },
+            },
+        };
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, label) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(label)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) if self.modifiers.is_empty() => write!(f, "{}", c),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::Up => f.write_str("↑"),
+            KeyCode::Down => f.write_str("↓"),
+            KeyCode::Left => f.write_str("←"),
+            KeyCode::Right => f.write_str("→"),
+            KeyCode::PageUp => f.write_str("PgUp"),
+            KeyCode::PageDown => f.write_str("PgDn"),
+            KeyCode::Esc => f.write_str("ESC"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// What a key press means, given the one before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Action(Action),
+    /// The first key of a two-key binding; wait for the second.
+    Pending(Chord),
+    Unbound,
+}
+
+/*
+Struct:     -Keymap
+Purpose:    -The TUI's key bindings: the defaults in ACTIONS, with any the
+             profile's [keys] table changes.
+
+Fields:
+            - Vec<(Vec<Chord>, Action)> bindings:  Each binding, one or two
+              key presses long.
+
+Details:
+            - A [keys] entry replaces that action's default keys, e.g.
+              quit = ["ctrl+q"] or select_oldest = ["g g", "Home"]; an empty
+              list unbinds it.
+            - Refuses, with an error naming them, two actions on the same
+              keys in one mode, a key that also starts a two-key binding, and
+              plain characters in INSERT mode, where they are typed.
+*/
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Vec<Chord>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&BTreeMap::new()).expect("the default keys are valid")
+    }
+}
+
+impl Keymap {
+    /// Build the keymap from a profile's [keys] table (action → keys).
+    pub fn from_config(keys: &BTreeMap<String, Vec<String>>) -> Result<Self> {
+        for name in keys.keys() {
+            if !ACTIONS.iter().any(|(_, known, _)| known == name) {
+                let known: Vec<&str> = ACTIONS.iter().map(|(_, name, _)| *name).collect();
+                bail!("unknown action {:?} in [keys] (known: {})", name, known.join(", "));
+            }
+        }
+        let mut bindings = Vec::new();
+        for (action, name, defaults) in ACTIONS {
+            let chosen: Vec<&str> = match keys.get(*name) {
+                Some(keys) => keys.iter().map(String::as_str).collect(),
+                None => defaults.to_vec(),
+            };
+            for keys in chosen {
+                let chords = keys
+                    .split_whitespace()
+                    .map(Chord::from_str)
+                    .collect::<Result<Vec<_>>>()
+                    .map_err(|e| anyhow!("{}: {}", name, e))?;
+                match chords.len() {
+                    1 | 2 => {}
+                    0 => bail!("{}: empty key", name),
+                    _ => bail!("{}: {:?} is more than two keys", name, keys),
+                }
+                if action.mode() == Mode::Insert && chords[0].is_typing() {
+                    bail!("{}: {:?} would stop you typing it", name, keys);
+                }
+                bindings.push((chords, *action));
+            }
+        }
+        let keymap = Self { bindings };
+        keymap.check()?;
+        Ok(keymap)
+    }
+
+    fn check(&self) -> Result<()> {
+        let mut seen: HashMap<(bool, &[Chord]), Action> = HashMap::new();
+        for (chords, action) in &self.bindings {
+            let insert = action.mode() == Mode::Insert;
+            if let Some(other) = seen.insert((insert, chords), *action)
+                && other != *action
+            {
+                bail!("{} is bound to both {} and {}", label(chords), other.name(), action.name());
+            }
+            let prefix = self
+                .bindings
+                .iter()
+                .find(|(long, other)| long.len() == 2 && long[0] == chords[0] && chords.len() == 1 && other.mode() == action.mode());
+            if let Some((long, other)) = prefix {
+                bail!(
+                    "{} ({}) starts {} ({}), which could then never be typed",
+                    label(chords),
+                    action.name(),
+                    label(long),
+                    other.name()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// What `key` does in `mode`, after `pending` if the last key started a
+    /// two-key binding.
+    pub fn lookup(&self, mode: &Mode, pending: Option<Chord>, key: &KeyEvent) -> Lookup {
+        let chord = Chord::of(key);
+        let in_mode = |action: &Action| action.mode() == *mode;
+        if let Some(first) = pending
+            && let Some((_, action)) = self
+                .bindings
+                .iter()
+                .find(|(chords, action)| in_mode(action) && chords[..] == [first, chord])
+        {
+            return Lookup::Action(*action);
+        }
+        for (chords, action) in &self.bindings {
+            if !in_mode(action) || chords[0] != chord {
+                continue;
+            }
+            return match chords.len() {
+                1 => Lookup::Action(*action),
+                _ => Lookup::Pending(chord),
+            };
+        }
+        Lookup::Unbound
+    }
+
+    /// The keys bound to `action`, for the help panel, e.g. "k/↑".
+    pub fn keys(&self, action: Action) -> String {
+        let keys: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(chords, _)| label(chords))
+            .collect();
+        if keys.is_empty() {
+            "unbound".to_string()
+        } else {
+            keys.join("/")
+        }
+    }
+
+    /// The actions bound differently in `other`, by name, with their keys
+    /// here and there, e.g. ("quit", "Ctrl+C", "Ctrl+Q").
+    pub fn changes(&self, other: &Keymap) -> Vec<(&'static str, String, String)> {
+        ACTIONS
+            .iter()
+            .map(|(action, name, _)| (*name, self.keys(*action), other.keys(*action)))
+            .filter(|(_, here, there)| here != there)
+            .collect()
+    }
+}
+
+fn label(chords: &[Chord]) -> String {
+    chords.iter().map(Chord::to_string).collect()
+}
@@ -0,0 +1,102 @@
+//! An end-to-end encrypted group chat over iroh gossip. The binary is one
+//! front end; apps embedding the client start a Session with
+//! ChatClientBuilder and talk to it through its ChatCommands and ChatEvents.
+//! For wasm32 only the protocol, crypto and ticket handling are built, for
+//! browser clients (see examples/browser).
+
+// Built everywhere, the browser included.
+pub mod crypto;
+pub mod i18n;
+pub mod invites;
+pub mod protocol;
+
+// Everything else needs an OS: sockets, files, the terminal.
+#[cfg(not(target_family = "wasm"))]
+pub mod app;
+#[cfg(not(target_family = "wasm"))]
+pub mod archive;
+#[cfg(not(target_family = "wasm"))]
+pub mod backup;
+#[cfg(not(target_family = "wasm"))]
+pub mod bidi;
+#[cfg(not(target_family = "wasm"))]
+pub mod bot;
+#[cfg(not(target_family = "wasm"))]
+pub mod bridge;
+#[cfg(not(target_family = "wasm"))]
+pub mod bundle;
+#[cfg(not(target_family = "wasm"))]
+pub mod bus;
+#[cfg(not(target_family = "wasm"))]
+pub mod client;
+#[cfg(not(target_family = "wasm"))]
+pub mod commands;
+#[cfg(not(target_family = "wasm"))]
+pub mod config;
+#[cfg(not(target_family = "wasm"))]
+pub mod conformance;
+#[cfg(not(target_family = "wasm"))]
+pub mod daemon;
+#[cfg(not(target_family = "wasm"))]
+pub mod directory;
+#[cfg(not(target_family = "wasm"))]
+pub mod drafts;
+#[cfg(all(feature = "ffi", not(target_family = "wasm")))]
+pub mod ffi;
+#[cfg(not(target_family = "wasm"))]
+pub mod filters;
+#[cfg(not(target_family = "wasm"))]
+pub mod gateway;
+#[cfg(not(target_family = "wasm"))]
+pub mod gossip;
+#[cfg(not(target_family = "wasm"))]
+pub mod history;
+#[cfg(not(target_family = "wasm"))]
+pub mod keychain;
+#[cfg(not(target_family = "wasm"))]
+pub mod keymap;
+#[cfg(not(target_family = "wasm"))]
+pub mod names;
+#[cfg(not(target_family = "wasm"))]
+pub mod netstats;
+#[cfg(not(target_family = "wasm"))]
+pub mod notify;
+#[cfg(not(target_family = "wasm"))]
+pub mod pack;
+#[cfg(not(target_family = "wasm"))]
+pub mod plain;
+#[cfg(not(target_family = "wasm"))]
+pub mod profile;
+#[cfg(test)]
+mod properties;
+#[cfg(not(target_family = "wasm"))]
+pub mod qr;
+#[cfg(not(target_family = "wasm"))]
+pub mod recent;
+#[cfg(not(target_family = "wasm"))]
+pub mod roomkeys;
+#[cfg(not(target_family = "wasm"))]
+pub mod session;
+#[cfg(test)]
+mod testing;
+#[cfg(not(target_family = "wasm"))]
+pub mod transcript;
+#[cfg(not(target_family = "wasm"))]
+pub mod tui;
+#[cfg(not(target_family = "wasm"))]
+pub mod voice;
+#[cfg(not(target_family = "wasm"))]
+pub mod webhook;
+#[cfg(not(target_family = "wasm"))]
+pub mod wipe;
+#[cfg(not(target_family = "wasm"))]
+pub mod wrap;
+
+#[cfg(not(target_family = "wasm"))]
+pub use app::ChatEvent;
+#[cfg(not(target_family = "wasm"))]
+pub use bus::{ChatCommand, Subscription};
+#[cfg(not(target_family = "wasm"))]
+pub use client::{ChatClientBuilder, CryptoMode, Discovery, HistoryBackend};
+#[cfg(not(target_family = "wasm"))]
+pub use session::Session;
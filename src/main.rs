@@ -1,150 +1,1082 @@
-mod app;
-mod crypto;
-mod gossip;
-mod protocol;
-mod tui;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    fs::OpenOptions,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
 
-use std::str::FromStr;
-
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use clap::Parser;
-use iroh::{protocol::Router, Endpoint};
-use iroh_gossip::net::Gossip;
-use tokio::sync::mpsc;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use iroh::{Endpoint, EndpointId, RelayUrl};
+use iroh_gossip::proto::TopicId;
+use tracing::{info, Level};
+use tracing_subscriber::{filter::Targets, prelude::*};
 
-use app::UiMessage;
-use crypto::encrypt_message;
-use protocol::{Message, MessageBody, Ticket};
+use p2p_chat::archive::{Archive, Compaction, StoreKey};
+use p2p_chat::bundle::Bundle;
+use p2p_chat::bridge::{self, IrcOptions, MatrixOptions};
+use p2p_chat::i18n::{self, t};
+use p2p_chat::netstats::human_bytes;
+use p2p_chat::pack::Pack;
+use p2p_chat::profile::{write_private, Profile};
+use p2p_chat::protocol::Ticket;
+use p2p_chat::recent::RecentRooms;
+use p2p_chat::session::Entry;
+use p2p_chat::transcript::TranscriptLog;
+use p2p_chat::{
+    backup, bot, commands, config, conformance, daemon, directory, gateway, keychain, plain, qr, tui, wipe, ChatClientBuilder,
+    HistoryBackend,
+};
 
 #[derive(Parser, Debug)]
 struct Args {
     #[clap(short, long)]
     name: Option<String>,
+    /// UDP port to listen on, e.g. one opened in a firewall. 0 picks a
+    /// random free port.
     #[clap(short, long, default_value = "0")]
     bind_port: u16,
+    /// Local address to listen on (one interface only). Listens on every
+    /// interface if not given.
+    #[clap(long)]
+    bind_addr: Option<IpAddr>,
+    /// Relay server to use instead of the defaults, e.g. a self-hosted one.
+    /// Repeat for several.
+    #[clap(long = "relay-url", value_name = "URL")]
+    relay_urls: Vec<RelayUrl>,
+    /// Never use a relay; connect to peers directly only.
+    #[clap(long, conflicts_with = "relay_urls")]
+    no_relay: bool,
+    /// Profile to run as. Each profile has its own identity, config, store
+    /// and rooms. Defaults to the one chosen with `profile use`, else
+    /// "default".
+    #[clap(long)]
+    profile: Option<String>,
+    /// Append every chat message to this file as it arrives (.json/.jsonl for
+    /// JSON Lines, anything else for plain text).
+    #[clap(long)]
+    log_transcript: Option<PathBuf>,
+    /// Write diagnostics (connections, decryption failures, …) to this file.
+    /// Nothing is logged without it, so the TUI stays clean.
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+    /// How much goes into --log-file: error, warn, info, debug or trace.
+    #[clap(long, default_value = "info", requires = "log_file")]
+    log_level: Level,
+    /// Keep every message of the room on disk and hand them to members who
+    /// were away. Meant for an always-on peer, e.g. with daemon.
+    #[clap(long)]
+    archive: bool,
+    /// Print the room line by line instead of drawing the full-screen UI:
+    /// no alternate screen, raw mode or colours. For screen readers, braille
+    /// displays and dumb terminals.
+    #[clap(long)]
+    plain: bool,
     #[clap(subcommand)]
     command: Command,
 }
 
 #[derive(Parser, Debug)]
 enum Command {
-    Open,
-    Join
+    Open {
+        /// Invite-only: approve each member before they can read the room.
+        #[clap(long)]
+        private: bool,
+        /// Encrypt the printed ticket with a passphrase, to share separately.
+        #[clap(long)]
+        protect: bool,
+        /// Also print the ticket as a QR code, for joining from another device.
+        #[clap(long)]
+        qr: bool,
+        /// Make the printed ticket stop working after this long (e.g. 30m, 1d).
+        #[clap(long, requires = "private", value_parser = commands::parse_duration)]
+        expires: Option<u64>,
+        /// Make the printed ticket work for one person only.
+        #[clap(long, requires = "private")]
+        once: bool,
+        /// Open a named room that others join with the name and a passphrase,
+        /// no ticket needed.
+        #[clap(long, conflicts_with_all = ["private", "expires", "once"])]
+        room: Option<String>,
+        /// Passphrase for --room; asked for if not given.
+        #[clap(long, requires = "room")]
+        passphrase: Option<String>,
+        /// Name the room in its tickets, so people see what they are joining.
+        #[clap(long, conflicts_with = "room")]
+        title: Option<String>,
+    },
+    Join {
+        /// Join a named room instead of pasting a ticket.
+        #[clap(long)]
+        room: Option<String>,
+        /// Join the Nth room of `profile rooms` again, with its saved ticket.
+        #[clap(long, value_name = "N", conflicts_with = "room")]
+        recent: Option<usize>,
+        /// Passphrase for --room; asked for if not given.
+        #[clap(long, requires = "room")]
+        passphrase: Option<String>,
+    },
+    /// List the rooms announced on the serverless directory and join one.
+    Browse {
+        /// Only rooms whose name or description contain this.
+        query: Vec<String>,
+    },
+    /// Manage profiles.
+    Profile {
+        #[clap(subcommand)]
+        action: ProfileAction,
+    },
+    /// Back up or restore the profile via a trusted peer.
+    Backup {
+        #[clap(subcommand)]
+        action: BackupAction,
+    },
+    /// Move a room to another machine.
+    Room {
+        #[clap(subcommand)]
+        action: RoomAction,
+    },
+    /// Share the profile's name colours and key bindings, or use someone
+    /// else's.
+    Pack {
+        #[clap(subcommand)]
+        action: PackAction,
+    },
+    /// Tidy the profile's message store.
+    Store {
+        #[clap(subcommand)]
+        action: StoreAction,
+    },
+    /// Securely delete the profile's chat history, drafts and remembered
+    /// rooms, for a shared or at-risk machine. Stop its daemon first.
+    Wipe {
+        /// Delete the identity key too.
+        #[clap(long)]
+        identity: bool,
+        /// Do not ask before deleting.
+        #[clap(long)]
+        yes: bool,
+    },
+    /// Check a directory of protocol test vectors (see conformance/).
+    Conformance { dir: PathBuf },
+    /// Join a room and expose it to local apps over a WebSocket + JSON API.
+    Serve {
+        /// Address to listen on. Anyone who can connect speaks for this peer.
+        #[clap(long, default_value = "127.0.0.1:7878")]
+        listen: SocketAddr,
+        /// Join a named room instead of pasting a ticket.
+        #[clap(long)]
+        room: Option<String>,
+        /// Passphrase for --room; asked for if not given.
+        #[clap(long, requires = "room")]
+        passphrase: Option<String>,
+    },
+    /// Join a room and keep it open in the background, for send, tail and
+    /// rooms to talk to.
+    Daemon {
+        /// Join a named room instead of pasting a ticket.
+        #[clap(long)]
+        room: Option<String>,
+        /// Passphrase for --room; asked for if not given.
+        #[clap(long, requires = "room")]
+        passphrase: Option<String>,
+    },
+    /// Send a message through the running daemon.
+    Send {
+        #[clap(required = true)]
+        text: Vec<String>,
+    },
+    /// Follow the running daemon's room.
+    Tail {
+        /// Print raw JSON events, one per line.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show the rooms the running daemon is in.
+    Rooms,
+    /// Join a room and answer or announce as set in the config's [bot]
+    /// table.
+    Bot {
+        /// Join a named room instead of pasting a ticket.
+        #[clap(long)]
+        room: Option<String>,
+        /// Passphrase for --room; asked for if not given.
+        #[clap(long, requires = "room")]
+        passphrase: Option<String>,
+    },
+    /// Join a room and mirror it to another chat network.
+    Bridge {
+        #[clap(subcommand)]
+        target: BridgeTarget,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum BridgeTarget {
+    /// Relay messages, joins and parts between the room and an IRC channel.
+    Irc {
+        /// IRC server host name.
+        #[clap(long)]
+        server: String,
+        /// IRC server port; defaults to 6697, or 6667 with --no-tls.
+        #[clap(long)]
+        port: Option<u16>,
+        /// Connect to IRC without TLS.
+        #[clap(long)]
+        no_tls: bool,
+        /// Channel to mirror, e.g. "#p2p".
+        #[clap(long)]
+        channel: String,
+        /// The bridge's nick on IRC.
+        #[clap(long, default_value = "p2p-bridge")]
+        nick: String,
+        /// Join a named room instead of pasting a ticket.
+        #[clap(long)]
+        room: Option<String>,
+        /// Passphrase for --room; asked for if not given.
+        #[clap(long, requires = "room")]
+        passphrase: Option<String>,
+    },
+    /// Relay messages, deletes and edits between the room and a Matrix room.
+    Matrix {
+        /// Homeserver URL or server name, e.g. "matrix.org".
+        #[clap(long)]
+        homeserver: String,
+        /// Matrix user the bridge logs in as.
+        #[clap(long)]
+        user: String,
+        /// Password for --user; asked for if not given.
+        #[clap(long)]
+        password: Option<String>,
+        /// Matrix room ID or alias to mirror, e.g. "#p2p:matrix.org".
+        #[clap(long)]
+        matrix_room: String,
+        /// Join a named room instead of pasting a ticket.
+        #[clap(long)]
+        room: Option<String>,
+        /// Passphrase for --room; asked for if not given.
+        #[clap(long, requires = "room")]
+        passphrase: Option<String>,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum ProfileAction {
+    /// List existing profiles, marking the current one.
+    List,
+    /// Create a new profile with a fresh identity.
+    Create {
+        name: String,
+        /// Nickname the profile goes by.
+        #[clap(long)]
+        nick: Option<String>,
+    },
+    /// Delete a profile, including its identity key.
+    Delete { name: String },
+    /// Run as this profile from now on when --profile is not given.
+    Use { name: String },
+    /// List the rooms the profile has joined, for `join --recent`.
+    Rooms,
+    /// Keep the identity key, and named rooms' passphrases once entered, in
+    /// the OS keychain instead of files.
+    Keychain {
+        /// Move the identity key back out to a file.
+        #[clap(long)]
+        off: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum BackupAction {
+    /// Back up the profile once, to the configured target or --to.
+    Now {
+        #[clap(long)]
+        to: Option<String>,
+    },
+    /// Restore the profile from a backup host (e.g. on a new machine).
+    Restore { from: String },
+}
+
+#[derive(Parser, Debug)]
+enum RoomAction {
+    /// Write the room's ticket, nickname, draft and archive, with the
+    /// identity key it knows us by, to a file sealed with a passphrase.
+    Export {
+        /// The room: its number in `profile rooms`, or its topic.
+        room: String,
+        /// File to write the bundle to.
+        file: PathBuf,
+    },
+    /// Add a room exported on another machine to the profile.
+    Import {
+        file: PathBuf,
+        /// Also take over the identity in the bundle, replacing the
+        /// profile's own, if they differ.
+        #[clap(long)]
+        identity: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum PackAction {
+    /// Write the profile's name colours and [keys] table to a file.
+    Export {
+        file: PathBuf,
+        /// What to call the pack; defaults to the file's name.
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Check a pack, show what it would change and use it in the profile.
+    Import {
+        file: PathBuf,
+        /// Do not ask before changing the config.
+        #[clap(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum StoreAction {
+    /// Drop what the --archive store no longer needs and write it out
+    /// afresh, reporting the space reclaimed. Archives also do this by
+    /// themselves as they go; stop the profile's daemon first.
+    Compact,
+}
+
+/// Read one line from stdin after printing a prompt.
+fn prompt(label: &str) -> Result<String> {
+    println!("{}", label);
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Like prompt, but without echoing what is typed, for passphrases and
+/// passwords. Falls back to prompt when stdin is not a terminal.
+fn prompt_secret(label: &str) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return prompt(label);
+    }
+    println!("{}", label);
+    terminal::enable_raw_mode()?;
+    let mut input = String::new();
+    let read = loop {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err(anyhow!("interrupted"));
+            }
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            _ => {}
+        }
+    };
+    terminal::disable_raw_mode()?;
+    println!();
+    read.map(|()| input.trim().to_string())
+}
+
+/// Sockets to bind from --bind-addr and --bind-port; empty leaves the
+/// endpoint's defaults.
+fn bind_addrs(args: &Args) -> Vec<SocketAddr> {
+    match (args.bind_addr, args.bind_port) {
+        (Some(ip), port) => vec![SocketAddr::new(ip, port)],
+        (None, 0) => vec![],
+        (None, port) => vec![
+            SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+            SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port),
+        ],
+    }
+}
+
+/// The relays --relay-url and --no-relay ask for.
+fn relays(args: &Args, mut client: ChatClientBuilder) -> ChatClientBuilder {
+    for url in &args.relay_urls {
+        client = client.relay(url.clone());
+    }
+    if args.no_relay {
+        client = client.no_relay();
+    }
+    client
+}
+
+/// The passphrase for a named room: as given on the command line, saved in
+/// the keychain if the profile uses it, or asked for. With the keychain, a
+/// new one is saved for next time.
+fn room_passphrase(profile: &Profile, room: &str, given: &Option<String>) -> Result<String> {
+    let in_keychain = config::Config::load(&profile.config_path())?.keychain;
+    let account = profile.keychain_account(&format!("room/{}", room));
+    if given.is_none()
+        && in_keychain
+        && let Some(saved) = keychain::get(&account)?
+    {
+        return Ok(saved);
+    }
+    let passphrase = match given {
+        Some(passphrase) => passphrase.clone(),
+        None => prompt_secret(t!("Room passphrase:"))?,
+    };
+    if passphrase.is_empty() {
+        anyhow::bail!("the passphrase must not be empty");
+    }
+    if in_keychain {
+        keychain::set(&account, &passphrase)?;
+    }
+    Ok(passphrase)
+}
+
+/// How the profile's archives are sealed, per the config; asks for the
+/// passphrase if that is how.
+fn archive_key(config: &config::Config) -> Result<StoreKey> {
+    if config.seal_archive && config.keychain {
+        Ok(StoreKey::Keychain)
+    } else if config.seal_archive {
+        let passphrase = prompt_secret(t!("Archive passphrase:"))?;
+        if passphrase.is_empty() {
+            anyhow::bail!("the passphrase must not be empty");
+        }
+        Ok(StoreKey::Passphrase(passphrase))
+    } else {
+        Ok(StoreKey::Plain)
+    }
+}
+
+fn run_room_command(profile_name: &str, action: &RoomAction) -> Result<()> {
+    let profile = Profile::open(profile_name)?;
+    let config = config::Config::load(&profile.config_path())?;
+    match action {
+        RoomAction::Export { room, file } => {
+            let topic = match room.parse::<usize>() {
+                Ok(n) => match RecentRooms::new(&profile).get(n) {
+                    Some(recent) => recent.topic,
+                    None => anyhow::bail!("no room {} in `profile rooms`", n),
+                },
+                Err(_) => room.clone(),
+            };
+            let topic = TopicId::from_str(&topic).context("not a room number or topic")?;
+            let store_key = if Archive::exists(&profile, &topic) { archive_key(&config)? } else { StoreKey::Plain };
+            let bundle = Bundle::collect(&profile, &topic, &store_key)?;
+            let passphrase = prompt_secret("Bundle passphrase:")?;
+            if passphrase.is_empty() {
+                anyhow::bail!("the passphrase must not be empty");
+            }
+            write_private(file, &bundle.seal(&passphrase)?)?;
+            println!("Exported room {} to {}", topic, file.display());
+        }
+        RoomAction::Import { file, identity } => {
+            let blob = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+            let bundle = Bundle::open(&blob, &prompt_secret("Bundle passphrase:")?)?;
+            let store_key = if bundle.has_history() { archive_key(&config)? } else { StoreKey::Plain };
+            let imported = bundle.import(&profile, &store_key, *identity)?;
+            println!("Imported room {} into profile '{}'", imported.topic, profile.name);
+            if imported.messages > 0 {
+                println!("Its archive holds {} messages.", imported.messages);
+            }
+            if imported.other_identity {
+                println!(
+                    "The room knows you by another identity: a private room's creator has to admit this one, \
+                     and archived messages may not be readable. Pass --identity to take that one over instead."
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_pack_command(profile_name: &str, action: &PackAction) -> Result<()> {
+    let profile = Profile::open(profile_name)?;
+    let mut config = config::Config::load(&profile.config_path())?;
+    match action {
+        PackAction::Export { file, name } => {
+            let name = match name {
+                Some(name) => name.clone(),
+                None => file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+            };
+            Pack::from_config(&name, &config).write(file)?;
+            println!("Exported the name colours and keys of profile '{}' to {}", profile.name, file.display());
+        }
+        PackAction::Import { file, yes } => {
+            let pack = Pack::read(file)?;
+            for line in pack.preview(&config)? {
+                println!("{}", line);
+            }
+            if !yes {
+                let answer = prompt(&format!("Use this pack in profile '{}'? [y/N]", profile.name))?;
+                if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+                    println!("Nothing was changed.");
+                    return Ok(());
+                }
+            }
+            pack.apply(&mut config);
+            config.save(&profile.config_path())?;
+            println!("Imported pack {:?} into profile '{}'", pack.name, profile.name);
+        }
+    }
+    Ok(())
+}
+
+fn run_store_command(profile_name: &str, action: &StoreAction) -> Result<()> {
+    let profile = Profile::open(profile_name)?;
+    let config = config::Config::load(&profile.config_path())?;
+    match action {
+        StoreAction::Compact => {
+            let topics = Archive::topics(&profile)?;
+            if topics.is_empty() {
+                println!("Profile '{}' keeps no archives.", profile.name);
+                return Ok(());
+            }
+            let store_key = archive_key(&config)?;
+            let mut total = Compaction::default();
+            for topic in &topics {
+                let compaction = Archive::open(&profile, topic, &store_key)?.compact()?;
+                println!(
+                    "{}: dropped {} messages, reclaimed {}",
+                    topic,
+                    compaction.dropped,
+                    human_bytes(compaction.reclaimed)
+                );
+                total.dropped += compaction.dropped;
+                total.reclaimed += compaction.reclaimed;
+            }
+            println!(
+                "Compacted {} archives: dropped {} messages, reclaimed {}",
+                topics.len(),
+                total.dropped,
+                human_bytes(total.reclaimed)
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn run_backup_command(profile_name: &str, action: &BackupAction) -> Result<()> {
+    // The host does not care who we are, so a throwaway identity will do.
+    let endpoint = Endpoint::bind().await?;
+    match action {
+        BackupAction::Now { to } => {
+            let profile = Profile::open(profile_name)?;
+            let config = config::Config::load(&profile.config_path())?;
+            let Some(target) = to.clone().or(config.backup.target) else {
+                anyhow::bail!("no backup target: pass --to or set backup.target in the config");
+            };
+            let host = EndpointId::from_str(&target).context("invalid backup target")?;
+            let passphrase = prompt_secret("Master passphrase:")?;
+            backup::upload(&endpoint, host, &profile, &passphrase).await?;
+            println!("Backed up profile '{}' to {}", profile.name, host.fmt_short());
+        }
+        BackupAction::Restore { from } => {
+            let host = EndpointId::from_str(from).context("invalid backup host")?;
+            let passphrase = prompt_secret("Master passphrase:")?;
+            let profile = backup::restore(&endpoint, host, profile_name, &passphrase).await?;
+            println!("Restored profile '{}' to {}", profile.name, profile.dir.display());
+        }
+    }
+    endpoint.close().await;
+    Ok(())
+}
+
+/*
+Function:   -init_logging
+Purpose:    -Send tracing output to a file.
+
+Parameters:
+            - &Path path:  File to append to; created if missing.
+            - Level level:  Most verbose level logged for this program.
+
+Details:
+            - Never logs to the terminal: the TUI owns it.
+            - Our own spans and events (the binary's and p2p_chat's) are
+              logged at `level`; iroh and the other libraries only at warn
+              and above, or they drown us out.
+*/
+fn init_logging(path: &Path, level: Level) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening log file {}", path.display()))?;
+    let filter = Targets::new()
+        .with_default(Level::WARN)
+        .with_target("p2p_chat", level)
+        .with_target(env!("CARGO_CRATE_NAME"), level);
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file)),
+        )
+        .with(filter)
+        .init();
+    Ok(())
+}
+
+fn run_conformance(dir: &Path) -> Result<()> {
+    let report = conformance::run(dir)?;
+    for (file, name, reason) in &report.failures {
+        println!("FAIL {} / {}: {}", file.display(), name, reason);
+    }
+    println!("{} passed, {} failed", report.passed, report.failures.len());
+    if !report.failures.is_empty() {
+        anyhow::bail!("conformance failures");
+    }
+    Ok(())
+}
+
+fn run_profile_command(profile_name: &str, action: &ProfileAction) -> Result<()> {
+    match action {
+        ProfileAction::List => {
+            let current = Profile::current()?;
+            for name in Profile::list()? {
+                let mark = if name == current { '*' } else { ' ' };
+                println!("{} {}", mark, name);
+            }
+        }
+        ProfileAction::Create { name, nick } => {
+            let profile = Profile::create(name)?;
+            if let Some(nick) = nick {
+                let mut config = config::Config::load(&profile.config_path())?;
+                config.name = Some(nick.clone());
+                config.save(&profile.config_path())?;
+            }
+            println!("Created profile '{}' at {}", profile.name, profile.dir.display());
+        }
+        ProfileAction::Delete { name } => {
+            Profile::delete(name)?;
+            println!("Deleted profile '{}'", name);
+        }
+        ProfileAction::Use { name } => {
+            Profile::set_current(name)?;
+            println!("Now running as profile '{}'", name);
+        }
+        ProfileAction::Keychain { off } => {
+            let profile = Profile::open(profile_name)?;
+            profile.set_keychain(!off)?;
+            if *off {
+                println!("Moved the identity of profile '{}' to {}", profile.name, profile.identity_path().display());
+            } else {
+                println!("Moved the identity of profile '{}' into the keychain", profile.name);
+            }
+        }
+        ProfileAction::Rooms => {
+            let profile = Profile::open(profile_name)?;
+            let config = config::Config::load(&profile.config_path())?;
+            for (n, room) in RecentRooms::new(&profile).list().iter().enumerate() {
+                let joined = chrono::DateTime::from_timestamp(room.joined as i64, 0)
+                    .map(|at| at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                match config.room_names.get(&room.topic) {
+                    Some(nick) => println!("{:>2}. {}  {}  (as {})", n + 1, room.topic, joined, nick),
+                    None => println!("{:>2}. {}  {}", n + 1, room.topic, joined),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find rooms on the directory topic and let the user pick one to join;
+/// None if there were none or they picked none.
+async fn browse_rooms(config: &config::Config, query: &str) -> Result<Option<Ticket>> {
+    let bootstrap = directory::bootstrap(&config.directory)?;
+    if bootstrap.is_empty() {
+        anyhow::bail!(t!("No directory peers configured – set peers or server in the [directory] section of config.toml."));
+    }
+    println!("{}", t!("Looking for rooms…"));
+    let listings = directory::browse(bootstrap, query).await?;
+    if listings.is_empty() {
+        println!("{}", t!("No rooms found."));
+        return Ok(None);
+    }
+    for (i, listing) in listings.iter().enumerate() {
+        println!(
+            "{}",
+            t!(
+                "{}. {} – {} ({} members, {})",
+                i + 1,
+                listing.name,
+                listing.description,
+                listing.members,
+                listing.policy()
+            )
+        );
+    }
+    let answer = prompt(t!("Number of the room to join (Enter to quit):"))?;
+    if answer.is_empty() {
+        return Ok(None);
+    }
+    match answer.parse::<usize>().ok().and_then(|n| listings.get(n.checked_sub(1)?)) {
+        Some(listing) => Ok(Some(listing.ticket()?)),
+        None => anyhow::bail!("no room {} in the list", answer),
+    }
+}
+
+/// Wipe a profile from the command line, once the user has typed "wipe"
+/// unless --yes was given.
+fn run_wipe_command(profile_name: &str, identity: bool, yes: bool) -> Result<()> {
+    let profile = Profile::open(profile_name)?;
+    if !yes {
+        let what = if identity {
+            "chat history, drafts, remembered rooms and identity key"
+        } else {
+            "chat history, drafts and remembered rooms"
+        };
+        let answer = prompt(&format!(
+            "This securely deletes the {} of profile '{}'. Type \"wipe\" to go ahead:",
+            what, profile.name
+        ))?;
+        if answer != "wipe" {
+            println!("Nothing was deleted.");
+            return Ok(());
+        }
+    }
+    wipe_profiles(&[profile.name], wipe::Request { identity })
+}
+
+/// Carry out a wipe asked for in the chat (/wipe yes), now that the sessions
+/// of these profiles are shut down.
+fn wipe_profiles(names: &[String], request: wipe::Request) -> Result<()> {
+    let mut names = names.to_vec();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let profile = Profile::open(&name)?;
+        let wiped = wipe::wipe(&profile, request.identity)?;
+        println!("{}", t!("Wiped {} files of profile '{}'.", wiped, profile.name));
+    }
+    Ok(())
+}
+
+/// Talk to the daemon running for `profile_name`.
+async fn run_control_command(profile_name: &str, command: &Command) -> Result<()> {
+    let path = Profile::open(profile_name)?.socket_path();
+    match command {
+        Command::Send { text } => {
+            let text = text.join(" ");
+            daemon::request(&path, &gateway::Request::Send { text, meta: None }).await?;
+        }
+        Command::Tail { json } => daemon::tail(&path, *json).await?,
+        Command::Rooms => {
+            if let gateway::Event::Rooms { rooms } = daemon::request(&path, &gateway::Request::Rooms).await? {
+                for room in rooms {
+                    println!("{}  {} member(s)", room.topic, room.members);
+                    println!("  {}", room.ticket);
+                }
+            }
+        }
+        _ => unreachable!("not a control command"),
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    if let Some(path) = &args.log_file {
+        init_logging(path, args.log_level)?;
+    }
+    let profile_name = match &args.profile {
+        Some(name) => name.clone(),
+        None => Profile::current()?,
+    };
+    info!(version = env!("CARGO_PKG_VERSION"), profile = %profile_name, "starting");
 
-    let (topic, endpoints) = match &args.command {
-        Command::Open => {
-            let topic = iroh_gossip::proto::TopicId::from_bytes(rand::random());
-            (topic, vec![])
+    if let Command::Profile { action } = &args.command {
+        return run_profile_command(&profile_name, action);
+    }
+    if let Command::Backup { action } = &args.command {
+        return run_backup_command(&profile_name, action).await;
+    }
+    if let Command::Room { action } = &args.command {
+        return run_room_command(&profile_name, action);
+    }
+    if let Command::Pack { action } = &args.command {
+        return run_pack_command(&profile_name, action);
+    }
+    if let Command::Store { action } = &args.command {
+        return run_store_command(&profile_name, action);
+    }
+    if let Command::Conformance { dir } = &args.command {
+        return run_conformance(dir);
+    }
+    if let Command::Wipe { identity, yes } = &args.command {
+        return run_wipe_command(&profile_name, *identity, *yes);
+    }
+    if let Command::Send { .. } | Command::Tail { .. } | Command::Rooms = &args.command {
+        return run_control_command(&profile_name, &args.command).await;
+    }
+
+    let profile = Profile::open(&profile_name)?;
+    let config = config::Config::load(&profile.config_path())?;
+    i18n::init(config.locale.as_deref());
+    let transcript = args
+        .log_transcript
+        .as_deref()
+        .map(TranscriptLog::open)
+        .transpose()?;
+
+    let entry = match &args.command {
+        Command::Open { room: Some(room), passphrase, .. } => Entry::Named {
+            name: room.clone(),
+            passphrase: room_passphrase(&profile, room, passphrase)?,
+            host: true,
+        },
+        Command::Open { private, title, .. } => Entry::Open { private: *private, title: title.clone() },
+        Command::Browse { query } => match browse_rooms(&config, &query.join(" ")).await? {
+            Some(ticket) => Entry::Join(ticket),
+            None => return Ok(()),
+        },
+        Command::Join { recent: Some(n), .. } => match RecentRooms::new(&profile).get(*n) {
+            Some(room) => Entry::Join(room.ticket()?),
+            None => anyhow::bail!("no room {} in `profile rooms`", n),
+        },
+        Command::Join { room: Some(room), passphrase, .. }
+        | Command::Bridge { target: BridgeTarget::Irc { room: Some(room), passphrase, .. } }
+        | Command::Bridge { target: BridgeTarget::Matrix { room: Some(room), passphrase, .. } }
+        | Command::Serve { room: Some(room), passphrase, .. }
+        | Command::Daemon { room: Some(room), passphrase }
+        | Command::Bot { room: Some(room), passphrase } => Entry::Named {
+            name: room.clone(),
+            passphrase: room_passphrase(&profile, room, passphrase)?,
+            host: false,
+        },
+        Command::Join { .. }
+        | Command::Bridge { .. }
+        | Command::Serve { .. }
+        | Command::Daemon { .. }
+        | Command::Bot { .. } => {
+            let ticket_str = prompt(t!("Paste your ticket and press Enter:"))?;
+            let ticket = if Ticket::is_protected(&ticket_str) {
+                let passphrase = prompt_secret(t!("This ticket is protected. Passphrase:"))?;
+                Ticket::unprotect(&ticket_str, &passphrase)?
+            } else {
+                Ticket::from_str(&ticket_str)?
+            };
+            // Only people joining by hand are asked; bridges and bots are
+            // pointed at their room on purpose.
+            if let Command::Join { .. } = &args.command
+                && let Some(summary) = ticket.info.summary()
+            {
+                let answer = prompt(&t!("Joining {} — continue? [y/N]", summary))?;
+                if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+                    println!("{}", t!("Not joining."));
+                    return Ok(());
+                }
+            }
+            Entry::Join(ticket)
         }
-        Command::Join => {
-            println!("Paste your ticket and press Enter:");
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input)?;
-            let ticket_str = input.trim();
-            let Ticket { topic, endpoints } = Ticket::from_str(ticket_str)?;
-            (topic, endpoints)
+        Command::Profile { .. }
+        | Command::Backup { .. }
+        | Command::Room { .. }
+        | Command::Pack { .. }
+        | Command::Store { .. }
+        | Command::Conformance { .. }
+        | Command::Wipe { .. }
+        | Command::Send { .. }
+        | Command::Tail { .. }
+        | Command::Rooms => {
+            unreachable!("handled above")
         }
     };
 
-    let endpoint = Endpoint::bind().await?;
-    let gossip = Gossip::builder().spawn(endpoint.clone());
-    let router = Router::builder(endpoint.clone())
-        .accept(iroh_gossip::ALPN, gossip.clone())
-        .spawn();
-
-    let ticket = {
-        let me = endpoint.addr();
-        let endpoints = vec![me];
-        Ticket { topic, endpoints }
-    };
-  
+    let mut client = ChatClientBuilder::new().profile(profile.clone()).entry(entry);
+    if let Some(name) = &args.name {
+        client = client.nickname(name);
+    }
+    for addr in bind_addrs(&args) {
+        client = client.bind(addr);
+    }
+    client = relays(&args, client);
+    if args.archive {
+        client = client.history(HistoryBackend::Archive);
+        client = client.archive_key(archive_key(&config)?);
+    }
+    let session = client.build().await?;
+    for addr in session.bound_sockets() {
+        println!("{}", t!("Listening on {}", addr));
+    }
+
+    if let Some(target) = &session.config.backup.target {
+        let host = EndpointId::from_str(target).context("invalid backup.target in config")?;
+        let passphrase = prompt_secret(t!("Master passphrase for backups:"))?;
+        session.start_backups(&profile, host, passphrase);
+    }
+
     match &args.command {
-        Command::Open => {
+        Command::Open { protect, qr, expires, once, room, .. } => {
+            let invite = if expires.is_some() || *once {
+                Some(session.invite(*expires, *once)?)
+            } else {
+                None
+            };
+            let plain = invite.as_ref().unwrap_or(&session.ticket);
+            let ticket = if *protect {
+                let passphrase = prompt_secret(t!("Passphrase to protect the ticket with:"))?;
+                if passphrase.is_empty() {
+                    anyhow::bail!("the passphrase must not be empty");
+                }
+                plain.protect(&passphrase)?
+            } else {
+                plain.to_string()
+            };
             println!("╔══════════════════════════════════════════════════════════════╗");
             println!("║                    ENCRYPTED CHAT ROOM                       ║");
             println!("╚══════════════════════════════════════════════════════════════╝");
             println!();
-            println!("Share this ticket with others to join:");
+            if let Some(room) = room {
+                println!("{}", t!("Others can join with: join --room {}", format!("{:?}", room)));
+                println!("{}", t!("(Give them the passphrase separately.) Or share this ticket:"));
+            } else {
+                println!("{}", t!("Share this ticket with others to join:"));
+            }
             println!("{}", ticket);
+            if *protect {
+                println!("{}", t!("(Give them the passphrase separately.)"));
+            }
+            if let Some(expires) = plain.expires.and_then(|at| chrono::DateTime::from_timestamp(at as i64, 0)) {
+                println!("{}", t!("(Valid until {}.)", expires.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")));
+            }
+            if *once {
+                println!("{}", t!("(Works for one person only.)"));
+            }
             println!();
+            if *qr {
+                for row in qr::render(&ticket)? {
+                    println!("{}", row);
+                }
+                println!();
+            }
         }
-        Command::Join => {
+        Command::Join { .. }
+        | Command::Browse { .. }
+        | Command::Bridge { .. }
+        | Command::Serve { .. }
+        | Command::Daemon { .. }
+        | Command::Bot { .. } => {
             println!("╔══════════════════════════════════════════════════════════════╗");
             println!("║                    ENCRYPTED CHAT ROOM                       ║");
             println!("╚══════════════════════════════════════════════════════════════╝");
             println!();
         }
+        Command::Profile { .. }
+        | Command::Backup { .. }
+        | Command::Room { .. }
+        | Command::Pack { .. }
+        | Command::Store { .. }
+        | Command::Conformance { .. }
+        | Command::Wipe { .. }
+        | Command::Send { .. }
+        | Command::Tail { .. }
+        | Command::Rooms => {
+            unreachable!("handled above")
+        }
     }
 
-
-    let (ui_tx, ui_rx) = mpsc::channel::<UiMessage>(100);
-    let (input_tx, mut input_rx) = mpsc::channel::<(String, u64)>(100);
-    let (delete_tx, mut delete_rx) = mpsc::channel::<u64>(32);
-
-    let endpoint_ids = endpoints.iter().map(|p| p.id).collect();
-
-    let (sender, receiver) = gossip
-        .subscribe_and_join(topic, endpoint_ids)
-        .await?
-        .split();
-
-    let my_name = args.name.clone().unwrap_or_else(|| "Anonymous".to_string());
-    let my_id = endpoint.id();
-
-    // Broadcast our name immediately.
-    let message = Message::new(MessageBody::AboutMe {
-        from: my_id,
-        name: my_name.clone(),
-    });
-    sender.broadcast(message.to_vec().into()).await?;
-
-    ui_tx
-        .send(UiMessage::System(format!("You joined as {}", my_name)))
-        .await?;
-    ui_tx
-        .send(UiMessage::System(
-            "INSERT mode – type & Enter to send. ESC for NORMAL mode.".to_string(),
-        ))
-        .await?;
-
-    // Spawn gossip receiver loop.
-    let ui_tx_clone = ui_tx.clone();
-    tokio::spawn(gossip::subscribe_loop(
-        receiver,
-        sender.clone(),
-        topic,
-        ui_tx_clone,
-        my_id,
-        my_name.clone(),
-    ));
-
-    // Spawn message sender / deleter loop.
-    tokio::spawn(async move {
-        loop {
-            tokio::select! {
-                Some((text, id)) = input_rx.recv() => {
-                    if let Ok(msg) = encrypt_message(&text, my_id, &topic, id) {
-                        let _ = sender.broadcast(msg.to_vec().into()).await;
-                    }
-                }
-                Some(id) = delete_rx.recv() => {
-                    let msg = Message::new(MessageBody::DeleteMessage { from: my_id, id });
-                    let _ = sender.broadcast(msg.to_vec().into()).await;
-                }
-                else => break,
+    if let Command::Daemon { .. } = &args.command {
+        let path = profile.socket_path();
+        println!("{}", t!("Daemon running on {}. Use send, tail and rooms to talk to it.", path.display()));
+        let mut session = session;
+        let client = || relays(&args, ChatClientBuilder::new());
+        let bots = match bot::Hosted::start(&config.bots, &profile, Profile::open, client).await {
+            Ok(bots) => bots,
+            Err(e) => {
+                session.shutdown().await?;
+                return Err(e);
             }
+        };
+        let result = daemon::run(&mut session, &path).await;
+        bots.shutdown().await;
+        session.shutdown().await?;
+        info!("shut down");
+        return result;
+    }
+    if let Command::Serve { listen, .. } = &args.command {
+        println!("{}", t!("Serving the room on ws://{}. Press Ctrl+C to stop.", listen));
+        let mut session = session;
+        let result = gateway::serve(&mut session, *listen).await;
+        session.shutdown().await?;
+        info!("shut down");
+        return result;
+    }
+    if let Command::Bot { .. } = &args.command {
+        println!("{}", t!("Bot running. Press Ctrl+C to stop."));
+        let mut session = session;
+        let result = match bot::Bot::from_config(&session.config.bot) {
+            Ok(bot) => bot.run(&mut session).await,
+            Err(e) => Err(e),
+        };
+        session.shutdown().await?;
+        info!("shut down");
+        return result;
+    }
+    if let Command::Bridge { target: BridgeTarget::Irc { server, port, no_tls, channel, nick, .. } } = &args.command {
+        let options = IrcOptions {
+            server: server.clone(),
+            port: port.unwrap_or(if *no_tls { 6667 } else { 6697 }),
+            tls: !*no_tls,
+            channel: channel.clone(),
+            nick: nick.clone(),
+        };
+        println!("{}", t!("Bridging to {} on {}. Press Ctrl+C to stop.", channel, server));
+        let mut session = session;
+        let result = bridge::run_irc(&mut session, &options).await;
+        session.shutdown().await?;
+        info!("shut down");
+        return result;
+    }
+    if let Command::Bridge { target: BridgeTarget::Matrix { homeserver, user, password, matrix_room, .. } } = &args.command {
+        let options = MatrixOptions {
+            homeserver: homeserver.clone(),
+            user: user.clone(),
+            password: match password {
+                Some(password) => password.clone(),
+                None => prompt_secret(t!("Matrix password:"))?,
+            },
+            room: matrix_room.clone(),
+        };
+        println!("{}", t!("Bridging to {} on {}. Press Ctrl+C to stop.", matrix_room, homeserver));
+        let mut session = session;
+        let result = bridge::run_matrix(&mut session, &options).await;
+        session.shutdown().await?;
+        info!("shut down");
+        return result;
+    }
+
+    if args.plain {
+        let mut session = session;
+        let result = plain::run(&mut session, transcript).await;
+        let name = session.profile.clone();
+        session.shutdown().await?;
+        info!("shut down");
+        if let Some(request) = result? {
+            wipe_profiles(&[name], request)?;
         }
-    });
+        return Ok(());
+    }
 
     // Run the TUI — opens immediately, peers appear as they connect.
-    tui::run_tui(ui_rx, input_tx, delete_tx).await?;
+    let (sessions, wipe) = tui::run_tui(vec![session], transcript).await?;
 
-    router.shutdown().await?;
+    let names: Vec<String> = sessions.iter().map(|session| session.profile.clone()).collect();
+    for session in sessions {
+        session.shutdown().await?;
+    }
+    info!("shut down");
+    if let Some(request) = wipe {
+        wipe_profiles(&names, request)?;
+    }
     std::process::exit(0);
 
 }
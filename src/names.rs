@@ -0,0 +1,121 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::Result;
+use iroh::EndpointId;
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+
+use crate::profile::{write_private, Profile};
+
+// ── Known names ───────────────────────────────────────────────────────────────
+
+/*
+Struct:     -KnownName
+Purpose:    -What we last knew of one member of a room.
+
+Fields:
+            - String name:  The name they last announced.
+            - bool verified:  Their latest readable message was signed by
+              them (app::Trust::Verified).
+            - bool blocked:  They were on our blocklist when last saved.
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownName {
+    pub name: String,
+    #[serde(default)]
+    pub verified: bool,
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+/*
+Struct:     -NameCache
+Purpose:    -The names of each room's members, kept in the profile so that
+             after a restart their messages are shown under their name
+             straight away rather than once they announce it again.
+
+Fields:
+            - PathBuf path:  <profile>/names.json, a map from room topic to
+              a map from endpoint ID to KnownName.
+
+Details:
+            - Like Drafts, every save re-reads the file, so sessions of the
+              same profile do not overwrite each other's rooms.
+            - The names are only hints: the receive loop treats them like
+              names learned from history, so members are not shown as
+              present until they speak up.
+*/
+#[derive(Clone, Debug)]
+pub struct NameCache {
+    path: PathBuf,
+}
+
+impl NameCache {
+    pub fn new(profile: &Profile) -> Self {
+        Self {
+            path: profile.names_path(),
+        }
+    }
+
+    fn read(&self) -> BTreeMap<String, BTreeMap<String, KnownName>> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// The members we know of in a room; entries that no longer parse are
+    /// skipped.
+    pub fn load(&self, topic: &TopicId) -> HashMap<EndpointId, KnownName> {
+        self.read()
+            .remove(&topic.to_string())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(id, known)| Some((EndpointId::from_str(&id).ok()?, known)))
+            .collect()
+    }
+
+    /// The name we know `id` by in a room, if any.
+    pub fn get(&self, topic: &TopicId, id: EndpointId) -> Option<KnownName> {
+        self.load(topic).remove(&id)
+    }
+
+    /// Change what we know of `id` in a room, starting from nothing under
+    /// `name` if they are new. Writes only if something changed.
+    pub fn update(&self, topic: &TopicId, id: EndpointId, name: &str, change: impl FnOnce(&mut KnownName)) -> Result<()> {
+        let mut rooms = self.read();
+        let room = rooms.entry(topic.to_string()).or_default();
+        let key = id.to_string();
+        let mut known = room.get(&key).cloned().unwrap_or_else(|| KnownName {
+            name: name.to_string(),
+            verified: false,
+            blocked: false,
+        });
+        change(&mut known);
+        if room.get(&key) == Some(&known) {
+            return Ok(());
+        }
+        room.insert(key, known);
+        write_private(&self.path, &serde_json::to_vec_pretty(&rooms)?)
+    }
+
+    /// Flag `id` as blocked or not in every room we know them from; the
+    /// blocklist itself is the profile's, not the room's.
+    pub fn set_blocked(&self, id: EndpointId, blocked: bool) -> Result<()> {
+        let mut rooms = self.read();
+        let key = id.to_string();
+        let mut changed = false;
+        for known in rooms.values_mut().filter_map(|room| room.get_mut(&key)) {
+            changed |= known.blocked != blocked;
+            known.blocked = blocked;
+        }
+        if !changed {
+            return Ok(());
+        }
+        write_private(&self.path, &serde_json::to_vec_pretty(&rooms)?)
+    }
+}
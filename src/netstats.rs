@@ -0,0 +1,499 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use iroh::{
+    endpoint::{AfterHandshakeOutcome, ConnectionInfo, EndpointHooks},
+    EndpointId,
+};
+
+use crate::config::LoadConfig;
+use crate::protocol::{Neighbour, MAX_NEIGHBOURS};
+
+// ── Connection diagnostics ────────────────────────────────────────────────────
+
+/// How we currently reach one peer, summed over every connection to it
+/// (gossip, backups, directory).
+pub struct PeerPath {
+    pub id: EndpointId,
+    /// Over a relay rather than a direct (holepunched or LAN) path. None
+    /// while no path is selected.
+    pub relayed: Option<bool>,
+    pub rtt: Option<Duration>,
+    pub sent: u64,
+    pub received: u64,
+}
+
+/*
+Struct:     -ConnectionTracker
+Purpose:    -Endpoint hook that remembers every connection the endpoint makes
+             or accepts, for the /net panel.
+
+Details:
+            - Holds weak handles (ConnectionInfo) only, so it never keeps a
+              connection open; closed ones are dropped on the next handshake
+              or snapshot.
+            - Gossip owns its connections, so hooking the endpoint is the
+              only way to see their paths and byte counts.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionTracker(Arc<Mutex<Vec<ConnectionInfo>>>);
+
+impl EndpointHooks for ConnectionTracker {
+    async fn after_handshake<'a>(&'a self, conn: &'a ConnectionInfo) -> AfterHandshakeOutcome {
+        let mut conns = self.0.lock().expect("connection tracker lock poisoned");
+        conns.retain(ConnectionInfo::is_alive);
+        conns.push(conn.clone());
+        AfterHandshakeOutcome::accept()
+    }
+}
+
+impl ConnectionTracker {
+    /// Current path, latency and traffic for each peer with an open
+    /// connection, in endpoint ID order.
+    pub fn snapshot(&self) -> Vec<PeerPath> {
+        let mut conns = self.0.lock().expect("connection tracker lock poisoned");
+        conns.retain(ConnectionInfo::is_alive);
+        let mut peers: BTreeMap<EndpointId, PeerPath> = BTreeMap::new();
+        for conn in conns.iter() {
+            let id = conn.remote_id();
+            let peer = peers.entry(id).or_insert(PeerPath {
+                id,
+                relayed: None,
+                rtt: None,
+                sent: 0,
+                received: 0,
+            });
+            if let Some(stats) = conn.stats() {
+                peer.sent += stats.udp_tx.bytes;
+                peer.received += stats.udp_rx.bytes;
+            }
+            // Prefer the fastest selected path when there are several
+            // connections to the same peer.
+            if let Some(path) = conn.selected_path()
+                && peer.rtt.is_none_or(|rtt| path.rtt() < rtt)
+            {
+                peer.relayed = Some(path.is_relay());
+                peer.rtt = Some(path.rtt());
+            }
+        }
+        peers.into_values().collect()
+    }
+}
+
+/*
+Struct:     -Swarm
+Purpose:    -The room's gossip overlay as far as we can see it, for the
+             /swarm panel.
+
+Fields:
+            - ConnectionTracker connections:  For how we reach each of our
+              neighbours.
+            - Arc<Mutex<SwarmState>> state:  Our neighbours, kept up to date
+              by the receive loop, and the neighbour lists other members sent
+              in answer to our last /swarm.
+
+Details:
+            - Gossip only tells us about our own neighbours; anything further
+              is what members report (MessageBody::Neighbours), so it is only
+              as fresh as the last ask.
+*/
+#[derive(Debug, Clone)]
+pub struct Swarm {
+    connections: ConnectionTracker,
+    state: Arc<Mutex<SwarmState>>,
+}
+
+#[derive(Debug, Default)]
+struct SwarmState {
+    neighbours: BTreeSet<EndpointId>,
+    reports: BTreeMap<EndpointId, Vec<Neighbour>>,
+}
+
+impl Swarm {
+    pub fn new(connections: ConnectionTracker) -> Self {
+        Self {
+            connections,
+            state: Arc::default(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SwarmState> {
+        self.state.lock().expect("swarm lock poisoned")
+    }
+
+    pub fn neighbour_up(&self, id: EndpointId) {
+        self.lock().neighbours.insert(id);
+    }
+
+    pub fn neighbour_down(&self, id: EndpointId) {
+        self.lock().neighbours.remove(&id);
+    }
+
+    /// Start over with a fresh subscription's neighbours.
+    pub fn reset(&self, neighbours: impl IntoIterator<Item = EndpointId>) {
+        self.lock().neighbours = neighbours.into_iter().collect();
+    }
+
+    /// Our neighbours and how we reach each, as we report them.
+    pub fn links(&self) -> Vec<Neighbour> {
+        let paths = self.connections.snapshot();
+        self.lock()
+            .neighbours
+            .iter()
+            .map(|id| Neighbour {
+                id: *id,
+                relayed: paths.iter().find(|path| path.id == *id).and_then(|path| path.relayed),
+            })
+            .collect()
+    }
+
+    /// Keep a member's reported neighbours.
+    pub fn report(&self, from: EndpointId, mut neighbours: Vec<Neighbour>) {
+        neighbours.truncate(MAX_NEIGHBOURS);
+        self.lock().reports.insert(from, neighbours);
+    }
+
+    /// Drop earlier reports before asking again.
+    pub fn forget_reports(&self) {
+        self.lock().reports.clear();
+    }
+
+    pub fn reports(&self) -> BTreeMap<EndpointId, Vec<Neighbour>> {
+        self.lock().reports.clone()
+    }
+}
+
+/// What the receive loop has seen of one peer, for /whois.
+#[derive(Debug, Clone, Copy)]
+pub struct Sighting {
+    pub first: DateTime<Local>,
+    pub last: DateTime<Local>,
+    /// Chat messages received from them live (not from an archive).
+    pub messages: u64,
+    /// Whether their latest readable message was signed by them
+    /// (app::Trust::Verified) rather than only under a key others hold
+    /// too. None until one has.
+    pub verified: Option<bool>,
+}
+
+/// Every peer the receive loop has heard from this session.
+#[derive(Debug, Clone, Default)]
+pub struct Sightings(Arc<Mutex<HashMap<EndpointId, Sighting>>>);
+
+impl Sightings {
+    fn update(&self, id: EndpointId, change: impl FnOnce(&mut Sighting)) {
+        let now = Local::now();
+        let mut seen = self.0.lock().expect("sightings lock poisoned");
+        let sighting = seen.entry(id).or_insert(Sighting {
+            first: now,
+            last: now,
+            messages: 0,
+            verified: None,
+        });
+        sighting.last = now;
+        change(sighting);
+    }
+
+    /// Anything at all arrived from `id`.
+    pub fn seen(&self, id: EndpointId) {
+        self.update(id, |_| {});
+    }
+
+    /// A chat message arrived from `id`.
+    pub fn message(&self, id: EndpointId) {
+        self.update(id, |sighting| sighting.messages += 1);
+    }
+
+    /// A message from `id` decrypted, under their own key or not.
+    pub fn decrypted(&self, id: EndpointId, verified: bool) {
+        self.update(id, |sighting| sighting.verified = Some(verified));
+    }
+
+    pub fn get(&self, id: EndpointId) -> Option<Sighting> {
+        self.0.lock().expect("sightings lock poisoned").get(&id).copied()
+    }
+
+    /// How many peers we heard from in the last `within`.
+    pub fn active(&self, within: Duration) -> usize {
+        let since = Local::now() - chrono::TimeDelta::from_std(within).unwrap_or(chrono::TimeDelta::MAX);
+        let seen = self.0.lock().expect("sightings lock poisoned");
+        seen.values().filter(|sighting| sighting.last >= since).count()
+    }
+
+    /// The one peer seen whose endpoint ID starts with `prefix`, as shown
+    /// in short form.
+    pub fn with_prefix(&self, prefix: &str) -> Option<EndpointId> {
+        let seen = self.0.lock().expect("sightings lock poisoned");
+        let mut matches = seen.keys().filter(|id| id.to_string().starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            (Some(id), None) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+/// Pings we sent and await a pong for, by nonce: who to and when.
+#[derive(Debug, Clone, Default)]
+pub struct Pings(Arc<Mutex<HashMap<u64, (EndpointId, Instant)>>>);
+
+impl Pings {
+    /// Note a ping to `to` going out now; returns its nonce.
+    pub fn start(&self, to: EndpointId) -> u64 {
+        let nonce = rand::random();
+        self.0.lock().expect("pings lock poisoned").insert(nonce, (to, Instant::now()));
+        nonce
+    }
+
+    /// The round trip of our ping `nonce`, if `from` is who it went to and
+    /// it is still awaited. Either way it is awaited no longer.
+    pub fn finish(&self, nonce: u64, from: EndpointId) -> Option<Duration> {
+        let mut pings = self.0.lock().expect("pings lock poisoned");
+        match pings.get(&nonce) {
+            Some((to, at)) if *to == from => {
+                let rtt = at.elapsed();
+                pings.remove(&nonce);
+                Some(rtt)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Seconds of traffic the chat message rate is taken over.
+const MESSAGE_WINDOW: u64 = 60;
+
+/*
+Struct:     -Load
+Purpose:    -Whether a room is large or our sending is backed up, so that
+             low-value broadcasts are cut back (config::LoadConfig).
+
+Details:
+            - The receive loop reports how many members it has heard from
+              lately (not everyone it has a name for), the send queue how
+              many broadcasts are waiting.
+            - Low-value means replaced by the next one of its kind: presence
+              and the periodic repeat of our name. A presence change held
+              back goes out with the next repeat.
+*/
+#[derive(Debug, Clone)]
+pub struct Load {
+    policy: LoadConfig,
+    members: Arc<AtomicUsize>,
+    queued: Arc<AtomicUsize>,
+    held: Arc<AtomicBool>,
+}
+
+impl Load {
+    pub fn new(policy: LoadConfig) -> Self {
+        Self {
+            policy,
+            members: Arc::default(),
+            queued: Arc::default(),
+            held: Arc::default(),
+        }
+    }
+
+    pub fn set_members(&self, members: usize) {
+        self.members.store(members, Ordering::Relaxed);
+    }
+
+    pub fn set_queued(&self, queued: usize) {
+        self.queued.store(queued, Ordering::Relaxed);
+    }
+
+    /// The room has at least `crowded_members` members.
+    pub fn crowded(&self) -> bool {
+        let limit = self.policy.crowded_members;
+        limit > 0 && self.members.load(Ordering::Relaxed) >= limit
+    }
+
+    /// At least `congested_queue` broadcasts are waiting to go out.
+    pub fn congested(&self) -> bool {
+        let limit = self.policy.congested_queue;
+        limit > 0 && self.queued.load(Ordering::Relaxed) >= limit
+    }
+
+    /// Note that a presence change was held back.
+    pub fn hold_presence(&self) {
+        self.held.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a presence change was held back since last asked.
+    pub fn take_held_presence(&self) -> bool {
+        self.held.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether to repeat our status to each newcomer.
+    pub fn greets_with_presence(&self) -> bool {
+        !self.crowded() && !self.congested()
+    }
+}
+
+/// Seconds of traffic the bandwidth figures are averaged over.
+const BANDWIDTH_WINDOW: u64 = 5;
+
+/// Traffic in one second: bytes in, bytes out, chat messages either way.
+#[derive(Debug, Default, Clone, Copy)]
+struct Second {
+    at: u64,
+    received: u64,
+    sent: u64,
+    messages: u64,
+}
+
+/*
+Struct:     -Traffic
+Purpose:    -Counts a room's gossip traffic for the status bar.
+
+Details:
+            - Fed by the receive loop (every message as received, chat
+              messages as they arrive) and the send path (every piece
+              broadcast, chat messages as they go out).
+            - Kept as per-second totals for the last MESSAGE_WINDOW seconds,
+              so memory stays fixed however busy the room is.
+*/
+#[derive(Debug, Clone)]
+pub struct Traffic {
+    start: Instant,
+    seconds: Arc<Mutex<VecDeque<Second>>>,
+}
+
+/// What Traffic::rates reports.
+pub struct Rates {
+    /// Bytes per second, received and sent.
+    pub received: u64,
+    pub sent: u64,
+    /// Chat messages per minute, either way.
+    pub messages: u64,
+}
+
+impl Default for Traffic {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            seconds: Arc::default(),
+        }
+    }
+}
+
+impl Traffic {
+    /// Add to the current second's totals, dropping seconds gone out of
+    /// the window.
+    fn count(&self, add: impl FnOnce(&mut Second)) {
+        let now = self.start.elapsed().as_secs();
+        let mut seconds = self.seconds.lock().expect("traffic lock poisoned");
+        while seconds.front().is_some_and(|s| s.at + MESSAGE_WINDOW <= now) {
+            seconds.pop_front();
+        }
+        if seconds.back().is_none_or(|s| s.at != now) {
+            seconds.push_back(Second { at: now, ..Second::default() });
+        }
+        add(seconds.back_mut().expect("just pushed"));
+    }
+
+    pub fn received(&self, bytes: usize) {
+        self.count(|s| s.received += bytes as u64);
+    }
+
+    pub fn sent(&self, bytes: usize) {
+        self.count(|s| s.sent += bytes as u64);
+    }
+
+    /// A chat message came in or went out.
+    pub fn message(&self) {
+        self.count(|s| s.messages += 1);
+    }
+
+    /// Current bandwidth and message rate.
+    pub fn rates(&self) -> Rates {
+        let now = self.start.elapsed().as_secs();
+        let seconds = self.seconds.lock().expect("traffic lock poisoned");
+        let recent = seconds.iter().filter(|s| s.at + BANDWIDTH_WINDOW > now);
+        let (received, sent) = recent.fold((0, 0), |(r, w), s| (r + s.received, w + s.sent));
+        let messages = seconds
+            .iter()
+            .filter(|s| s.at + MESSAGE_WINDOW > now)
+            .map(|s| s.messages)
+            .sum::<u64>();
+        Rates {
+            received: received / BANDWIDTH_WINDOW,
+            sent: sent / BANDWIDTH_WINDOW,
+            messages: messages * 60 / MESSAGE_WINDOW,
+        }
+    }
+}
+
+/// Byte count for display: B, KiB, MiB or GiB.
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crowded_rooms_stop_repeating_presence() {
+        let load = Load::new(LoadConfig { crowded_members: 50, congested_queue: 0 });
+        load.set_members(49);
+        assert!(!load.crowded());
+        assert!(load.greets_with_presence());
+        load.set_members(50);
+        assert!(load.crowded());
+        assert!(!load.greets_with_presence());
+
+        // 0 turns the limit off, however large the room.
+        let load = Load::new(LoadConfig { crowded_members: 0, congested_queue: 0 });
+        load.set_members(10_000);
+        assert!(!load.crowded());
+        assert!(load.greets_with_presence());
+    }
+
+    #[test]
+    fn congestion_holds_back_presence() {
+        let load = Load::new(LoadConfig { crowded_members: 0, congested_queue: 20 });
+        load.set_queued(19);
+        assert!(!load.congested());
+        load.set_queued(20);
+        assert!(load.congested());
+        assert!(!load.greets_with_presence());
+        load.hold_presence();
+        assert!(load.take_held_presence());
+        assert!(!load.take_held_presence());
+
+        let load = Load::new(LoadConfig { crowded_members: 0, congested_queue: 0 });
+        load.set_queued(10_000);
+        assert!(!load.congested());
+        assert!(load.greets_with_presence());
+    }
+
+    #[test]
+    fn only_recent_sightings_are_active() {
+        let sightings = Sightings::default();
+        let old = iroh::SecretKey::from_bytes(&[1; 32]).public();
+        let recent = iroh::SecretKey::from_bytes(&[2; 32]).public();
+        sightings.seen(old);
+        sightings.seen(recent);
+        sightings.0.lock().unwrap().get_mut(&old).unwrap().last -= chrono::TimeDelta::hours(1);
+        assert_eq!(sightings.active(Duration::from_secs(30 * 60)), 1);
+        assert_eq!(sightings.active(Duration::from_secs(2 * 60 * 60)), 2);
+    }
+}
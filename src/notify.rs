@@ -0,0 +1,195 @@
+use std::{collections::HashSet, io::Write};
+
+use anyhow::{Context, Result};
+use chrono::NaiveTime;
+use iroh::EndpointId;
+use notify_rust::Notification;
+use tracing::warn;
+
+use crate::config::NotifyConfig;
+use crate::i18n::t;
+
+// ── Desktop notifications ─────────────────────────────────────────────────────
+
+/// Maximum number of characters of the message body shown in a notification.
+const PREVIEW_LEN: usize = 80;
+
+/*
+Function:   -preview
+Purpose:    -Truncate message content to a short single-line preview.
+
+Parameters:
+            - &str content:  The full message text.
+
+Details:
+            - Newlines are collapsed to spaces so the preview fits one line.
+            - Content longer than PREVIEW_LEN characters is cut and suffixed
+              with an ellipsis. Truncation is by char, never splitting UTF-8.
+*/
+pub fn preview(content: &str) -> String {
+    let flat: String = content
+        .chars()
+        .map(|c| if c == '\n' { ' ' } else { c })
+        .collect();
+    if flat.chars().count() > PREVIEW_LEN {
+        let mut cut: String = flat.chars().take(PREVIEW_LEN).collect();
+        cut.push('…');
+        cut
+    } else {
+        flat
+    }
+}
+
+/*
+Function:   -notify
+Purpose:    -Raise a desktop notification for a newly received chat message.
+
+Parameters:
+            - String sender:  Display name of the sender, used as the summary.
+            - String content:  Message text, shown as a truncated preview.
+
+Details:
+            - Showing a notification can block on the session bus, so it runs
+              on tokio's blocking pool and never stalls the draw loop.
+            - If no notification daemon is available (headless box, SSH
+              session), falls back to ringing the terminal bell.
+*/
+pub fn notify(sender: String, content: String) {
+    tokio::task::spawn_blocking(move || {
+        let shown = Notification::new()
+            .summary(&sender)
+            .body(&preview(&content))
+            .appname("Encrypted Chat")
+            .show();
+        if shown.is_err() {
+            bell();
+        }
+    });
+}
+
+/// Ring the terminal bell. Used as a fallback when desktop notifications are
+/// unavailable.
+pub fn bell() {
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(b"\x07");
+    let _ = stdout.flush();
+}
+
+// ── Notification rules ────────────────────────────────────────────────────────
+
+/*
+Struct:     -Rules
+Purpose:    -Decides, in one place, whether a received message notifies.
+
+Fields:
+            - bool on_mention:  Mentions notify even when focused
+              (notify_on_mention).
+            - NotifyConfig config:  The [notify] table as last set, to show
+              and change with /notify.
+            - HashSet<EndpointId> muted:  config.muted, parsed.
+            - Option<(NaiveTime, NaiveTime)> quiet:  config.quiet_hours,
+              parsed.
+
+Details:
+            - Checked in order: muted senders and quiet hours never notify;
+              mentions (with on_mention) and keywords always do; anything
+              else does when we are not looking, unless mentions_only.
+            - Do-not-disturb is checked by the caller, before any of this.
+            - Invalid muted IDs and quiet hours in the config are ignored
+              with a warning rather than keeping the room from opening.
+*/
+#[derive(Debug, Clone)]
+pub struct Rules {
+    on_mention: bool,
+    pub config: NotifyConfig,
+    muted: HashSet<EndpointId>,
+    quiet: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::new(true, NotifyConfig::default())
+    }
+}
+
+impl Rules {
+    pub fn new(on_mention: bool, config: NotifyConfig) -> Self {
+        let muted = config
+            .muted
+            .iter()
+            .filter_map(|id| match id.parse() {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    warn!(id, error = %e, "ignoring invalid muted peer in config");
+                    None
+                }
+            })
+            .collect();
+        let quiet = config.quiet_hours.as_deref().and_then(|hours| match quiet_hours(hours) {
+            Ok(quiet) => Some(quiet),
+            Err(e) => {
+                warn!(hours, error = %e, "ignoring quiet hours in config");
+                None
+            }
+        });
+        Self { on_mention, config, muted, quiet }
+    }
+
+    /*
+    Function:   -allows
+    Purpose:    -Whether a received message should notify.
+
+    Parameters:
+                - EndpointId from:  Its sender.
+                - &str content:  Its text, for keywords.
+                - bool mentioned:  It mentions us or a filter highlighted it.
+                - bool away:  The newest messages are not on screen.
+                - NaiveTime now:  Local time of day, for quiet hours.
+    */
+    pub fn allows(&self, from: EndpointId, content: &str, mentioned: bool, away: bool, now: NaiveTime) -> bool {
+        if self.muted.contains(&from) || self.is_quiet(now) {
+            return false;
+        }
+        let text = content.to_lowercase();
+        let keyword = self.config.keywords.iter().any(|k| text.contains(&k.to_lowercase()));
+        if (mentioned && self.on_mention) || keyword {
+            return true;
+        }
+        away && !self.config.mentions_only
+    }
+
+    fn is_quiet(&self, now: NaiveTime) -> bool {
+        match self.quiet {
+            Some((start, end)) if start <= end => start <= now && now < end,
+            // Wraps past midnight, e.g. 22:00-07:00.
+            Some((start, end)) => now >= start || now < end,
+            None => false,
+        }
+    }
+
+    /// The rules as lines for /notify, with muted peers named by `label`.
+    pub fn describe(&self, label: impl Fn(EndpointId) -> String) -> Vec<String> {
+        let list = |items: &[String]| if items.is_empty() { t!("none").to_string() } else { items.join(", ") };
+        let mut muted: Vec<String> = self.muted.iter().map(|id| label(*id)).collect();
+        muted.sort();
+        vec![
+            t!("Mentions: {}", if self.on_mention { t!("always notify") } else { t!("like any message") }),
+            t!(
+                "Other messages: {}",
+                if self.config.mentions_only { t!("never") } else { t!("when not looking") }
+            ),
+            t!("Keywords: {}", list(&self.config.keywords)),
+            t!("Muted: {}", list(&muted)),
+            t!("Quiet hours: {}", self.config.quiet_hours.as_deref().unwrap_or(t!("none"))),
+        ]
+    }
+}
+
+/// Parse quiet hours given as "HH:MM-HH:MM".
+pub fn quiet_hours(text: &str) -> Result<(NaiveTime, NaiveTime)> {
+    let (start, end) = text.split_once('-').context("expected HH:MM-HH:MM, e.g. 22:00-07:00")?;
+    let time = |t: &str| {
+        NaiveTime::parse_from_str(t.trim(), "%H:%M").with_context(|| format!("invalid time {:?}", t.trim()))
+    };
+    Ok((time(start)?, time(end)?))
+}
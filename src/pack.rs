@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::keymap::Keymap;
+
+// ── Theme and keymap packs ────────────────────────────────────────────────────
+
+/// Largest pack file read; real ones are a few hundred bytes.
+const MAX_PACK: u64 = 64 * 1024;
+
+/*
+Struct:     -Pack
+Purpose:    -A theme and keymap to share with others: the palette sender
+             names are coloured from and the key bindings, in one TOML file
+             (pack export / pack import).
+
+Fields:
+            - String name:  What the pack is called, shown before it is
+              imported.
+            - Vec<String> nick_colors:  As DisplayConfig::nick_colors; empty
+              for the terminal's own colours.
+            - BTreeMap<String, Vec<String>> keys:  As Config::keys: only the
+              actions the pack binds differently from the defaults.
+
+Details:
+            - A pack is checked as it is read: every colour must parse and
+              the keys must make a valid Keymap, so a broken one is refused
+              before it touches the config.
+            - Importing replaces the profile's palette and [keys] table and
+              leaves every other setting alone.
+*/
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pack {
+    pub name: String,
+    pub nick_colors: Vec<String>,
+    pub keys: BTreeMap<String, Vec<String>>,
+}
+
+impl Pack {
+    /// The palette and keys a profile uses now, as a pack called `name`.
+    pub fn from_config(name: &str, config: &Config) -> Self {
+        Self {
+            name: name.to_string(),
+            nick_colors: config.display.nick_colors.clone(),
+            keys: config.keys.clone(),
+        }
+    }
+
+    /// Read and check a pack file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let size = std::fs::metadata(path).with_context(|| format!("reading {}", path.display()))?.len();
+        if size > MAX_PACK {
+            bail!("{} is too large for a pack ({} bytes)", path.display(), size);
+        }
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let pack: Self = toml::from_str(&text).with_context(|| format!("{} is not a pack", path.display()))?;
+        pack.keymap()?;
+        for color in &pack.nick_colors {
+            color.parse::<Color>().map_err(|_| anyhow!("unknown colour {:?} in nick_colors", color))?;
+        }
+        Ok(pack)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?).with_context(|| format!("writing {}", path.display()))
+    }
+
+    fn keymap(&self) -> Result<Keymap> {
+        Keymap::from_config(&self.keys).context("the pack's keys are invalid")
+    }
+
+    /// What importing the pack would change in `config`, one line each,
+    /// for the user to look over first.
+    pub fn preview(&self, config: &Config) -> Result<Vec<String>> {
+        let mut lines = vec![format!("Pack {:?}", self.name)];
+        if self.nick_colors != config.display.nick_colors {
+            let palette = |colors: &[String]| {
+                if colors.is_empty() {
+                    "the terminal's own".to_string()
+                } else {
+                    colors.join(", ")
+                }
+            };
+            lines.push(format!(
+                "Name colours: {} → {}",
+                palette(&config.display.nick_colors),
+                palette(&self.nick_colors)
+            ));
+        }
+        let current = Keymap::from_config(&config.keys).unwrap_or_default();
+        for (action, before, after) in current.changes(&self.keymap()?) {
+            lines.push(format!("{}: {} → {}", action, before, after));
+        }
+        if lines.len() == 1 {
+            lines.push("Nothing would change.".to_string());
+        }
+        Ok(lines)
+    }
+
+    /// Use the pack's palette and keys in `config`.
+    pub fn apply(&self, config: &mut Config) {
+        config.display.nick_colors = self.nick_colors.clone();
+        config.keys = self.keys.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_carry_colours_and_keys() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("solarized.toml");
+        let mut config = Config::default();
+        config.display.nick_colors = vec!["light-green".to_string(), "#268bd2".to_string()];
+        config.keys.insert("quit".to_string(), vec!["ctrl+q".to_string()]);
+        let pack = Pack::from_config("solarized", &config);
+        pack.write(&path)?;
+        let read = Pack::read(&path)?;
+        assert_eq!(read, pack);
+
+        let mut other = Config::default();
+        let preview = read.preview(&other)?;
+        assert!(preview.iter().any(|line| line.starts_with("Name colours:")));
+        assert!(preview.contains(&"quit: Ctrl+C → Ctrl+Q".to_string()));
+        read.apply(&mut other);
+        assert_eq!(read.preview(&other)?.last().map(String::as_str), Some("Nothing would change."));
+        Ok(())
+    }
+
+    #[test]
+    fn broken_packs_are_refused() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("broken.toml");
+        for text in [
+            "nick_colors = [\"not-a-colour\"]",
+            "[keys]\nquit = [\"q\"]\ninsert = [\"q\"]",
+            "[keys]\nlaunch = [\"x\"]",
+        ] {
+            std::fs::write(&path, text)?;
+            assert!(Pack::read(&path).is_err(), "{:?} was accepted", text);
+        }
+        Ok(())
+    }
+}
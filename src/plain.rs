@@ -0,0 +1,270 @@
+use std::io::{self, Write};
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+use crate::app::{App, ChatMessage, Trust, ChatEvent};
+use crate::commands;
+use crate::i18n::t;
+use crate::protocol::ChatPayload;
+use crate::session::Session;
+use crate::transcript::TranscriptLog;
+use crate::tui::{net_lines, pins_panel_lines, quit_signals, run_command, send_chat, swarm_lines, TabAction};
+use crate::wipe;
+
+// ── Plain mode ────────────────────────────────────────────────────────────────
+
+/// Shown while waiting for a line of input.
+const PROMPT: &str = "> ";
+
+/*
+Function:   -run
+Purpose:    -Chat in a room line by line on stdin and stdout, in place of the
+             TUI, for screen readers, braille displays and dumb terminals.
+
+Parameters:
+            - &mut Session session:  The room, already joined.
+            - Option<TranscriptLog> transcript:  Where to log chat messages,
+              as in the TUI.
+
+Details:
+            - No alternate screen, raw mode or colours: every message is
+              printed once, as a line of plain text, and typed lines are sent
+              when Enter is pressed. Continuation lines of a multi-line
+              message are indented.
+            - Slash commands work as in the TUI. Panels (/net, /swarm, /pins)
+              are printed instead; tabs are not available. /quit, Ctrl+D or
+              Ctrl+C leave the room.
+            - Polls and voice messages are shown, but voting and playing need
+              the TUI, which can select a message.
+            - Stdin is read asynchronously (see stdin), so leaving the room
+              leaves no thread behind parked in a read.
+            - Returns the wipe asked for with /wipe yes, for the caller to do
+              once the session is shut down.
+*/
+pub async fn run(session: &mut Session, transcript: Option<TranscriptLog>) -> Result<Option<wipe::Request>> {
+    let mut quit = quit_signals()?;
+    let mut app = App::new(session.my_name.clone());
+    app.messages.set_capacity(session.config.display.history);
+    app.creator = session.moderator;
+    app.transcript = transcript;
+    let mut lines = BufReader::new(stdin()?).lines();
+    prompt();
+    let mut wipe = None;
+    loop {
+        let before = app.messages.added();
+        let mut entered = false;
+        tokio::select! {
+            _ = quit.recv() => break,
+            line = lines.next_line() => {
+                // None: stdin was closed (Ctrl+D).
+                let Ok(Some(line)) = line else { break };
+                entered = true;
+                let line = line.trim_end();
+                if line == "/quit" {
+                    break;
+                }
+                if commands::is_command(line) {
+                    match run_command(&mut app, session, line).await {
+                        Some(TabAction::Wipe(request)) => {
+                            wipe = Some(request);
+                            break;
+                        }
+                        Some(_) => {
+                            app.add_message(ChatEvent::System(t!("Tabs are not available in plain mode.").to_string()));
+                        }
+                        None => {}
+                    }
+                    show_panels(&mut app, session);
+                } else if !line.is_empty() {
+                    send_chat(&mut app, session, ChatPayload::text(line.to_string())).await;
+                }
+            }
+            Some(ui) = session.events.recv() => {
+                if let ChatEvent::Recorded(clip) = ui {
+                    send_chat(&mut app, session, ChatPayload::voice(clip)).await;
+                } else {
+                    app.add_message(ui);
+                }
+            }
+        }
+        // Whatever the history gained is at its end.
+        let added = app.messages.since(before).next().is_some();
+        if added {
+            let mut out = io::stdout().lock();
+            // Start over the prompt rather than after it.
+            let _ = write!(out, "\r");
+            for msg in app.messages.since(before) {
+                for line in describe(msg) {
+                    let _ = writeln!(out, "{}", line);
+                }
+            }
+        }
+        if added || entered {
+            prompt();
+        }
+    }
+    println!();
+    Ok(wipe)
+}
+
+fn prompt() {
+    let mut out = io::stdout().lock();
+    let _ = write!(out, "{}", PROMPT);
+    let _ = out.flush();
+}
+
+/// Print the panels a command opened, as notes, and close them again.
+fn show_panels(app: &mut App, session: &Session) {
+    let mut lines = Vec::new();
+    if std::mem::take(&mut app.net_panel) {
+        lines.extend(net_lines(app, &session.peer_paths()));
+    }
+    if std::mem::take(&mut app.swarm_panel) {
+        let (links, reports) = session.swarm();
+        lines.extend(swarm_lines(app, session.my_id, &links, &reports));
+    }
+    if std::mem::take(&mut app.pins_panel) {
+        lines.extend(pins_panel_lines(app));
+    }
+    for line in lines {
+        app.add_message(ChatEvent::System(line.to_string()));
+    }
+}
+
+/// A history entry as lines of plain text; empty for the kinds not shown.
+fn describe(msg: &ChatEvent) -> Vec<String> {
+    match msg {
+        ChatEvent::Chat(chat) => chat_lines(chat),
+        ChatEvent::System(text) => text.split('\n').map(|line| format!("* {}", line)).collect(),
+        ChatEvent::Qr(rows) => rows.clone(),
+        _ => Vec::new(),
+    }
+}
+
+/// "[HH:MM] sender: text", then any further lines of the message indented.
+fn chat_lines(chat: &ChatMessage) -> Vec<String> {
+    let mut marks = String::new();
+    match chat.trust {
+        Trust::SharedKey => marks.push_str(t!(" (unverified)")),
+        Trust::Unencrypted => marks.push_str(t!(" (unencrypted)")),
+        _ => {}
+    }
+    if chat.expires.is_some() {
+        marks.push_str(t!(" (disappearing)"));
+    }
+    let text = match (&chat.voice, &chat.poll) {
+        (Some(voice), _) => t!("(voice message, {})", voice.duration()),
+        (_, Some(poll)) => t!("Poll: {}", poll.question),
+        _ => chat.content.clone(),
+    };
+    let mut text = text.split('\n');
+    let mut lines = vec![format!(
+        "[{}] {}{}: {}",
+        chat.at.format("%H:%M"),
+        chat.sender,
+        marks,
+        text.next().unwrap_or_default()
+    )];
+    lines.extend(text.map(|line| format!("    {}", line)));
+    if let Some(poll) = &chat.poll {
+        lines.extend(poll.options.iter().enumerate().map(|(n, option)| format!("    {}. {}", n + 1, option)));
+    }
+    lines
+}
+
+// ── Stdin ─────────────────────────────────────────────────────────────────────
+
+/*
+Function:   -stdin
+Purpose:    -Our stdin, for reading without tying up a thread.
+
+Details:
+            - On Unix it is polled by the runtime (see Stdin), so a pending
+              read is simply dropped when we quit.
+            - Elsewhere it is tokio's, which reads on a blocking thread; the
+              process exits without waiting for it.
+*/
+#[cfg(unix)]
+fn stdin() -> io::Result<impl AsyncRead + Unpin> {
+    Stdin::new()
+}
+
+#[cfg(not(unix))]
+fn stdin() -> io::Result<impl AsyncRead + Unpin> {
+    Ok(tokio::io::stdin())
+}
+
+/*
+Enum:       -Stdin
+Purpose:    -Stdin as an AsyncRead, on Unix.
+
+Variants:
+            - Polled:  A terminal or pipe, switched to non-blocking and
+              watched for readiness. The flags it had are put back on drop,
+              since the shell we were started from shares them.
+            - File:  A regular file or /dev/null, which cannot be polled but
+              never blocks either, so it is read directly.
+*/
+#[cfg(unix)]
+enum Stdin {
+    Polled(tokio::io::unix::AsyncFd<std::fs::File>, rustix::fs::OFlags),
+    File(std::fs::File),
+}
+
+#[cfg(unix)]
+impl Stdin {
+    fn new() -> io::Result<Self> {
+        use rustix::fs::{fcntl_getfl, fcntl_setfl, OFlags};
+        use std::os::fd::AsFd;
+
+        let file = std::fs::File::from(io::stdin().as_fd().try_clone_to_owned()?);
+        let flags = fcntl_getfl(&file)?;
+        fcntl_setfl(&file, flags | OFlags::NONBLOCK)?;
+        match tokio::io::unix::AsyncFd::try_new(file) {
+            Ok(polled) => Ok(Stdin::Polled(polled, flags)),
+            Err(e) => {
+                let (file, _) = e.into_parts();
+                fcntl_setfl(&file, flags)?;
+                Ok(Stdin::File(file))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsyncRead for Stdin {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        use std::io::Read;
+        use std::task::Poll;
+
+        let polled = match self.get_mut() {
+            Stdin::Polled(polled, _) => polled,
+            Stdin::File(file) => {
+                let n = file.read(buf.initialize_unfilled())?;
+                buf.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+        };
+        loop {
+            let mut ready = std::task::ready!(polled.poll_read_ready(cx))?;
+            if let Ok(read) = ready.try_io(|fd| fd.get_ref().read(buf.initialize_unfilled())) {
+                buf.advance(read?);
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Stdin {
+    fn drop(&mut self) {
+        if let Stdin::Polled(polled, flags) = self {
+            let _ = rustix::fs::fcntl_setfl(polled.get_ref(), *flags);
+        }
+    }
+}
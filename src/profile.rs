@@ -0,0 +1,370 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use iroh::SecretKey;
+
+use crate::config::Config;
+use crate::keychain;
+
+// ── Profiles ──────────────────────────────────────────────────────────────────
+
+/// Profile used when --profile is not given and none was chosen with
+/// `profile use`. Created on first use.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/*
+Struct:     -Profile
+Purpose:    -An isolated directory holding one identity, its config and its store.
+
+Fields:
+            - String name:  Profile name as given on the command line.
+            - PathBuf dir:  <config dir>/p2p-chat/profiles/<name>
+
+Details:
+            - Each profile has its own endpoint secret key, so two profiles are
+              two distinct peers and can run side by side in separate processes.
+            - Layout:
+                - config.toml:   user settings (see Config)
+                - identity.key:  hex-encoded endpoint secret key, unless
+                                 kept in the OS keychain
+                - drafts.json:   unsent input per room (see Drafts)
+                - rooms.json:    rooms joined by ticket (see RecentRooms)
+                - room-keys.json: keys of private rooms we created (see
+                                 CreatorKeys)
+                - store/:        local data owned by this profile
+                - backups/:      other peers' encrypted backups, when hosting
+*/
+#[derive(Clone)]
+pub struct Profile {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+impl Profile {
+    /// Directory containing all profiles.
+    pub fn root() -> Result<PathBuf> {
+        let base = dirs::config_dir().context("could not determine the config directory")?;
+        Ok(base.join("p2p-chat").join("profiles"))
+    }
+
+    /// File naming the profile chosen with `profile use`, next to the
+    /// profiles.
+    fn current_path() -> Result<PathBuf> {
+        Ok(Self::root()?.with_file_name("current-profile"))
+    }
+
+    /// The profile to run as when --profile is not given.
+    pub fn current() -> Result<String> {
+        let name = match std::fs::read_to_string(Self::current_path()?) {
+            Ok(name) => name.trim().to_string(),
+            Err(_) => return Ok(DEFAULT_PROFILE.to_string()),
+        };
+        validate_name(&name)?;
+        Ok(name)
+    }
+
+    /// Run as this existing profile from now on when --profile is not
+    /// given.
+    pub fn set_current(name: &str) -> Result<()> {
+        let profile = Self::open(name)?;
+        let path = Self::current_path()?;
+        std::fs::write(&path, &profile.name).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /*
+    Function:   -open
+    Purpose:    -Open an existing profile by name.
+
+    Parameters:
+                - &str name:  Profile name.
+
+    Details:
+                - The default profile is created on demand so first runs work
+                  without any setup.
+                - Any other profile must have been created with
+                  `profile create <name>` first; a typo should not silently
+                  produce a fresh identity.
+    */
+    pub fn open(name: &str) -> Result<Self> {
+        validate_name(name)?;
+        let dir = Self::root()?.join(name);
+        if !dir.is_dir() {
+            if name == DEFAULT_PROFILE {
+                return Self::create(name);
+            }
+            bail!(
+                "profile '{}' does not exist – create it with `profile create {}`",
+                name,
+                name
+            );
+        }
+        Ok(Self {
+            name: name.to_string(),
+            dir,
+        })
+    }
+
+    /*
+    Function:   -at
+    Purpose:    -Open a profile kept in any directory, e.g. one an app
+                 embedding the client manages, creating it if needed.
+
+    Parameters:
+                - PathBuf dir:  The profile's directory.
+
+    Details:
+                - Named after the directory. Unlike `create`, an existing
+                  profile is simply opened.
+    */
+    pub fn at(dir: PathBuf) -> Result<Self> {
+        let name = match dir.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => bail!("{} cannot hold a profile", dir.display()),
+        };
+        let profile = Self { name, dir };
+        std::fs::create_dir_all(profile.store_dir())
+            .with_context(|| format!("creating {}", profile.dir.display()))?;
+        Ok(profile)
+    }
+
+    /*
+    Function:   -create
+    Purpose:    -Create a new profile directory with a fresh identity.
+
+    Parameters:
+                - &str name:  Profile name. Letters, digits, '-' and '_' only.
+
+    Details:
+                - Fails if a profile with this name already exists.
+                - Writes an empty config.toml and generates identity.key.
+    */
+    pub fn create(name: &str) -> Result<Self> {
+        validate_name(name)?;
+        let dir = Self::root()?.join(name);
+        if dir.exists() {
+            bail!("profile '{}' already exists", name);
+        }
+        let profile = Self {
+            name: name.to_string(),
+            dir,
+        };
+        std::fs::create_dir_all(profile.store_dir())
+            .with_context(|| format!("creating {}", profile.dir.display()))?;
+        Config::default().save(&profile.config_path())?;
+        profile.secret_key()?;
+        Ok(profile)
+    }
+
+    /// Remove a profile and everything in it, including its identity key,
+    /// in the keychain too. If it was the current profile, the default one
+    /// takes over.
+    pub fn delete(name: &str) -> Result<()> {
+        validate_name(name)?;
+        let dir = Self::root()?.join(name);
+        if !dir.is_dir() {
+            bail!("profile '{}' does not exist", name);
+        }
+        let profile = Self {
+            name: name.to_string(),
+            dir: dir.clone(),
+        };
+        if Config::load(&profile.config_path())?.keychain {
+            keychain::delete(&profile.keychain_account("identity"))?;
+        }
+        std::fs::remove_dir_all(&dir).with_context(|| format!("removing {}", dir.display()))?;
+        if Self::current()? == name {
+            let _ = std::fs::remove_file(Self::current_path()?);
+        }
+        Ok(())
+    }
+
+    /// Names of all existing profiles, sorted.
+    pub fn list() -> Result<Vec<String>> {
+        let root = Self::root()?;
+        if !root.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.dir.join("config.toml")
+    }
+
+    pub fn identity_path(&self) -> PathBuf {
+        self.dir.join("identity.key")
+    }
+
+    pub fn store_dir(&self) -> PathBuf {
+        self.dir.join("store")
+    }
+
+    pub fn backups_dir(&self) -> PathBuf {
+        self.dir.join("backups")
+    }
+
+    pub fn drafts_path(&self) -> PathBuf {
+        self.dir.join("drafts.json")
+    }
+
+    pub fn names_path(&self) -> PathBuf {
+        self.dir.join("names.json")
+    }
+
+    pub fn rooms_path(&self) -> PathBuf {
+        self.dir.join("rooms.json")
+    }
+
+    pub fn room_keys_path(&self) -> PathBuf {
+        self.dir.join("room-keys.json")
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        self.dir.join("daemon.sock")
+    }
+
+    /// Name of one of this profile's secrets in the OS keychain. The
+    /// directory tells apart profiles with the same name (see `at`).
+    pub fn keychain_account(&self, secret: &str) -> String {
+        format!("{}/{}", self.dir.display(), secret)
+    }
+
+    /*
+    Function:   -secret_key
+    Purpose:    -Load this profile's endpoint secret key, generating it if absent.
+
+    Parameters:
+                - None
+
+    Details:
+                - The key is stored hex-encoded in identity.key, or in the OS
+                  keychain when the config says `keychain = true`. A key file
+                  left over from before is moved into the keychain then.
+                - On Unix the file is created with mode 0600.
+                - A persistent key keeps the profile's EndpointId stable across
+                  restarts, which is what makes it an identity.
+    */
+    pub fn secret_key(&self) -> Result<SecretKey> {
+        let in_keychain = Config::load(&self.config_path())?.keychain;
+        let account = self.keychain_account("identity");
+        if in_keychain && let Some(text) = keychain::get(&account)? {
+            return parse_key(&text).context("the identity key in the keychain is invalid");
+        }
+
+        let path = self.identity_path();
+        let key = if path.exists() {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            parse_key(&text).with_context(|| format!("{} is not a 32-byte key", path.display()))?
+        } else {
+            SecretKey::from_bytes(&rand::random())
+        };
+        if in_keychain {
+            keychain::set(&account, &hex::encode(key.to_bytes()))?;
+            if path.exists() {
+                std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+            }
+        } else if !path.exists() {
+            write_private(&path, hex::encode(key.to_bytes()).as_bytes())?;
+        }
+        Ok(key)
+    }
+
+    /// Replace this profile's identity key, wherever it is kept.
+    pub fn set_secret_key(&self, key: &SecretKey) -> Result<()> {
+        let text = hex::encode(key.to_bytes());
+        if Config::load(&self.config_path())?.keychain {
+            keychain::set(&self.keychain_account("identity"), &text)
+        } else {
+            write_private(&self.identity_path(), text.as_bytes())
+        }
+    }
+
+    /*
+    Function:   -set_keychain
+    Purpose:    -Move the identity key into the OS keychain, or back out to
+                 identity.key.
+
+    Parameters:
+                - bool on:  Into the keychain if true.
+
+    Details:
+                - Reads the key back before the old copy goes, so a keychain
+                  that silently drops secrets cannot lose the identity.
+                - Named rooms' passphrases saved in the keychain stay there;
+                  they are only used while the keychain is on.
+    */
+    pub fn set_keychain(&self, on: bool) -> Result<()> {
+        let mut config = Config::load(&self.config_path())?;
+        if config.keychain == on {
+            return Ok(());
+        }
+        let key = self.secret_key()?;
+        let account = self.keychain_account("identity");
+        if on {
+            keychain::set(&account, &hex::encode(key.to_bytes()))?;
+            if keychain::get(&account)?.as_deref() != Some(hex::encode(key.to_bytes()).as_str()) {
+                bail!("the keychain did not keep the identity key");
+            }
+            config.keychain = true;
+            config.save(&self.config_path())?;
+            std::fs::remove_file(self.identity_path())
+                .with_context(|| format!("removing {}", self.identity_path().display()))?;
+        } else {
+            write_private(&self.identity_path(), hex::encode(key.to_bytes()).as_bytes())?;
+            config.keychain = false;
+            config.save(&self.config_path())?;
+            keychain::delete(&account)?;
+        }
+        Ok(())
+    }
+}
+
+/// A hex-encoded secret key, as in identity.key.
+fn parse_key(text: &str) -> Result<SecretKey> {
+    let bytes: [u8; 32] = hex::decode(text.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("not a 32-byte key"))?;
+    Ok(SecretKey::from_bytes(&bytes))
+}
+
+/// Profile names become directory names, so keep them to a safe charset.
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!(
+            "invalid profile name '{}': use letters, digits, '-' and '_' only",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Write a file readable only by the current user.
+pub fn write_private(path: &std::path::Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("writing {}", path.display()))?;
+    file.write_all(contents)?;
+    Ok(())
+}
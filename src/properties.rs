@@ -0,0 +1,520 @@
+use std::net::SocketAddr;
+
+use iroh::{EndpointAddr, EndpointId, SecretKey, Signature};
+use iroh_gossip::proto::TopicId;
+use rand::{rngs::StdRng, RngExt, SeedableRng};
+
+use crate::crypto::{
+    current_step, decrypt_message, encrypt_message, open_with_key, seal_with_key, sealed_salt, verify_message,
+    MessageKey, SenderKeys,
+};
+use crate::protocol::{
+    ChatPayload, Interaction, Membership, Message, MessageBody, ModAction, Neighbour, Presence, Role, RoomInfo, Ticket,
+    MAX_MESSAGE, MAX_NAME, PROTOCOL_VERSION,
+};
+
+// ── Generators ────────────────────────────────────────────────────────────────
+
+/// Cases each property is checked against.
+const CASES: u64 = 256;
+
+/// First seed checked when PROPERTY_SEED is not set.
+const DEFAULT_SEED: u64 = 0x5eed;
+
+/*
+Function:   -check
+Purpose:    -Check `property` against CASES generated inputs, failing with
+             the seed of the first case that breaks it.
+
+Parameters:
+            - &str name:  The property, for the failure message.
+            - FnMut(&mut Gen) -> Result<(), String> property:  Draws its
+              input from the generator; Err says what went wrong.
+
+Details:
+            - Case n is generated from seed PROPERTY_SEED + n. PROPERTY_SEED
+              defaults to DEFAULT_SEED, so every run checks the same cases;
+              set it in the environment to try others, or to the seed in a
+              failure to replay that case first.
+*/
+fn check(name: &str, mut property: impl FnMut(&mut Gen) -> Result<(), String>) {
+    let base = std::env::var("PROPERTY_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(DEFAULT_SEED);
+    for case in 0..CASES {
+        let seed = base.wrapping_add(case);
+        if let Err(e) = property(&mut Gen(StdRng::seed_from_u64(seed))) {
+            panic!("{} failed (PROPERTY_SEED={}): {}", name, seed, e);
+        }
+    }
+}
+
+/// Random inputs for one case.
+struct Gen(StdRng);
+
+impl Gen {
+    fn u64(&mut self) -> u64 {
+        // Edges are where encodings break; favour them.
+        match self.0.random_range(0..4) {
+            0 => [0, 1, u64::MAX][self.0.random_range(0..3)],
+            1 => self.0.random_range(0..1000),
+            _ => self.0.random(),
+        }
+    }
+
+    fn bool(&mut self) -> bool {
+        self.0.random()
+    }
+
+    fn bytes(&mut self, max: usize) -> Vec<u8> {
+        let mut bytes = vec![0; self.0.random_range(0..=max)];
+        self.0.fill(&mut bytes[..]);
+        bytes
+    }
+
+    fn nonce(&mut self) -> [u8; 12] {
+        self.0.random()
+    }
+
+    /// Up to `max` bytes of text, heavy on what JSON has to escape.
+    fn text(&mut self, max: usize) -> String {
+        const AWKWARD: &[char] = &['"', '\\', '\n', '\u{0}', '\u{1f}', '\u{7f}', 'é', '€', '🦀', '\u{202e}', '\u{fffd}'];
+        let mut text = String::new();
+        for _ in 0..self.0.random_range(0..=max) {
+            let c = match self.0.random_range(0..3) {
+                0 => AWKWARD[self.0.random_range(0..AWKWARD.len())],
+                1 => self.0.random_range(' '..='~'),
+                _ => self.0.random(),
+            };
+            if text.len() + c.len_utf8() > max {
+                break;
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    fn secret_key(&mut self) -> SecretKey {
+        SecretKey::from_bytes(&self.0.random())
+    }
+
+    fn id(&mut self) -> EndpointId {
+        self.secret_key().public()
+    }
+
+    fn signature(&mut self) -> Signature {
+        let message = self.bytes(32);
+        self.secret_key().sign(&message)
+    }
+
+    fn list<T>(&mut self, max: usize, mut item: impl FnMut(&mut Self) -> T) -> Vec<T> {
+        (0..self.0.random_range(0..=max)).map(|_| item(self)).collect()
+    }
+
+    fn option<T>(&mut self, item: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        self.bool().then(|| item(self))
+    }
+
+    fn addr(&mut self) -> EndpointAddr {
+        let id = self.id();
+        let ips: Vec<SocketAddr> = self.list(3, |g| {
+            if g.bool() {
+                SocketAddr::from((g.0.random::<[u8; 4]>(), g.0.random()))
+            } else {
+                SocketAddr::from((g.0.random::<[u16; 8]>(), g.0.random()))
+            }
+        });
+        ips.into_iter().fold(EndpointAddr::new(id), EndpointAddr::with_ip_addr)
+    }
+
+    fn ticket(&mut self) -> Ticket {
+        Ticket {
+            topic: TopicId::from_bytes(self.0.random()),
+            endpoints: self.list(4, Self::addr),
+            creator: self.option(Self::id),
+            private: self.bool(),
+            expires: self.option(Self::u64),
+            token: self.option(Self::u64),
+            info: RoomInfo {
+                title: self.option(|g| g.text(MAX_NAME)),
+                creator_name: self.option(|g| g.text(MAX_NAME)),
+                created: self.option(Self::u64),
+            },
+        }
+    }
+
+    /// Any kind of body, with fields anywhere in their types' range.
+    fn body(&mut self) -> MessageBody {
+        let from = self.id();
+        match self.0.random_range(0..22) {
+            0 => MessageBody::AboutMe {
+                from,
+                name: self.text(MAX_NAME),
+                nonce: self.u64(),
+                signature: self.option(Self::signature),
+            },
+            1 => MessageBody::EncryptedMessage {
+                from,
+                id: self.u64(),
+                key_id: self.u64(),
+                step: self.u64(),
+                ciphertext: self.bytes(512),
+                nonce: self.nonce(),
+                pow: self.option(Self::u64),
+                signature: self.option(Self::signature),
+            },
+            2 => MessageBody::DeleteMessage {
+                from,
+                id: self.u64(),
+                signature: self.signature(),
+            },
+            3 => MessageBody::Interaction {
+                from,
+                to: self.id(),
+                interaction: Interaction {
+                    message_id: self.u64(),
+                    component_id: self.text(40),
+                    value: self.option(|g| g.text(40)),
+                },
+            },
+            4 => MessageBody::Knock {
+                from,
+                name: self.text(MAX_NAME),
+                token: self.option(Self::u64),
+            },
+            5 => MessageBody::Admit {
+                from,
+                to: self.id(),
+                epoch: self.u64(),
+                step: self.u64(),
+                wrapped: self.bytes(64),
+                nonce: self.nonce(),
+            },
+            6 => MessageBody::KeyRequest {
+                from,
+                sent: self.u64(),
+                nonce: self.u64(),
+                signature: self.signature(),
+            },
+            7 => MessageBody::SenderKey {
+                from,
+                to: self.id(),
+                room_epoch: self.u64(),
+                room_step: self.u64(),
+                key_id: self.u64(),
+                step: self.u64(),
+                wrapped: self.bytes(64),
+                nonce: self.nonce(),
+            },
+            8 => MessageBody::Rekey {
+                from,
+                epoch: self.u64(),
+                step: self.u64(),
+                keys: self.list(4, |g| (g.id(), g.bytes(64), g.nonce())),
+            },
+            9 => MessageBody::Membership {
+                from,
+                membership: Membership {
+                    version: self.u64(),
+                    roles: self.list(4, |g| {
+                        let role = [Role::Admin, Role::Member, Role::ReadOnly][g.0.random_range(0..3)];
+                        (g.id(), role)
+                    }),
+                },
+                signature: self.signature(),
+            },
+            10 => MessageBody::Moderation {
+                from,
+                action: if self.bool() {
+                    ModAction::Kick { target: self.id() }
+                } else {
+                    ModAction::Mute { target: self.id() }
+                },
+                signature: self.signature(),
+            },
+            11 => MessageBody::Pin {
+                from,
+                id: self.u64(),
+                pinned: self.bool(),
+                signature: self.signature(),
+            },
+            12 => MessageBody::Leave { from },
+            13 => MessageBody::Archive { from },
+            14 => MessageBody::Ack {
+                from,
+                to: self.id(),
+                id: self.u64(),
+            },
+            15 => MessageBody::Neighbours {
+                from,
+                neighbours: self.list(4, |g| Neighbour {
+                    id: g.id(),
+                    relayed: g.option(Self::bool),
+                }),
+                ask: self.bool(),
+            },
+            16 => MessageBody::Ping {
+                from,
+                to: self.id(),
+                nonce: self.u64(),
+            },
+            17 => MessageBody::Pong {
+                from,
+                to: self.id(),
+                nonce: self.u64(),
+            },
+            18 => MessageBody::Presence {
+                from,
+                presence: [Presence::Online, Presence::Away, Presence::Busy][self.0.random_range(0..3)],
+            },
+            19 => MessageBody::Revoke {
+                from,
+                token: self.u64(),
+                signature: self.signature(),
+            },
+            20 => MessageBody::Message {
+                from,
+                text: self.text(200),
+                id: self.u64(),
+            },
+            _ => MessageBody::Chunk {
+                from,
+                id: self.u64(),
+                index: self.0.random(),
+                total: self.0.random(),
+                data: self.text(256),
+            },
+        }
+    }
+
+    fn message(&mut self) -> Message {
+        let mut message = Message::new(self.body());
+        // Older clients send no version.
+        if self.bool() {
+            message.version = [0, PROTOCOL_VERSION, u32::MAX][self.0.random_range(0..3)];
+        }
+        message
+    }
+
+    /// `bytes` damaged at a few random places: flipped, cut short, dropped
+    /// or repeated bytes.
+    fn damage(&mut self, mut bytes: Vec<u8>) -> Vec<u8> {
+        for _ in 0..self.0.random_range(1..=4) {
+            if bytes.is_empty() {
+                break;
+            }
+            let at = self.0.random_range(0..bytes.len());
+            match self.0.random_range(0..4) {
+                0 => bytes[at] ^= 1 << self.0.random_range(0..8),
+                1 => bytes.truncate(at),
+                2 => {
+                    bytes.remove(at);
+                }
+                _ => bytes.insert(at, bytes[at]),
+            }
+        }
+        bytes
+    }
+}
+
+// ── Properties ────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        check("messages_round_trip", |g| {
+            let bytes = g.message().to_vec();
+            if bytes.len() > MAX_MESSAGE {
+                return Ok(());
+            }
+            let decoded = Message::from_bytes(&bytes).map_err(|e| format!("{:#} decoding {}", e, show(&bytes)))?;
+            let again = decoded.to_vec();
+            (again == bytes)
+                .then_some(())
+                .ok_or_else(|| format!("{} came back as {}", show(&bytes), show(&again)))
+        });
+    }
+
+    #[test]
+    fn names_are_cut_on_a_char_boundary() {
+        check("names_are_cut_on_a_char_boundary", |g| {
+            let name = g.text(4 * MAX_NAME);
+            let message = Message::new(MessageBody::about_me(g.id(), name.clone()));
+            let Ok(Message { body: MessageBody::AboutMe { name: cut, .. }, .. }) = Message::from_bytes(&message.to_vec())
+            else {
+                return Err(format!("AboutMe {:?} did not decode", name));
+            };
+            (cut.len() <= MAX_NAME && name.starts_with(&cut) && (cut == name || name.len() > MAX_NAME))
+                .then_some(())
+                .ok_or_else(|| format!("{:?} was cut to {:?}", name, cut))
+        });
+    }
+
+    #[test]
+    fn tickets_round_trip() {
+        check("tickets_round_trip", |g| {
+            let ticket = g.ticket();
+            let text = ticket.to_string();
+            let parsed: Ticket = text.parse().map_err(|e| format!("{:#} parsing {:?}", e, ticket))?;
+            (parsed.to_bytes() == ticket.to_bytes())
+                .then_some(())
+                .ok_or_else(|| format!("{:?} came back as {:?}", ticket, parsed))
+        });
+    }
+
+    #[test]
+    fn ticket_summaries_stay_on_one_line() {
+        check("ticket_summaries_stay_on_one_line", |g| {
+            let info = g.ticket().info;
+            match info.summary() {
+                Some(summary) if summary.chars().any(|c| c.is_control() || c == '\u{202e}') => {
+                    Err(format!("{:?} for {:?}", summary, info))
+                }
+                None if !info.is_empty() => Err(format!("no summary for {:?}", info)),
+                _ => Ok(()),
+            }
+        });
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic() {
+        check("arbitrary_bytes_never_panic", |g| {
+            let bytes = g.bytes(4096);
+            // Anything that does decode must encode again.
+            if let Ok(message) = Message::from_bytes(&bytes) {
+                message.to_vec();
+            }
+            let _ = ChatPayload::from_bytes(&bytes);
+            let _ = String::from_utf8(bytes).map(|text| text.parse::<Ticket>());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn damaged_messages_never_panic() {
+        check("damaged_messages_never_panic", |g| {
+            let bytes = g.message().to_vec();
+            let damaged = g.damage(bytes);
+            if let Ok(message) = Message::from_bytes(&damaged) {
+                message.to_vec();
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        check("deep_nesting_is_refused", |g| {
+            let depth = g.0.random_range(17..10_000);
+            let open = if g.bool() { "[" } else { "{\"a\":" };
+            let bytes = format!("{{\"body\":{}", open.repeat(depth)).into_bytes();
+            Message::from_bytes(&bytes)
+                .is_err()
+                .then_some(())
+                .ok_or_else(|| format!("nesting {} deep decoded", depth))
+        });
+    }
+
+    #[test]
+    fn payloads_survive_encryption() {
+        check("payloads_survive_encryption", |g| {
+            let payload = ChatPayload::text(g.text(1024));
+            let key = MessageKey {
+                epoch: g.u64(),
+                step: g.u64(),
+                key: g.0.random(),
+            };
+            let secret_key = g.secret_key();
+            let topic = TopicId::from_bytes(g.0.random());
+            let message = encrypt_message(&payload, &secret_key, &topic, key, g.u64()).map_err(|e| format!("{:#}", e))?;
+            // Signed by us, for this room only.
+            if !verify_message(&message.body, &topic).map_err(|e| format!("{:#}", e))? {
+                return Err("encrypt_message did not sign".to_string());
+            }
+            if verify_message(&message.body, &TopicId::from_bytes(g.0.random())).is_ok() {
+                return Err("the signature held in another room".to_string());
+            }
+            let MessageBody::EncryptedMessage { ciphertext, nonce, .. } = message.body else {
+                return Err("encrypt_message did not make an EncryptedMessage".to_string());
+            };
+            let decrypted = decrypt_message(&ciphertext, &nonce, &key.key).map_err(|e| format!("{:#}", e))?;
+            if decrypted.text != payload.text {
+                return Err(format!("{:?} decrypted as {:?}", payload.text, decrypted.text));
+            }
+            // Any damage to the ciphertext, or another key, is caught.
+            let damaged = g.damage(ciphertext.clone());
+            if damaged != ciphertext && decrypt_message(&damaged, &nonce, &key.key).is_ok() {
+                return Err(format!("damaged ciphertext for {:?} decrypted", payload.text));
+            }
+            let other: [u8; 32] = g.0.random();
+            if other != key.key && decrypt_message(&ciphertext, &nonce, &other).is_ok() {
+                return Err(format!("{:?} decrypted under another key", payload.text));
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn arbitrary_ciphertexts_are_refused() {
+        check("arbitrary_ciphertexts_are_refused", |g| {
+            let ciphertext = g.bytes(1024);
+            decrypt_message(&ciphertext, &g.nonce(), &g.0.random())
+                .is_err()
+                .then_some(())
+                .ok_or_else(|| format!("{} bytes of noise decrypted", ciphertext.len()))
+        });
+    }
+
+    #[test]
+    fn sender_keys_start_near_the_clock() {
+        check("sender_keys_start_near_the_clock", |g| {
+            let keys = SenderKeys::default();
+            let now = current_step();
+            // Any step a peer likes, but mostly ones near ours.
+            let step = match g.0.random_range(0..3) {
+                0 => g.u64(),
+                _ => now.saturating_sub(4) + g.0.random_range(0..8),
+            };
+            let key = MessageKey { epoch: g.u64(), step, key: g.0.random() };
+            let near = step.saturating_add(2) >= now && step <= now + 1;
+            if keys.insert(g.id(), key) != near {
+                return Err(format!("a key at step {} was {} at step {}", step, if near { "refused" } else { "taken" }, now));
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn sealed_data_opens_with_its_key_only() {
+        check("sealed_data_opens_with_its_key_only", |g| {
+            let data = g.bytes(1024);
+            let (key, salt): ([u8; 32], [u8; 16]) = (g.0.random(), g.0.random());
+            let sealed = seal_with_key(&data, &key, salt).map_err(|e| format!("{:#}", e))?;
+            if sealed_salt(&sealed).ok() != Some(&salt) {
+                return Err("the salt did not survive sealing".to_string());
+            }
+            let opened = open_with_key(&sealed, &key).map_err(|e| format!("{:#}", e))?;
+            if opened != data {
+                return Err(format!("{} sealed bytes opened as {}", data.len(), opened.len()));
+            }
+            let damaged = g.damage(sealed.clone());
+            if damaged != sealed && damaged.get(..16) == Some(&salt[..]) && open_with_key(&damaged, &key).is_ok() {
+                return Err(format!("damaged seal over {} opened", show(&data)));
+            }
+            let other: [u8; 32] = g.0.random();
+            if other != key && open_with_key(&sealed, &other).is_ok() {
+                return Err(format!("{} opened under another key", show(&data)));
+            }
+            Ok(())
+        });
+    }
+
+    /// Bytes for a failure message: as text when they are, else as hex.
+    fn show(bytes: &[u8]) -> String {
+        std::str::from_utf8(bytes).map_or_else(|_| hex::encode(bytes), str::to_string)
+    }
+}
@@ -1,22 +1,74 @@
-use std::{fmt, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    time::Duration,
+};
+#[cfg(not(target_family = "wasm"))]
+use std::time::Instant;
 
-use anyhow::Result;
-use iroh::{EndpointAddr, EndpointId};
+use anyhow::{bail, ensure, Result};
+use data_encoding::BASE64;
+use iroh::{EndpointAddr, EndpointId, Signature};
 use iroh_gossip::proto::TopicId;
 use serde::{Deserialize, Serialize};
+#[cfg(target_family = "wasm")]
+use web_time::Instant;
+
+use crate::i18n::t;
 
 // ── Wire protocol ─────────────────────────────────────────────────────────────
 
+/// Envelope version we send. Messages without one (0) are from clients that
+/// predate the envelope; they decode the same.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/*
+Struct:     -Message
+Purpose:    -The envelope every message in a room travels in.
+
+Fields:
+            - u32 version:  PROTOCOL_VERSION of the sender; 0, and left out
+              on the wire, for older clients.
+            - MessageBody body:  What the message is, tagged by its kind.
+
+Details:
+            - Unknown fields are ignored, so a newer client may add fields
+              to the envelope or to a body without breaking older ones.
+            - A body of a kind we do not know (a newer client's) fails to
+              decode with UnknownKind rather than as malformed, so the
+              receiver can skip it quietly; see Message::from_bytes.
+*/
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Message {
+    #[serde(default, skip_serializing_if = "is_legacy")]
+    pub version: u32,
     pub body: MessageBody,
 }
 
+fn is_legacy(version: &u32) -> bool {
+    *version == 0
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum MessageBody {
+    /// `from`'s nickname. Sent on joining, again every few minutes and when
+    /// a neighbour connects, so late joiners learn it too. `nonce` makes
+    /// each announcement differ, as gossip drops bytes it has already
+    /// delivered; older clients leave it out, and `signature`, `from`'s
+    /// over the name and nonce (see crypto::sign_about_me), too. Only
+    /// signed names are saved to the profile.
     AboutMe {
         from: EndpointId,
         name: String,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        nonce: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<Signature>,
     },
     /// Encrypted chat message.
     EncryptedMessage {
@@ -24,25 +76,290 @@ pub enum MessageBody {
         /// Unique message ID, stored outside the ciphertext so peers can
         /// reference it for deletion without decrypting first.
         id: u64,
+        /// Which of the sender's keys encrypted it (see SenderKeys). 0 from
+        /// older clients, which use the topic key.
+        #[serde(default)]
+        key_id: u64,
+        /// Ratchet step of that key; 0 from older clients.
+        #[serde(default)]
+        step: u64,
         ciphertext: Vec<u8>,
         nonce: [u8; 12],
+        /// Proof-of-work stamp (see crypto::mint_pow), for public rooms that
+        /// ask for one; absent otherwise.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pow: Option<u64>,
+        /// `from`'s signature over the rest (see crypto::verify_message).
+        /// Sender keys are handed to every member, so this, not the key,
+        /// shows who wrote it. Absent from older clients.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<Signature>,
+    },
+    /// Plaintext chat message of the legacy protocol, from clients that
+    /// predate encryption. Current clients only send one next to the
+    /// encrypted message, with its `id`, when Config::legacy_compat is on;
+    /// legacy clients leave `id` out.
+    Message {
+        from: EndpointId,
+        text: String,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        id: u64,
     },
     /// Cooperative delete request – all peers should remove the message with
-    /// this ID from their display. Only honored when `from` matches the
-    /// original sender.
+    /// this ID from their display. `signature` is `from`'s signature over the
+    /// ID (see crypto::sign_delete); it is only honored when `from` is the
+    /// original sender or an admin.
     DeleteMessage {
         from: EndpointId,
         id: u64,
+        signature: Signature,
+    },
+    /// A click on a component of a bot message, addressed to the message's
+    /// author (`to`). Gossiped to the whole room; everyone else ignores it.
+    Interaction {
+        from: EndpointId,
+        to: EndpointId,
+        interaction: Interaction,
     },
+    /// Request to join a private room. Only the creator acts on it. `token`
+    /// is the invite token from our ticket, if it had one.
+    Knock {
+        from: EndpointId,
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<u64>,
+    },
+    /// The creator letting `to` into a private room: the room key, wrapped
+    /// so only `to` can read it (see crypto::wrap_room_key).
+    Admit {
+        from: EndpointId,
+        to: EndpointId,
+        epoch: u64,
+        step: u64,
+        wrapped: Vec<u8>,
+        nonce: [u8; 12],
+    },
+    /// Ask every peer to hand us their sender key, on joining and whenever
+    /// ours has changed. Signed by `from` over `sent` (seconds since the
+    /// Unix epoch) and `nonce` (see crypto::sign_key_request), so nobody
+    /// can make the room send keys on someone else's behalf, and no two
+    /// requests are alike for gossip to drop as repeats.
+    KeyRequest {
+        from: EndpointId,
+        sent: u64,
+        nonce: u64,
+        signature: Signature,
+    },
+    /// A sender key for one peer, wrapped to them and bound to the room key
+    /// at (room_epoch, room_step).
+    SenderKey {
+        from: EndpointId,
+        to: EndpointId,
+        room_epoch: u64,
+        room_step: u64,
+        key_id: u64,
+        step: u64,
+        wrapped: Vec<u8>,
+        nonce: [u8; 12],
+    },
+    /// The creator rotating a private room's key: the key for `epoch` as of
+    /// ratchet `step`, wrapped for each remaining member as (member,
+    /// ciphertext, nonce).
+    Rekey {
+        from: EndpointId,
+        epoch: u64,
+        step: u64,
+        keys: Vec<(EndpointId, Vec<u8>, [u8; 12])>,
+    },
+    /// The room's roles, signed by the creator like Moderation. Peers keep
+    /// the highest `version` they have seen.
+    Membership {
+        from: EndpointId,
+        membership: Membership,
+        signature: Signature,
+    },
+    /// Moderation action by the room creator. `signature` covers the topic
+    /// and the action, and is checked against the creator's key from the
+    /// ticket; anything else is ignored.
+    Moderation {
+        from: EndpointId,
+        action: ModAction,
+        signature: Signature,
+    },
+    /// The creator withdrawing the invite `token` of a private room (see
+    /// crypto::sign_revoke). Knocks presenting it are turned away from then
+    /// on; the creator follows it with a Rekey that leaves out whoever got in
+    /// with it. Repeated to newcomers like Moderation.
+    Revoke {
+        from: EndpointId,
+        token: u64,
+        signature: Signature,
+    },
+    /// Pin (or, with `pinned` false, unpin) the message with this ID for
+    /// everyone. `signature` is `from`'s (see crypto::sign_pin); in a room
+    /// with a creator only admins' pins are honored.
+    Pin {
+        from: EndpointId,
+        id: u64,
+        pinned: bool,
+        signature: Signature,
+    },
+    /// Sent on quit, so peers drop our name at once rather than keep
+    /// showing it until it goes stale.
+    Leave { from: EndpointId },
+    /// `from` keeps the room's history and hands it out over
+    /// archive::ARCHIVE_ALPN. Sent by `--archive` peers when someone joins.
+    Archive { from: EndpointId },
+    /// `from` received and could read our chat message `id`; addressed to
+    /// its sender (`to`). Sent after a random delay, and not at all by
+    /// members who see another's ack first. Unsigned: at worst a forged one
+    /// marks a message delivered too early.
+    Ack { from: EndpointId, to: EndpointId, id: u64 },
+    /// `from`'s gossip neighbours, for /swarm. With `ask` set, everyone
+    /// answers with their own (`ask` unset).
+    Neighbours {
+        from: EndpointId,
+        neighbours: Vec<Neighbour>,
+        ask: bool,
+    },
+    /// /ping: `to` should answer with a Pong carrying the same `nonce`.
+    Ping { from: EndpointId, to: EndpointId, nonce: u64 },
+    /// Answer to a Ping from `to`.
+    Pong { from: EndpointId, to: EndpointId, nonce: u64 },
+    /// `from` set their presence (/status, or away after a while without
+    /// typing). Repeated to newcomers unless it is Online.
+    Presence { from: EndpointId, presence: Presence },
+    /// Piece `index` of `total` of a message too large to gossip whole (see
+    /// fragment). `id` ties the pieces together; `data` is base64.
+    Chunk {
+        from: EndpointId,
+        id: u64,
+        index: u32,
+        total: u32,
+        data: String,
+    },
+}
+
+/// Every MessageBody kind, as tagged on the wire. A body tagged with
+/// anything else is from a newer client.
+const KINDS: &[&str] = &[
+    "AboutMe",
+    "EncryptedMessage",
+    "Message",
+    "DeleteMessage",
+    "Interaction",
+    "Knock",
+    "Admit",
+    "KeyRequest",
+    "SenderKey",
+    "Rekey",
+    "Membership",
+    "Moderation",
+    "Revoke",
+    "Pin",
+    "Leave",
+    "Archive",
+    "Ack",
+    "Neighbours",
+    "Ping",
+    "Pong",
+    "Presence",
+    "Chunk",
+];
+
+/// Longest kind name reported in UnknownKind; longer ones count as
+/// malformed.
+const MAX_KIND: usize = 32;
+
+/// A message whose body is of a kind this version does not know, as
+/// returned (inside anyhow::Error) by Message::from_bytes.
+#[derive(Debug)]
+pub struct UnknownKind {
+    /// The body's tag, e.g. "Reaction".
+    pub kind: String,
+    /// The sender's PROTOCOL_VERSION.
+    pub version: u32,
+}
+
+impl fmt::Display for UnknownKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown message kind {} (protocol version {})", self.kind, self.version)
+    }
+}
+
+impl std::error::Error for UnknownKind {}
+
+impl MessageBody {
+    /// An unsigned announcement of our nickname, unlike any sent before,
+    /// as older clients send it; see crypto::sign_about_me.
+    pub fn about_me(from: EndpointId, name: String) -> Self {
+        MessageBody::AboutMe {
+            from,
+            name,
+            nonce: rand::random(),
+            signature: None,
+        }
+    }
+
+    /// The endpoint that sent this message.
+    pub fn from(&self) -> EndpointId {
+        match self {
+            MessageBody::AboutMe { from, .. }
+            | MessageBody::EncryptedMessage { from, .. }
+            | MessageBody::Message { from, .. }
+            | MessageBody::DeleteMessage { from, .. }
+            | MessageBody::Pin { from, .. }
+            | MessageBody::Interaction { from, .. }
+            | MessageBody::Knock { from, .. }
+            | MessageBody::Admit { from, .. }
+            | MessageBody::Rekey { from, .. }
+            | MessageBody::KeyRequest { from, .. }
+            | MessageBody::Leave { from }
+            | MessageBody::Archive { from }
+            | MessageBody::Ack { from, .. }
+            | MessageBody::Neighbours { from, .. }
+            | MessageBody::Ping { from, .. }
+            | MessageBody::Pong { from, .. }
+            | MessageBody::Presence { from, .. }
+            | MessageBody::Chunk { from, .. }
+            | MessageBody::SenderKey { from, .. }
+            | MessageBody::Membership { from, .. }
+            | MessageBody::Moderation { from, .. }
+            | MessageBody::Revoke { from, .. } => *from,
+        }
+    }
 }
 
 impl Message {
+    /*
+    Function:   -from_bytes
+    Purpose:    -Decode a message from the room, which anyone may have sent.
+
+    Details:
+                - Refused unread when over MAX_MESSAGE bytes or nested deeper
+                  than MAX_DEPTH, so what a peer can make us allocate stays
+                  in proportion to what it sent.
+                - Names are cut to MAX_NAME bytes.
+                - A well-formed envelope around a body of a kind not in KINDS
+                  fails with UnknownKind; anything else that does not decode
+                  fails with serde's error.
+    */
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        serde_json::from_slice(bytes).map_err(Into::into)
+        ensure!(bytes.len() <= MAX_MESSAGE, "message too large ({} bytes)", bytes.len());
+        check_depth(bytes)?;
+        let mut message: Self = match serde_json::from_slice(bytes) {
+            Ok(message) => message,
+            Err(e) => return Err(unknown_kind(bytes).map_or_else(|| e.into(), Into::into)),
+        };
+        if let MessageBody::AboutMe { name, .. } | MessageBody::Knock { name, .. } = &mut message.body {
+            name.truncate(name.floor_char_boundary(MAX_NAME));
+        }
+        Ok(message)
     }
 
     pub fn new(body: MessageBody) -> Self {
         Self {
+            version: PROTOCOL_VERSION,
             body,
         }
     }
@@ -52,12 +369,745 @@ impl Message {
     }
 }
 
+/// The kind of a message that failed to decode, if it is an envelope with a
+/// body of a kind we do not know.
+fn unknown_kind(bytes: &[u8]) -> Option<UnknownKind> {
+    #[derive(Deserialize)]
+    struct Envelope {
+        #[serde(default)]
+        version: u32,
+        body: HashMap<String, serde::de::IgnoredAny>,
+    }
+    let envelope: Envelope = serde_json::from_slice(bytes).ok()?;
+    let mut kinds = envelope.body.into_keys();
+    let kind = kinds.next()?;
+    let named = !kind.is_empty()
+        && kind.len() <= MAX_KIND
+        && kind.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    (kinds.next().is_none() && named && !KINDS.contains(&kind.as_str()))
+        .then_some(UnknownKind { kind, version: envelope.version })
+}
+
+/// Longest member name taken from the room, in bytes.
+pub const MAX_NAME: usize = 64;
+
+/// Deepest nesting of arrays and objects in anything decoded from a peer.
+/// Ours go six levels deep at most (a Rekey's keys).
+const MAX_DEPTH: usize = 16;
+
+/// Fail for JSON nested deeper than MAX_DEPTH, before serde recurses into it.
+fn check_depth(bytes: &[u8]) -> Result<()> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in bytes {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                ensure!(depth <= MAX_DEPTH, "message nested too deeply");
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Most neighbours a /swarm report may list; gossip keeps far fewer.
+pub const MAX_NEIGHBOURS: usize = 32;
+
+/// One of a member's gossip neighbours and how they are connected: over a
+/// relay, directly, or (None) not yet known.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Neighbour {
+    pub id: EndpointId,
+    pub relayed: Option<bool>,
+}
+
+/// Whether a member is around, shown next to their name in /members.
+/// Members who never said are Online.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+    #[default]
+    Online,
+    Away,
+    Busy,
+}
+
+impl Presence {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "online" => Some(Presence::Online),
+            "away" => Some(Presence::Away),
+            "busy" => Some(Presence::Busy),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Presence::Online => t!("online"),
+            Presence::Away => t!("away"),
+            Presence::Busy => t!("busy"),
+        }
+    }
+}
+
+// ── Chunking ──────────────────────────────────────────────────────────────────
+
+/// Largest message we gossip in one piece. Gossip refuses frames over 4096
+/// bytes (its default max_message_size); this leaves room for its framing.
+pub const MAX_GOSSIP_MESSAGE: usize = 3584;
+
+/// Bytes of the original message per Chunk; base64 and the JSON around it
+/// stay under MAX_GOSSIP_MESSAGE.
+const CHUNK_SIZE: usize = 2048;
+
+/// Most pieces one message may be split into (about 512 KiB).
+pub const MAX_CHUNKS: u32 = 256;
+
+/// Largest encoded message we read, chunked or not: what MAX_CHUNKS pieces
+/// hold. Anything bigger is dropped before it is parsed.
+pub const MAX_MESSAGE: usize = MAX_CHUNKS as usize * CHUNK_SIZE;
+
+/// Chunked messages one peer may have in flight at once.
+const MAX_PARTIAL_PER_PEER: usize = 4;
+
+/// Chunked messages in flight from everyone together: with MAX_MESSAGE each,
+/// at most 32 MiB.
+const MAX_PARTIAL: usize = 64;
+
+/// A message whose pieces have not all arrived within this time is dropped.
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/*
+Function:   -fragment
+Purpose:    -Split an encoded message into pieces gossip will carry.
+
+Parameters:
+            - Vec<u8> bytes:  The encoded Message.
+            - EndpointId from:  Our endpoint, for each Chunk.
+
+Details:
+            - Messages up to MAX_GOSSIP_MESSAGE go out as they are, so peers
+              that predate chunking still read everything that fits.
+            - Fails for messages over MAX_CHUNKS pieces.
+*/
+pub fn fragment(bytes: Vec<u8>, from: EndpointId) -> Result<Vec<Vec<u8>>> {
+    if bytes.len() <= MAX_GOSSIP_MESSAGE {
+        return Ok(vec![bytes]);
+    }
+    let total = bytes.len().div_ceil(CHUNK_SIZE);
+    ensure!(
+        total <= MAX_CHUNKS as usize,
+        "message too large ({} bytes)",
+        bytes.len()
+    );
+    let id = rand::random();
+    Ok(bytes
+        .chunks(CHUNK_SIZE)
+        .enumerate()
+        .map(|(index, piece)| {
+            Message::new(MessageBody::Chunk {
+                from,
+                id,
+                index: index as u32,
+                total: total as u32,
+                data: BASE64.encode(piece),
+            })
+            .to_vec()
+        })
+        .collect())
+}
+
+/// The pieces of one message received so far.
+struct Partial {
+    pieces: Vec<Option<Vec<u8>>>,
+    started: Instant,
+}
+
+/*
+Struct:     -Reassembly
+Purpose:    -Puts chunked messages back together on the receiving side.
+
+Details:
+            - Keyed by sender and message ID, so pieces of different messages
+              cannot mix.
+            - Incomplete messages are dropped after REASSEMBLY_TIMEOUT. Along
+              with MAX_PARTIAL_PER_PEER and MAX_PARTIAL this bounds what peers
+              can make us hold: pieces of a peer's further messages are
+              refused until earlier ones complete or time out, and once
+              MAX_PARTIAL are in flight the oldest is dropped for a new one,
+              so peers filling the table cannot stop everyone else's.
+*/
+#[derive(Default)]
+pub struct Reassembly {
+    partial: HashMap<(EndpointId, u64), Partial>,
+}
+
+impl Reassembly {
+    /// Store one piece; returns the whole message once every piece is in.
+    pub fn add(
+        &mut self,
+        from: EndpointId,
+        id: u64,
+        index: u32,
+        total: u32,
+        data: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        self.partial.retain(|_, partial| partial.started.elapsed() < REASSEMBLY_TIMEOUT);
+        ensure!(total <= MAX_CHUNKS && index < total, "bad chunk {}/{}", index, total);
+        ensure!(data.len() <= BASE64.encode_len(CHUNK_SIZE), "oversized chunk");
+        let piece = BASE64.decode(data.as_bytes())?;
+        ensure!(piece.len() <= CHUNK_SIZE, "oversized chunk");
+        if !self.partial.contains_key(&(from, id)) {
+            let from_peer = self.partial.keys().filter(|(sender, _)| *sender == from).count();
+            ensure!(from_peer < MAX_PARTIAL_PER_PEER, "too many chunked messages in flight");
+            if self.partial.len() >= MAX_PARTIAL
+                && let Some(oldest) = self.partial.iter().min_by_key(|(_, partial)| partial.started).map(|(key, _)| *key)
+            {
+                self.partial.remove(&oldest);
+            }
+        }
+        let partial = self.partial.entry((from, id)).or_insert_with(|| Partial {
+            pieces: vec![None; total as usize],
+            started: Instant::now(),
+        });
+        ensure!(partial.pieces.len() == total as usize, "chunk count changed");
+        partial.pieces[index as usize] = Some(piece);
+        if partial.pieces.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+        let partial = self.partial.remove(&(from, id)).expect("just inserted");
+        Ok(Some(partial.pieces.into_iter().flatten().flatten().collect()))
+    }
+}
+
+// ── Moderation ────────────────────────────────────────────────────────────────
+
+/*
+Enum:       -ModAction
+Purpose:    -A moderation decision about one member of the room.
+
+Variants:
+            - Kick { target }:  Ignore everything from `target`, including its
+              name announcements.
+            - Mute { target }:  Ignore chat messages and interactions from
+              `target`; it stays listed in the room.
+
+Details:
+            - Only valid when signed by the room creator; see
+              crypto::sign_moderation.
+            - Actions last for the lifetime of the session. The creator repeats
+              them to newcomers so late joiners enforce them too.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ModAction {
+    Kick { target: EndpointId },
+    Mute { target: EndpointId },
+}
+
+impl ModAction {
+    pub fn target(&self) -> EndpointId {
+        match self {
+            ModAction::Kick { target } | ModAction::Mute { target } => *target,
+        }
+    }
+}
+
+// ── Roles ─────────────────────────────────────────────────────────────────────
+
+/*
+Enum:       -Role
+Purpose:    -What a member may do in a room.
+
+Variants:
+            - Admin:  May delete anyone's messages.
+            - Member:  The default for everyone not listed.
+            - ReadOnly:  Sees the room, but peers ignore its chat messages and
+              interactions.
+
+Details:
+            - Enforced by each client on what it receives; a read-only member
+              can still broadcast, it is just not shown.
+            - The room creator is always an admin, whatever the document says.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Member,
+    ReadOnly,
+}
+
+impl Role {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "admin" => Some(Role::Admin),
+            "member" => Some(Role::Member),
+            "readonly" | "read-only" | "read_only" => Some(Role::ReadOnly),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Role::Admin => t!("admin"),
+            Role::Member => t!("member"),
+            Role::ReadOnly => t!("read-only"),
+        }
+    }
+}
+
+/// The room's role assignments. Members not listed have Role::Member.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Membership {
+    /// Bumped by the creator on every change; older documents are ignored.
+    pub version: u64,
+    pub roles: Vec<(EndpointId, Role)>,
+}
+
+impl Membership {
+    /// Role of `id`, given the room's creator (always an admin).
+    pub fn role(&self, id: EndpointId, creator: Option<EndpointId>) -> Role {
+        if Some(id) == creator {
+            return Role::Admin;
+        }
+        self.roles
+            .iter()
+            .find(|(member, _)| *member == id)
+            .map(|(_, role)| *role)
+            .unwrap_or(Role::Member)
+    }
+
+    /// A copy with `id` set to `role` and the version bumped.
+    pub fn with_role(&self, id: EndpointId, role: Role) -> Self {
+        let mut roles: Vec<(EndpointId, Role)> =
+            self.roles.iter().copied().filter(|(member, _)| *member != id).collect();
+        if role != Role::Member {
+            roles.push((id, role));
+        }
+        Self {
+            version: self.version + 1,
+            roles,
+        }
+    }
+}
+
+// ── Encrypted payload ─────────────────────────────────────────────────────────
+
+/// Plaintext carried inside `EncryptedMessage::ciphertext`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatPayload {
+    pub text: String,
+    /// Machine-readable annotations for bots and plugins (correlation IDs,
+    /// card layouts, ...). Human clients ignore it; unknown keys inside are
+    /// preserved as-is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+    /// Interactive components (buttons, selects) attached by bots.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<Component>,
+    /// Seconds after receipt at which every peer removes the message.
+    /// Relative rather than absolute, so clock skew between peers does not
+    /// matter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    /// A recorded voice message (/voice).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voice: Option<VoiceClip>,
+    /// New room topic (/topic); empty clears it. Such a payload changes the
+    /// room's state rather than being shown as chat; `text` says what
+    /// happened for clients that predate topics.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// A poll (/poll). `text` repeats the question and options for clients
+    /// that predate polls.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<Poll>,
+    /// A vote in a poll. Like a topic change it updates state rather than
+    /// being shown as chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vote: Option<Vote>,
+}
+
+impl ChatPayload {
+    /// A plain text message with no metadata.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            meta: None,
+            components: Vec::new(),
+            ttl: None,
+            voice: None,
+            subject: None,
+            poll: None,
+            vote: None,
+        }
+    }
+
+    /// A voice message with no text.
+    pub fn voice(clip: VoiceClip) -> Self {
+        Self {
+            voice: Some(clip),
+            ..Self::text("")
+        }
+    }
+
+    /// A room topic change; an empty subject clears the topic.
+    pub fn subject(subject: impl Into<String>) -> Self {
+        let mut subject: String = subject.into();
+        subject.truncate(subject.floor_char_boundary(MAX_SUBJECT));
+        let text = if subject.is_empty() {
+            "cleared the room topic".to_string()
+        } else {
+            format!("changed the room topic to: {}", subject)
+        };
+        Self {
+            subject: Some(subject),
+            ..Self::text(text)
+        }
+    }
+
+    /// A poll with up to MAX_POLL_OPTIONS options.
+    pub fn poll(question: impl Into<String>, options: Vec<String>) -> Self {
+        let mut poll = Poll {
+            question: question.into(),
+            options,
+        };
+        poll.clamp();
+        let choices: Vec<String> = poll
+            .options
+            .iter()
+            .enumerate()
+            .map(|(n, option)| format!("{}) {}", n + 1, option))
+            .collect();
+        let text = format!("📊 {}  {}", poll.question, choices.join("  "));
+        Self {
+            poll: Some(poll),
+            ..Self::text(text)
+        }
+    }
+
+    /// A vote for option `choice` (counted from 0) of the poll in message
+    /// `poll`; `option` is its label, for clients that predate polls.
+    pub fn vote(poll: u64, choice: u32, option: &str) -> Self {
+        Self {
+            vote: Some(Vote { poll, choice }),
+            ..Self::text(format!("voted for: {}", option))
+        }
+    }
+
+    /// A text message that disappears `ttl` seconds after it is received.
+    pub fn ephemeral(text: impl Into<String>, ttl: u64) -> Self {
+        Self {
+            ttl: Some(ttl.min(MAX_TTL)),
+            ..Self::text(text)
+        }
+    }
+
+    /// Decode a decrypted payload. Older clients encrypt the bare UTF-8 text,
+    /// so anything that is not a JSON payload object is taken as text.
+    /// Components and TTLs are clamped to the schema limits. Nesting past
+    /// MAX_DEPTH fails, as for Message.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        check_depth(bytes)?;
+        if let Ok(mut payload) = serde_json::from_slice::<Self>(bytes) {
+            payload.ttl = payload.ttl.map(|ttl| ttl.min(MAX_TTL));
+            payload.components.truncate(MAX_COMPONENTS);
+            if let Some(subject) = &mut payload.subject {
+                subject.truncate(subject.floor_char_boundary(MAX_SUBJECT));
+            }
+            if let Some(voice) = &mut payload.voice {
+                voice.clamp();
+            }
+            if let Some(poll) = &mut payload.poll {
+                poll.clamp();
+            }
+            for component in &mut payload.components {
+                component.clamp();
+            }
+            return Ok(payload);
+        }
+        Ok(Self::text(String::from_utf8(bytes.to_vec())?))
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("serde_json::to_vec is infallible")
+    }
+}
+
+/// Longest chat message text we send, in characters. Peers show longer
+/// text from other clients as it is, up to MAX_MESSAGE.
+pub const MAX_TEXT: usize = 4000;
+
+/// Longest room topic, in bytes.
+pub const MAX_SUBJECT: usize = 200;
+
+/// Longest lifetime an ephemeral message may ask for: one week.
+pub const MAX_TTL: u64 = 7 * 24 * 60 * 60;
+
+// ── Polls ─────────────────────────────────────────────────────────────────────
+
+/// Most options a poll may have: one per number key.
+pub const MAX_POLL_OPTIONS: usize = 9;
+
+/// Longest poll question or option, in bytes.
+pub const MAX_POLL_TEXT: usize = 200;
+
+/// A question members answer by picking one of the options (/poll).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+    pub question: String,
+    pub options: Vec<String>,
+}
+
+impl Poll {
+    /// Bring a poll from the wire within the schema limits.
+    fn clamp(&mut self) {
+        self.question.truncate(self.question.floor_char_boundary(MAX_POLL_TEXT));
+        self.options.truncate(MAX_POLL_OPTIONS);
+        for option in &mut self.options {
+            option.truncate(option.floor_char_boundary(MAX_POLL_TEXT));
+        }
+    }
+}
+
+/// One member's pick in a poll. A member's latest vote replaces their
+/// earlier one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Vote {
+    /// ID of the message carrying the poll.
+    pub poll: u64,
+    /// Index of the chosen option.
+    pub choice: u32,
+}
+
+// ── Voice messages ────────────────────────────────────────────────────────────
+
+/// Longest voice message, in seconds.
+pub const MAX_VOICE_SECONDS: u64 = 30;
+
+/// Opus frames per second of audio (20 ms frames).
+pub const VOICE_FRAMES_PER_SECOND: u64 = 50;
+
+/*
+Struct:     -VoiceClip
+Purpose:    -A short recorded audio clip sent with a chat message.
+
+Fields:
+            - u32 duration_ms:  Length of the recording, for the badge shown
+              before it is played.
+            - Vec<String> frames:  Opus packets, base64, each 20 ms of mono
+              audio at 16 kHz (see voice.rs).
+
+Details:
+            - Clips are at most MAX_VOICE_SECONDS long; longer ones are cut
+              on receipt.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoiceClip {
+    pub duration_ms: u32,
+    pub frames: Vec<String>,
+}
+
+impl VoiceClip {
+    fn clamp(&mut self) {
+        self.frames.truncate((MAX_VOICE_SECONDS * VOICE_FRAMES_PER_SECOND) as usize);
+        self.duration_ms = self.duration_ms.min(MAX_VOICE_SECONDS as u32 * 1000);
+    }
+
+    /// Length as m:ss.
+    pub fn duration(&self) -> String {
+        let seconds = self.duration_ms.div_ceil(1000);
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+// ── Components ────────────────────────────────────────────────────────────────
+
+/// Most components a single message may carry; extras are dropped.
+pub const MAX_COMPONENTS: usize = 5;
+/// Most options a single select may offer; extras are dropped.
+pub const MAX_OPTIONS: usize = 10;
+/// Longest label rendered, in characters; longer labels are cut.
+pub const MAX_LABEL_LEN: usize = 40;
+
+/*
+Enum:       -Component
+Purpose:    -A constrained interactive element a bot can attach to a message.
+
+Variants:
+            - Button { id, label }:  A single clickable button.
+            - Select { id, options }:  Pick exactly one of several options.
+
+Details:
+            - `id` is chosen by the bot and echoed back in the Interaction so
+              it can tell which component was used.
+            - The schema is deliberately small so every client can render it in
+              a single terminal line; limits are enforced on receipt.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Component {
+    Button { id: String, label: String },
+    Select { id: String, options: Vec<SelectOption> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelectOption {
+    pub value: String,
+    pub label: String,
+}
+
+impl Component {
+    pub fn id(&self) -> &str {
+        match self {
+            Component::Button { id, .. } | Component::Select { id, .. } => id,
+        }
+    }
+
+    /// Enforce MAX_OPTIONS and MAX_LABEL_LEN.
+    fn clamp(&mut self) {
+        fn cut(label: &mut String) {
+            if let Some((i, _)) = label.char_indices().nth(MAX_LABEL_LEN) {
+                label.truncate(i);
+            }
+        }
+        match self {
+            Component::Button { label, .. } => cut(label),
+            Component::Select { options, .. } => {
+                options.truncate(MAX_OPTIONS);
+                for option in options {
+                    cut(&mut option.label);
+                }
+            }
+        }
+    }
+}
+
+/// The user activated a component: which message, which component and, for
+/// selects, the chosen option's value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub message_id: u64,
+    pub component_id: String,
+    pub value: Option<String>,
+}
+
 // ── Ticket ────────────────────────────────────────────────────────────────────
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Ticket {
     pub topic: TopicId,
     pub endpoints: Vec<EndpointAddr>,
+    /// The endpoint that opened the room and may moderate it. Missing in
+    /// tickets from older clients, whose rooms have no moderator.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator: Option<EndpointId>,
+    /// Invite-only room: the ticket alone is not enough, the creator must
+    /// admit each member before they get the room key.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    /// When the ticket stops working, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    /// Invite token for private rooms, checked by the creator on every
+    /// knock (see invites::Invites). Expiring and single-use tickets carry
+    /// one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<u64>,
+    /// What the room is, for whoever is deciding whether to join it.
+    #[serde(default, skip_serializing_if = "RoomInfo::is_empty")]
+    pub info: RoomInfo,
+}
+
+/*
+Struct:     -RoomInfo
+Purpose:    -What a ticket says about its room, shown before joining so the
+             user is not dropped into a bare topic hash.
+
+Fields:
+            - Option<String> title:  The room's name (open --title).
+            - Option<String> creator_name:  The creator's nickname when they
+              opened it.
+            - Option<u64> created:  When it was opened, in seconds since the
+              Unix epoch.
+
+Details:
+            - Set by the creator and handed on unchanged by every member's
+              ticket. Unsigned, like the rest of the ticket: it is only as
+              trustworthy as whoever passed the ticket on.
+            - Missing in tickets from older clients and in named rooms'.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+}
+
+impl RoomInfo {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// E.g. "'rust-meetup' created by alice 2 days ago", or None if the
+    /// ticket says nothing about its room.
+    pub fn summary(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        // Whoever made the ticket chose these; keep them to one short line,
+        // and do not let them reorder the rest of it.
+        fn clean(text: &str) -> String {
+            let text: String = text
+                .chars()
+                .filter(|c| !c.is_control() && !matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}'))
+                .collect();
+            text[..text.floor_char_boundary(MAX_NAME)].to_string()
+        }
+        let mut summary = match &self.title {
+            Some(title) => format!("'{}'", clean(title)),
+            None => t!("a room").to_string(),
+        };
+        if let Some(name) = &self.creator_name {
+            summary.push_str(&t!(" created by {}", clean(name)));
+        }
+        if let Some(created) = self.created {
+            summary.push(' ');
+            summary.push_str(&ago(created));
+        }
+        Some(summary)
+    }
+}
+
+/// How long ago `at` (seconds since the Unix epoch) was, roughly.
+pub fn ago(at: u64) -> String {
+    let secs = crate::invites::now().saturating_sub(at);
+    match secs {
+        0..60 => t!("just now").to_string(),
+        60..120 => t!("1 minute ago").to_string(),
+        120..3600 => t!("{} minutes ago", secs / 60),
+        3600..7200 => t!("1 hour ago").to_string(),
+        7200..86400 => t!("{} hours ago", secs / 3600),
+        86400..172800 => t!("1 day ago").to_string(),
+        _ => t!("{} days ago", secs / 86400),
+    }
 }
 
 impl Ticket {
@@ -68,8 +1118,49 @@ impl Ticket {
     pub fn to_bytes(&self) -> Vec<u8> {
         serde_json::to_vec(self).expect("serde_json::to_vec is infallible")
     }
+
+    /// Whether the ticket's expiry, if any, has passed.
+    pub fn has_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| crate::invites::now() >= expires)
+    }
+
+    /// Whether `text` is a passphrase-protected ticket (see `protect`).
+    pub fn is_protected(text: &str) -> bool {
+        text.trim().starts_with(PROTECTED_PREFIX)
+    }
+
+    /*
+    Function:   -protect
+    Purpose:    -Encode the ticket encrypted under a passphrase.
+
+    Details:
+                - `locked-` followed by base32 of crypto::seal_with_passphrase
+                  over the ticket bytes. The dash cannot occur in a plain
+                  ticket, so the two forms are told apart unambiguously.
+                - Topic, creator and addresses are all hidden, so the ticket
+                  can be posted somewhere semi-public and the passphrase
+                  shared separately.
+    */
+    pub fn protect(&self, passphrase: &str) -> Result<String> {
+        let blob = crate::crypto::seal_with_passphrase(&self.to_bytes(), passphrase)?;
+        let mut text = data_encoding::BASE32_NOPAD.encode(&blob);
+        text.make_ascii_lowercase();
+        Ok(format!("{}{}", PROTECTED_PREFIX, text))
+    }
+
+    /// Reverse of `protect`. A wrong passphrase fails to decrypt.
+    pub fn unprotect(text: &str, passphrase: &str) -> Result<Self> {
+        let Some(encoded) = text.trim().strip_prefix(PROTECTED_PREFIX) else {
+            bail!("not a protected ticket");
+        };
+        let blob = data_encoding::BASE32_NOPAD.decode(encoded.to_ascii_uppercase().as_bytes())?;
+        Self::from_bytes(&crate::crypto::open_with_passphrase(&blob, passphrase)?)
+    }
 }
 
+/// Start of a passphrase-protected ticket.
+const PROTECTED_PREFIX: &str = "locked-";
+
 impl fmt::Display for Ticket {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut text = data_encoding::BASE32_NOPAD.encode(&self.to_bytes()[..]);
@@ -81,7 +1172,55 @@ impl fmt::Display for Ticket {
 impl FromStr for Ticket {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if Self::is_protected(s) {
+            bail!("this ticket is passphrase-protected; join it with `join` from the command line");
+        }
         let bytes = data_encoding::BASE32_NOPAD.decode(s.to_ascii_uppercase().as_bytes())?;
         Self::from_bytes(&bytes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first piece of a two-piece message.
+    fn first_piece(reassembly: &mut Reassembly, from: EndpointId, id: u64) -> Result<Option<Vec<u8>>> {
+        reassembly.add(from, id, 0, 2, &BASE64.encode(&id.to_be_bytes()))
+    }
+
+    fn last_piece(reassembly: &mut Reassembly, from: EndpointId, id: u64) -> Result<Option<Vec<u8>>> {
+        reassembly.add(from, id, 1, 2, &BASE64.encode(b"!"))
+    }
+
+    #[test]
+    fn full_reassembly_drops_the_oldest() -> Result<()> {
+        let mut reassembly = Reassembly::default();
+        let peer = || iroh::SecretKey::from_bytes(&rand::random()).public();
+        let first = peer();
+        first_piece(&mut reassembly, first, 0)?;
+        std::thread::sleep(Duration::from_millis(5));
+        let busy = peer();
+        let mut from = busy;
+        while reassembly.partial.len() < MAX_PARTIAL {
+            for id in 0..MAX_PARTIAL_PER_PEER as u64 {
+                if reassembly.partial.len() < MAX_PARTIAL {
+                    first_piece(&mut reassembly, from, id)?;
+                }
+            }
+            from = peer();
+        }
+
+        // One peer still gets no more than its share.
+        assert!(first_piece(&mut reassembly, busy, 100).is_err());
+
+        // A newcomer's message makes room by dropping the oldest one, so its
+        // last piece finds nothing to complete; the others are untouched.
+        let newcomer = peer();
+        first_piece(&mut reassembly, newcomer, 0)?;
+        assert_eq!(reassembly.partial.len(), MAX_PARTIAL);
+        assert!(last_piece(&mut reassembly, first, 0)?.is_none());
+        assert!(last_piece(&mut reassembly, newcomer, 0)?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use anyhow::{Context, Result};
+use qrcode::{render::unicode::Dense1x2, EcLevel, QrCode};
+
+// ── QR codes ──────────────────────────────────────────────────────────────────
+
+/*
+Function:   -render
+Purpose:    -Draw text as a QR code made of unicode half-blocks.
+
+Parameters:
+            - &str text:  What to encode, e.g. a ticket.
+
+Details:
+            - Each character covers two modules stacked vertically, so the
+              code is roughly square in a terminal.
+            - Light modules are drawn as blocks and dark modules as spaces,
+              which reads correctly on a dark background. Callers that can
+              set colours should draw it light-on-dark explicitly.
+            - Includes the quiet zone scanners need around the code.
+            - Uses low error correction to keep long tickets small enough to
+              fit a terminal.
+*/
+pub fn render(text: &str) -> Result<Vec<String>> {
+    let code = QrCode::with_error_correction_level(text, EcLevel::L)
+        .context("too much data for a QR code")?;
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    Ok(image.lines().map(str::to_string).collect())
+}
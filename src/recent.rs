@@ -0,0 +1,93 @@
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::invites;
+use crate::profile::{write_private, Profile};
+use crate::protocol::Ticket;
+
+// ── Recent rooms ──────────────────────────────────────────────────────────────
+
+/// Rooms remembered per profile; the least recently joined go first.
+const MAX_RECENT: usize = 20;
+
+/*
+Struct:     -RecentRoom
+Purpose:    -One room a profile has joined.
+
+Fields:
+            - String topic:  The room's topic.
+            - String ticket:  The ticket it was last joined with.
+            - u64 joined:  When, in seconds since the Unix epoch.
+*/
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecentRoom {
+    pub topic: String,
+    pub ticket: String,
+    pub joined: u64,
+}
+
+impl RecentRoom {
+    pub fn ticket(&self) -> Result<Ticket> {
+        Ticket::from_str(&self.ticket).with_context(|| format!("the saved ticket for {} is invalid", self.topic))
+    }
+}
+
+/*
+Struct:     -RecentRooms
+Purpose:    -The rooms a profile has joined by ticket, so they can be joined
+             again without pasting it.
+
+Fields:
+            - PathBuf path:  <profile>/rooms.json, a map from room topic to
+              RecentRoom.
+
+Details:
+            - Like Drafts, every save re-reads the file, so sessions of the
+              same profile do not overwrite each other's rooms.
+            - Rooms we opened are not kept: their ticket names only
+              ourselves. Named rooms are joined by name anyway.
+*/
+pub struct RecentRooms {
+    path: PathBuf,
+}
+
+impl RecentRooms {
+    pub fn new(profile: &Profile) -> Self {
+        Self {
+            path: profile.rooms_path(),
+        }
+    }
+
+    fn read(&self) -> BTreeMap<String, RecentRoom> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// The rooms, most recently joined first.
+    pub fn list(&self) -> Vec<RecentRoom> {
+        let mut rooms: Vec<RecentRoom> = self.read().into_values().collect();
+        rooms.sort_by_key(|room| std::cmp::Reverse(room.joined));
+        rooms
+    }
+
+    /// The `n`th room of `list`, counting from 1.
+    pub fn get(&self, n: usize) -> Option<RecentRoom> {
+        self.list().into_iter().nth(n.checked_sub(1)?)
+    }
+
+    /// Note that we joined a room with this ticket just now.
+    pub fn record(&self, ticket: &Ticket) -> Result<()> {
+        let mut rooms = self.read();
+        let topic = ticket.topic.to_string();
+        rooms.insert(topic.clone(), RecentRoom { topic, ticket: ticket.to_string(), joined: invites::now() });
+        while rooms.len() > MAX_RECENT {
+            let oldest = rooms.values().min_by_key(|room| room.joined).map(|room| room.topic.clone());
+            rooms.remove(&oldest.expect("not empty"));
+        }
+        write_private(&self.path, &serde_json::to_vec_pretty(&rooms)?)
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use iroh::EndpointId;
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::crypto::MessageKey;
+use crate::keychain;
+use crate::profile::{write_private, Profile};
+
+// ── Private rooms' keys ───────────────────────────────────────────────────────
+
+/*
+Struct:     -SavedRoom
+Purpose:    -The key of one private room we created, as last saved.
+
+Fields:
+            - u64 epoch:  Its /rekey epoch.
+            - u64 step:  The ratchet step `key` belongs to.
+            - Option<String> key:  Hex-encoded, or None when it is kept in
+              the OS keychain.
+            - Vec<String> admitted:  Members let in and not kicked since,
+              who get the new key on /rekey.
+*/
+#[derive(Serialize, Deserialize)]
+struct SavedRoom {
+    epoch: u64,
+    step: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default)]
+    admitted: Vec<String>,
+}
+
+/*
+Struct:     -CreatorKeys
+Purpose:    -The keys of the private rooms a profile created, and whom it
+             let in, so a restart carries on with the same key and members
+             instead of splitting the room in two.
+
+Fields:
+            - PathBuf path:  <profile>/room-keys.json, a map from room topic
+              to SavedRoom.
+            - Profile profile:  Whose keychain to use.
+            - bool keychain:  Keep the keys themselves in the OS keychain,
+              under room-key/<topic>.
+
+Details:
+            - Only the key for the step it was saved at is kept; steps
+              before it cannot be worked out from it, so saving again as the
+              ratchet moves on keeps the forward secrecy of older traffic.
+            - Like Drafts, every save re-reads the file, so sessions of the
+              same profile do not overwrite each other's rooms.
+*/
+#[derive(Clone)]
+pub struct CreatorKeys {
+    path: PathBuf,
+    profile: Profile,
+    keychain: bool,
+}
+
+impl CreatorKeys {
+    pub fn new(profile: &Profile) -> Result<Self> {
+        Ok(Self {
+            path: profile.room_keys_path(),
+            profile: profile.clone(),
+            keychain: Config::load(&profile.config_path())?.keychain,
+        })
+    }
+
+    fn read(&self) -> BTreeMap<String, SavedRoom> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn account(&self, topic: &str) -> String {
+        self.profile.keychain_account(&format!("room-key/{}", topic))
+    }
+
+    /// Topics of every room with a saved key.
+    pub fn topics(&self) -> Vec<String> {
+        self.read().into_keys().collect()
+    }
+
+    /// The saved key for a room we created and the members admitted to it,
+    /// if there is one.
+    pub fn load(&self, topic: &TopicId) -> Result<Option<(MessageKey, HashSet<EndpointId>)>> {
+        let topic = topic.to_string();
+        let Some(saved) = self.read().remove(&topic) else {
+            return Ok(None);
+        };
+        let text = match saved.key {
+            Some(text) => text,
+            None => keychain::get(&self.account(&topic))?
+                .context("the room key is missing from the keychain")?,
+        };
+        let key = hex::decode(text)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .with_context(|| format!("the saved key for {} is invalid", topic))?;
+        let admitted = saved.admitted.iter().filter_map(|id| id.parse().ok()).collect();
+        Ok(Some((MessageKey { epoch: saved.epoch, step: saved.step, key }, admitted)))
+    }
+
+    /// Replace the saved key and members for a room.
+    pub fn save(&self, topic: &TopicId, key: &MessageKey, admitted: &HashSet<EndpointId>) -> Result<()> {
+        let topic = topic.to_string();
+        let text = hex::encode(key.key);
+        let key_field = if self.keychain {
+            keychain::set(&self.account(&topic), &text)?;
+            None
+        } else {
+            Some(text)
+        };
+        let mut rooms = self.read();
+        let admitted = admitted.iter().map(EndpointId::to_string).collect();
+        rooms.insert(topic, SavedRoom { epoch: key.epoch, step: key.step, key: key_field, admitted });
+        write_private(&self.path, &serde_json::to_vec_pretty(&rooms)?)
+    }
+}
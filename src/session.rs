@@ -0,0 +1,1457 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use futures_lite::StreamExt;
+use iroh::{
+    address_lookup::{DiscoveryEvent, MdnsAddressLookup, MemoryLookup},
+    endpoint::{BindOpts, TransportAddrUsage},
+    protocol::Router, Endpoint, EndpointAddr, EndpointId, RelayMap, RelayMode, RelayUrl, SecretKey, TransportAddr,
+    Watcher,
+};
+use iroh_gossip::{net::Gossip, proto::TopicId};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::app::ChatEvent;
+use crate::archive::{Archive, ArchiveHost, StoreKey, ARCHIVE_ALPN};
+use crate::backup::{self, BackupHost, BACKUP_ALPN};
+use crate::bus::{self, ChatCommand, Events, Subscription};
+use crate::client::Discovery;
+use crate::config::{Config, NotifyConfig};
+use crate::directory::{self, DirectoryHost, DirectoryTopic, Listing, SignedListing, DIRECTORY_ALPN};
+use crate::crypto::{
+    encrypt_message, get_encryption_key, lan_service_name, named_room, sign_about_me, sign_delete, sign_key_request, sign_listing, sign_pin, sign_membership, sign_moderation, sign_revoke, stamp_pow, wrap_room_key, MessageKey, RoomKey,
+    SenderKeys, MAX_POW_BITS,
+};
+use crate::filters::Filters;
+use crate::gossip::{self, Room, RoomSender};
+use crate::i18n::t;
+use crate::invites::{self, Invites};
+use crate::names::NameCache;
+use crate::netstats::{ConnectionTracker, Load, PeerPath, Pings, Rates, Sightings, Swarm, Traffic};
+use crate::profile::Profile;
+use crate::recent::RecentRooms;
+use crate::roomkeys::CreatorKeys;
+use crate::protocol::{
+    Message, MessageBody, ModAction, Neighbour, Presence, RoomInfo, Ticket, VoiceClip,
+    MAX_TEXT,
+};
+use crate::voice;
+use crate::webhook::Webhook;
+
+// ── Session ───────────────────────────────────────────────────────────────────
+
+/// Longest we wait on quit for queued messages and Leave to be sent.
+const LEAVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time gossip gets to deliver Leave before the endpoint closes.
+const LEAVE_GRACE: Duration = Duration::from_millis(300);
+
+/// Tries per outgoing message before it is marked as failed, and the wait
+/// before the first retry (doubled each time).
+const SEND_ATTEMPTS: u32 = 5;
+const SEND_RETRY: Duration = Duration::from_millis(500);
+
+/// How long a /ping waits for its pong.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a session enters its room.
+pub enum Entry {
+    /// Open a new room with a random topic, as its creator. Private rooms
+    /// admit members one by one (see MessageBody::Knock). `title` names it
+    /// in its tickets (see RoomInfo).
+    Open { private: bool, title: Option<String> },
+    /// Join an existing room from its ticket.
+    Join(Ticket),
+    /// Meet in a room derived from a name and passphrase (crypto::named_room).
+    /// Named rooms are open and have no creator. The one who opens it
+    /// (`host`) runs the rendezvous endpoint the others dial.
+    Named { name: String, passphrase: String, host: bool },
+}
+
+/*
+Struct:     -NetOptions
+Purpose:    -Network settings from the command line.
+
+Fields:
+            - Vec<SocketAddr> bind:  UDP sockets to bind instead of the
+              defaults (every interface, random ports). Empty for the defaults.
+            - Vec<RelayUrl> relays:  Relays to use instead of iroh's defaults,
+              e.g. self-hosted ones. Takes precedence over relay_pins.
+            - bool no_relay:  Direct connections only.
+            - bool archive:  Keep the room's history and hand it to members
+              who were away (see archive::Archive).
+            - StoreKey archive_key:  How that history is kept on disk.
+            - Option<Discovery> discovery:  Replaces the config's [lan]
+              table. None to go by the config.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct NetOptions {
+    pub bind: Vec<SocketAddr>,
+    pub relays: Vec<RelayUrl>,
+    pub no_relay: bool,
+    pub archive: bool,
+    pub archive_key: StoreKey,
+    pub discovery: Option<Discovery>,
+}
+
+impl NetOptions {
+    /// The same options for another session in this process: relays carry
+    /// over, but a fixed port can only be bound once.
+    pub fn for_tab(&self) -> Self {
+        Self {
+            bind: Vec::new(),
+            ..self.clone()
+        }
+    }
+}
+
+/*
+Struct:     -Session
+Purpose:    -One profile's live presence in one room: its own endpoint, gossip
+             instance and background loops, plus the channels the TUI uses to
+             talk to them.
+
+Fields:
+            - String profile:  Name of the profile this session runs as.
+            - Config config:  That profile's settings.
+            - String my_name:  Nickname announced in the room.
+            - EndpointId my_id:  Our endpoint ID in this session.
+            - Ticket ticket:  Ticket other peers can use to join via us.
+            - Option<EndpointId> moderator:  The room creator, whose signed
+              kicks and mutes we enforce. None for rooms joined with a ticket
+              from an older client.
+            - RoomKey room_key:  Key for the room's messages. Empty while we
+              wait to be admitted to a private room.
+            - Subscription events:  The front end's subscription to the
+              room's events, made before the first was published.
+            - Sender<ChatCommand> commands:  What the front end asks of the
+              room, carried out in order by the send loop.
+            - Presence presence:  Ours, as last set with set_presence.
+            - JoinHandle<()> send_task:  The send queue (send_queue), awaited
+              on shutdown so nothing queued is lost.
+            - Invites invites:  Tokens in the expiring and single-use tickets
+              we handed out, checked when someone knocks.
+            - Option<RelayUrl> pinned_relay:  Relay pinned for this room in the
+              config, if any and not overridden on the command line.
+            - NetOptions net:  Command line network settings, passed on to
+              sessions opened in new tabs.
+            - ConnectionTracker connections:  Every connection the endpoint
+              has open, for /net.
+            - Swarm swarm:  The gossip overlay as far as we know it, for
+              /swarm.
+            - Traffic traffic:  The room's gossip traffic, for the status
+              bar.
+            - Sightings sightings:  What the receive loop has seen of each
+              peer, for /whois.
+            - Endpoint endpoint:  Kept for network diagnostics (/network),
+              backups and the rooms directory.
+            - Option<DirectoryTopic> directory:  The serverless directory,
+              if [directory] gossip is on.
+            - Events ui_tx:  Where the gossip loop and session-level tasks
+              such as backups publish events.
+            - Router router:  Accept loop for the endpoint; shut down on exit.
+            - Option<Router> rendezvous:  The rendezvous endpoint of a named
+              room we opened.
+            - Arc<Filters> filters:  The profile's message filters; front
+              ends run outgoing messages through them before sending.
+            - Arc<Mutex<HashSet<EndpointId>>> blocked:  Peers blocked in the
+              profile's config, shared with the receive loop, which drops
+              everything they send.
+            - NameCache names:  Members' names as last saved, kept up to date
+              by the receive loop.
+
+Details:
+            - Sessions share nothing, so several can run in one process – one
+              per TUI tab – each with a distinct identity.
+*/
+pub struct Session {
+    pub profile: String,
+    pub config: Config,
+    pub my_name: String,
+    pub my_id: EndpointId,
+    pub ticket: Ticket,
+    pub moderator: Option<EndpointId>,
+    pub room_key: RoomKey,
+    pub events: Subscription,
+    pub commands: mpsc::Sender<ChatCommand>,
+    pub presence: Presence,
+    send_task: JoinHandle<()>,
+    invites: Invites,
+    pinned_relay: Option<RelayUrl>,
+    pub net: NetOptions,
+    connections: ConnectionTracker,
+    swarm: Swarm,
+    traffic: Traffic,
+    sightings: Sightings,
+    endpoint: Endpoint,
+    directory: Option<DirectoryTopic>,
+    ui_tx: Events,
+    router: Router,
+    rendezvous: Option<Router>,
+    pub filters: Arc<Filters>,
+    blocked: Arc<Mutex<HashSet<EndpointId>>>,
+    names: NameCache,
+}
+
+impl Session {
+    /*
+    Function:   -start
+    Purpose:    -Bind an endpoint for a profile and open or join a room.
+
+    Parameters:
+                - &Profile profile:  Identity and config to run as.
+                - Option<String> name:  Nickname override; falls back to the
+                  profile's nickname for this room, its configured name, then
+                  "Anonymous".
+                - Entry entry:  Open a new room (with ourselves as moderator),
+                  join one from a ticket, or meet in a named room.
+                - NetOptions net:  Sockets and relays from the command line.
+
+    Details:
+                - Relays given on the command line replace the defaults; else
+                  if the config pins a relay for this topic the endpoint uses
+                  only that relay; otherwise iroh probes the default relays and
+                  homes on the one with the lowest latency. --no-relay beats
+                  all of these.
+                - With [lan] discovery on, also finds members on the local
+                  network over mDNS; in offline mode that is the only way in.
+                - Rooms joined by ticket are remembered in the profile (see
+                  RecentRooms).
+                - Announces our name with AboutMe as soon as we are subscribed.
+                - In a private room we do not own, also knocks; messages stay
+                  pending until the creator admits us.
+                - Spawns the gossip receive loop and the send/delete loop; both
+                  end when the TUI drops its side of the channels.
+    */
+    pub async fn start(
+        profile: &Profile,
+        name: Option<String>,
+        entry: Entry,
+        net: NetOptions,
+    ) -> Result<Self> {
+        let mut config = Config::load(&profile.config_path())?;
+        if let Some(discovery) = net.discovery {
+            config.lan = discovery.lan();
+        }
+        let secret_key = profile.secret_key()?;
+        let opened = matches!(entry, Entry::Open { .. });
+        let (topic, endpoints, moderator, private, token, rendezvous, mut info) = match entry {
+            Entry::Join(ticket) if ticket.has_expired() => {
+                anyhow::bail!("this ticket has expired; ask for a new one");
+            }
+            Entry::Join(ticket) => {
+                // For `join --recent`; not being able to remember the room
+                // is no reason to stay out of it.
+                if let Err(e) = RecentRooms::new(profile).record(&ticket) {
+                    warn!(error = %e, "could not save the room to the profile");
+                }
+                let Ticket { topic, endpoints, creator, private, token, info, .. } = ticket;
+                (topic, endpoints, creator, private, token, None, info)
+            }
+            Entry::Open { private, title } => (
+                TopicId::from_bytes(rand::random()),
+                vec![],
+                Some(secret_key.public()),
+                private,
+                None,
+                None,
+                RoomInfo { title, creator_name: None, created: Some(invites::now()) },
+            ),
+            Entry::Named { name, passphrase, host } => {
+                let (topic, rendezvous) =
+                    tokio::task::spawn_blocking(move || named_room(&name, &passphrase)).await??;
+                if host {
+                    (topic, vec![], None, false, None, Some(rendezvous), RoomInfo::default())
+                } else {
+                    let endpoints = vec![EndpointAddr::from(rendezvous.public())];
+                    (topic, endpoints, None, false, None, None, RoomInfo::default())
+                }
+            }
+        };
+        // Public rooms derive the key from the topic. A private room's key is
+        // random; only its creator has it to begin with, and keeps it in the
+        // profile so the room survives a restart.
+        let creator_keys = (private && moderator == Some(secret_key.public()))
+            .then(|| CreatorKeys::new(profile))
+            .transpose()?;
+        // Members we let in and have not kicked since; they get the new key
+        // on /rekey.
+        let mut admitted = HashSet::new();
+        let room_key = match &creator_keys {
+            Some(keys) => {
+                let saved = keys.load(&topic).unwrap_or_else(|e| {
+                    warn!(error = %e, "could not load the room key");
+                    None
+                });
+                let restored = saved.and_then(|(key, members)| Some((RoomKey::restore(key)?, members)));
+                let room_key = match restored {
+                    Some((room_key, members)) => {
+                        admitted = members;
+                        room_key
+                    }
+                    None => RoomKey::private(true),
+                };
+                if let Some(key) = room_key.current() {
+                    keys.save(&topic, &key, &admitted).context("could not save the room key")?;
+                }
+                room_key
+            }
+            None if private => RoomKey::private(false),
+            None => RoomKey::public(get_encryption_key(&topic)),
+        };
+
+        let pin = config.relay_pins.get(&topic.to_string()).filter(|_| net.relays.is_empty());
+        let pinned_relay = match pin {
+            Some(url) => Some(
+                url.parse::<RelayUrl>()
+                    .with_context(|| format!("invalid pinned relay URL '{}'", url))?,
+            ),
+            None => None,
+        };
+        let relay_mode = match &pinned_relay {
+            _ if net.no_relay => RelayMode::Disabled,
+            _ if !net.relays.is_empty() => RelayMode::Custom(net.relays.iter().cloned().collect()),
+            Some(url) => RelayMode::Custom(RelayMap::from(url.clone())),
+            None => RelayMode::Default,
+        };
+
+        let mut builder = if config.lan.offline {
+            Endpoint::empty_builder(RelayMode::Disabled)
+        } else {
+            Endpoint::builder().relay_mode(relay_mode)
+        };
+        let connections = ConnectionTracker::default();
+        builder = builder.hooks(connections.clone());
+        let swarm = Swarm::new(connections.clone());
+        if !net.bind.is_empty() {
+            builder = builder.clear_ip_transports();
+            for addr in &net.bind {
+                // Not every host has IPv6, so binding its wildcard address
+                // may fail without stopping us.
+                let optional = addr.is_ipv6() && addr.ip().is_unspecified();
+                builder = builder
+                    .bind_addr_with_opts(*addr, BindOpts::default().set_is_required(!optional))
+                    .with_context(|| format!("cannot bind to {}", addr))?;
+            }
+        }
+        let endpoint = builder.secret_key(secret_key.clone()).bind().await?;
+        info!(topic = %topic, id = %endpoint.id(), sockets = ?endpoint.bound_sockets(), "endpoint bound");
+        // Dial the ticket's peers at the addresses it lists, not only those
+        // relays and address lookup know of.
+        let ticket_addrs = MemoryLookup::new();
+        for addr in &endpoints {
+            ticket_addrs.add_endpoint_info(addr.clone());
+        }
+        endpoint.address_lookup().add(ticket_addrs);
+        let lan = if config.lan.enabled() {
+            let mdns = MdnsAddressLookup::builder()
+                .service_name(lan_service_name(&topic))
+                .build(endpoint.id())
+                .context("could not start local network discovery")?;
+            endpoint.address_lookup().add(mdns.clone());
+            Some(mdns)
+        } else {
+            None
+        };
+        let gossip = Gossip::builder().spawn(endpoint.clone());
+        let mut router = Router::builder(endpoint.clone()).accept(iroh_gossip::ALPN, gossip.clone());
+        if config.backup.host {
+            router = router.accept(BACKUP_ALPN, BackupHost::new(profile.backups_dir()));
+        }
+        if config.directory.host {
+            router = router.accept(DIRECTORY_ALPN, DirectoryHost::default());
+        }
+        let archive = if net.archive {
+            let (profile, key) = (profile.clone(), net.archive_key.clone());
+            let archive = tokio::task::spawn_blocking(move || Archive::open(&profile, &topic, &key)).await??;
+            router = router.accept(ARCHIVE_ALPN, ArchiveHost::new(archive.clone(), topic));
+            Some(archive)
+        } else {
+            None
+        };
+        let router = router.spawn();
+        let directory = if config.directory.gossip {
+            let mut bootstrap = directory::bootstrap(&config.directory)?;
+            bootstrap.retain(|peer| *peer != endpoint.id());
+            Some(DirectoryTopic::new(gossip.clone(), bootstrap))
+        } else {
+            None
+        };
+        // A host stays on the directory topic for others to enter by.
+        if config.directory.host
+            && let Some(directory) = directory.clone()
+        {
+            tokio::spawn(async move {
+                if let Err(e) = directory.get().await {
+                    warn!("could not join the directory topic: {:#}", e);
+                }
+            });
+        }
+        let rendezvous = match rendezvous {
+            Some(key) => Some(start_rendezvous(key, topic, endpoint.id()).await?),
+            None => None,
+        };
+
+        let my_name = name
+            .or_else(|| config.room_names.get(&topic.to_string()).cloned())
+            .or_else(|| config.name.clone())
+            .unwrap_or_else(|| "Anonymous".to_string());
+        if opened {
+            info.creator_name = Some(my_name.clone());
+        }
+        let ticket = {
+            let me = endpoint.addr();
+            let endpoints = vec![me];
+            Ticket { topic, endpoints, creator: moderator, private, expires: None, token: None, info }
+        };
+
+        // Subscribe the front end before anything is published.
+        let ui_tx = bus::events();
+        let events = Subscription::new(&ui_tx);
+        let (commands, mut command_rx) = mpsc::channel::<ChatCommand>(100);
+        let (loopback_tx, loopback_rx) = mpsc::channel::<Vec<u8>>(8);
+        let pings = Pings::default();
+        let sightings = Sightings::default();
+        let names = NameCache::new(profile);
+        let (out_tx, out_rx) = mpsc::channel::<(Vec<u8>, Option<u64>)>(100);
+
+        let endpoint_ids: Vec<EndpointId> = endpoints.iter().map(|p| p.id).collect();
+
+        // With nobody to join (a room we just opened) there is no one to wait
+        // for: members come to us.
+        let subscription = if endpoint_ids.is_empty() {
+            gossip.subscribe(topic, Vec::new()).await?
+        } else {
+            gossip.subscribe_and_join(topic, endpoint_ids.clone()).await?
+        };
+        let (sender, receiver) = subscription.split();
+        let traffic = Traffic::default();
+        let load = Load::new(config.load);
+        let sender = RoomSender::new(sender, endpoint.id(), receiver.is_joined(), traffic.clone(), load);
+
+        let my_id = endpoint.id();
+
+        // Broadcast our name immediately.
+        sender.broadcast(sign_about_me(my_name.clone(), &secret_key, &topic).to_vec()).await?;
+
+        let _ = ui_tx.send(ChatEvent::System(t!("You joined as {}", my_name)));
+        let _ = ui_tx.send(ChatEvent::System(
+            t!("INSERT mode – type & Enter to send. ESC for NORMAL mode.").to_string(),
+        ));
+        // Members hand us their sender keys when asked; a private room's
+        // members only once we have been admitted.
+        let sender_keys = SenderKeys::default();
+        let invites = Invites::default();
+        if room_key.current().is_some() {
+            sender.broadcast(sign_key_request(&secret_key, &topic).to_vec()).await?;
+        } else {
+            let knock = Message::new(MessageBody::Knock {
+                from: my_id,
+                name: my_name.clone(),
+                token,
+            });
+            sender.broadcast(knock.to_vec()).await?;
+            let _ = ui_tx.send(ChatEvent::System(
+                t!("This room is invite-only. Waiting for its creator to let you in…").to_string(),
+            ));
+        }
+
+        if let Some(mdns) = lan {
+            tokio::spawn(join_lan_peers(mdns, sender.clone(), ui_tx.clone()));
+        }
+
+        // Report the relay we settled on once the first probe finishes.
+        if !config.lan.offline && !net.no_relay {
+            let mut report = endpoint.net_report();
+            let ui_tx = ui_tx.clone();
+            let pinned = pinned_relay.is_some();
+            tokio::spawn(async move {
+                let report = report.initialized().await;
+                let text = match &report.preferred_relay {
+                    Some(url) if pinned => t!("Using pinned relay {}", url),
+                    Some(url) => match relay_latency(&report, url) {
+                        Some(latency) => t!("Using relay {} ({} ms)", url, latency.as_millis()),
+                        None => t!("Using relay {}", url),
+                    },
+                    None => t!("No relay reachable; direct connections only.").to_string(),
+                };
+                let _ = ui_tx.send(ChatEvent::System(text));
+            });
+        }
+
+        // Both loops log under the room's span.
+        let span = info_span!("room", topic = %topic);
+
+        let blocked: HashSet<EndpointId> = config
+            .blocked
+            .iter()
+            .filter_map(|id| match EndpointId::from_str(id) {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    warn!(id, error = %e, "ignoring invalid blocked peer in config");
+                    None
+                }
+            })
+            .collect();
+        let blocked = Arc::new(Mutex::new(blocked));
+        let filters = Arc::new(Filters::from_config(&config.filters)?);
+        let webhook = Webhook::start(&config.webhook, topic)?;
+
+        // Proof of work only makes sense where anyone may post.
+        let pow_bits = if private { 0 } else { config.pow_bits.min(MAX_POW_BITS) };
+        // Plaintext would undo a private room's admission.
+        let legacy_compat = config.legacy_compat && !private;
+
+        // Spawn gossip receiver loop.
+        tokio::spawn(gossip::subscribe_loop(
+            receiver,
+            sender.clone(),
+            ui_tx.clone(),
+            loopback_rx,
+            commands.clone(),
+            Room {
+                topic,
+                my_id,
+                my_name: my_name.clone(),
+                secret_key: secret_key.clone(),
+                creator: moderator,
+                private,
+                key: room_key.clone(),
+                sender_keys: sender_keys.clone(),
+                token,
+                invites: invites.clone(),
+                gossip: gossip.clone(),
+                endpoint: endpoint.clone(),
+                bootstrap: endpoint_ids,
+                filters: filters.clone(),
+                webhook,
+                blocked: blocked.clone(),
+                pow_bits,
+                archive,
+                swarm: swarm.clone(),
+                pings: pings.clone(),
+                sightings: sightings.clone(),
+                known: names.clone(),
+            },
+        ).instrument(span.clone()));
+
+        // Spawn message sender / deleter / interaction loop.
+        let send_key = room_key.clone();
+        let asked = swarm.clone();
+        let notice_tx = ui_tx.clone();
+        let send_task = tokio::spawn(
+            send_queue(sender.clone(), out_rx, ui_tx.clone()).instrument(span.clone()),
+        );
+        tokio::spawn(async move {
+            // One channel, so commands are carried out in the order given and
+            // everything queued goes out before Leave.
+            while let Some(command) = command_rx.recv().await {
+                match command {
+                    ChatCommand::Send { payload, id } => {
+                        // Not admitted yet: nobody could read it anyway.
+                        let Some(room) = send_key.current() else {
+                            warn!(id, "no room key to send with");
+                            let _ = notice_tx.send(ChatEvent::SendFailed(id));
+                            continue;
+                        };
+                        // The TUI refuses these; bots and bridges may not.
+                        if payload.text.chars().count() > MAX_TEXT {
+                            warn!(id, "message too long to send");
+                            let _ = notice_tx.send(ChatEvent::SendFailed(id));
+                            continue;
+                        }
+                        let key = sender_keys.own(room.epoch);
+                        let encrypted = encrypt_message(&payload, &secret_key, &topic, key, id);
+                        // Subjects and votes mean nothing to legacy clients.
+                        let legacy = (legacy_compat
+                            && !payload.text.is_empty()
+                            && payload.subject.is_none()
+                            && payload.vote.is_none())
+                        .then(|| Message::new(MessageBody::Message { from: my_id, text: payload.text.clone(), id }));
+                        let stamped = match encrypted {
+                            Ok(mut msg) if pow_bits > 0 => {
+                                tokio::task::spawn_blocking(move || {
+                                    stamp_pow(&mut msg, pow_bits);
+                                    msg
+                                })
+                                .await
+                                .map_err(Into::into)
+                            }
+                            other => other,
+                        };
+                        match stamped {
+                            Ok(msg) => {
+                                let _ = out_tx.send((msg.to_vec(), Some(id))).await;
+                                if let Some(legacy) = legacy {
+                                    let _ = out_tx.send((legacy.to_vec(), None)).await;
+                                }
+                            }
+                            Err(e) => {
+                                warn!(id, error = %e, "could not encrypt message");
+                                let _ = notice_tx.send(ChatEvent::SendFailed(id));
+                            }
+                        }
+                    }
+                    ChatCommand::Delete(id) => {
+                        let msg = sign_delete(id, &secret_key, &topic);
+                        let _ = out_tx.send((msg.to_vec(), None)).await;
+                    }
+                    ChatCommand::Pin { id, pinned } => {
+                        let msg = sign_pin(id, pinned, &secret_key, &topic);
+                        let _ = out_tx.send((msg.to_vec(), None)).await;
+                    }
+                    ChatCommand::Interact { to, interaction } => {
+                        let msg = Message::new(MessageBody::Interaction { from: my_id, to, interaction });
+                        let _ = out_tx.send((msg.to_vec(), None)).await;
+                    }
+                    ChatCommand::Moderate(action) => {
+                        if let ModAction::Kick { target } = action
+                            && admitted.remove(&target)
+                        {
+                            save_room_key(creator_keys.as_ref(), &topic, &send_key, &admitted);
+                        }
+                        let bytes = sign_moderation(action, &secret_key, &topic).to_vec();
+                        gossip::broadcast(&sender, bytes.clone()).await;
+                        // Apply it locally too; gossip skips the sender.
+                        let _ = loopback_tx.send(bytes).await;
+                    }
+                    ChatCommand::Membership(membership) => {
+                        let bytes = sign_membership(membership, &secret_key, &topic).to_vec();
+                        gossip::broadcast(&sender, bytes.clone()).await;
+                        let _ = loopback_tx.send(bytes).await;
+                    }
+                    ChatCommand::Admit(to) => {
+                        let Some(key) = send_key.current() else {
+                            continue;
+                        };
+                        if let Ok((wrapped, nonce)) = wrap_room_key(&key, &secret_key, to, &topic) {
+                            let (epoch, step) = (key.epoch, key.step);
+                            let msg = Message::new(MessageBody::Admit { from: my_id, to, epoch, step, wrapped, nonce });
+                            gossip::broadcast(&sender, msg.to_vec()).await;
+                            if admitted.insert(to) {
+                                save_room_key(creator_keys.as_ref(), &topic, &send_key, &admitted);
+                            }
+                        }
+                    }
+                    ChatCommand::Revoke { token, admitted: revoked } => {
+                        for id in &revoked {
+                            admitted.remove(id);
+                        }
+                        save_room_key(creator_keys.as_ref(), &topic, &send_key, &admitted);
+                        let bytes = sign_revoke(token, &secret_key, &topic).to_vec();
+                        gossip::broadcast(&sender, bytes.clone()).await;
+                        let _ = loopback_tx.send(bytes).await;
+                    }
+                    ChatCommand::Rekey => {
+                        let Some(current) = send_key.current() else {
+                            continue;
+                        };
+                        let key = MessageKey {
+                            epoch: current.epoch + 1,
+                            step: current.step,
+                            key: rand::random(),
+                        };
+                        let keys: Vec<_> = admitted
+                            .iter()
+                            .filter_map(|to| {
+                                let (wrapped, nonce) = wrap_room_key(&key, &secret_key, *to, &topic).ok()?;
+                                Some((*to, wrapped, nonce))
+                            })
+                            .collect();
+                        let count = keys.len();
+                        let (epoch, step) = (key.epoch, key.step);
+                        let bytes = Message::new(MessageBody::Rekey { from: my_id, epoch, step, keys }).to_vec();
+                        if sender.broadcast(bytes.clone()).await.is_err() {
+                            continue;
+                        }
+                        send_key.advance(key);
+                        save_room_key(creator_keys.as_ref(), &topic, &send_key, &admitted);
+                        // The receive loop hands out our new sender key.
+                        let _ = loopback_tx.send(bytes).await;
+                        let _ = notice_tx
+                            .send(ChatEvent::System(t!(
+                                "Rotated the room key (epoch {}) for {} member(s).",
+                                epoch, count
+                            )));
+                    }
+                    ChatCommand::AskSwarm => {
+                        asked.forget_reports();
+                        let msg = Message::new(MessageBody::Neighbours {
+                            from: my_id,
+                            neighbours: asked.links(),
+                            ask: true,
+                        });
+                        let _ = out_tx.send((msg.to_vec(), None)).await;
+                    }
+                    ChatCommand::Nick(name) => {
+                        let bytes = sign_about_me(name, &secret_key, &topic).to_vec();
+                        let _ = out_tx.send((bytes.clone(), None)).await;
+                        // The receive loop re-announces us under the new name.
+                        let _ = loopback_tx.send(bytes).await;
+                    }
+                    ChatCommand::Presence(presence) => {
+                        let bytes = Message::new(MessageBody::Presence { from: my_id, presence }).to_vec();
+                        // Only the latest matters; the receive loop sends it
+                        // with the next repeat of our name.
+                        if sender.load().congested() {
+                            debug!("sending is backed up; holding back our presence");
+                            sender.load().hold_presence();
+                        } else {
+                            let _ = out_tx.send((bytes.clone(), None)).await;
+                        }
+                        let _ = loopback_tx.send(bytes).await;
+                    }
+                    ChatCommand::Ping(to) => {
+                        let nonce = pings.start(to);
+                        let msg = Message::new(MessageBody::Ping { from: my_id, to, nonce });
+                        let _ = out_tx.send((msg.to_vec(), None)).await;
+                        let (pings, notice_tx) = (pings.clone(), notice_tx.clone());
+                        tokio::spawn(async move {
+                            tokio::time::sleep(PING_TIMEOUT).await;
+                            if pings.finish(nonce, to).is_some() {
+                                let _ = notice_tx
+                                    .send(ChatEvent::System(t!(
+                                        "No pong from {} within {} s.",
+                                        to.fmt_short(),
+                                        PING_TIMEOUT.as_secs()
+                                    )));
+                            }
+                        });
+                    }
+                    ChatCommand::Leave => {
+                        let msg = Message::new(MessageBody::Leave { from: my_id });
+                        let _ = out_tx.send((msg.to_vec(), None)).await;
+                        // Saved at the step we leave on, so the file no
+                        // longer opens what was said before.
+                        save_room_key(creator_keys.as_ref(), &topic, &send_key, &admitted);
+                        break;
+                    }
+                }
+            }
+            info!("left room");
+        }.instrument(span));
+
+        Ok(Self {
+            profile: profile.name.clone(),
+            config,
+            my_name,
+            my_id,
+            ticket,
+            moderator,
+            room_key,
+            events,
+            commands,
+            presence: Presence::Online,
+            send_task,
+            invites,
+            pinned_relay,
+            net,
+            connections,
+            swarm,
+            traffic,
+            sightings,
+            endpoint,
+            directory,
+            ui_tx,
+            router,
+            rendezvous,
+            filters,
+            blocked,
+            names,
+        })
+    }
+
+    /// Another view of this session's events, for a listener besides the
+    /// front end. Sees those published from now on.
+    pub fn subscribe(&self) -> Subscription {
+        Subscription::new(&self.ui_tx)
+    }
+
+    /*
+    Function:   -start_backups
+    Purpose:    -Back the profile up to the configured host while this session
+                 runs.
+
+    Parameters:
+                - &Profile profile:  The profile this session runs as.
+                - EndpointId host:  Backup target from the config.
+                - String passphrase:  Master passphrase.
+
+    Details:
+                - Uses the config's interval; results appear as system
+                  messages in this session.
+    */
+    pub fn start_backups(&self, profile: &Profile, host: EndpointId, passphrase: String) {
+        let interval = Duration::from_secs(self.config.backup.interval_mins.max(1) * 60);
+        backup::spawn_periodic(
+            self.endpoint.clone(),
+            host,
+            profile.clone(),
+            passphrase,
+            interval,
+            self.ui_tx.clone(),
+        );
+    }
+
+    /*
+    Function:   -invite
+    Purpose:    -Make a ticket for our private room that expires, works only
+                 once, or both.
+
+    Parameters:
+                - Option<u64> ttl:  Seconds until the ticket stops working.
+                - bool once:  Only the first person to use it gets in.
+
+    Details:
+                - Creator of a private room only. In an open room the ticket
+                  is the key, so there is nothing we could refuse.
+                - Knocks with a valid invite are let in without asking.
+    */
+    pub fn invite(&self, ttl: Option<u64>, once: bool) -> Result<Ticket> {
+        if self.moderator != Some(self.my_id) {
+            anyhow::bail!(t!("Only the room's creator can issue invites."));
+        }
+        if !self.ticket.private {
+            anyhow::bail!(t!("Expiring and single-use tickets need an invite-only room (open --private)."));
+        }
+        let expires = ttl.map(|ttl| invites::now() + ttl);
+        Ok(Ticket {
+            topic: self.ticket.topic,
+            endpoints: self.ticket.endpoints.clone(),
+            creator: self.ticket.creator,
+            private: true,
+            expires,
+            token: Some(self.invites.issue(expires, once)),
+            info: self.ticket.info.clone(),
+        })
+    }
+
+    /*
+    Function:   -revoke
+    Purpose:    -Withdraw an invite ticket we issued, and rotate the room key
+                 past anyone who got in with it.
+
+    Parameters:
+                - &Ticket ticket:  The invite, as `invite` made it.
+
+    Details:
+                - Returns how many members had been admitted with it.
+                - The room is told with a signed Revoke, so knocks with the
+                  ticket are turned away from then on, and a Rekey follows
+                  that leaves those members out: they keep what they have
+                  read, but nothing sent after it.
+    */
+    pub async fn revoke(&self, ticket: &Ticket) -> Result<usize> {
+        if self.moderator != Some(self.my_id) {
+            anyhow::bail!(t!("Only the room's creator can revoke invites."));
+        }
+        let Some(token) = ticket.token.filter(|_| ticket.private && ticket.topic == self.ticket.topic) else {
+            anyhow::bail!(t!("That is not an invite ticket for this room."));
+        };
+        let admitted = self.invites.revoke(token)?;
+        let count = admitted.len();
+        let _ = self.commands.send(ChatCommand::Revoke { token, admitted }).await;
+        let _ = self.commands.send(ChatCommand::Rekey).await;
+        Ok(count)
+    }
+
+    /// The configured directory peer and directory topic, or an error
+    /// explaining how to set one up.
+    fn directories(&self) -> Result<(Option<EndpointId>, Option<DirectoryTopic>)> {
+        let server = match &self.config.directory.server {
+            Some(server) => Some(
+                server
+                    .parse()
+                    .with_context(|| format!("invalid directory server '{}'", server))?,
+            ),
+            None => None,
+        };
+        if server.is_none() && self.directory.is_none() {
+            anyhow::bail!(t!("No directory configured – set server or gossip in the [directory] section of config.toml."));
+        }
+        Ok((server, self.directory.clone()))
+    }
+
+    /*
+    Function:   -search_directory
+    Purpose:    -Look rooms up on the configured directory and directory
+                 topic.
+
+    Parameters:
+                - String query:  Text to find in room names and descriptions;
+                  empty lists everything.
+
+    Details:
+                - Runs in the background; results arrive as a
+                  ChatEvent::Directory, failures as a system message.
+                - A room listed on both shows up once.
+    */
+    pub fn search_directory(&self, query: String) -> Result<()> {
+        let (server, topic) = self.directories()?;
+        let endpoint = self.endpoint.clone();
+        let ui_tx = self.ui_tx.clone();
+        tokio::spawn(async move {
+            let search = async {
+                let mut found = match &topic {
+                    Some(topic) => topic.get().await?.search(&query).await,
+                    None => Vec::new(),
+                };
+                if let Some(server) = server {
+                    for listing in directory::search(&endpoint, server, &query).await? {
+                        if !found.iter().any(|known| known.ticket == listing.ticket) {
+                            found.push(listing);
+                        }
+                    }
+                }
+                anyhow::Ok(found)
+            };
+            let message = match search.await {
+                Ok(listings) => ChatEvent::Directory(listings),
+                Err(e) => {
+                    warn!("directory search failed: {:#}", e);
+                    ChatEvent::System(t!("Directory search failed: {}", format!("{:#}", e)))
+                }
+            };
+            let _ = ui_tx.send(message);
+        });
+        Ok(())
+    }
+
+    /*
+    Function:   -publish_listing
+    Purpose:    -List this room on the configured directory and directory
+                 topic, or refresh its listing.
+
+    Parameters:
+                - String name:  Room name to list under.
+                - String description:  One line about the room.
+                - usize members:  Current member count.
+
+    Details:
+                - Creator only: the listing is signed with our key and
+                  checked against the ticket's creator by whoever shows it.
+                - Runs in the background; only failures are reported.
+    */
+    pub fn publish_listing(&self, name: String, description: String, members: usize) -> Result<()> {
+        if self.moderator != Some(self.my_id) {
+            anyhow::bail!(t!("Only the room's creator can list it in the directory."));
+        }
+        let (server, topic) = self.directories()?;
+        let listing = Listing {
+            name,
+            description,
+            members,
+            ticket: self.ticket.to_string(),
+        };
+        let signature = sign_listing(&listing, self.endpoint.secret_key(), &self.ticket.topic);
+        let signed = SignedListing { listing, signature };
+        let endpoint = self.endpoint.clone();
+        let ui_tx = self.ui_tx.clone();
+        tokio::spawn(async move {
+            let publish = async {
+                if let Some(topic) = &topic {
+                    topic.get().await?.publish(signed.clone()).await?;
+                }
+                if let Some(server) = server {
+                    directory::publish(&endpoint, server, signed).await?;
+                }
+                anyhow::Ok(())
+            };
+            if let Err(e) = publish.await {
+                warn!("directory listing failed: {:#}", e);
+                let _ = ui_tx
+                    .send(ChatEvent::System(t!("Directory listing failed: {}", format!("{:#}", e))));
+            }
+        });
+        Ok(())
+    }
+
+    /*
+    Function:   -record_voice
+    Purpose:    -Record a voice message in the background.
+
+    Parameters:
+                - u64 seconds:  Length of the recording.
+
+    Details:
+                - The clip arrives as ChatEvent::Recorded, for the TUI to send;
+                  failures (no microphone, no voice support) as a system
+                  message.
+    */
+    pub fn record_voice(&self, seconds: u64) {
+        let ui_tx = self.ui_tx.clone();
+        tokio::spawn(async move {
+            let message = match tokio::task::spawn_blocking(move || voice::record(seconds)).await {
+                Ok(Ok(clip)) => ChatEvent::Recorded(clip),
+                Ok(Err(e)) => ChatEvent::System(t!("Could not record: {}", format!("{:#}", e))),
+                Err(e) => ChatEvent::System(t!("Could not record: {}", e)),
+            };
+            let _ = ui_tx.send(message);
+        });
+    }
+
+    /// Play a voice message in the background; failures appear as a
+    /// system message.
+    pub fn play_voice(&self, clip: VoiceClip) {
+        let ui_tx = self.ui_tx.clone();
+        tokio::spawn(async move {
+            let error = match tokio::task::spawn_blocking(move || voice::play(&clip)).await {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => format!("{:#}", e),
+                Err(e) => e.to_string(),
+            };
+            let _ = ui_tx.send(ChatEvent::System(t!("Could not play: {}", error)));
+        });
+    }
+
+    /// Local UDP sockets the endpoint is listening on.
+    pub fn bound_sockets(&self) -> Vec<SocketAddr> {
+        self.endpoint.bound_sockets()
+    }
+
+    /// Peers this profile blocks, in a stable order for /blocks.
+    pub fn blocked(&self) -> Vec<EndpointId> {
+        let mut blocked: Vec<EndpointId> = self
+            .blocked
+            .lock()
+            .expect("blocklist lock poisoned")
+            .iter()
+            .copied()
+            .collect();
+        blocked.sort_by_key(|id| id.to_string());
+        blocked
+    }
+
+    /*
+    Function:   -set_blocked
+    Purpose:    -Block or unblock a peer, now and in the profile's config.
+
+    Parameters:
+                - EndpointId id:  The peer.
+                - bool blocked:  Block (true) or unblock (false).
+
+    Details:
+                - The config is re-read before saving, so other tabs' blocks
+                  and any hand edits are kept. Other tabs of the profile
+                  pick the change up when they next start.
+                - The peer is flagged in the name cache as well, so /blocks
+                  can still name them.
+    */
+    pub fn set_blocked(&mut self, id: EndpointId, blocked: bool) -> Result<()> {
+        {
+            let mut set = self.blocked.lock().expect("blocklist lock poisoned");
+            if blocked {
+                set.insert(id);
+            } else {
+                set.remove(&id);
+            }
+        }
+        let path = Profile::open(&self.profile)?.config_path();
+        let mut config = Config::load(&path)?;
+        let key = id.to_string();
+        config.blocked.retain(|b| *b != key);
+        if blocked {
+            config.blocked.push(key);
+        }
+        config.save(&path)?;
+        self.config.blocked = config.blocked;
+        self.names.set_blocked(id, blocked)
+    }
+
+    /*
+    Function:   -rename
+    Purpose:    -Change our nickname in this room.
+
+    Parameters:
+                - Option<String> name:  The new nickname, or None to go back
+                  to the profile's own name.
+                - bool remember:  Also keep the change in the profile's
+                  nicknames for this room (room_names), for the next time we
+                  join it.
+
+    Details:
+                - The room hears of it with AboutMe, like a join.
+                - The config is re-read before saving, like set_blocked.
+    */
+    pub async fn rename(&mut self, name: Option<String>, remember: bool) -> Result<()> {
+        if remember {
+            self.set_room_name(name.clone())?;
+        }
+        let name = name
+            .or_else(|| self.config.name.clone())
+            .unwrap_or_else(|| "Anonymous".to_string());
+        let _ = self.commands.send(ChatCommand::Nick(name.clone())).await;
+        self.my_name = name;
+        Ok(())
+    }
+
+    /// Save new notification rules in the profile's [notify] table.
+    pub fn set_notify(&mut self, notify: NotifyConfig) -> Result<()> {
+        let path = Profile::open(&self.profile)?.config_path();
+        let mut config = Config::load(&path)?;
+        config.notify = notify;
+        config.save(&path)?;
+        self.config.notify = config.notify;
+        Ok(())
+    }
+
+    /// Tell the room we are online, away or busy.
+    pub async fn set_presence(&mut self, presence: Presence) {
+        let _ = self.commands.send(ChatCommand::Presence(presence)).await;
+        self.presence = presence;
+    }
+
+    fn set_room_name(&mut self, name: Option<String>) -> Result<()> {
+        let path = Profile::open(&self.profile)?.config_path();
+        let mut config = Config::load(&path)?;
+        let topic = self.ticket.topic.to_string();
+        match name {
+            Some(name) => config.room_names.insert(topic, name),
+            None => config.room_names.remove(&topic),
+        };
+        config.save(&path)?;
+        self.config.room_names = config.room_names;
+        Ok(())
+    }
+
+    /// Path, latency and traffic per connected peer, for /net.
+    pub fn peer_paths(&self) -> Vec<PeerPath> {
+        self.connections.snapshot()
+    }
+
+    /// Bandwidth and chat message rate in this room, for the status bar.
+    pub fn traffic(&self) -> Rates {
+        self.traffic.rates()
+    }
+
+    /// Our gossip neighbours, and theirs as reported since the last /swarm.
+    pub fn swarm(&self) -> (Vec<Neighbour>, BTreeMap<EndpointId, Vec<Neighbour>>) {
+        (self.swarm.links(), self.swarm.reports())
+    }
+
+    /// Short description of the relay in use, for the status line.
+    pub fn relay_status(&self) -> String {
+        if self.net.no_relay || self.config.lan.offline {
+            return t!("direct only").to_string();
+        }
+        match self.endpoint.net_report().get().and_then(|report| report.preferred_relay) {
+            Some(url) => url.host_str().unwrap_or(t!("relay")).to_string(),
+            None => t!("no relay").to_string(),
+        }
+    }
+
+    /*
+    Function:   -network_status
+    Purpose:    -Describe the session's relay situation for /network.
+
+    Details:
+                - Lists every probed relay with its best latency, marking the
+                  one in use and whether it was pinned in the config.
+                - Includes the topic ID, which is the key for relay_pins.
+    */
+    pub fn network_status(&self) -> Vec<String> {
+        let mut lines = vec![t!("Room: {}", self.ticket.topic)];
+        for addr in self.bound_sockets() {
+            lines.push(t!("Bound: {}", addr));
+        }
+        if self.net.no_relay || self.config.lan.offline {
+            lines.push(t!("Relays disabled; direct connections only.").to_string());
+            return lines;
+        }
+        let Some(report) = self.endpoint.net_report().get() else {
+            lines.push(t!("Relay probe still running…").to_string());
+            return lines;
+        };
+        match (&report.preferred_relay, &self.pinned_relay) {
+            (Some(url), Some(_)) => lines.push(t!("Relay: {} (pinned)", url)),
+            (Some(url), None) => lines.push(t!("Relay: {} (lowest latency)", url)),
+            (None, _) => lines.push(t!("Relay: none reachable").to_string()),
+        }
+        let mut urls: Vec<&RelayUrl> = report.relay_latency.iter().map(|(_, url, _)| url).collect();
+        urls.sort();
+        urls.dedup();
+        for url in urls {
+            if let Some(latency) = relay_latency(&report, url) {
+                let marker = if report.preferred_relay.as_ref() == Some(url) { "*" } else { " " };
+                lines.push(format!("{} {} {} ms", marker, url, latency.as_millis()));
+            }
+        }
+        lines
+    }
+
+    /// The name `id` went by in this room when last heard from, in this
+    /// session or an earlier one.
+    pub fn known_name(&self, id: EndpointId) -> Option<String> {
+        self.names.get(&self.ticket.topic, id).map(|known| known.name)
+    }
+
+    /// The peer seen in this room whose endpoint ID starts with `prefix`
+    /// (e.g. the short form shown in the UI), if exactly one does.
+    pub fn peer_with_prefix(&self, prefix: &str) -> Option<EndpointId> {
+        self.sightings.with_prefix(prefix)
+    }
+
+    /*
+    Function:   -whois
+    Purpose:    -Describe a peer for /whois.
+
+    Parameters:
+                - EndpointId id:  The peer.
+
+    Details:
+                - Addresses and relay are what iroh knows of the peer,
+                  marking the ones in use; the connection line is our
+                  current path to it, as in /net.
+                - First and last seen, message count and key status come
+                  from the receive loop and only cover this session.
+    */
+    pub async fn whois(&self, id: EndpointId) -> Vec<String> {
+        let mut lines = vec![format!("ID: {}", id)];
+        let addrs: Vec<(TransportAddr, bool)> = match self.endpoint.remote_info(id).await {
+            Some(info) => info
+                .into_addrs()
+                .map(|addr| {
+                    let active = matches!(addr.usage(), TransportAddrUsage::Active);
+                    (addr.into_addr(), active)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let in_use = |active: bool| if active { t!(" (in use)") } else { "" };
+        let mut direct = Vec::new();
+        let mut relays = Vec::new();
+        for (addr, active) in &addrs {
+            match addr {
+                TransportAddr::Ip(ip) => direct.push(format!("{}{}", ip, in_use(*active))),
+                TransportAddr::Relay(url) => relays.push(format!("{}{}", url, in_use(*active))),
+                other => direct.push(format!("{:?}{}", other, in_use(*active))),
+            }
+        }
+        if direct.is_empty() {
+            direct.push(t!("none known").to_string());
+        }
+        if relays.is_empty() {
+            relays.push(t!("none known").to_string());
+        }
+        lines.push(t!("Addresses: {}", direct.join(", ")));
+        lines.push(t!("Relay: {}", relays.join(", ")));
+        match self.peer_paths().into_iter().find(|path| path.id == id) {
+            Some(PeerPath { relayed: Some(relayed), rtt: Some(rtt), .. }) => lines.push(t!(
+                "Connection: {}, {} ms",
+                if relayed { t!("relayed") } else { t!("direct") },
+                rtt.as_millis()
+            )),
+            _ => lines.push(t!("Connection: none open").to_string()),
+        }
+        let Some(seen) = self.sightings.get(id) else {
+            lines.push(t!("Not heard from in this session.").to_string());
+            return lines;
+        };
+        lines.push(match seen.verified {
+            Some(true) => t!("Key: signed by them 🔒 (verified)").to_string(),
+            Some(false) => t!("Key: not signed, shared key 🔓 (not verified)").to_string(),
+            None => t!("Key: no readable message yet").to_string(),
+        });
+        lines.push(t!(
+            "First seen: {}, last seen: {}",
+            seen.first.format("%Y-%m-%d %H:%M:%S"),
+            seen.last.format("%Y-%m-%d %H:%M:%S")
+        ));
+        lines.push(t!("Chat messages received: {}", seen.messages));
+        lines
+    }
+
+    /*
+    Function:   -shutdown
+    Purpose:    -Leave the room and close the endpoint.
+
+    Details:
+                - Messages still queued are sent first, then Leave, so peers
+                  see us go rather than our name going stale.
+                - Waits at most LEAVE_TIMEOUT for that, then LEAVE_GRACE for
+                  gossip to pass it on before the connections close.
+    */
+    pub async fn shutdown(self) -> Result<()> {
+        if self.commands.send(ChatCommand::Leave).await.is_ok()
+            && tokio::time::timeout(LEAVE_TIMEOUT, self.send_task).await.is_ok()
+        {
+            tokio::time::sleep(LEAVE_GRACE).await;
+        }
+        if let Some(rendezvous) = self.rendezvous {
+            rendezvous.shutdown().await?;
+        }
+        self.router.shutdown().await?;
+        Ok(())
+    }
+}
+
+/*
+Function:   -send_queue
+Purpose:    -Broadcast chat messages, deletes, clicks and Leave in order,
+             retrying failures.
+
+Parameters:
+            - RoomSender sender:  The room.
+            - Receiver<(Vec<u8>, Option<u64>)> queue:  Messages to send, with
+              the ID of the chat message each carries, if any.
+            - Events ui_tx:  For ChatEvent::Queued, Sent and
+              SendFailed.
+
+Details:
+            - While we have no neighbours, messages are held in an outbox
+              instead of broadcast to nobody. Chat messages are marked as
+              pending in the TUI (Queued); the outbox is sent, in order, as
+              soon as a neighbour turns up.
+            - Every chat message that goes out, held or not, is reported as
+              Sent, for its ✓.
+            - A failed broadcast is retried up to SEND_ATTEMPTS times, waiting
+              SEND_RETRY and then twice as long each time; this rides out the
+              receive loop rejoining the topic. Later messages wait their turn.
+            - A chat message that never goes out is marked as failed in the
+              TUI, which can queue it again (Ctrl+R).
+            - Keeps the room's Load told how many broadcasts are waiting, so
+              presence changes can be held back while it is backed up.
+            - Ends once the send loop has gone and the queue is empty, or the
+              outbox can no longer be sent.
+*/
+async fn send_queue(
+    sender: RoomSender,
+    mut queue: mpsc::Receiver<(Vec<u8>, Option<u64>)>,
+    ui_tx: Events,
+) {
+    let mut outbox: VecDeque<(Vec<u8>, Option<u64>)> = VecDeque::new();
+    let mut open = true;
+    loop {
+        sender.load().set_queued(outbox.len() + queue.len());
+        while !sender.is_joined() {
+            if !open && outbox.is_empty() {
+                return;
+            }
+            tokio::select! {
+                item = queue.recv(), if open => match item {
+                    Some((bytes, id)) => {
+                        if let Some(id) = id {
+                            let _ = ui_tx.send(ChatEvent::Queued(id));
+                        }
+                        outbox.push_back((bytes, id));
+                        sender.load().set_queued(outbox.len() + queue.len());
+                    }
+                    None => open = false,
+                },
+                _ = sender.wait_joined() => {}
+            }
+        }
+        let held = !outbox.is_empty();
+        let (bytes, id) = match outbox.pop_front() {
+            Some(item) => item,
+            None => match queue.recv().await {
+                Some(item) => item,
+                None => break,
+            },
+        };
+        // The last neighbour may have gone while we waited for this one.
+        if !sender.is_joined() {
+            if !held && let Some(id) = id {
+                let _ = ui_tx.send(ChatEvent::Queued(id));
+            }
+            outbox.push_front((bytes, id));
+            continue;
+        }
+        let mut wait = SEND_RETRY;
+        let mut attempt = 1;
+        let mut sent = true;
+        while let Err(e) = sender.broadcast(bytes.clone()).await {
+            if attempt == SEND_ATTEMPTS {
+                warn!(?id, error = %e, "giving up on broadcast");
+                if let Some(id) = id {
+                    let _ = ui_tx.send(ChatEvent::SendFailed(id));
+                }
+                sent = false;
+                break;
+            }
+            debug!(?id, attempt, error = %e, "broadcast failed, retrying");
+            tokio::time::sleep(wait).await;
+            wait *= 2;
+            attempt += 1;
+        }
+        if sent && let Some(id) = id {
+            sender.traffic().message();
+            let _ = ui_tx.send(ChatEvent::Sent(id));
+        }
+    }
+}
+
+/*
+Function:   -start_rendezvous
+Purpose:    -Run a named room's rendezvous endpoint next to our own.
+
+Parameters:
+            - SecretKey key:  The rendezvous identity from crypto::named_room.
+            - TopicId topic:  The named room's topic.
+            - EndpointId via:  Our own endpoint, the rendezvous's first peer.
+
+Details:
+            - Joiners only know the rendezvous ID, which they look up through
+              the default address lookup. It joins the topic like any peer, so
+              gossip introduces them to the rest of the room.
+            - Relays messages but never reads them; its events are drained
+              so it does not fall behind.
+            - Once it goes away the members already in the room stay
+              connected to each other, but newcomers cannot find them.
+*/
+async fn start_rendezvous(key: SecretKey, topic: TopicId, via: EndpointId) -> Result<Router> {
+    let endpoint = Endpoint::builder().secret_key(key).bind().await?;
+    let gossip = Gossip::builder().spawn(endpoint.clone());
+    let router = Router::builder(endpoint).accept(iroh_gossip::ALPN, gossip.clone()).spawn();
+    let mut events = gossip.subscribe(topic, vec![via]).await?;
+    tokio::spawn(async move { while let Ok(Some(_)) = events.try_next().await {} });
+    Ok(router)
+}
+
+/// Join every member of the room that mDNS finds on the local network, for as
+/// long as the session runs.
+async fn join_lan_peers(mdns: MdnsAddressLookup, sender: RoomSender, ui_tx: Events) {
+    let mut events = mdns.subscribe().await;
+    let mut seen = HashSet::new();
+    while let Some(event) = events.next().await {
+        let DiscoveryEvent::Discovered { endpoint_info, .. } = event else {
+            continue;
+        };
+        let id = endpoint_info.endpoint_id;
+        if let Err(e) = sender.join_peers(vec![id]).await {
+            warn!(peer = %id.fmt_short(), "stopped joining LAN peers: {:#}", e);
+            break;
+        }
+        if seen.insert(id) {
+            let _ = ui_tx
+                .send(ChatEvent::System(t!("Found {} on the local network.", id.fmt_short())));
+        }
+    }
+}
+
+/// Best latency measured to `url` across all probe kinds.
+fn relay_latency(report: &iroh::NetReport, url: &RelayUrl) -> Option<Duration> {
+    report
+        .relay_latency
+        .iter()
+        .filter(|(_, u, _)| *u == url)
+        .map(|(_, _, latency)| latency)
+        .min()
+}
+
+/// Save a private room's current key and admitted members for its creator;
+/// the room goes on regardless if that fails.
+fn save_room_key(keys: Option<&CreatorKeys>, topic: &TopicId, room_key: &RoomKey, admitted: &HashSet<EndpointId>) {
+    if let (Some(keys), Some(key)) = (keys, room_key.current())
+        && let Err(e) = keys.save(topic, &key, admitted)
+    {
+        warn!(error = %e, "could not save the room key");
+    }
+}
@@ -0,0 +1,834 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use iroh::EndpointId;
+use tempfile::TempDir;
+
+use crate::app::{ChatEvent, ChatMessage, Trust};
+use crate::archive::{Archive, StoreKey};
+use crate::bundle::Bundle;
+use crate::bus::ChatCommand;
+use crate::client::{ChatClientBuilder, HistoryBackend};
+use crate::config::Config;
+use crate::drafts::Drafts;
+use crate::names::NameCache;
+use crate::profile::Profile;
+use crate::protocol::{ChatPayload, Message, MessageBody, ModAction, Presence, Ticket};
+use crate::recent::RecentRooms;
+use crate::roomkeys::CreatorKeys;
+use crate::session::{Entry, Session};
+use crate::wipe::wipe;
+
+// ── Simulated peers ───────────────────────────────────────────────────────────
+
+/// Longest a test waits for an event before failing.
+const EVENT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/*
+Struct:     -Peer
+Purpose:    -A room member run in-process for tests: a full Session with its
+             own throwaway profile, reachable only over localhost.
+
+Fields:
+            - Session session:  The member's session, driven through the
+              same channels as the TUI.
+            - Profile profile:  The member's throwaway profile.
+            - TempDir _dir:  Holds the profile's directory, removed when the
+              peer is dropped.
+
+Details:
+            - Endpoints bind 127.0.0.1 with relays off, so tests need no
+              network and peers in different tests never meet. Members find
+              each other through the tickets they pass around, as real ones
+              do.
+            - Events are read from session.events; `expect` skips the ones a
+              test does not care about.
+*/
+pub struct Peer {
+    pub session: Session,
+    pub profile: Profile,
+    _dir: TempDir,
+}
+
+impl Peer {
+    /// Open a new room as its creator.
+    pub async fn open(name: &str, private: bool) -> Result<Self> {
+        Self::start(name, Entry::Open { private, title: None }, |_| {}).await
+    }
+
+    /// Join the room a ticket names.
+    pub async fn join(name: &str, ticket: &Ticket) -> Result<Self> {
+        let ticket: Ticket = ticket.to_string().parse()?;
+        Self::start(name, Entry::Join(ticket), |_| {}).await
+    }
+
+    /// Open or join as `entry` says, with the profile's config changed by
+    /// `configure` first.
+    pub async fn start(name: &str, entry: Entry, configure: impl FnOnce(&mut Config)) -> Result<Self> {
+        Self::start_in(tempfile::tempdir()?, name, entry, configure, HistoryBackend::Memory).await
+    }
+
+    /// Like start, with the profile at `<dir>/<name>`, which a test may
+    /// have filled beforehand, and keeping history as `history` says.
+    pub async fn start_in(
+        dir: TempDir,
+        name: &str,
+        entry: Entry,
+        configure: impl FnOnce(&mut Config),
+        history: HistoryBackend,
+    ) -> Result<Self> {
+        let profile = Profile::at(dir.path().join(name))?;
+        let mut config = Config::default();
+        configure(&mut config);
+        config.save(&profile.config_path())?;
+        let session = ChatClientBuilder::new()
+            .profile(profile.clone())
+            .nickname(name)
+            .entry(entry)
+            .history(history)
+            .bind("127.0.0.1:0".parse()?)
+            .no_relay()
+            .build()
+            .await?;
+        Ok(Self { session, profile, _dir: dir })
+    }
+
+    pub fn id(&self) -> EndpointId {
+        self.session.my_id
+    }
+
+    /// Send a text message; returns its ID.
+    pub async fn send(&self, text: &str) -> Result<u64> {
+        let id = rand::random();
+        self.session.commands.send(ChatCommand::Send { payload: ChatPayload::text(text), id }).await?;
+        Ok(id)
+    }
+
+    /// Delete one of our messages everywhere.
+    pub async fn delete(&self, id: u64) -> Result<()> {
+        Ok(self.session.commands.send(ChatCommand::Delete(id)).await?)
+    }
+
+    /// Let a member who knocked into our private room.
+    pub async fn admit(&self, id: EndpointId) -> Result<()> {
+        Ok(self.session.commands.send(ChatCommand::Admit(id)).await?)
+    }
+
+    /// Kick a member, as the room's creator.
+    pub async fn kick(&self, id: EndpointId) -> Result<()> {
+        Ok(self.session.commands.send(ChatCommand::Moderate(ModAction::Kick { target: id })).await?)
+    }
+
+    /// Rotate our private room's key.
+    pub async fn rekey(&self) -> Result<()> {
+        Ok(self.session.commands.send(ChatCommand::Rekey).await?)
+    }
+
+    /// The next event `pick` accepts, skipping the others; fails after
+    /// EVENT_TIMEOUT or when the session ends.
+    pub async fn expect<T>(&mut self, what: &str, mut pick: impl FnMut(ChatEvent) -> Option<T>) -> Result<T> {
+        let wait = async {
+            while let Some(event) = self.session.events.recv().await {
+                if let Some(found) = pick(event) {
+                    return Ok(found);
+                }
+            }
+            bail!("the session ended")
+        };
+        tokio::time::timeout(EVENT_TIMEOUT, wait)
+            .await
+            .with_context(|| format!("timed out waiting for {}", what))?
+    }
+
+    /// The next chat message with this text.
+    pub async fn expect_chat(&mut self, text: &str) -> Result<ChatMessage> {
+        self.expect(&format!("chat {:?}", text), |event| match event {
+            ChatEvent::Chat(chat) if chat.content == text => Some(chat),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Whether no chat message with this text arrives within `wait`.
+    pub async fn never_sees(&mut self, text: &str, wait: Duration) -> bool {
+        let seen = tokio::time::timeout(wait, async {
+            while let Some(event) = self.session.events.recv().await {
+                if matches!(&event, ChatEvent::Chat(chat) if chat.content == text) {
+                    return true;
+                }
+            }
+            false
+        });
+        !seen.await.unwrap_or(false)
+    }
+
+    /// Wait until every one of `others` has a name here, i.e. their
+    /// announcements got through.
+    pub async fn expect_members(&mut self, others: &[EndpointId]) -> Result<()> {
+        let mut missing: HashSet<EndpointId> = others.iter().copied().collect();
+        self.expect("members' names", |event| {
+            if let ChatEvent::Name { from, .. } = event {
+                missing.remove(&from);
+            }
+            missing.is_empty().then_some(())
+        })
+        .await
+    }
+
+    pub async fn shutdown(self) -> Result<()> {
+        self.session.shutdown().await
+    }
+}
+
+// ── Scenarios ─────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+    use iroh_gossip::proto::TopicId;
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, UnixStream},
+    };
+
+    use super::*;
+    use crate::bot::Hosted;
+    use crate::bridge::{run_irc, IrcOptions};
+    use crate::gateway::{Event, Request};
+    use crate::config::{BotReply, HostedBot};
+    use crate::crypto::{
+        current_step, encrypt_message, get_encryption_key, ratchet, wrap_sender_key, MessageKey, RoomKey,
+    };
+    use crate::protocol::RoomInfo;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn message_reaches_the_room() -> Result<()> {
+        let alice = Peer::open("alice", false).await?;
+        let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+        bob.expect_members(&[alice.id()]).await?;
+        alice.send("hello bob").await?;
+        let chat = bob.expect_chat("hello bob").await?;
+        assert_eq!(chat.from, alice.id());
+        assert_eq!(chat.sender, "alice");
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn delete_reaches_the_room() -> Result<()> {
+        let alice = Peer::open("alice", false).await?;
+        let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+        bob.expect_members(&[alice.id()]).await?;
+        let id = alice.send("oops").await?;
+        bob.expect_chat("oops").await?;
+        alice.delete(id).await?;
+        bob.expect("the delete", |event| matches!(event, ChatEvent::Delete(deleted) if deleted == id).then_some(()))
+            .await?;
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn joined_rooms_are_remembered() -> Result<()> {
+        let alice = Peer::open("alice", false).await?;
+        let bob = Peer::join("bob", &alice.session.ticket).await?;
+        // Only the room Bob joined by ticket: Alice's names only herself.
+        assert!(RecentRooms::new(&alice.profile).list().is_empty());
+        let room = RecentRooms::new(&bob.profile).get(1).context("no recent room")?;
+        assert_eq!(room.ticket()?.topic, alice.session.ticket.topic);
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rooms_move_between_profiles() -> Result<()> {
+        let mut alice = Peer::open("alice", true).await?;
+        let bob = Peer::join("bob", &alice.session.ticket).await?;
+        let topic = alice.session.ticket.topic;
+        let bob_id = bob.id();
+        let knocked = alice
+            .expect("a knock", |event| match event {
+                ChatEvent::Knock { from, .. } => Some(from),
+                _ => None,
+            })
+            .await?;
+        alice.admit(knocked).await?;
+        let Peer { session, profile, _dir } = bob;
+        session.shutdown().await?;
+        let alice_id = alice.id();
+        let Peer { session, profile: alice_profile, _dir: _alice_dir } = alice;
+        session.shutdown().await?;
+        Drafts::new(&profile).save(&topic, "see you there")?;
+        let about = Message::new(MessageBody::about_me(bob_id, "bob".to_string()));
+        assert_eq!(Archive::open(&profile, &topic, &StoreKey::Plain)?.restore(&[about.to_vec()])?, 1);
+
+        let sealed = Bundle::collect(&profile, &topic, &StoreKey::Plain)?.seal("moving day")?;
+        assert!(Bundle::open(&sealed, "wrong").is_err());
+        let dir = tempfile::tempdir()?;
+        let laptop = Profile::at(dir.path().join("laptop"))?;
+        let imported = Bundle::open(&sealed, "moving day")?.import(&laptop, &StoreKey::Plain, false)?;
+        assert_eq!(imported.messages, 1);
+        assert!(imported.other_identity);
+        assert_ne!(laptop.secret_key()?.public(), bob_id);
+        assert_eq!(RecentRooms::new(&laptop).get(1).context("no recent room")?.ticket()?.topic, topic);
+        assert_eq!(Drafts::new(&laptop).load(&topic), "see you there");
+
+        let imported = Bundle::open(&sealed, "moving day")?.import(&laptop, &StoreKey::Plain, true)?;
+        assert!(!imported.other_identity);
+        assert_eq!(laptop.secret_key()?.public(), bob_id);
+
+        // The creator's move takes the room key and who was let in along.
+        let (key, admitted) = CreatorKeys::new(&alice_profile)?.load(&topic)?.context("no saved room key")?;
+        assert_eq!(admitted, HashSet::from([bob_id]));
+        let sealed = Bundle::collect(&alice_profile, &topic, &StoreKey::Plain)?.seal("moving day")?;
+        let desktop = Profile::at(dir.path().join("desktop"))?;
+        Bundle::open(&sealed, "moving day")?.import(&desktop, &StoreKey::Plain, true)?;
+        assert_eq!(desktop.secret_key()?.public(), alice_id);
+        let (moved, admitted) = CreatorKeys::new(&desktop)?.load(&topic)?.context("the room key did not move")?;
+        assert_eq!((moved.epoch, moved.step, moved.key), (key.epoch, key.step, key.key));
+        assert_eq!(admitted, HashSet::from([bob_id]));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn archive_replays_old_history() -> Result<()> {
+        // Dave handed Carol his key ten hours ago and spoke five hours
+        // later; Carol only learns of either from Alice's archive.
+        let topic = TopicId::from_bytes(rand::random());
+        let carol_dir = tempfile::tempdir()?;
+        let carol_id = Profile::at(carol_dir.path().join("carol"))?.secret_key()?.public();
+        let dave = SecretKey::from_bytes(&rand::random());
+        let now = current_step();
+        let handed = MessageKey { epoch: rand::random::<u64>().max(1), step: now - 10, key: rand::random() };
+        let room_key = RoomKey::public(get_encryption_key(&topic))
+            .for_message(0, handed.step)
+            .context("no room key")?;
+        let (wrapped, nonce) = wrap_sender_key(&handed, &room_key, &dave, carol_id, &topic)?;
+        let key_msg = Message::new(MessageBody::SenderKey {
+            from: dave.public(),
+            to: carol_id,
+            room_epoch: 0,
+            room_step: handed.step,
+            key_id: handed.epoch,
+            step: handed.step,
+            wrapped,
+            nonce,
+        });
+        let mut later = MessageKey { step: now - 5, ..handed };
+        for _ in handed.step..later.step {
+            later.key = ratchet(&later.key);
+        }
+        let said = encrypt_message(&ChatPayload::text("while you were away"), &dave, &topic, later, rand::random())?;
+        let about = Message::new(MessageBody::about_me(dave.public(), "dave".to_string()));
+        let alice_dir = tempfile::tempdir()?;
+        let alice_profile = Profile::at(alice_dir.path().join("alice"))?;
+        let history = [said.to_vec(), key_msg.to_vec(), about.to_vec()];
+        assert_eq!(Archive::open(&alice_profile, &topic, &StoreKey::Plain)?.restore(&history)?, 3);
+
+        let ticket = Ticket {
+            topic,
+            endpoints: Vec::new(),
+            creator: None,
+            private: false,
+            expires: None,
+            token: None,
+            info: RoomInfo::default(),
+        };
+        let alice = Peer::start_in(alice_dir, "alice", Entry::Join(ticket), |_| {}, HistoryBackend::Archive).await?;
+        let join = Entry::Join(alice.session.ticket.to_string().parse()?);
+        let mut carol = Peer::start_in(carol_dir, "carol", join, |_| {}, HistoryBackend::Memory).await?;
+        let chat = carol.expect_chat("while you were away").await?;
+        assert_eq!(chat.sender, "dave");
+        assert_eq!(chat.trust, Trust::Verified);
+        carol.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn wipe_keeps_only_the_config() -> Result<()> {
+        let alice = Peer::open("alice", false).await?;
+        let bob = Peer::join("bob", &alice.session.ticket).await?;
+        let topic = alice.session.ticket.topic;
+        Drafts::new(&bob.profile).save(&topic, "half a thought")?;
+        let mut config = Config { name: Some("bob".to_string()), ..Config::default() };
+        config.room_names.insert(topic.to_string(), "bobby".to_string());
+        config.save(&bob.profile.config_path())?;
+        // Wiping waits for the session: it saves drafts and archives.
+        let Peer { session, profile, _dir } = bob;
+        session.shutdown().await?;
+        alice.shutdown().await?;
+
+        assert!(wipe(&profile, false)? >= 2);
+        for gone in [profile.drafts_path(), profile.rooms_path(), profile.names_path(), profile.store_dir()] {
+            assert!(!gone.exists(), "{} survived", gone.display());
+        }
+        assert!(profile.identity_path().exists());
+        wipe(&profile, true)?;
+        assert!(!profile.identity_path().exists());
+        let config = Config::load(&profile.config_path())?;
+        assert_eq!(config.name.as_deref(), Some("bob"));
+        assert!(config.room_names.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn names_are_remembered() -> Result<()> {
+        let alice = Peer::open("alice", false).await?;
+        let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+        bob.expect_members(&[alice.id()]).await?;
+        alice.send("remember me").await?;
+        bob.expect_chat("remember me").await?;
+        let alice_id = alice.id();
+        alice.shutdown().await?;
+
+        // Alice's name and key status outlive the session; blocking her
+        // is noted alongside.
+        let topic = bob.session.ticket.topic;
+        let names = NameCache::new(&bob.profile);
+        let known = names.get(&topic, alice_id).context("alice not cached")?;
+        assert_eq!(known.name, "alice");
+        assert!(known.verified);
+        names.set_blocked(alice_id, true)?;
+        assert!(names.get(&topic, alice_id).is_some_and(|known| known.blocked));
+        assert_eq!(bob.session.known_name(alice_id).as_deref(), Some("alice"));
+        bob.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn legacy_copies_are_not_shown_twice() -> Result<()> {
+        let alice = Peer::start("alice", Entry::Open { private: false, title: None }, |config| {
+            config.legacy_compat = true;
+        })
+        .await?;
+        let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+        bob.expect_members(&[alice.id()]).await?;
+        // The plaintext copy is for legacy clients; Bob reads the encrypted
+        // message alone.
+        alice.send("in both formats").await?;
+        assert_eq!(bob.expect_chat("in both formats").await?.trust, Trust::Verified);
+        assert!(bob.never_sees("in both formats", Duration::from_secs(3)).await);
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn late_joiner_reads_the_room() -> Result<()> {
+        let alice = Peer::open("alice", false).await?;
+        let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+        bob.expect_members(&[alice.id()]).await?;
+        alice.send("before carol").await?;
+        bob.expect_chat("before carol").await?;
+
+        // Carol joins through Bob's ticket, learns both their names and
+        // reads both of them.
+        let mut carol = Peer::join("carol", &bob.session.ticket).await?;
+        carol.expect_members(&[bob.id(), alice.id()]).await?;
+        bob.send("hi carol").await?;
+        let chat = carol.expect_chat("hi carol").await?;
+        assert_eq!(chat.sender, "bob");
+        alice.send("welcome").await?;
+        assert_eq!(carol.expect_chat("welcome").await?.sender, "alice");
+        bob.expect_chat("welcome").await?;
+
+        // Announcing a name again shows nothing new.
+        bob.session.rename(Some("bob".to_string()), false).await?;
+        bob.send("still bob").await?;
+        let repeats = carol
+            .expect("chat \"still bob\"", |event| match event {
+                ChatEvent::System(text) if text == "bob joined the chat" => Some(true),
+                ChatEvent::Chat(chat) if chat.content == "still bob" => Some(false),
+                _ => None,
+            })
+            .await?;
+        assert!(!repeats, "the repeat showed as a join");
+        carol.shutdown().await?;
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn private_room_admits_and_rekeys() -> Result<()> {
+        let mut alice = Peer::open("alice", true).await?;
+        let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+        let mut carol = Peer::join("carol", &alice.session.ticket).await?;
+        for _ in 0..2 {
+            let knocked = alice
+                .expect("a knock", |event| match event {
+                    ChatEvent::Knock { from, .. } => Some(from),
+                    _ => None,
+                })
+                .await?;
+            alice.admit(knocked).await?;
+        }
+        bob.expect_members(&[carol.id()]).await?;
+        alice.send("members only").await?;
+        bob.expect_chat("members only").await?;
+        carol.expect_chat("members only").await?;
+
+        // Carol is kicked and the key rotated: Bob still reads the room,
+        // Carol no longer does.
+        // Rekey only once the kick has renewed Alice's sender key, or the
+        // renewal would follow the rekey's handover and Bob miss the new key.
+        alice.kick(carol.id()).await?;
+        alice
+            .expect("the kick", |event| match event {
+                ChatEvent::System(text) if text.ends_with("was kicked by the moderator.") => Some(()),
+                _ => None,
+            })
+            .await?;
+        alice.rekey().await?;
+        bob.expect("the new key", |event| match event {
+            ChatEvent::System(text) if text.starts_with("The room's creator rotated the key") => Some(()),
+            _ => None,
+        })
+        .await?;
+        alice.send("after the kick").await?;
+        bob.expect_chat("after the kick").await?;
+        assert!(carol.never_sees("after the kick", Duration::from_secs(3)).await);
+        carol.shutdown().await?;
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn private_room_survives_its_creator_restarting() -> Result<()> {
+        let mut alice = Peer::open("alice", true).await?;
+        let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+        let knocked = alice
+            .expect("a knock", |event| match event {
+                ChatEvent::Knock { from, .. } => Some(from),
+                _ => None,
+            })
+            .await?;
+        alice.admit(knocked).await?;
+        alice.send("before the restart").await?;
+        bob.expect_chat("before the restart").await?;
+
+        // Alice comes back through Bob with the key and members she saved,
+        // not a new key Bob was never given.
+        let Peer { session, _dir, .. } = alice;
+        session.shutdown().await?;
+        let ticket: Ticket = bob.session.ticket.to_string().parse()?;
+        let alice = Peer::start_in(_dir, "alice", Entry::Join(ticket), |_| {}, HistoryBackend::Memory).await?;
+        bob.expect_members(&[alice.id()]).await?;
+        alice.send("after the restart").await?;
+        bob.expect_chat("after the restart").await?;
+
+        // She still knows Bob is in, so a rekey reaches him.
+        alice.rekey().await?;
+        bob.expect("the new key", |event| match event {
+            ChatEvent::System(text) if text.starts_with("The room's creator rotated the key") => Some(()),
+            _ => None,
+        })
+        .await?;
+        alice.send("after the rekey").await?;
+        bob.expect_chat("after the rekey").await?;
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn crowded_rooms_skip_status_repeats() -> Result<()> {
+        // Two members make the room crowded for Alice; 0 turns that off.
+        for (crowded_members, repeated) in [(2, false), (0, true)] {
+            let open = Entry::Open { private: false, title: None };
+            let alice = Peer::start("alice", open, |config| config.load.crowded_members = crowded_members).await?;
+            let mut bob = Peer::join("bob", &alice.session.ticket).await?;
+            bob.expect_members(&[alice.id()]).await?;
+            alice.session.commands.send(ChatCommand::Presence(Presence::Away)).await?;
+            let alice_id = alice.id();
+            let away = move |event| match event {
+                ChatEvent::Presence { from, presence: Presence::Away } if from == alice_id => Some(()),
+                _ => None,
+            };
+            bob.expect("Alice's status", away).await?;
+
+            // Carol only learns it if Alice repeats it to newcomers.
+            let mut carol = Peer::join("carol", &alice.session.ticket).await?;
+            carol.expect_members(&[alice.id()]).await?;
+            let seen = tokio::time::timeout(Duration::from_secs(3), carol.expect("Alice's status", away)).await;
+            assert_eq!(seen.is_ok(), repeated, "crowded_members = {}", crowded_members);
+            carol.shutdown().await?;
+            bob.shutdown().await?;
+            alice.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_readers_acknowledge() -> Result<()> {
+        let mut alice = Peer::open("alice", true).await?;
+        let carol = Peer::join("carol", &alice.session.ticket).await?;
+        let knocked = alice
+            .expect("a knock", |event| match event {
+                ChatEvent::Knock { from, .. } => Some(from),
+                _ => None,
+            })
+            .await?;
+
+        // Carol gets the message but has no key for it yet: no ack.
+        let id = alice.send("anyone there?").await?;
+        let delivered = move |event| matches!(event, ChatEvent::Delivered(acked) if acked == id).then_some(());
+        let early = tokio::time::timeout(Duration::from_secs(3), alice.expect("an ack", delivered)).await;
+        assert!(early.is_err(), "acknowledged before it could be read");
+
+        // Once let in she reads what she held back, and says so.
+        alice.admit(knocked).await?;
+        alice.expect("an ack", delivered).await?;
+        carol.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn directory_topic_lists_rooms() -> Result<()> {
+        let open = Entry::Open { private: false, title: None };
+        let alice = Peer::start("alice", open, |config| config.directory.gossip = true).await?;
+        alice.session.publish_listing("meetup".into(), "Monthly meetup".into(), 1)?;
+
+        // Bob enters the topic through Alice, who answers his search.
+        let peers = vec![alice.id().to_string()];
+        let join = Entry::Join(alice.session.ticket.to_string().parse()?);
+        let mut bob = Peer::start("bob", join, |config| {
+            config.directory.gossip = true;
+            config.directory.peers = peers;
+        })
+        .await?;
+        bob.session.search_directory("meet".into())?;
+        let listings = bob
+            .expect("the search results", |event| match event {
+                ChatEvent::Directory(listings) => Some(listings),
+                _ => None,
+            })
+            .await?;
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].ticket()?.topic, alice.session.ticket.topic);
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn invites_expire_and_run_out() -> Result<()> {
+        let mut alice = Peer::open("alice", true).await?;
+        let once = alice.session.invite(None, true)?;
+        let expiring = alice.session.invite(Some(5), false)?;
+        let told = |expected: String| {
+            move |event| match event {
+                ChatEvent::System(text) if text == expected => Some(()),
+                _ => None,
+            }
+        };
+        let bob = Peer::join("bob", &once).await?;
+        alice.expect("bob let in", told("Let bob in with their invite.".to_string())).await?;
+        let carol = Peer::join("carol", &once).await?;
+        let turned_away = "Turned carol away: their single-use ticket was already used.".to_string();
+        alice.expect("carol turned away", told(turned_away)).await?;
+        let dave = Peer::join("dave", &expiring).await?;
+        alice.expect("dave let in", told("Let dave in with their invite.".to_string())).await?;
+
+        // Past its time the ticket is refused on joining, and the creator
+        // turns away a knock with it from a client that does not check.
+        let expires = expiring.expires.context("the invite does not expire")?;
+        tokio::time::sleep(Duration::from_secs(expires.saturating_sub(crate::invites::now()) + 1)).await;
+        assert!(Peer::join("erin", &expiring).await.is_err());
+        let mut unchecked: Ticket = expiring.to_string().parse()?;
+        unchecked.expires = None;
+        let erin = Peer::join("erin", &unchecked).await?;
+        alice.expect("erin turned away", told("Turned erin away: their ticket has expired.".to_string())).await?;
+        for peer in [bob, carol, dave, erin] {
+            peer.shutdown().await?;
+        }
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn revoked_invite_turns_knocks_away() -> Result<()> {
+        let mut alice = Peer::open("alice", true).await?;
+        let invite = alice.session.invite(None, false)?;
+        let mut bob = Peer::join("bob", &invite).await?;
+        alice
+            .expect("bob let in", |event| match event {
+                ChatEvent::System(text) if text == "Let bob in with their invite." => Some(()),
+                _ => None,
+            })
+            .await?;
+        alice.send("invited").await?;
+        bob.expect_chat("invited").await?;
+
+        // Bob got in with the revoked invite, so the rotation leaves him out,
+        // and Carol cannot use it any more.
+        assert_eq!(alice.session.revoke(&invite).await?, 1);
+        assert!(alice.session.revoke(&invite).await.is_err());
+        bob.expect("the revocation", |event| match event {
+            ChatEvent::System(text) if text.starts_with("The room's creator revoked an invite") => Some(()),
+            _ => None,
+        })
+        .await?;
+        alice
+            .expect("the new key", |event| match event {
+                ChatEvent::System(text) if text.starts_with("Rotated the room key") => Some(()),
+                _ => None,
+            })
+            .await?;
+        let carol = Peer::join("carol", &invite).await?;
+        alice
+            .expect("carol turned away", |event| match event {
+                ChatEvent::System(text) if text == "Turned carol away: their ticket was revoked." => Some(()),
+                _ => None,
+            })
+            .await?;
+        carol.shutdown().await?;
+        alice.send("after the revocation").await?;
+        assert!(bob.never_sees("after the revocation", Duration::from_secs(3)).await);
+        bob.shutdown().await?;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn daemon_hosts_bots_under_their_own_keys() -> Result<()> {
+        let mut alice = Peer::open("alice", false).await?;
+        let alice_id = alice.id();
+        let dir = tempfile::tempdir()?;
+        for (name, reply) in [("feed", "fresh news"), ("reminder", "standup at 10")] {
+            let mut config = Config::default();
+            config.bot.replies.push(BotReply { pattern: "!ping".to_string(), reply: reply.to_string() });
+            config.save(&Profile::at(dir.path().join(name))?.config_path())?;
+        }
+        let ticket = alice.session.ticket.to_string();
+        let hosted = |profile: &str| HostedBot { profile: profile.to_string(), rooms: vec![ticket.clone()], per_minute: 1 };
+        let open = |name: &str| Profile::at(dir.path().join(name));
+        let client = || ChatClientBuilder::new().bind("127.0.0.1:0".parse().expect("a socket address")).no_relay();
+        let daemon = Profile::at(dir.path().join("daemon"))?;
+
+        // No bot may speak as the daemon or as another bot.
+        assert!(Hosted::start(&[hosted("daemon")], &daemon, open, client).await.is_err());
+        assert!(Hosted::start(&[hosted("feed"), hosted("feed")], &daemon, open, client).await.is_err());
+
+        let bots = Hosted::start(&[hosted("feed"), hosted("reminder")], &daemon, open, client).await?;
+        let feed = open("feed")?.secret_key()?.public();
+        let reminder = open("reminder")?.secret_key()?.public();
+        alice.expect_members(&[feed, reminder]).await?;
+        alice.send("!ping").await?;
+        let mut replies = HashMap::new();
+        while replies.len() < 2 {
+            let chat = alice
+                .expect("the bots' replies", |event| match event {
+                    ChatEvent::Chat(chat) if chat.from != alice_id => Some(chat),
+                    _ => None,
+                })
+                .await?;
+            assert_eq!(chat.trust, Trust::Verified);
+            replies.insert(chat.content, chat.from);
+        }
+        assert_eq!(replies["fresh news"], feed);
+        assert_eq!(replies["standup at 10"], reminder);
+
+        // One message a minute each, and both have had theirs.
+        alice.send("!ping").await?;
+        assert!(alice.never_sees("fresh news", Duration::from_secs(3)).await);
+        bots.shutdown().await;
+        alice.shutdown().await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn irc_bridge_relays_both_ways() -> Result<()> {
+        // A scripted IRC server: welcome the bridge, say something once it
+        // has joined, and hand back what it relays from the room.
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            while let Some(line) = lines.next_line().await? {
+                if line.starts_with("USER ") {
+                    write.write_all(b":irc.test 001 p2p-bridge :Welcome\r\n:irc.test 376 p2p-bridge :End of MOTD\r\n").await?;
+                } else if line.starts_with("JOIN #p2p") {
+                    write.write_all(b":carol!carol@irc.test PRIVMSG #p2p :hello from irc\r\n").await?;
+                } else if line.starts_with("PRIVMSG #p2p :") && line.ends_with("hi irc") {
+                    return Ok(line);
+                }
+            }
+            bail!("the bridge hung up")
+        });
+
+        let mut alice = Peer::open("alice", false).await?;
+        let mut bridge = Peer::join("bridge", &alice.session.ticket).await?;
+        alice.expect_members(&[bridge.id()]).await?;
+        let options = IrcOptions {
+            server: "127.0.0.1".to_string(),
+            port,
+            tls: false,
+            channel: "#p2p".to_string(),
+            nick: "p2p-bridge".to_string(),
+        };
+        let bridging = tokio::spawn(async move {
+            let result = run_irc(&mut bridge.session, &options).await;
+            bridge.shutdown().await?;
+            result
+        });
+        let chat = alice.expect_chat("<carol> hello from irc").await?;
+        assert_eq!(chat.meta, Some(serde_json::json!({ "irc": { "nick": "carol" } })));
+        alice.send("hi irc").await?;
+        let relayed = tokio::time::timeout(EVENT_TIMEOUT, server).await???;
+        assert_eq!(relayed, "PRIVMSG #p2p :<alice> hi irc");
+        // With the server gone, the bridge stops by itself.
+        tokio::time::timeout(EVENT_TIMEOUT, bridging).await???;
+        alice.shutdown().await
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn daemon_answers_control_commands() -> Result<()> {
+        let mut alice = Peer::open("alice", false).await?;
+        let mut daemon = Peer::join("daemon", &alice.session.ticket).await?;
+        alice.expect_members(&[daemon.id()]).await?;
+        let topic = daemon.session.ticket.topic.to_string();
+        let path = daemon.profile.socket_path();
+        let running = tokio::spawn({
+            let path = path.clone();
+            async move { crate::daemon::run(&mut daemon.session, &path).await }
+        });
+        let socket = async {
+            while !path.exists() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        tokio::time::timeout(EVENT_TIMEOUT, socket).await?;
+
+        let send = Request::Send { text: "from the command line".to_string(), meta: None };
+        assert!(matches!(crate::daemon::request(&path, &send).await?, Event::Sent { .. }));
+        alice.expect_chat("from the command line").await?;
+        let Event::Rooms { rooms } = crate::daemon::request(&path, &Request::Rooms).await? else {
+            bail!("no room list");
+        };
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].topic, topic);
+
+        // A subscribed client hears the room; one-shot ones only replies.
+        let mut tail = BufReader::new(UnixStream::connect(&path).await?);
+        tail.get_mut().write_all(b"{\"type\":\"subscribe\"}\n").await?;
+        alice.send("to whoever is tailing").await?;
+        let heard = async {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if tail.read_line(&mut line).await? == 0 {
+                    bail!("the daemon hung up");
+                }
+                if let Ok(Event::Chat { content, .. }) = serde_json::from_str(&line)
+                    && content == "to whoever is tailing"
+                {
+                    return Ok(());
+                }
+            }
+        };
+        tokio::time::timeout(EVENT_TIMEOUT, heard).await??;
+        assert!(crate::daemon::run(&mut alice.session, &path).await.is_err(), "a second daemon took the socket");
+        running.abort();
+        alice.shutdown().await
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::app::{ChatEvent, ChatMessage};
+
+// ── Transcripts ───────────────────────────────────────────────────────────────
+
+/// Output format, chosen from the file extension: `.json` and `.jsonl` give
+/// JSON, anything else plain text.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") | Some("jsonl") => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// One chat message as written to a JSON transcript.
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    id: u64,
+    sender: &'a str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<&'a serde_json::Value>,
+}
+
+impl<'a> From<&'a ChatMessage> for Record<'a> {
+    fn from(chat: &'a ChatMessage) -> Self {
+        Self {
+            timestamp: chat.at.to_rfc3339(),
+            id: chat.id,
+            sender: &chat.sender,
+            content: &chat.content,
+            meta: chat.meta.as_ref(),
+        }
+    }
+}
+
+/// A whole exported history, as written to a JSON export.
+#[derive(Serialize)]
+struct Export<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<&'a str>,
+    messages: Vec<Record<'a>>,
+}
+
+/// `[2024-05-01 18:30:12] alice: hello`
+fn text_line(chat: &ChatMessage) -> String {
+    format!(
+        "[{}] {}: {}",
+        chat.at.format("%Y-%m-%d %H:%M:%S"),
+        chat.sender,
+        chat.content
+    )
+}
+
+/*
+Function:   -export
+Purpose:    -Write the decrypted chat history currently held in memory to a file.
+
+Parameters:
+            - &Path path:  Destination; its extension selects the format.
+            - Option<&str> subject:  The room topic, if one is set.
+            - messages:  The history to write, typically &App::messages.
+
+Details:
+            - Only chat messages are written; system notices and ephemeral
+              messages are skipped.
+            - JSON output is an object with the topic ("subject", left out
+              when there is none) and an array of records ("messages"); plain
+              text starts with a "Topic:" line, then one line per message.
+            - Overwrites an existing file. Returns the number of messages written.
+*/
+pub fn export<'a>(
+    path: &Path,
+    subject: Option<&str>,
+    messages: impl IntoIterator<Item = &'a ChatEvent>,
+) -> Result<usize> {
+    let chats: Vec<&ChatMessage> = messages
+        .into_iter()
+        .filter_map(|m| match m {
+            ChatEvent::Chat(chat) if chat.expires.is_none() => Some(chat),
+            _ => None,
+        })
+        .collect();
+
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    match Format::from_path(path) {
+        Format::Json => {
+            let records: Vec<Record> = chats.iter().map(|c| Record::from(*c)).collect();
+            let export = Export { subject, messages: records };
+            serde_json::to_writer_pretty(&mut out, &export)?;
+            writeln!(out)?;
+        }
+        Format::Text => {
+            if let Some(subject) = subject {
+                writeln!(out, "Topic: {}\n", subject)?;
+            }
+            for chat in &chats {
+                writeln!(out, "{}", text_line(chat))?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(chats.len())
+}
+
+/*
+Struct:     -TranscriptLog
+Purpose:    -Continuous transcript: every chat message is appended as it is shown.
+
+Fields:
+            - PathBuf path:  File being written, for error messages.
+            - File file:  Opened in append mode, so restarts keep adding to it.
+            - Format format:  JSON Lines (one record per line) or plain text.
+
+Details:
+            - Each message is written and flushed immediately so the log is
+              complete even if the process is killed.
+*/
+pub struct TranscriptLog {
+    path: PathBuf,
+    file: File,
+    format: Format,
+}
+
+impl TranscriptLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            format: Format::from_path(path),
+        })
+    }
+
+    /// Append one message. Errors name the transcript file.
+    pub fn append(&mut self, chat: &ChatMessage) -> Result<()> {
+        let line = match self.format {
+            Format::Json => serde_json::to_string(&Record::from(chat))?,
+            Format::Text => text_line(chat),
+        };
+        writeln!(self.file, "{}", line)
+            .with_context(|| format!("writing {}", self.path.display()))
+    }
+}
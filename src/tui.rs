@@ -1,54 +1,1378 @@
-use std::io;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
-    execute,
+    event::{
+        self, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, EnableMouseCapture, Event as CEvent, KeyCode, MouseButton, MouseEventKind,
+    },
+    cursor, execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use data_encoding::BASE64;
+use iroh::EndpointId;
+use tokio::sync::mpsc;
+use tracing::warn;
+use unicode_width::UnicodeWidthStr;
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Position, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    widgets::{Block, Borders, Clear, Padding, Paragraph},
     Terminal,
 };
-use tokio::sync::mpsc;
 
-use crate::app::{App, ChatMessage, Mode, UiMessage};
+use crate::app::{App, ChatEvent, ChatMessage, Delivery, Mode, Trust, MAX_COUNT};
+use crate::bidi;
+use crate::bus::ChatCommand;
+use crate::commands::{self, NotifyRule, SlashCommand};
+use crate::config::DisplayConfig;
+use crate::directory::REPUBLISH_INTERVAL;
+use crate::drafts::Drafts;
+use crate::i18n::t;
+use crate::netstats::{human_bytes, PeerPath};
+use crate::keymap::{Action, Keymap, Lookup};
+use crate::notify::{self, Rules};
+use crate::profile::Profile;
+use crate::protocol::{ChatPayload, Component, Interaction, ModAction, Neighbour, Presence, Role, Ticket, MAX_TEXT};
+use crate::qr;
+use crate::session::{Entry, NetOptions, Session};
+use crate::transcript::{self, TranscriptLog};
+use crate::wipe;
+use crate::wrap::wrap_line;
+
+// ── Tabs ──────────────────────────────────────────────────────────────────────
+
+/// One top-level tab: a session (profile + room) and its UI state.
+struct Tab {
+    session: Session,
+    app: App,
+    /// Where the input is saved as it changes, and what was saved last.
+    drafts: Option<Drafts>,
+    draft: String,
+}
+
+impl Tab {
+    fn new(session: Session) -> Self {
+        let mut app = App::new(session.my_name.clone());
+        app.messages.set_capacity(session.config.display.history);
+        app.notify = Rules::new(session.config.notify_on_mention, session.config.notify.clone());
+        app.creator = session.moderator;
+        match Keymap::from_config(&session.config.keys) {
+            Ok(keys) => app.keys = keys,
+            Err(e) => app.add_message(ChatEvent::System(t!("Ignoring [keys] in the config: {}", e))),
+        }
+        let drafts = Profile::open(&session.profile).ok().map(|profile| Drafts::new(&profile));
+        let draft = drafts
+            .as_ref()
+            .map(|drafts| drafts.load(&session.ticket.topic))
+            .unwrap_or_default();
+        app.input = draft.clone();
+        Self { session, app, drafts, draft }
+    }
+
+    /// Save the input as this room's draft if it changed since last time.
+    fn save_draft(&mut self) {
+        if self.app.input == self.draft {
+            return;
+        }
+        self.draft = self.app.input.clone();
+        if let Some(drafts) = &self.drafts
+            && let Err(e) = drafts.save(&self.session.ticket.topic, &self.draft)
+        {
+            warn!(error = %e, "could not save the draft");
+        }
+    }
+}
+
+/// Tab changes requested while handling input. Applied once the active tab is
+/// no longer borrowed, since they add to or remove from the tab list.
+pub enum TabAction {
+    Next,
+    Prev,
+    Open(String),
+    Join(String, Ticket),
+    Close,
+    /// Quit, then wipe the profiles of every tab.
+    Wipe(wipe::Request),
+}
+
+/*
+Function:   -start_tab
+Purpose:    -Start a new session for a profile, for display in a new tab.
+
+Parameters:
+            - &str profile:  Name of an existing profile.
+            - Entry entry:  Room to join, or a new room to open.
+            - NetOptions net:  Relays and sockets, usually the current tab's.
+
+Details:
+            - Opening a room posts the shareable ticket into the new tab,
+              since stdout is not visible while the TUI is running.
+*/
+async fn start_tab(profile: &str, entry: Entry, net: NetOptions) -> anyhow::Result<Tab> {
+    let profile = Profile::open(profile)?;
+    let opening = matches!(entry, Entry::Open { .. });
+    let mut tab = Tab::new(Session::start(&profile, None, entry, net).await?);
+    if opening {
+        tab.app.add_message(ChatEvent::System(t!(
+            "Share this ticket with others to join: {}",
+            tab.session.ticket
+        )));
+    }
+    Ok(tab)
+}
+
+// ── Message rendering ─────────────────────────────────────────────────────────
+
+/// What a click on one row of the messages pane lands on.
+enum RowTarget {
+    /// System lines, separators, empty space.
+    Nothing,
+    /// The text line of a chat message.
+    Message(u64),
+    /// A row of component controls: the message, its author (who receives
+    /// the interaction), and each control's column range within the pane.
+    Controls {
+        message: u64,
+        author: EndpointId,
+        hits: Vec<(u16, u16, Interaction)>,
+    },
+}
+
+/*
+Function:   -controls
+Purpose:    -Flatten a message's components into activatable controls.
+
+Parameters:
+            - &App app:  UI state; supplies our earlier picks in selects.
+            - &ChatMessage chat:  The message carrying the components.
+
+Details:
+            - Buttons become one control; selects become one control per option,
+              marked (•) when it is our current pick.
+            - Returns (label, interaction) pairs in display order, which is also
+              the order the 1–9 keys address them in.
+*/
+fn controls(app: &App, chat: &ChatMessage) -> Vec<(String, Interaction)> {
+    let mut out = Vec::new();
+    for component in &chat.components {
+        let interaction = |value: Option<String>| Interaction {
+            message_id: chat.id,
+            component_id: component.id().to_string(),
+            value,
+        };
+        match component {
+            Component::Button { label, .. } => {
+                out.push((format!("[ {} ]", label), interaction(None)));
+            }
+            Component::Select { id, options } => {
+                let chosen = app.choices.get(&(chat.id, id.clone()));
+                for option in options {
+                    let mark = if chosen == Some(&option.value) { "(•)" } else { "( )" };
+                    out.push((
+                        format!("{} {}", mark, option.label),
+                        interaction(Some(option.value.clone())),
+                    ));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Lines Ctrl+U and Ctrl+D scroll: half the messages pane.
+fn half_page(messages_area: Rect) -> usize {
+    (messages_area.height as usize / 2).max(1)
+}
+
+/// Messages from one sender this close together are grouped under one name.
+const GROUP_WINDOW: TimeDelta = TimeDelta::minutes(5);
+
+/// The row above the first message of each day: "Today", "Yesterday" or the
+/// date.
+fn date_separator(date: NaiveDate) -> Line<'static> {
+    let today = Local::now().date_naive();
+    let label = if date == today {
+        t!("Today").to_string()
+    } else if today.pred_opt() == Some(date) {
+        t!("Yesterday").to_string()
+    } else {
+        date.format(t!("%A %-d %B %Y")).to_string()
+    };
+    Line::from(Span::styled(
+        format!("──────── {} ────────", label),
+        Style::default().fg(Color::DarkGray),
+    ))
+}
+
+/// Width of a poll option's bar when it has every vote.
+const POLL_BAR: usize = 20;
+
+/// Sender name colours when the config has no palette of its own: the
+/// terminal's named colours, so they follow its colour scheme, minus the
+/// greys and whites used for text.
+const NICK_COLORS: [Color; 10] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Red,
+    Color::LightCyan,
+    Color::LightGreen,
+    Color::LightBlue,
+    Color::LightRed,
+];
+
+/// A sender's name colour: the same for a peer on every screen and every
+/// run, since it comes from their endpoint ID.
+fn nick_color(id: EndpointId, display: &DisplayConfig) -> Color {
+    let palette: Vec<Color> = display
+        .nick_colors
+        .iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+    let palette = if palette.is_empty() { &NICK_COLORS[..] } else { &palette[..] };
+    let bytes = id.as_bytes();
+    let hash = u64::from_le_bytes(bytes[..8].try_into().expect("endpoint IDs are 32 bytes"));
+    palette[(hash % palette.len() as u64) as usize]
+}
+
+/*
+Function:   -chat_lines
+Purpose:    -Render one chat message as wrapped lines.
+
+Parameters:
+            - &App app:  UI state (nickname for mentions, selection, picks).
+            - &ChatMessage chat:  The message to render.
+            - usize width:  Width of the messages pane, for wrapping.
+            - bool grouped:  Follows a message from the same sender within
+              GROUP_WINDOW, so the name is left blank.
+
+Details:
+            - Sender, optional ⚙ marker for annotated messages, then the text,
+              word-wrapped to the pane width. A grouped message keeps its
+              trust badge and markers, with blanks where the name was.
+            - Right-to-left messages are right-aligned and, unless disabled in
+              the config, reordered for display after wrapping.
+            - A poll shows its question as the text and one line per option
+              below it: number, label, a bar and the vote count, with ✓ on
+              our pick.
+            - If the message has components, one more indented line shows them,
+              numbered for keyboard activation. It is not wrapped, so control
+              columns stay valid for mouse hit-testing.
+            - Returns the lines and a RowTarget per line.
+*/
+fn chat_lines(
+    app: &App,
+    chat: &ChatMessage,
+    width: usize,
+    grouped: bool,
+    display: &DisplayConfig,
+) -> (Vec<Line<'static>>, Vec<RowTarget>) {
+    // Mentions of our nickname stand out from regular chat.
+    let content_style = if chat.trust == Trust::Failed {
+        Style::default().fg(Color::DarkGray)
+    } else if chat.highlight || app.mentions_me(&chat.content) {
+        Style::default()
+            .fg(Color::LightMagenta)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let badge = match chat.trust {
+        Trust::Verified => Span::styled("🔒 ", Style::default().fg(Color::Green)),
+        Trust::SharedKey => Span::styled("🔓 ", Style::default().fg(Color::Yellow)),
+        Trust::Failed => Span::styled("⚠ ", Style::default().fg(Color::Red)),
+        Trust::Unencrypted => Span::styled("🔓 ", Style::default().fg(Color::Red)),
+    };
+    let name = if grouped {
+        Span::raw(" ".repeat(chat.sender.width()))
+    } else {
+        Span::styled(
+            chat.sender.clone(),
+            Style::default()
+                .fg(nick_color(chat.from, display))
+                .add_modifier(Modifier::BOLD),
+        )
+    };
+    let mut spans = vec![badge, name];
+    if chat.trust == Trust::Unencrypted && !grouped {
+        spans.push(Span::styled(t!(" [unencrypted]"), Style::default().fg(Color::Red)));
+    }
+    // Admins are marked next to their name.
+    if app.role(chat.from) == Role::Admin && !grouped {
+        spans.push(Span::styled(" ★", Style::default().fg(Color::Yellow)));
+    }
+    // Annotated (bot) messages get a small marker; the metadata itself is for
+    // machines, not people.
+    if chat.meta.is_some() && display.detailed() {
+        spans.push(Span::styled(" ⚙", Style::default().fg(Color::DarkGray)));
+    }
+    // Ephemeral messages show how long they have left.
+    if let Some(expires) = chat.expires {
+        let left = (expires - Local::now()).num_seconds().max(0);
+        let left = match left {
+            0..60 => format!("{}s", left),
+            60..3600 => format!("{}m", left / 60),
+            3600..86400 => format!("{}h", left / 3600),
+            _ => format!("{}d", left / 86400),
+        };
+        spans.push(Span::styled(format!(" ⏳{}", left), Style::default().fg(Color::DarkGray)));
+    }
+    if chat.failed {
+        spans.push(Span::styled(t!(" ✗ not sent"), Style::default().fg(Color::Red)));
+    } else if let Some(delivery) = chat.delivery {
+        let (mark, color) = match delivery {
+            Delivery::Sending => (" …", Color::DarkGray),
+            Delivery::Queued => (t!(" … pending"), Color::DarkGray),
+            Delivery::Broadcast => (" ✓", Color::DarkGray),
+            Delivery::Delivered => (" ✓✓", Color::Green),
+        };
+        spans.push(Span::styled(mark, Style::default().fg(color)));
+    }
+    spans.push(Span::raw(if grouped { "  " } else { ": " }));
+    if let Some(voice) = &chat.voice {
+        spans.push(Span::styled(
+            format!("🎤 {} ", voice.duration()),
+            Style::default().fg(Color::Black).bg(Color::LightBlue),
+        ));
+        spans.push(Span::styled(t!(" p to play "), Style::default().fg(Color::DarkGray)));
+    }
+    // A poll shows its question here and its options below, in place of
+    // the fallback text.
+    let text = match &chat.poll {
+        Some(poll) => format!("📊 {}", poll.question),
+        None => chat.content.clone(),
+    };
+    let rtl = bidi::is_rtl(&text);
+    spans.push(Span::styled(text, content_style));
+
+    let mut lines = wrap_line(&Line::from(spans), width);
+    if display.reorder_rtl {
+        lines = lines.iter().map(|line| visual_line(line, rtl)).collect();
+    }
+    // Right-to-left messages hug the right edge, as they would in a
+    // graphical client.
+    if rtl {
+        for line in &mut lines {
+            line.alignment = Some(Alignment::Right);
+        }
+    }
+    let mut rows: Vec<RowTarget> = lines.iter().map(|_| RowTarget::Message(chat.id)).collect();
+
+    if let Some(poll) = &chat.poll {
+        let counts = app.tally(chat.id, poll.options.len());
+        let total: usize = counts.iter().sum();
+        let mine = app.my_votes.get(&chat.id).map(|choice| *choice as usize);
+        for (n, (option, count)) in poll.options.iter().zip(counts).enumerate() {
+            let bar = (count * POLL_BAR).checked_div(total).unwrap_or(0);
+            let mark = if mine == Some(n) { "✓" } else { " " };
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {} ", n + 1), Style::default().fg(Color::DarkGray)),
+                Span::styled(format!("{} {} ", mark, option), Style::default().fg(Color::LightBlue)),
+                Span::styled("█".repeat(bar), Style::default().fg(Color::LightBlue)),
+                Span::styled(format!(" {}", count), Style::default().fg(Color::Gray)),
+            ]));
+            rows.push(RowTarget::Message(chat.id));
+        }
+    }
+
+    let controls = controls(app, chat);
+    if !controls.is_empty() {
+        let mut spans = vec![Span::raw("  ")];
+        let mut hits = Vec::new();
+        let mut x: u16 = 2;
+        for (n, (label, interaction)) in controls.into_iter().enumerate() {
+            let number = Span::styled(
+                format!("{} ", n + 1),
+                Style::default().fg(Color::DarkGray),
+            );
+            let label = Span::styled(label, Style::default().fg(Color::LightBlue));
+            let end = x + (number.width() + label.width()) as u16;
+            hits.push((x, end, interaction));
+            spans.extend([number, label, Span::raw("  ")]);
+            x = end + 2;
+        }
+        lines.push(Line::from(spans));
+        rows.push(RowTarget::Controls {
+            message: chat.id,
+            author: chat.from,
+            hits,
+        });
+    }
+
+    if app.selected == Some(chat.id) {
+        for line in &mut lines {
+            line.style = Style::default().bg(Color::DarkGray);
+        }
+    }
+    (lines, rows)
+}
+
+/// The scroll offset closest to `offset` that shows every row of message
+/// `id`, or as much of it as fits from its top.
+fn offset_showing(rows: &[RowTarget], id: u64, height: usize, offset: usize) -> usize {
+    let of_message = |row: &RowTarget| {
+        matches!(row, RowTarget::Message(m) | RowTarget::Controls { message: m, .. } if *m == id)
+    };
+    let (Some(first), Some(last)) = (rows.iter().position(of_message), rows.iter().rposition(of_message)) else {
+        return offset;
+    };
+    let top = rows.len().saturating_sub(height + offset);
+    if first < top {
+        rows.len().saturating_sub(height + first)
+    } else if last >= top + height {
+        rows.len() - last - 1
+    } else {
+        offset
+    }
+}
+
+/// A wrapped line in display order: each span reordered on its own (see
+/// bidi::visual), keeping its style.
+fn visual_line(line: &Line, rtl: bool) -> Line<'static> {
+    let spans: Vec<Span<'static>> = line
+        .spans
+        .iter()
+        .map(|span| Span::styled(bidi::visual(&span.content, rtl), span.style))
+        .collect();
+    Line::from(spans)
+}
+
+/*
+Function:   -screen_layout
+Purpose:    -Split the screen into header, pinned strip, messages, input and
+             key help panes.
+
+Parameters:
+            - Rect area:  The whole terminal.
+            - &DisplayConfig display:  The profile's display scale.
+            - u16 pins:  Height of the pinned strip; 0 when nothing is pinned.
+
+Details:
+            - At larger scales the key help pane is dropped (zero height) so
+              the extra spacing goes to messages rather than to reference text.
+*/
+fn screen_layout(area: Rect, display: &DisplayConfig, pins: u16, input_rows: u16) -> [Rect; 5] {
+    let controls = if display.detailed() { 5 } else { 0 };
+    Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),        // Header / mode indicator
+            Constraint::Length(pins),     // Pinned messages
+            Constraint::Min(0),           // Messages
+            Constraint::Length(input_rows + 2), // Input
+            Constraint::Length(controls), // Controls
+        ])
+        .areas(area)
+}
+
+/// Most lines of a multi-line input shown at once.
+const MAX_INPUT_ROWS: usize = 8;
+
+/// Most pins shown in the strip; the rest are listed by /pins.
+const PIN_STRIP_ROWS: usize = 3;
+
+/// The pinned strip under the header: the latest pins one per row, or a
+/// single summary row when folded. Empty when nothing is pinned.
+fn pin_lines(app: &App, width: usize) -> Vec<Line<'static>> {
+    let style = Style::default().fg(Color::Magenta);
+    if app.pins.is_empty() {
+        return Vec::new();
+    }
+    if app.pins_collapsed {
+        return vec![Line::from(Span::styled(
+            t!("📌 {} pinned  (z to expand)", app.pins.len()),
+            style,
+        ))];
+    }
+    let hidden = app.pins.len().saturating_sub(PIN_STRIP_ROWS);
+    let mut lines: Vec<Line<'static>> = app.pins[hidden..]
+        .iter()
+        .filter_map(|(_, text)| {
+            let line = Line::from(Span::styled(format!("📌 {}", text), style));
+            wrap_line(&line, width).into_iter().next()
+        })
+        .collect();
+    if hidden > 0 {
+        lines.push(Line::from(Span::styled(
+            t!("   +{} more  (/pins)", hidden),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    lines
+}
+
+/// Rows of the /pins panel: every pinned message, oldest pin first.
+pub fn pins_panel_lines(app: &App) -> Vec<Line<'static>> {
+    if app.pins.is_empty() {
+        return vec![Line::from(t!("No pinned messages."))];
+    }
+    app.pins
+        .iter()
+        .enumerate()
+        .map(|(n, (_, text))| Line::from(format!("{}. {}", n + 1, text)))
+        .collect()
+}
+
+/// Rows of the /net panel: a header, then one row per connected peer.
+pub fn net_lines(app: &App, peers: &[PeerPath]) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{:<20} {:<8} {:>8} {:>12} {:>12}",
+            t!("Peer"),
+            t!("Path"),
+            t!("RTT"),
+            t!("Sent"),
+            t!("Received")
+        ),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+    if peers.is_empty() {
+        lines.push(Line::from(t!("No open connections.")));
+    }
+    for peer in peers {
+        let name = app
+            .names
+            .get(&peer.id)
+            .cloned()
+            .unwrap_or_else(|| peer.id.fmt_short().to_string());
+        let (path, color) = match peer.relayed {
+            Some(false) => (t!("direct"), Color::Green),
+            Some(true) => (t!("relay"), Color::Yellow),
+            None => (t!("none"), Color::Red),
+        };
+        let rtt = peer
+            .rtt
+            .map(|rtt| format!("{} ms", rtt.as_millis()))
+            .unwrap_or_else(|| "–".to_string());
+        lines.push(Line::from(vec![
+            Span::raw(format!("{:<20} ", name.chars().take(20).collect::<String>())),
+            Span::styled(format!("{:<8} ", path), Style::default().fg(color)),
+            Span::raw(format!(
+                "{:>8} {:>12} {:>12}",
+                rtt,
+                human_bytes(peer.sent),
+                human_bytes(peer.received)
+            )),
+        ]));
+    }
+    lines
+}
+
+/// How two members are connected, for the /swarm graph.
+fn link_span(relayed: Option<bool>) -> Span<'static> {
+    match relayed {
+        Some(false) => Span::styled("direct ", Style::default().fg(Color::Green)),
+        Some(true) => Span::styled("relay  ", Style::default().fg(Color::Yellow)),
+        None => Span::styled("?      ", Style::default().fg(Color::DarkGray)),
+    }
+}
+
+/*
+Function:   -swarm_lines
+Purpose:    -Draw the gossip overlay around us as a tree for the /swarm panel.
+
+Parameters:
+            - &App app:  For members' names.
+            - EndpointId me:  Our endpoint, the root.
+            - &[Neighbour] links:  Our neighbours and how we reach them.
+            - &BTreeMap reports:  Neighbour lists members sent in answer to
+              /swarm.
+
+Details:
+            - Our neighbours branch off the root, each with its own
+              neighbours below it once it has answered.
+            - Reports from members who are not our neighbours follow, as
+              "member: neighbour, …".
+            - Members we know of who appear nowhere in the graph are listed
+              last: the likely other side of a partition.
+*/
+pub fn swarm_lines(
+    app: &App,
+    me: EndpointId,
+    links: &[Neighbour],
+    reports: &BTreeMap<EndpointId, Vec<Neighbour>>,
+) -> Vec<Line<'static>> {
+    let label = |id: EndpointId| if id == me { t!("you").to_string() } else { peer_label(app, id) };
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut lines = vec![Line::from(Span::styled(
+        t!("You"),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+    if links.is_empty() {
+        lines.push(Line::from(Span::styled(t!("└─ no neighbours"), dim)));
+    }
+    for (n, link) in links.iter().enumerate() {
+        let last = n + 1 == links.len();
+        let (branch, indent) = if last { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+        lines.push(Line::from(vec![
+            Span::raw(branch),
+            link_span(link.relayed),
+            Span::raw(label(link.id)),
+        ]));
+        let Some(theirs) = reports.get(&link.id) else {
+            lines.push(Line::from(Span::styled(format!("{}{}", indent, t!("└─ no answer yet")), dim)));
+            continue;
+        };
+        for (m, far) in theirs.iter().enumerate() {
+            let branch = if m + 1 == theirs.len() { "└─ " } else { "├─ " };
+            lines.push(Line::from(vec![
+                Span::raw(format!("{}{}", indent, branch)),
+                link_span(far.relayed),
+                Span::raw(label(far.id)),
+            ]));
+        }
+    }
+
+    let further: Vec<_> = reports
+        .iter()
+        .filter(|(from, _)| !links.iter().any(|link| link.id == **from))
+        .collect();
+    if !further.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(t!("Further away"), Style::default().add_modifier(Modifier::BOLD))));
+        for (from, theirs) in further {
+            let mut spans = vec![Span::raw(format!("{}: ", label(*from)))];
+            for far in theirs {
+                spans.push(link_span(far.relayed));
+                spans.push(Span::raw(format!("{}  ", label(far.id))));
+            }
+            lines.push(Line::from(spans));
+        }
+    }
+
+    let seen = |id: &EndpointId| {
+        links.iter().any(|link| link.id == *id)
+            || reports.contains_key(id)
+            || reports.values().flatten().any(|far| far.id == *id)
+    };
+    let unseen: Vec<String> = app
+        .names
+        .keys()
+        .filter(|id| **id != me && !seen(id))
+        .map(|id| label(*id))
+        .collect();
+    if !unseen.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            t!("Not seen in the swarm: {}", unseen.join(", ")),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    lines
+}
+
+/// Send a chat message to the room and show it in our own history.
+pub async fn send_chat(app: &mut App, session: &Session, payload: ChatPayload) {
+    if session.room_key.current().is_none() {
+        app.add_message(ChatEvent::System(
+            t!("You can't send messages until the room's creator lets you in.").to_string(),
+        ));
+        return;
+    }
+    if app.role(session.my_id) == Role::ReadOnly {
+        app.add_message(ChatEvent::System(
+            t!("You are read-only in this room; nobody would see your message.").to_string(),
+        ));
+        return;
+    }
+    if too_long(app, &payload.text) {
+        return;
+    }
+    let Some(payload) = session.filters.outbound(payload) else {
+        app.add_message(ChatEvent::System(t!("A filter stopped that message.").to_string()));
+        return;
+    };
+    let id: u64 = rand::random();
+
+    // Remember the ID so we can delete it later.
+    app.my_sent_ids.push(id);
+    // Show immediately in our own UI.
+    app.add_message(ChatEvent::Chat(
+        ChatMessage::new(id, session.my_id, t!("You").to_string(), payload.clone())
+            .with_delivery(Delivery::Sending),
+    ));
+
+    let _ = session.commands.send(ChatCommand::Send { payload, id }).await;
+}
+
+/// Whether `text` is over MAX_TEXT characters, saying so if it is.
+fn too_long(app: &mut App, text: &str) -> bool {
+    let length = text.chars().count();
+    if length > MAX_TEXT {
+        app.add_message(ChatEvent::System(t!(
+            "That message is {} characters long; the limit is {}.",
+            length,
+            MAX_TEXT
+        )));
+    }
+    length > MAX_TEXT
+}
+
+/// Sign and broadcast a kick or mute of the member called `name`, if we
+/// are the room's moderator.
+async fn moderate(
+    app: &mut App,
+    session: &Session,
+    name: &str,
+    action: impl FnOnce(EndpointId) -> ModAction,
+) {
+    if session.moderator != Some(session.my_id) {
+        app.add_message(ChatEvent::System(
+            t!("Only the room's creator can kick or mute members.").to_string(),
+        ));
+        return;
+    }
+    match app.member_named(name) {
+        Ok(target) if target == session.my_id => {
+            app.add_message(ChatEvent::System(t!("You cannot moderate yourself.").to_string()));
+        }
+        Ok(target) => {
+            let _ = session.commands.send(ChatCommand::Moderate(action(target))).await;
+        }
+        Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+    }
+}
+
+/// Change the room topic for everyone. Gossip does not echo our own
+/// messages, so it is applied here too.
+async fn set_subject(app: &mut App, session: &Session, subject: String) {
+    if session.room_key.current().is_none() || app.role(session.my_id) == Role::ReadOnly {
+        app.add_message(ChatEvent::System(t!("You can't change the topic in this room.").to_string()));
+        return;
+    }
+    let payload = ChatPayload::subject(subject);
+    let subject = payload.subject.clone().unwrap_or_default();
+    let _ = session.commands.send(ChatCommand::Send { payload, id: rand::random() }).await;
+    app.add_message(ChatEvent::Subject {
+        name: session.my_name.clone(),
+        subject,
+    });
+}
+
+/// Vote in the poll carried by message `poll`. Gossip does not echo our own
+/// messages, so the vote is counted here too.
+async fn vote(app: &mut App, session: &Session, poll: u64, choice: u32, option: &str) {
+    if session.room_key.current().is_none() || app.role(session.my_id) == Role::ReadOnly {
+        app.add_message(ChatEvent::System(t!("You can't vote in this room.").to_string()));
+        return;
+    }
+    let payload = ChatPayload::vote(poll, choice, option);
+    let _ = session.commands.send(ChatCommand::Send { payload, id: rand::random() }).await;
+    app.my_votes.insert(poll, choice);
+    app.add_message(ChatEvent::Vote {
+        poll,
+        from: session.my_id,
+        choice,
+    });
+}
+
+/// A peer as "name (short ID)", or just the short ID if we never learned
+/// their name.
+fn peer_label(app: &App, id: EndpointId) -> String {
+    match app.names.get(&id) {
+        Some(name) => format!("{} ({})", name, id.fmt_short()),
+        None => id.fmt_short().to_string(),
+    }
+}
+
+/// Handle /block and /unblock. `peer` is a member's name, an endpoint ID
+/// or, to unblock, a number from /blocks.
+fn block(app: &mut App, session: &mut Session, peer: &str, blocked: bool) {
+    let id = match peer.parse::<usize>() {
+        Ok(n) if !blocked => session
+            .blocked()
+            .get(n.wrapping_sub(1))
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("No blocked peer number {} – see /blocks", n)),
+        _ => peer.parse::<EndpointId>().or_else(|_| app.member_named(peer)),
+    };
+    let note = match id {
+        Ok(id) if id == session.my_id => t!("You cannot block yourself.").to_string(),
+        Ok(id) => match session.set_blocked(id, blocked) {
+            Ok(()) if blocked => t!("Blocked {}; nothing they send will be shown.", peer_label(app, id)),
+            Ok(()) => t!("Unblocked {}.", peer_label(app, id)),
+            Err(e) => t!("Could not save the block list: {}", e),
+        },
+        Err(e) => e.to_string(),
+    };
+    app.add_message(ChatEvent::System(note));
+}
+
+/// Handle /nick: rename ourselves in this room, and with --room keep the
+/// name (or forget it) for next time.
+async fn nick(app: &mut App, session: &mut Session, name: Option<String>, room: bool) {
+    let note = match session.rename(name, room).await {
+        Ok(()) if room => t!("You are now {} in this room, also next time.", session.my_name),
+        Ok(()) => t!("You are now {} in this room.", session.my_name),
+        Err(e) => t!("Could not save the name: {}", e),
+    };
+    app.my_name = session.my_name.clone();
+    app.add_message(ChatEvent::System(note));
+}
+
+/// Handle /notify: show the notification rules, or change one and save it.
+fn notify_rule(app: &mut App, session: &mut Session, rule: Option<NotifyRule>) {
+    let mut config = app.notify.config.clone();
+    let note = match rule {
+        None => {
+            for line in app.notify.describe(|id| peer_label(app, id)) {
+                app.add_message(ChatEvent::System(line));
+            }
+            return;
+        }
+        Some(NotifyRule::MentionsOnly(on)) => {
+            config.mentions_only = on;
+            if on { t!("Only mentions and keywords will notify.") } else { t!("Messages will notify while you are not looking.") }
+                .to_string()
+        }
+        Some(NotifyRule::Keyword { word, add }) => {
+            config.keywords.retain(|k| !k.eq_ignore_ascii_case(&word));
+            if add {
+                config.keywords.push(word.clone());
+                t!("\"{}\" will notify like a mention.", word)
+            } else {
+                t!("\"{}\" is no longer a keyword.", word)
+            }
+        }
+        Some(NotifyRule::Mute { peer, muted }) => {
+            let id = match peer.parse::<EndpointId>().or_else(|_| app.member_named(&peer)) {
+                Ok(id) => id,
+                Err(e) => {
+                    app.add_message(ChatEvent::System(e.to_string()));
+                    return;
+                }
+            };
+            config.muted.retain(|m| *m != id.to_string());
+            if muted {
+                config.muted.push(id.to_string());
+                t!("{} will not notify.", peer_label(app, id))
+            } else {
+                t!("{} will notify again.", peer_label(app, id))
+            }
+        }
+        Some(NotifyRule::Quiet(hours)) => {
+            let note = match &hours {
+                Some(hours) => t!("No notifications between {}.", hours.replace('-', t!(" and "))),
+                None => t!("Quiet hours are off.").to_string(),
+            };
+            config.quiet_hours = hours;
+            note
+        }
+    };
+    let note = match session.set_notify(config.clone()) {
+        Ok(()) => note,
+        Err(e) => t!("{} (Could not save it: {})", note, e),
+    };
+    app.notify = Rules::new(session.config.notify_on_mention, config);
+    app.add_message(ChatEvent::System(note));
+}
+
+/// Handle /ping: send a ping through the room, and say what the direct
+/// connection to the peer (if any) measures meanwhile. The pong is
+/// reported by the receive loop.
+async fn ping(app: &mut App, session: &Session, peer: &str) {
+    let id = match peer.parse::<EndpointId>().or_else(|_| app.member_named(peer)) {
+        Ok(id) if id == session.my_id => {
+            app.add_message(ChatEvent::System(t!("You cannot ping yourself.").to_string()));
+            return;
+        }
+        Ok(id) => id,
+        Err(e) => {
+            app.add_message(ChatEvent::System(e.to_string()));
+            return;
+        }
+    };
+    let _ = session.commands.send(ChatCommand::Ping(id)).await;
+    let connection = session
+        .peer_paths()
+        .into_iter()
+        .find(|path| path.id == id)
+        .and_then(|path| Some((path.relayed?, path.rtt?)));
+    let note = match connection {
+        Some((relayed, rtt)) => t!(
+            "Pinging {}… Our {} connection to them measures {} ms.",
+            peer_label(app, id),
+            if relayed { t!("relayed") } else { t!("direct") },
+            rtt.as_millis()
+        ),
+        None => t!("Pinging {}…", peer_label(app, id)),
+    };
+    app.add_message(ChatEvent::System(note));
+}
+
+/// Handle /whois. `peer` is a member's name, a full endpoint ID or the
+/// start of one, such as the short form shown in the UI.
+async fn whois(app: &mut App, session: &Session, peer: &str) {
+    let id = peer
+        .parse::<EndpointId>()
+        .or_else(|_| app.member_named(peer))
+        .or_else(|e| session.peer_with_prefix(peer).ok_or(e));
+    match id {
+        Ok(id) => {
+            app.add_message(ChatEvent::System(peer_label(app, id)));
+            for line in session.whois(id).await {
+                app.add_message(ChatEvent::System(format!("  {}", line)));
+            }
+        }
+        Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+    }
+}
+
+/// Send a component interaction to the message's author, remembering our
+/// pick for selects so it renders as checked.
+async fn activate(app: &mut App, session: &Session, author: EndpointId, interaction: Interaction) {
+    if let Some(value) = &interaction.value {
+        app.choices.insert(
+            (interaction.message_id, interaction.component_id.clone()),
+            value.clone(),
+        );
+    }
+    let _ = session.commands.send(ChatCommand::Interact { to: author, interaction }).await;
+}
+
+/*
+Function:   -run_command
+Purpose:    -Carry out a slash command typed in a room.
+
+Parameters:
+            - &mut App app:  The room's state; replies go into its history.
+            - &mut Session session:  The room.
+            - &str input:  The typed line, starting with '/'.
+
+Details:
+            - Shared by the TUI and plain mode. Panels are opened by setting
+              their flag on `app`; tab changes are returned for the TUI to
+              apply once the tab is no longer borrowed.
+*/
+pub async fn run_command(app: &mut App, session: &mut Session, input: &str) -> Option<TabAction> {
+    let mut tab_action = None;
+    match SlashCommand::parse(input) {
+        Ok(SlashCommand::Dnd) => {
+            app.dnd = !app.dnd;
+            app.add_message(ChatEvent::System(
+                if app.dnd { t!("Do not disturb enabled.") } else { t!("Do not disturb disabled.") }
+                    .to_string(),
+            ));
+        }
+        Ok(SlashCommand::TabOpen { profile }) => {
+            tab_action = Some(TabAction::Open(profile));
+        }
+        Ok(SlashCommand::TabJoin { profile, ticket }) => {
+            tab_action = Some(TabAction::Join(profile, ticket));
+        }
+        Ok(SlashCommand::TabClose) => {
+            tab_action = Some(TabAction::Close);
+        }
+        Ok(SlashCommand::Export { path }) => {
+            let note = match transcript::export(&path, app.subject.as_deref(), &app.messages) {
+                Ok(n) => t!(
+                    "Exported {} messages to {}",
+                    n,
+                    path.display()
+                ),
+                Err(e) => t!("Export failed: {}", e),
+            };
+            app.add_message(ChatEvent::System(note));
+        }
+        Ok(SlashCommand::Topic { subject: None }) => {
+            let note = match &app.subject {
+                Some(subject) => t!("Topic: {}", subject),
+                None => t!("No topic set. Set one with /topic <text>.").to_string(),
+            };
+            app.add_message(ChatEvent::System(note));
+        }
+        Ok(SlashCommand::Topic { subject: Some(subject) }) => {
+            set_subject(app, session, subject).await;
+        }
+        Ok(SlashCommand::Ephemeral { ttl, text }) => {
+            send_chat(app, session, ChatPayload::ephemeral(text, ttl)).await;
+        }
+        Ok(SlashCommand::Kick { name }) => {
+            moderate(app, session, &name, |target| ModAction::Kick { target }).await;
+        }
+        Ok(SlashCommand::Mute { name }) => {
+            moderate(app, session, &name, |target| ModAction::Mute { target }).await;
+        }
+        Ok(SlashCommand::Approve { name }) => {
+            if session.moderator != Some(session.my_id) {
+                app.add_message(ChatEvent::System(
+                    t!("Only the room's creator can let people in.").to_string(),
+                ));
+            } else {
+                match app.take_knock(&name) {
+                    Ok((id, name)) => {
+                        let _ = session.commands.send(ChatCommand::Admit(id)).await;
+                        app.add_message(ChatEvent::System(t!("Let {} in.", name)));
+                    }
+                    Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+                }
+            }
+        }
+        Ok(SlashCommand::Deny { name }) => {
+            if session.moderator != Some(session.my_id) {
+                app.add_message(ChatEvent::System(
+                    t!("Only the room's creator can turn people away.").to_string(),
+                ));
+            } else {
+                match app.take_knock(&name) {
+                    // A denied knocker is kicked, so members ignore anything
+                    // else it sends.
+                    Ok((id, _)) => {
+                        let _ = session.commands.send(ChatCommand::Moderate(ModAction::Kick { target: id })).await;
+                    }
+                    Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+                }
+            }
+        }
+        Ok(SlashCommand::Rekey) => {
+            if session.moderator != Some(session.my_id) {
+                app.add_message(ChatEvent::System(
+                    t!("Only the room's creator can rotate its key.").to_string(),
+                ));
+            } else if !session.ticket.private {
+                app.add_message(ChatEvent::System(
+                    t!("Only invite-only rooms have a key to rotate; in open rooms the ticket is the key.")
+                        .to_string(),
+                ));
+            } else {
+                let _ = session.commands.send(ChatCommand::Rekey).await;
+            }
+        }
+        Ok(SlashCommand::SetRole { name, role }) => {
+            if session.moderator != Some(session.my_id) {
+                app.add_message(ChatEvent::System(
+                    t!("Only the room's creator can assign roles.").to_string(),
+                ));
+            } else {
+                match app.member_named(&name) {
+                    Ok(id) if id == session.my_id => app.add_message(ChatEvent::System(
+                        t!("The creator is always an admin.").to_string(),
+                    )),
+                    Ok(id) => {
+                        let membership = app.membership.with_role(id, role);
+                        let _ = session.commands.send(ChatCommand::Membership(membership)).await;
+                    }
+                    Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+                }
+            }
+        }
+        Ok(SlashCommand::Members) => {
+            let mut members: Vec<(String, Role, Presence)> = app
+                .names
+                .iter()
+                .map(|(id, name)| {
+                    let presence = app.presence.get(id).copied().unwrap_or_default();
+                    (name.clone(), app.role(*id), presence)
+                })
+                .collect();
+            members.sort_by(|a, b| a.0.cmp(&b.0));
+            for (name, role, presence) in members {
+                let line = match presence {
+                    Presence::Online => format!("{} – {}", name, role.label()),
+                    _ => format!("{} – {} ({})", name, role.label(), presence.label()),
+                };
+                app.add_message(ChatEvent::System(line));
+            }
+        }
+        Ok(SlashCommand::Block { peer }) => block(app, session, &peer, true),
+        Ok(SlashCommand::Unblock { peer }) => block(app, session, &peer, false),
+        Ok(SlashCommand::Blocks) => {
+            let blocked = session.blocked();
+            if blocked.is_empty() {
+                app.add_message(ChatEvent::System(t!("No one is blocked.").to_string()));
+            }
+            for (n, id) in blocked.into_iter().enumerate() {
+                // Someone blocked is never heard from, so their name, if
+                // any, is the one they had before.
+                let label = match session.known_name(id).filter(|_| !app.names.contains_key(&id)) {
+                    Some(name) => format!("{} ({})", name, id.fmt_short()),
+                    None => peer_label(app, id),
+                };
+                let line = format!("{}. {}  (/unblock {})", n + 1, label, n + 1);
+                app.add_message(ChatEvent::System(line));
+            }
+        }
+        Ok(SlashCommand::Network) => {
+            for line in session.network_status() {
+                app.add_message(ChatEvent::System(line));
+            }
+        }
+        Ok(SlashCommand::Net) => app.net_panel = true,
+        Ok(SlashCommand::Ping { peer }) => ping(app, session, &peer).await,
+        Ok(SlashCommand::Whois { peer }) => whois(app, session, &peer).await,
+        Ok(SlashCommand::Swarm) => {
+            app.swarm_panel = true;
+            let _ = session.commands.try_send(ChatCommand::AskSwarm);
+        }
+        Ok(SlashCommand::Pins) => app.pins_panel = true,
+        Ok(SlashCommand::Ticket { qr: false }) => {
+            app.add_message(ChatEvent::System(t!(
+                "Ticket: {}",
+                session.ticket
+            )));
+        }
+        Ok(SlashCommand::Ticket { qr: true }) => match qr::render(&session.ticket.to_string()) {
+            Ok(rows) => app.add_message(ChatEvent::Qr(rows)),
+            Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+        },
+        Ok(SlashCommand::Invite { ttl, once }) => match session.invite(ttl, once) {
+            Ok(ticket) => app.add_message(ChatEvent::System(t!(
+                "Invite ticket{}{}: {}",
+                ttl.map(|ttl| t!(", valid for {}s", ttl)).unwrap_or_default(),
+                if once { t!(", single use") } else { "" },
+                ticket
+            ))),
+            Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+        },
+        Ok(SlashCommand::Revoke { ticket }) => match session.revoke(&ticket).await {
+            Ok(count) => app.add_message(ChatEvent::System(t!(
+                "Revoked the invite; the key rotation that follows leaves out the {} member(s) who joined with it.",
+                count
+            ))),
+            Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+        },
+        Ok(SlashCommand::Directory { query }) => {
+            match session.search_directory(query) {
+                Ok(()) => app.add_message(ChatEvent::System(t!("Searching the directory…").to_string())),
+                Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+            }
+        }
+        Ok(SlashCommand::DirectoryJoin { index }) => {
+            match app.directory.get(index - 1).map(|listing| listing.ticket()) {
+                Some(Ok(ticket)) => {
+                    tab_action = Some(TabAction::Join(session.profile.clone(), ticket));
+                }
+                Some(Err(e)) => app.add_message(ChatEvent::System(t!("Bad ticket in listing: {}", e))),
+                None => app.add_message(ChatEvent::System(
+                    t!("No such room – run /directory first.").to_string(),
+                )),
+            }
+        }
+        Ok(SlashCommand::Publish { name, description }) => {
+            let members = app.names.len() + 1;
+            match session.publish_listing(name.clone(), description.clone(), members) {
+                Ok(()) => {
+                    app.add_message(ChatEvent::System(t!("Listed as {} in the directory.", name)));
+                    app.published = Some((name, description, Instant::now()));
+                }
+                Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+            }
+        }
+        Ok(SlashCommand::Voice { seconds }) => {
+            session.record_voice(seconds);
+            app.add_message(ChatEvent::System(t!("Recording for {} s…", seconds)));
+        }
+        Ok(SlashCommand::Poll { question, options }) => {
+            send_chat(app, session, ChatPayload::poll(question, options)).await;
+        }
+        Ok(SlashCommand::Nick { name, room }) => nick(app, session, name, room).await,
+        Ok(SlashCommand::Notify { rule }) => notify_rule(app, session, rule),
+        Ok(SlashCommand::Clear) => app.clear(),
+        Ok(SlashCommand::Wipe { confirmed: true, identity }) => {
+            tab_action = Some(TabAction::Wipe(wipe::Request { identity }));
+        }
+        Ok(SlashCommand::Wipe { identity: true, .. }) => app.add_message(ChatEvent::System(
+            t!("This securely deletes the chat history, drafts, remembered rooms and identity key of this profile, then quits. Type /wipe yes identity to go ahead.").to_string(),
+        )),
+        Ok(SlashCommand::Wipe { .. }) => app.add_message(ChatEvent::System(
+            t!("This securely deletes the chat history, drafts and remembered rooms of this profile, then quits. Type /wipe yes to go ahead, or /wipe yes identity to delete the identity key too.").to_string(),
+        )),
+        Ok(SlashCommand::Status { presence }) => {
+            app.auto_away = false;
+            session.set_presence(presence).await;
+            app.add_message(ChatEvent::System(t!("You are now {}.", presence.label())));
+        }
+        Err(e) => app.add_message(ChatEvent::System(e.to_string())),
+    }
+    tab_action
+}
+
+/// Put text on the system clipboard with an OSC 52 escape, which most
+/// terminals honour, over SSH too.
+fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let mut out = io::stdout();
+    write!(out, "\x1b]52;c;{}\x07", BASE64.encode(text.as_bytes()))?;
+    out.flush()
+}
 
 // ── TUI ───────────────────────────────────────────────────────────────────────
 
+/// Puts the terminal back however run_tui ends: by quitting, an error or a
+/// panic.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableFocusChange,
+            DisableBracketedPaste,
+            cursor::Show
+        );
+    }
+}
+
+/*
+Function:   -quit_signals
+Purpose:    -Turn SIGINT, SIGTERM and SIGHUP into a quit request.
+
+Details:
+            - In raw mode a typed Ctrl+C arrives as a key, so these come from
+              outside: kill, or the terminal window closing.
+            - Quitting this way still leaves every room properly.
+*/
+pub fn quit_signals() -> Result<mpsc::Receiver<()>> {
+    let (tx, rx) = mpsc::channel(1);
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = interrupt.recv() => {}
+                _ = terminate.recv() => {}
+                _ = hangup.recv() => {}
+            }
+            let _ = tx.send(()).await;
+        });
+    }
+    #[cfg(not(unix))]
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = tx.send(()).await;
+        }
+    });
+    Ok(rx)
+}
+
+/*
+Function:   -run_tui
+Purpose:    -Run the interactive terminal UI until the user quits.
+
+Parameters:
+            - Vec<Session> sessions:  Sessions to show as tabs; the first is
+              active initially.
+            - Option<TranscriptLog> transcript:  Continuous transcript for the
+              first tab (--log-transcript).
+
+Details:
+            - More tabs can be opened from inside the TUI with /tab.
+            - Returns every session still open so the caller can shut them down,
+              and the wipe asked for with /wipe yes, for the caller to do
+              once they are.
+            - Also returns on a quit signal (quit_signals). The terminal is
+              restored on every way out, errors included.
+*/
 pub async fn run_tui(
-    mut ui_rx: mpsc::Receiver<UiMessage>,
-    input_tx: mpsc::Sender<(String, u64)>,
-    delete_tx: mpsc::Sender<u64>,
-) -> Result<()> {
+    sessions: Vec<Session>,
+    transcript: Option<TranscriptLog>,
+) -> Result<(Vec<Session>, Option<wipe::Request>)> {
+    let mut quit_rx = quit_signals()?;
     enable_raw_mode()?;
+    let guard = TerminalGuard;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableFocusChange, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = App::new();
+    let mut tabs: Vec<Tab> = sessions.into_iter().map(Tab::new).collect();
+    if let Some(first) = tabs.first_mut() {
+        first.app.transcript = transcript;
+    }
+    let mut active = 0;
+    let mut terminal_focused = true;
+    // For auto-away: typing in any tab counts for every room.
+    let mut last_key = Instant::now();
+    // Geometry of the messages pane from the last draw, for mouse hit-testing:
+    // its inner area, the first visible line, and what each line hits.
+    let mut messages_area = Rect::default();
+    let mut view_top = 0;
+    let mut line_targets: Vec<RowTarget> = Vec::new();
+    let mut wipe = None;
 
     loop {
-        while let Ok(msg) = ui_rx.try_recv() {
-            app.add_message(msg);
+        for tab in tabs.iter_mut() {
+            tab.save_draft();
+        }
+        if quit_rx.try_recv().is_ok() {
+            break;
         }
 
+        // Drain every tab, not just the visible one, so background tabs
+        // still collect messages and raise notifications.
+        for (i, tab) in tabs.iter_mut().enumerate() {
+            let app = &mut tab.app;
+            app.focused = terminal_focused && i == active;
+            while let Some(msg) = tab.session.events.try_recv() {
+                if let ChatEvent::Recorded(clip) = msg {
+                    send_chat(app, &tab.session, ChatPayload::voice(clip)).await;
+                    continue;
+                }
+                if let ChatEvent::Chat(chat) = &msg
+                    && app.should_notify(chat)
+                {
+                    notify::notify(chat.sender.clone(), chat.content.clone());
+                }
+                app.add_message(msg);
+            }
+            app.expire_messages();
+            app.mark_read();
+            let away_after = tab.session.config.away_after_mins;
+            let idle = away_after > 0 && last_key.elapsed() >= Duration::from_secs(away_after * 60);
+            if idle && !app.auto_away && tab.session.presence == Presence::Online {
+                app.auto_away = true;
+                tab.session.set_presence(Presence::Away).await;
+            } else if !idle && app.auto_away {
+                app.auto_away = false;
+                tab.session.set_presence(Presence::Online).await;
+            }
+            // Refresh our directory listing before the directory drops it.
+            if let Some((name, description, at)) = &mut app.published
+                && at.elapsed() >= REPUBLISH_INTERVAL
+            {
+                *at = Instant::now();
+                let _ = tab.session.publish_listing(name.clone(), description.clone(), app.names.len() + 1);
+            }
+        }
+
+        let tab_titles: Vec<String> = tabs
+            .iter()
+            .map(|t| match t.app.unread {
+                0 => t.session.profile.clone(),
+                n => format!("{} ({})", t.session.profile, n),
+            })
+            .collect();
+        let Tab { session, app, .. } = &mut tabs[active];
+        let mut tab_action = None;
+
         // ── Draw ─────────────────────────────────────────────────────────────
         terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(3), // Header / mode indicator
-                    Constraint::Min(0),    // Messages
-                    Constraint::Length(3), // Input
-                    Constraint::Length(5), // Controls
-                ])
-                .split(f.area());
+            let display = &session.config.display;
+            let pinned = pin_lines(app, f.area().width as usize);
+            let input_rows = app.input.split('\n').count().clamp(1, MAX_INPUT_ROWS);
+            let chunks = screen_layout(f.area(), display, pinned.len() as u16, input_rows as u16);
+            let pane = || {
+                Block::default()
+                    .borders(Borders::ALL)
+                    .padding(Padding::horizontal(display.gutter()))
+            };
+
+            let key = |action| app.keys.keys(action);
 
             // Header shows current mode prominently.
             let (mode_label, mode_hint) = match app.mode {
@@ -61,7 +1385,7 @@ pub async fn run_tui(
                             .add_modifier(Modifier::BOLD),
                     ),
                     Span::styled(
-                        "  ESC → normal mode",
+                        t!("  {} → normal mode", key(Action::Normal)),
                         Style::default().fg(Color::DarkGray),
                     ),
                 ),
@@ -74,13 +1398,23 @@ pub async fn run_tui(
                             .add_modifier(Modifier::BOLD),
                     ),
                     Span::styled(
-                        "  i → insert  |  Ctrl+D → delete last msg  |  Ctrl+C → quit",
+                        t!(
+                            "  {} → insert  |  {} {} {} {} → select  |  {} → delete  |  {} → copy  |  {} → quit",
+                            key(Action::Insert),
+                            key(Action::SelectNewer),
+                            key(Action::SelectOlder),
+                            key(Action::SelectOldest),
+                            key(Action::SelectNewest),
+                            key(Action::Delete),
+                            key(Action::Copy),
+                            key(Action::Quit)
+                        ),
                         Style::default().fg(Color::DarkGray),
                     ),
                 ),
             };
 
-            let header = Paragraph::new(vec![Line::from(vec![
+            let mut header_spans = vec![
                 Span::styled(
                     "Encrypted Chat  ",
                     Style::default()
@@ -88,49 +1422,211 @@ pub async fn run_tui(
                         .add_modifier(Modifier::BOLD),
                 ),
                 mode_label,
-                mode_hint,
-            ])])
-            .block(Block::default().borders(Borders::ALL));
+            ];
+            if let Some(count) = app.count {
+                header_spans.push(Span::styled(
+                    format!(" {}", count),
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                ));
+            }
+            if display.detailed() {
+                header_spans.push(mode_hint);
+            }
+            header_spans.push(Span::styled(
+                format!("  ⇄ {}", session.relay_status()),
+                Style::default().fg(Color::DarkGray),
+            ));
+            let traffic = session.traffic();
+            header_spans.push(Span::styled(
+                t!(
+                    "  ↓{}/s ↑{}/s {} msg/min",
+                    human_bytes(traffic.received),
+                    human_bytes(traffic.sent),
+                    traffic.messages
+                ),
+                Style::default().fg(Color::DarkGray),
+            ));
+            if app.dnd {
+                header_spans.push(Span::styled(
+                    "  DND",
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                ));
+            }
+            let mut header_block = pane();
+            if let Some(subject) = &app.subject {
+                header_block = header_block.title(
+                    Line::from(Span::styled(format!(" {} ", subject), Style::default().fg(Color::White)))
+                        .right_aligned(),
+                );
+            }
+            if tab_titles.len() > 1 {
+                let titles: Vec<Span> = tab_titles
+                    .iter()
+                    .enumerate()
+                    .map(|(i, title)| {
+                        let style = if i == active {
+                            Style::default().fg(Color::Black).bg(Color::Cyan)
+                        } else {
+                            Style::default().fg(Color::DarkGray)
+                        };
+                        Span::styled(format!(" {}:{} ", i + 1, title), style)
+                    })
+                    .collect();
+                header_block = header_block.title(Line::from(titles));
+            }
+            let header = Paragraph::new(vec![Line::from(header_spans)]).block(header_block);
             f.render_widget(header, chunks[0]);
+            f.render_widget(Paragraph::new(pinned), chunks[1]);
 
             // Messages list — scroll_offset=0 means pinned to bottom.
-            let messages: Vec<ListItem> = app
-                .messages
-                .iter()
-                .map(|m| match m {
-                UiMessage::Chat(chat) => ListItem::new(Line::from(vec![
-                    Span::styled(
-                        &chat.sender,
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
-                    ),
-                    Span::raw(": "),
-                    Span::styled(&chat.content, Style::default().fg(Color::White)),
-                    ])),
-                    UiMessage::System(text) => ListItem::new(Line::from(Span::styled(
-                        format!("• {}", text),
-                        Style::default()
-                            .fg(Color::Yellow)
-                            .add_modifier(Modifier::ITALIC),
-                    ))),
-                    UiMessage::Delete(_) => ListItem::new(Line::from("")),
-                })
-                .collect();
+            messages_area = pane().inner(chunks[2]);
+            let width = messages_area.width as usize;
+            let mut lines: Vec<Line> = Vec::new();
+            line_targets.clear();
+            // The day of the last chat message drawn, and its sender and time
+            // while later ones may still be grouped with it.
+            let mut day: Option<NaiveDate> = None;
+            let mut group: Option<(EndpointId, DateTime<Local>)> = None;
+            for m in &app.messages {
+                if let ChatEvent::Chat(chat) = m {
+                    let date = chat.at.date_naive();
+                    if day != Some(date) {
+                        day = Some(date);
+                        group = None;
+                        lines.push(date_separator(date));
+                        line_targets.push(RowTarget::Nothing);
+                    }
+                    if app.first_unread == Some(chat.id) {
+                        group = None;
+                        lines.push(Line::from(Span::styled(
+                            "──────── new messages ────────",
+                            Style::default().fg(Color::Red),
+                        )));
+                        line_targets.push(RowTarget::Nothing);
+                    }
+                }
+                match m {
+                    ChatEvent::Chat(chat) => {
+                        let grouped = group.is_some_and(|(from, at)| from == chat.from && chat.at - at <= GROUP_WINDOW);
+                        group = Some((chat.from, chat.at));
+                        let (chat_lines, targets) = chat_lines(app, chat, width, grouped, display);
+                        lines.extend(chat_lines);
+                        line_targets.extend(targets);
+                        for _ in 0..display.line_spacing() {
+                            lines.push(Line::default());
+                            line_targets.push(RowTarget::Nothing);
+                        }
+                    }
+                    ChatEvent::System(text) => {
+                        group = None;
+                        let line = Line::from(Span::styled(
+                            format!("• {}", text),
+                            Style::default()
+                                .fg(Color::Yellow)
+                                .add_modifier(Modifier::ITALIC),
+                        ));
+                        for line in wrap_line(&line, width) {
+                            let line = if display.reorder_rtl { visual_line(&line, false) } else { line };
+                            lines.push(line);
+                            line_targets.push(RowTarget::Nothing);
+                        }
+                    }
+                    // Drawn unwrapped and light-on-dark whatever the
+                    // terminal's colours, so it stays scannable.
+                    ChatEvent::Qr(rows) => {
+                        for row in rows {
+                            lines.push(Line::from(Span::styled(
+                                row.clone(),
+                                Style::default().fg(Color::White).bg(Color::Black),
+                            )));
+                            line_targets.push(RowTarget::Nothing);
+                        }
+                    }
+                    // Never stored; handled in App::add_message.
+                    ChatEvent::Delete(_)
+                    | ChatEvent::Pin { .. }
+                    | ChatEvent::Subject { .. }
+                    | ChatEvent::Vote { .. }
+                    | ChatEvent::Name { .. }
+                    | ChatEvent::Presence { .. }
+                    | ChatEvent::Left(_)
+                    | ChatEvent::SendFailed(_)
+                    | ChatEvent::Queued(_)
+                    | ChatEvent::Sent(_)
+                    | ChatEvent::Delivered(_)
+                    | ChatEvent::Recorded(_)
+                    | ChatEvent::Knock { .. }
+                    | ChatEvent::Membership(_)
+                    | ChatEvent::Directory(_) => {}
+                }
+            }
 
-            let total = messages.len();
-            let mut list_state = ListState::default();
-            if total > 0 {
-                let selected = total.saturating_sub(1 + app.scroll_offset);
-                list_state.select(Some(selected));
+            // Scroll in rendered lines: clamp now that the wrapped height is
+            // known, then show the window ending scroll_offset lines above the
+            // bottom.
+            let height = messages_area.height as usize;
+            app.max_scroll = lines.len().saturating_sub(height);
+            if app.reveal_selection {
+                app.reveal_selection = false;
+                app.scroll_offset = match app.selected {
+                    Some(id) => offset_showing(&line_targets, id, height, app.scroll_offset),
+                    None => 0,
+                };
             }
+            app.scroll_offset = app.scroll_offset.min(app.max_scroll);
+            view_top = lines.len().saturating_sub(height + app.scroll_offset);
+            let visible: Vec<Line> = lines.into_iter().skip(view_top).take(height).collect();
 
-            let messages_widget = List::new(messages)
-                .block(Block::default().borders(Borders::ALL).title(
-                    if app.scroll_offset > 0 { "Messages  ↑ scrolled" } else { "Messages" }
-                ))
-                .highlight_style(Style::default());
-            f.render_stateful_widget(messages_widget, chunks[1], &mut list_state);
+            let mut messages_title = String::from(t!("Messages"));
+            if app.unread > 0 {
+                messages_title.push_str(&t!("  ({} unread, {} to jump)", app.unread, key(Action::FirstUnread)));
+            }
+            if app.scroll_offset > 0 {
+                messages_title.push_str(t!("  ↑ scrolled"));
+            }
+            let messages_widget = Paragraph::new(visible)
+                .block(pane().title(messages_title));
+            f.render_widget(messages_widget, chunks[2]);
+
+            if app.net_panel {
+                let lines = net_lines(app, &session.peer_paths());
+                let area = chunks[2].centered(
+                    Constraint::Length(70.min(chunks[2].width)),
+                    Constraint::Length((lines.len() as u16 + 2).min(chunks[2].height)),
+                );
+                f.render_widget(Clear, area);
+                f.render_widget(
+                    Paragraph::new(lines).block(pane().title(t!("Connections  (Esc to close)"))),
+                    area,
+                );
+            }
+
+            if app.swarm_panel {
+                let (links, reports) = session.swarm();
+                let lines = swarm_lines(app, session.my_id, &links, &reports);
+                let area = chunks[2].centered(
+                    Constraint::Length(70.min(chunks[2].width)),
+                    Constraint::Length((lines.len() as u16 + 2).min(chunks[2].height)),
+                );
+                f.render_widget(Clear, area);
+                f.render_widget(
+                    Paragraph::new(lines).block(pane().title(t!("Gossip swarm  (Esc to close)"))),
+                    area,
+                );
+            }
+
+            if app.pins_panel {
+                let lines = pins_panel_lines(app);
+                let area = chunks[2].centered(
+                    Constraint::Length(70.min(chunks[2].width)),
+                    Constraint::Length((lines.len() as u16 + 2).min(chunks[2].height)),
+                );
+                f.render_widget(Clear, area);
+                f.render_widget(
+                    Paragraph::new(lines).block(pane().title(t!("Pinned messages  (Esc to close)"))),
+                    area,
+                );
+            }
 
             // Input box – dim it in Normal mode to signal it's inactive.
             let input_style = match app.mode {
@@ -138,129 +1634,386 @@ pub async fn run_tui(
                 Mode::Normal => Style::default().fg(Color::DarkGray),
             };
             let input_title = match app.mode {
-                Mode::Insert => "Input",
-                Mode::Normal => "Input (press i to type)",
+                Mode::Insert => t!("Input").to_string(),
+                Mode::Normal => t!("Input (press {} to type)", key(Action::Insert)),
             };
+            // Multi-line input (a paste) grows the box, then keeps its last
+            // lines in view.
+            let hidden = app.input.split('\n').count() - input_rows;
+            let mut input_block = pane().title(input_title);
+            // Characters typed against the limit, red once over it.
+            if !app.input.is_empty() {
+                let length = app.input.chars().count();
+                let color = if length > MAX_TEXT { Color::Red } else { Color::DarkGray };
+                input_block = input_block.title(
+                    Line::from(Span::styled(format!(" {}/{} ", length, MAX_TEXT), Style::default().fg(color)))
+                        .right_aligned(),
+                );
+            }
             let input = Paragraph::new(app.input.as_str())
+                .scroll((hidden as u16, 0))
                 .style(input_style)
-                .block(Block::default().borders(Borders::ALL).title(input_title));
-            f.render_widget(input, chunks[2]);
+                .block(input_block);
+            f.render_widget(input, chunks[3]);
 
             // Controls Description Panel.
             let controls_text = match app.mode {
                 Mode::Insert => vec![
                     Line::from(vec![
                         Span::styled("Enter", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
-                        Span::styled("  send message    ", Style::default().fg(Color::Gray)),
+                        Span::styled(t!("  send message    "), Style::default().fg(Color::Gray)),
                         Span::styled("Backspace", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
-                        Span::styled("  delete char    ", Style::default().fg(Color::Gray)),
-                        Span::styled("ESC", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
-                        Span::styled("  normal mode", Style::default().fg(Color::Gray)),
+                        Span::styled(t!("  delete char    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Complete), Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  complete @name    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Normal), Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  normal mode"), Style::default().fg(Color::Gray)),
                     ]),
                 ],
                 Mode::Normal => vec![
                     Line::from(vec![
-                        Span::styled("i", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-                        Span::styled("  insert mode    ", Style::default().fg(Color::Gray)),
-                        Span::styled("↑↓ / Up and Down Arrows", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-                        Span::styled("  scroll    ", Style::default().fg(Color::Gray)),
-                        Span::styled("Ctrl+D", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-                        Span::styled("  delete last msg    ", Style::default().fg(Color::Gray)),
-                        Span::styled("Ctrl+C", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-                        Span::styled("  quit", Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Insert), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  insert mode    "), Style::default().fg(Color::Gray)),
+                        Span::styled(format!("{} {}", key(Action::SelectNewer), key(Action::SelectOlder)), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  select msg    "), Style::default().fg(Color::Gray)),
+                        Span::styled(
+                            format!(
+                                "{} {} {} {}",
+                                key(Action::ScrollUp),
+                                key(Action::ScrollDown),
+                                key(Action::HalfPageUp),
+                                key(Action::HalfPageDown)
+                            ),
+                            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  scroll    "), Style::default().fg(Color::Gray)),
+                        Span::styled(format!("{} {}", key(Action::SelectOldest), key(Action::SelectNewest)), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  oldest/newest    "), Style::default().fg(Color::Gray)),
+                        Span::styled(
+                            format!("{} {}", key(Action::FirstUnread), key(Action::Bottom)),
+                            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                        ),
+                        Span::styled(t!("  first unread/bottom    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Delete), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  delete selected    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Copy), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  copy selected    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Pin), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  pin/unpin    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::FoldPins), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  fold pins    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::DeleteLast), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  delete last msg    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Retry), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  resend failed msg    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Quit), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  quit"), Style::default().fg(Color::Gray)),
+                    ]),
+                    Line::from(vec![
+                        Span::styled(format!("{} {}", key(Action::PrevTab), key(Action::NextTab)), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  switch tab    "), Style::default().fg(Color::Gray)),
+                        Span::styled("/tab open|join|close", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  manage tabs    "), Style::default().fg(Color::Gray)),
+                        Span::styled("1-9", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  press button / vote on selected msg    "), Style::default().fg(Color::Gray)),
+                        Span::styled(key(Action::Play), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                        Span::styled(t!("  play voice msg"), Style::default().fg(Color::Gray)),
                     ]),
                 ],
             };
             let controls = Paragraph::new(controls_text)
-                .block(Block::default().borders(Borders::ALL).title("Controls"));
-            f.render_widget(controls, chunks[3]);
+                .block(Block::default().borders(Borders::ALL).title(t!("Controls")));
+            f.render_widget(controls, chunks[4]);
         })?;
 
         // ── Input handling ────────────────────────────────────────────────────
         if event::poll(std::time::Duration::from_millis(100))? {
-            if let CEvent::Key(key) = event::read()? {
-                match app.mode {
-                    // ── INSERT mode ──────────────────────────────────────────
-                    Mode::Insert => match key.code {
-                        KeyCode::Esc => {
-                            app.mode = Mode::Normal;
-                        }
-                        KeyCode::Char(c) => {
-                            app.input.push(c);
-                        }
-                        KeyCode::Backspace => {
-                            app.input.pop();
-                        }
-                        KeyCode::Enter => {
-                            if !app.input.is_empty() {
-                                let text = app.input.clone();
-                                let id: u64 = rand::random();
-
-                                // Show immediately in our own UI.
-                                app.add_message(UiMessage::Chat(ChatMessage {
-                                    id,
-                                    sender: "You".to_string(),
-                                    content: text.clone(),
-                                }));
-                                // Remember the ID so we can delete it later.
-                                app.my_sent_ids.push(id);
-
-                                let _ = input_tx.send((text, id)).await;
-                                app.input.clear();
+            match event::read()? {
+                CEvent::FocusGained => terminal_focused = true,
+                CEvent::FocusLost => terminal_focused = false,
+                // A paste arrives whole, newlines included, so it becomes one
+                // message rather than a send per line. It always goes to the
+                // input, never to NORMAL mode's keys.
+                CEvent::Paste(text) => {
+                    last_key = Instant::now();
+                    app.completion = None;
+                    app.mode = Mode::Insert;
+                    app.input.push_str(&text.replace("\r\n", "\n").replace('\r', "\n").replace('\t', "    "));
+                }
+                CEvent::Mouse(mouse) => match mouse.kind {
+                    MouseEventKind::ScrollUp => app.scroll_up(3),
+                    MouseEventKind::ScrollDown => app.scroll_down(3),
+                    // Clicking a chat message selects it, clicking one of its
+                    // controls also activates that control, and clicking
+                    // anything else in the pane clears the selection.
+                    MouseEventKind::Down(MouseButton::Left)
+                        if messages_area.contains(Position::new(mouse.column, mouse.row)) =>
+                    {
+                        let row = view_top + (mouse.row - messages_area.y) as usize;
+                        match line_targets.get(row).unwrap_or(&RowTarget::Nothing) {
+                            RowTarget::Nothing => app.selected = None,
+                            RowTarget::Message(id) => app.selected = Some(*id),
+                            RowTarget::Controls { message, author, hits } => {
+                                app.selected = Some(*message);
+                                let column = mouse.column - messages_area.x;
+                                if let Some((_, _, interaction)) = hits
+                                    .iter()
+                                    .find(|(start, end, _)| (*start..*end).contains(&column))
+                                {
+                                    activate(app, session, *author, interaction.clone()).await;
+                                }
                             }
                         }
-                        _ => {}
-                    },
-
-                    // ── NORMAL Mode ──────────────────────────────────────────
-                    Mode::Normal => match key.code {
-                        // Return to typing.
-                        KeyCode::Char('i') => {
-                            app.mode = Mode::Insert;
-                        }
+                    }
+                    _ => {}
+                },
+                CEvent::Key(key) => {
+                    last_key = Instant::now();
+                    if key.code != KeyCode::Tab {
+                        app.completion = None;
+                    }
+                    let pending_key = app.pending_key.take();
+                    let lookup = app.keys.lookup(&app.mode, pending_key, &key);
+                    let count = app.count.take();
+                    let by = count.unwrap_or(1);
+                    match app.mode {
+                        // ── INSERT mode ──────────────────────────────────────────
+                        Mode::Insert => match key.code {
+                            _ if lookup == Lookup::Action(Action::Normal) && app.close_panel() => {}
+                            _ if lookup == Lookup::Action(Action::Normal) => {
+                                app.mode = Mode::Normal;
+                            }
+                            _ if lookup == Lookup::Action(Action::Complete) => {
+                                app.complete_mention();
+                            }
+                            KeyCode::Char(c) => {
+                                app.input.push(c);
+                            }
+                            KeyCode::Backspace => {
+                                app.input.pop();
+                            }
+                            KeyCode::Enter if commands::is_command(&app.input) => {
+                                let input = std::mem::take(&mut app.input);
+                                if let Some(action) = run_command(app, session, &input).await {
+                                    tab_action = Some(action);
+                                }
+                            }
+                            KeyCode::Enter if !app.input.is_empty() => {
+                                let payload = ChatPayload::text(app.input.clone());
+                                // Too long: keep the text, so it can be shortened.
+                                if !too_long(app, &payload.text) {
+                                    send_chat(app, session, payload).await;
+                                    app.input.clear();
+                                }
+                            }
+                            _ => {}
+                        },
 
-                        // Scroll up/down.
-                        KeyCode::Up => { app.scroll_up(10); }
-                        KeyCode::Down => { app.scroll_down(10); }
+                        // ── NORMAL Mode ──────────────────────────────────────────
+                        Mode::Normal => match lookup {
+                            Lookup::Pending(first) => {
+                                app.pending_key = Some(first);
+                                app.count = count;
+                            }
+                            Lookup::Action(Action::Back) if app.close_panel() => {}
+                            Lookup::Action(Action::Back) => app.selected = None,
+                            // Return to typing.
+                            Lookup::Action(Action::Insert) => {
+                                app.mode = Mode::Insert;
+                            }
 
-                        // Quit.
-                        KeyCode::Char('c')
-                            if key.modifiers.contains(event::KeyModifiers::CONTROL) =>
-                        {
-                            break;
-                        }
+                            // Scroll up/down.
+                            Lookup::Action(Action::ScrollUp) => { app.scroll_up(10 * by); }
+                            Lookup::Action(Action::ScrollDown) => { app.scroll_down(10 * by); }
+                            Lookup::Action(Action::HalfPageUp) => app.scroll_up(half_page(messages_area) * by),
+                            Lookup::Action(Action::HalfPageDown) => app.scroll_down(half_page(messages_area) * by),
 
-                        // Delete our most recent message on all peers.
-                        KeyCode::Char('d')
-                            if key.modifiers.contains(event::KeyModifiers::CONTROL) =>
-                        {
-                            if let Some(id) = app.my_sent_ids.pop() {
-                                // Remove locally first for instant feedback.
-                                app.add_message(UiMessage::Delete(id));
-                                // Broadcast the deletion to all peers.
-                                let _ = delete_tx.send(id).await;
-                            } else {
-                                app.add_message(UiMessage::System(
-                                    "No messages to delete.".to_string(),
-                                ));
+                            // Move the selection to an older/newer message.
+                            Lookup::Action(Action::SelectOlder) => app.move_selection(true, by),
+                            Lookup::Action(Action::SelectNewer) => app.move_selection(false, by),
+                            Lookup::Action(Action::SelectOldest) => app.jump_selection(true),
+                            Lookup::Action(Action::SelectNewest) => app.jump_selection(false),
+                            Lookup::Action(Action::FirstUnread) if !app.jump_to_unread() => {
+                                app.add_message(ChatEvent::System(t!("No unread messages.").to_string()));
+                            }
+                            Lookup::Action(Action::FirstUnread) => {}
+                            Lookup::Action(Action::Bottom) => app.jump_to_bottom(),
+
+                            // Pin or unpin the selected message for everyone.
+                            Lookup::Action(Action::Pin) => match app.selected_chat().map(|chat| chat.id) {
+                                Some(_) if session.moderator.is_some() && app.role(session.my_id) != Role::Admin => {
+                                    app.add_message(ChatEvent::System(t!("Only admins can pin messages.").to_string()));
+                                }
+                                Some(id) => {
+                                    let pinned = !app.pins.iter().any(|(p, _)| *p == id);
+                                    let _ = session.commands.send(ChatCommand::Pin { id, pinned }).await;
+                                    // Gossip does not echo it back; apply it here.
+                                    let name = session.my_name.clone();
+                                    app.add_message(ChatEvent::Pin { id, name, pinned });
+                                }
+                                None => app.add_message(ChatEvent::System(
+                                    t!("Select a message with j/k first.").to_string(),
+                                )),
+                            },
+                            // Fold or unfold the pinned strip.
+                            Lookup::Action(Action::FoldPins) => app.pins_collapsed = !app.pins_collapsed,
+
+                            // Copy the selected message.
+                            Lookup::Action(Action::Copy) => match app.selected_chat().map(|chat| chat.content.clone()) {
+                                Some(text) => match copy_to_clipboard(&text) {
+                                    Ok(()) => app.add_message(ChatEvent::System(t!("Copied to the clipboard.").to_string())),
+                                    Err(e) => app.add_message(ChatEvent::System(t!("Could not copy: {}", e))),
+                                },
+                                None => app.add_message(ChatEvent::System(
+                                    t!("Select a message with j/k first.").to_string(),
+                                )),
+                            },
+
+                            // Activate the nth control on the selected message,
+                            // or count for the next motion.
+                            Lookup::Unbound if let KeyCode::Char(c @ '0'..='9') = key.code => {
+                                let digit = c as usize - '0' as usize;
+                                let n = digit.wrapping_sub(1);
+                                let selected = app.selected_chat();
+                                let pressable = selected.is_some_and(|chat| chat.poll.is_some() || !controls(app, chat).is_empty());
+                                let poll = selected
+                                    .and_then(|chat| Some((chat.id, chat.poll.as_ref()?.options.get(n)?.clone())));
+                                let target = selected.and_then(|chat| {
+                                    controls(app, chat)
+                                        .into_iter()
+                                        .nth(n)
+                                        .map(|(_, interaction)| (chat.from, interaction))
+                                });
+                                if count.is_none() && pressable {
+                                    if let Some((poll, option)) = poll {
+                                        vote(app, session, poll, n as u32, &option).await;
+                                    } else if let Some((author, interaction)) = target {
+                                        activate(app, session, author, interaction).await;
+                                    }
+                                } else if count.is_some() || digit > 0 {
+                                    app.count = Some((count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                                }
+                            }
+
+                            // Switch tabs.
+                            Lookup::Action(Action::NextTab) => tab_action = Some(TabAction::Next),
+                            Lookup::Action(Action::PrevTab) => tab_action = Some(TabAction::Prev),
+
+                            // Quit.
+                            Lookup::Action(Action::Quit) => break,
+
+                            // Play a voice message.
+                            Lookup::Action(Action::Play) => match app.voice_clip() {
+                                Some(clip) => session.play_voice(clip),
+                                None => app.add_message(ChatEvent::System(
+                                    t!("No voice messages to play.").to_string(),
+                                )),
+                            },
+
+                            // Send a message that failed again, under the same ID.
+                            Lookup::Action(Action::Retry) => {
+                                match app.retry_failed() {
+                                    Some((id, payload)) => {
+                                        let _ = session.commands.send(ChatCommand::Send { payload, id }).await;
+                                    }
+                                    None => app.add_message(ChatEvent::System(
+                                        t!("No failed messages to resend.").to_string(),
+                                    )),
+                                }
+                            }
+
+                            // Delete the selected message on all peers: ours,
+                            // or anyone's for admins.
+                            Lookup::Action(Action::Delete) => {
+                                match app.selected_chat() {
+                                    Some(chat)
+                                        if chat.from == session.my_id
+                                            || app.role(session.my_id) == Role::Admin =>
+                                    {
+                                        let id = chat.id;
+                                        app.add_message(ChatEvent::Delete(id));
+                                        let _ = session.commands.send(ChatCommand::Delete(id)).await;
+                                    }
+                                    Some(_) => app.add_message(ChatEvent::System(
+                                        t!("You can only delete your own messages.").to_string(),
+                                    )),
+                                    None => app.add_message(ChatEvent::System(
+                                        t!("Select a message with j/k first.").to_string(),
+                                    )),
+                                }
+                            }
+
+                            // Delete our most recent message on all peers.
+                            Lookup::Action(Action::DeleteLast) => {
+                                // Admins delete the selected message, whoever wrote it.
+                                let selected = app
+                                    .selected_chat()
+                                    .filter(|chat| chat.from != session.my_id)
+                                    .map(|chat| chat.id)
+                                    .filter(|_| app.role(session.my_id) == Role::Admin);
+                                if let Some(id) = selected.or_else(|| app.my_sent_ids.pop()) {
+                                    // Remove locally first for instant feedback.
+                                    app.add_message(ChatEvent::Delete(id));
+                                    // Broadcast the deletion to all peers.
+                                    let _ = session.commands.send(ChatCommand::Delete(id)).await;
+                                } else {
+                                    app.add_message(ChatEvent::System(
+                                        t!("No messages to delete.").to_string(),
+                                    ));
+                                }
                             }
-                        }
 
-                        _ => {}
-                    },
+                            _ => {}
+                        },
+                    }
                 }
+                _ => {}
             }
         }
+
+        match tab_action {
+            Some(TabAction::Next) => active = (active + 1) % tabs.len(),
+            Some(TabAction::Prev) => active = (active + tabs.len() - 1) % tabs.len(),
+            Some(TabAction::Open(profile)) => match start_tab(&profile, Entry::Open { private: false, title: None }, tabs[active].session.net.for_tab()).await {
+                Ok(tab) => {
+                    tabs.push(tab);
+                    active = tabs.len() - 1;
+                }
+                Err(e) => tabs[active]
+                    .app
+                    .add_message(ChatEvent::System(t!("Could not open tab: {}", e))),
+            },
+            Some(TabAction::Join(profile, ticket)) => {
+                match start_tab(&profile, Entry::Join(ticket), tabs[active].session.net.for_tab()).await {
+                    Ok(tab) => {
+                        tabs.push(tab);
+                        active = tabs.len() - 1;
+                    }
+                    Err(e) => tabs[active]
+                        .app
+                        .add_message(ChatEvent::System(t!("Could not join: {}", e))),
+                }
+            }
+            Some(TabAction::Close) if tabs.len() == 1 => {
+                tabs[active].app.add_message(ChatEvent::System(
+                    t!("This is the last tab – use Ctrl+C in NORMAL mode to quit.").to_string(),
+                ));
+            }
+            Some(TabAction::Close) => {
+                let tab = tabs.remove(active);
+                active = active.min(tabs.len() - 1);
+                let _ = tab.session.shutdown().await;
+            }
+            // Before the drafts are saved again at the top of the loop.
+            Some(TabAction::Wipe(request)) => {
+                wipe = Some(request);
+                break;
+            }
+            None => {}
+        }
     }
 
     // Restore terminal.
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-
-    Ok(())
+    drop(guard);
+
+    Ok((tabs.into_iter().map(|t| t.session).collect(), wipe))
 }
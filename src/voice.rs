@@ -0,0 +1,216 @@
+use anyhow::Result;
+
+use crate::protocol::VoiceClip;
+
+#[cfg(feature = "voice")]
+use {
+    anyhow::{bail, Context},
+    cpal::{
+        traits::{DeviceTrait, HostTrait, StreamTrait},
+        FromSample, Sample, SampleFormat, SizedSample, StreamConfig,
+    },
+    data_encoding::BASE64,
+    std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tracing::warn,
+};
+
+#[cfg(feature = "voice")]
+use crate::protocol::VOICE_FRAMES_PER_SECOND;
+
+// ── Voice messages ────────────────────────────────────────────────────────────
+
+/// Clips are encoded at this rate, plenty for speech.
+#[cfg(feature = "voice")]
+const SAMPLE_RATE: u32 = 16_000;
+
+/// Samples in one Opus frame.
+#[cfg(feature = "voice")]
+const FRAME: usize = (SAMPLE_RATE as u64 / VOICE_FRAMES_PER_SECOND) as usize;
+
+/// Opus bitrate: 16 kbit/s keeps a 30 s clip around 60 KiB.
+#[cfg(feature = "voice")]
+const BITRATE: i32 = 16_000;
+
+/// Largest Opus packet we accept from the encoder or a peer.
+#[cfg(feature = "voice")]
+const MAX_PACKET: usize = 4000;
+
+#[cfg(not(feature = "voice"))]
+const NO_VOICE: &str = "voice messages are not available in this build (rebuild with --features voice)";
+
+/*
+Function:   -record
+Purpose:    -Record a clip from the default microphone.
+
+Parameters:
+            - u64 seconds:  How long to record; at most MAX_VOICE_SECONDS.
+
+Details:
+            - Blocks for the whole recording; run it off the async runtime.
+            - Mixes down to mono and resamples to SAMPLE_RATE before encoding,
+              whatever the device delivers.
+*/
+#[cfg(feature = "voice")]
+pub fn record(seconds: u64) -> Result<VoiceClip> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .context("no microphone found")?;
+    let config = device.default_input_config()?;
+    let rate = config.sample_rate();
+    let samples: Arc<Mutex<Vec<f32>>> = Arc::default();
+    let stream = match config.sample_format() {
+        SampleFormat::I16 => capture::<i16>(&device, config.config(), samples.clone())?,
+        SampleFormat::I32 => capture::<i32>(&device, config.config(), samples.clone())?,
+        SampleFormat::F32 => capture::<f32>(&device, config.config(), samples.clone())?,
+        format => bail!("unsupported microphone sample format {}", format),
+    };
+    stream.play()?;
+    std::thread::sleep(Duration::from_secs(seconds));
+    drop(stream);
+    let samples = std::mem::take(&mut *samples.lock().expect("voice buffer lock poisoned"));
+    encode(&resample(&samples, rate, SAMPLE_RATE))
+}
+
+#[cfg(not(feature = "voice"))]
+pub fn record(_seconds: u64) -> Result<VoiceClip> {
+    anyhow::bail!(NO_VOICE)
+}
+
+/*
+Function:   -play
+Purpose:    -Play a clip on the default output device.
+
+Details:
+            - Blocks until the clip has played; run it off the async runtime.
+            - A packet that does not decode is an error; nothing is played.
+*/
+#[cfg(feature = "voice")]
+pub fn play(clip: &VoiceClip) -> Result<()> {
+    let samples = decode(clip)?;
+    let device = cpal::default_host()
+        .default_output_device()
+        .context("no audio output found")?;
+    let config = device.default_output_config()?;
+    let rate = config.sample_rate();
+    let samples = resample(&samples, SAMPLE_RATE, rate);
+    let length = Duration::from_secs_f64(samples.len() as f64 / rate as f64);
+    let stream = match config.sample_format() {
+        SampleFormat::I16 => playback::<i16>(&device, config.config(), samples)?,
+        SampleFormat::I32 => playback::<i32>(&device, config.config(), samples)?,
+        SampleFormat::F32 => playback::<f32>(&device, config.config(), samples)?,
+        format => bail!("unsupported output sample format {}", format),
+    };
+    stream.play()?;
+    // Let the device drain its buffer before the stream goes.
+    std::thread::sleep(length + Duration::from_millis(200));
+    Ok(())
+}
+
+#[cfg(not(feature = "voice"))]
+pub fn play(_clip: &VoiceClip) -> Result<()> {
+    anyhow::bail!(NO_VOICE)
+}
+
+/// Input stream appending everything it hears to `samples`, mixed to mono.
+#[cfg(feature = "voice")]
+fn capture<T>(
+    device: &cpal::Device,
+    config: StreamConfig,
+    samples: Arc<Mutex<Vec<f32>>>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels as usize;
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &_| {
+            let mut samples = samples.lock().expect("voice buffer lock poisoned");
+            samples.extend(data.chunks(channels).map(|frame| {
+                frame.iter().map(|s| f32::from_sample(*s)).sum::<f32>() / channels as f32
+            }));
+        },
+        |e| warn!(error = %e, "microphone error"),
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Output stream playing `samples` on every channel, then silence.
+#[cfg(feature = "voice")]
+fn playback<T>(device: &cpal::Device, config: StreamConfig, samples: Vec<f32>) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let mut samples = samples.into_iter();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &_| {
+            for frame in data.chunks_mut(channels) {
+                frame.fill(T::from_sample(samples.next().unwrap_or(0.0)));
+            }
+        },
+        |e| warn!(error = %e, "audio output error"),
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Linear resampling; good enough for speech.
+#[cfg(feature = "voice")]
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let len = (samples.len() as u64 * to as u64 / from as u64) as usize;
+    let step = from as f64 / to as f64;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let a = samples[index];
+            let b = samples.get(index + 1).copied().unwrap_or(a);
+            a + (b - a) * fraction
+        })
+        .collect()
+}
+
+#[cfg(feature = "voice")]
+fn encode(samples: &[f32]) -> Result<VoiceClip> {
+    let mut encoder = opus::Encoder::new(SAMPLE_RATE, opus::Channels::Mono, opus::Application::Voip)?;
+    encoder.set_bitrate(opus::Bitrate::Bits(BITRATE))?;
+    let mut frames = Vec::new();
+    for chunk in samples.chunks(FRAME) {
+        // Pad the last frame with silence.
+        let mut frame = chunk.to_vec();
+        frame.resize(FRAME, 0.0);
+        frames.push(BASE64.encode(&encoder.encode_vec_float(&frame, MAX_PACKET)?));
+    }
+    Ok(VoiceClip {
+        duration_ms: (samples.len() as u64 * 1000 / SAMPLE_RATE as u64) as u32,
+        frames,
+    })
+}
+
+#[cfg(feature = "voice")]
+fn decode(clip: &VoiceClip) -> Result<Vec<f32>> {
+    let mut decoder = opus::Decoder::new(SAMPLE_RATE, opus::Channels::Mono)?;
+    // Room for the longest frame Opus allows (120 ms).
+    let mut frame = vec![0.0; FRAME * 6];
+    let mut samples = Vec::new();
+    for packet in &clip.frames {
+        let packet = BASE64.decode(packet.as_bytes())?;
+        if packet.len() > MAX_PACKET {
+            bail!("voice packet too large");
+        }
+        let n = decoder.decode_float(&packet, &mut frame, false)?;
+        samples.extend_from_slice(&frame[..n]);
+    }
+    Ok(samples)
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use iroh_gossip::proto::TopicId;
+use reqwest::{Client, StatusCode, Url};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::app::{ChatMessage, Trust};
+use crate::config::WebhookConfig;
+
+// ── Webhook ───────────────────────────────────────────────────────────────────
+
+/// Messages waiting to be posted before new ones are dropped.
+const WEBHOOK_QUEUE: usize = 256;
+
+/// How long one POST may take before it counts as failed.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait before the first retry; doubled for each one after.
+const RETRY_FIRST: Duration = Duration::from_secs(1);
+
+/*
+Struct:     -Webhook
+Purpose:    -Posts every message received in a room, decrypted, as JSON to
+             the URL in the profile's [webhook] table.
+
+Fields:
+            - Sender queue:  Messages for the delivery task, as the JSON
+              body to post.
+
+Details:
+            - The body is {"topic", "id", "from", "sender", "content",
+              "meta", "at"}, like the gateway's chat event; IDs are strings.
+            - Messages are posted one at a time, in the order received. A
+              failed POST (no connection, a timeout, 429 or a 5xx) is retried
+              with doubling waits up to `retries` times, then given up on;
+              any other status is not retried.
+            - Posting never holds up the room: when the endpoint falls too
+              far behind, new messages are dropped with a warning.
+            - Our own messages and ones that did not decrypt are not posted.
+*/
+#[derive(Clone)]
+pub struct Webhook {
+    queue: mpsc::Sender<Value>,
+    topic: TopicId,
+}
+
+impl Webhook {
+    /// Start posting for a room, if the config names a URL.
+    pub fn start(config: &WebhookConfig, topic: TopicId) -> Result<Option<Self>> {
+        let Some(url) = &config.url else {
+            return Ok(None);
+        };
+        let url = Url::parse(url).with_context(|| format!("invalid webhook.url {:?}", url))?;
+        let client = Client::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+        let (queue, rx) = mpsc::channel(WEBHOOK_QUEUE);
+        tokio::spawn(deliver(client, url, config.retries, rx));
+        Ok(Some(Self { queue, topic }))
+    }
+
+    /// Queue a received message for posting.
+    pub fn post(&self, msg: &ChatMessage) {
+        if msg.trust == Trust::Failed {
+            return;
+        }
+        let body = json!({
+            "topic": self.topic.to_string(),
+            "id": msg.id.to_string(),
+            "from": msg.from.to_string(),
+            "sender": msg.sender,
+            "content": msg.content,
+            "meta": msg.meta,
+            "at": msg.at.to_rfc3339(),
+        });
+        if self.queue.try_send(body).is_err() {
+            warn!(id = msg.id, "webhook is falling behind; dropping a message");
+        }
+    }
+}
+
+/// Post queued messages in order until the room closes.
+async fn deliver(client: Client, url: Url, retries: u32, mut rx: mpsc::Receiver<Value>) {
+    while let Some(body) = rx.recv().await {
+        let mut wait = RETRY_FIRST;
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                tokio::time::sleep(wait).await;
+                wait *= 2;
+            }
+            match client.post(url.clone()).json(&body).send().await {
+                Ok(response) if response.status().is_success() => {
+                    debug!(status = %response.status(), "webhook posted");
+                    break;
+                }
+                Ok(response) if retryable(response.status()) => {
+                    warn!(status = %response.status(), attempt, "webhook failed");
+                }
+                Ok(response) => {
+                    warn!(status = %response.status(), "webhook refused a message; not retrying");
+                    break;
+                }
+                Err(e) => warn!(error = %e, attempt, "webhook failed"),
+            }
+        }
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
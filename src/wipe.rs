@@ -0,0 +1,144 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::Config;
+use crate::keychain;
+use crate::profile::Profile;
+use crate::roomkeys::CreatorKeys;
+
+// ── Wiping local data ─────────────────────────────────────────────────────────
+
+/// A wipe asked for with `/wipe yes`, carried out once the chat has shut
+/// down, so nothing it still runs writes the files back.
+#[derive(Clone, Copy, Debug)]
+pub struct Request {
+    /// Delete the identity key too.
+    pub identity: bool,
+}
+
+/*
+Function:   -wipe
+Purpose:    -Securely delete what a profile remembers about its rooms:
+             history, drafts, remembered rooms, members' names and per-room
+             nicknames, the keys of private rooms it created, and
+             the identity key if asked.
+
+Parameters:
+            - &Profile profile:  Whose data to delete.
+            - bool identity:  Delete the identity key as well, making the
+              profile a stranger to every room on its next start.
+
+Details:
+            - Returns the number of files deleted.
+            - Each file is overwritten with random bytes and flushed to disk
+              before it is removed. On SSDs and copy-on-write filesystems the
+              old blocks may survive that; full-disk encryption is the only
+              real protection there.
+            - Archive and room keys and, with `identity`, the identity key are removed
+              from the OS keychain too if the profile uses it.
+            - The config is kept, bar the per-room nicknames, which name the
+              rooms joined. So are the backups hosted for other peers: they
+              are theirs, and sealed.
+            - Keeps going past a file it cannot delete and fails at the end,
+              so one stuck file does not leave the rest behind.
+            - Sessions of the profile must be shut down first: they save
+              drafts and archives as they go.
+*/
+pub fn wipe(profile: &Profile, identity: bool) -> Result<usize> {
+    let mut config = Config::load(&profile.config_path())?;
+    let mut files = Vec::new();
+    let mut topics = Vec::new();
+    collect(&profile.store_dir(), &mut files)?;
+    for path in &files {
+        if path.parent().and_then(Path::file_name).is_some_and(|dir| dir == "archive")
+            && let Some(topic) = path.file_stem()
+        {
+            topics.push(topic.to_string_lossy().into_owned());
+        }
+    }
+    let room_keys = CreatorKeys::new(profile)?.topics();
+    files.extend([profile.drafts_path(), profile.rooms_path(), profile.names_path(), profile.room_keys_path()]);
+    if identity {
+        files.push(profile.identity_path());
+    }
+
+    let mut failures = Vec::new();
+    let mut wiped = 0;
+    for path in files.iter().filter(|path| path.exists()) {
+        match shred(path) {
+            Ok(()) => wiped += 1,
+            Err(e) => failures.push(e),
+        }
+    }
+    if profile.store_dir().exists()
+        && let Err(e) = std::fs::remove_dir_all(profile.store_dir())
+    {
+        failures.push(anyhow::Error::new(e).context(format!("removing {}", profile.store_dir().display())));
+    }
+    if !config.room_names.is_empty() {
+        config.room_names.clear();
+        if let Err(e) = config.save(&profile.config_path()) {
+            failures.push(e);
+        }
+    }
+    if config.keychain {
+        topics.dedup();
+        let mut accounts: Vec<String> =
+            topics.iter().map(|topic| profile.keychain_account(&format!("archive/{}", topic))).collect();
+        accounts.extend(room_keys.iter().map(|topic| profile.keychain_account(&format!("room-key/{}", topic))));
+        if identity {
+            accounts.push(profile.keychain_account("identity"));
+        }
+        for account in accounts {
+            if let Err(e) = keychain::delete(&account) {
+                failures.push(e);
+            }
+        }
+    }
+
+    if let Some(first) = failures.first() {
+        bail!("{} item(s) could not be wiped; the first: {:#}", failures.len(), first);
+    }
+    Ok(wiped)
+}
+
+/// Every file under `dir`, at any depth.
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Overwrite a file with random bytes, flush that to disk and remove it.
+fn shred(path: &Path) -> Result<()> {
+    let len = std::fs::metadata(path).with_context(|| format!("reading {}", path.display()))?.len();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let mut block = [0u8; 4096];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(block.len() as u64) as usize;
+        rand::fill(&mut block[..n]);
+        file.write_all(&block[..n]).with_context(|| format!("overwriting {}", path.display()))?;
+        left -= n as u64;
+    }
+    file.sync_all().with_context(|| format!("overwriting {}", path.display()))?;
+    drop(file);
+    std::fs::remove_file(path).with_context(|| format!("removing {}", path.display()))
+}
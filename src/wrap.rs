@@ -0,0 +1,123 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_width::UnicodeWidthStr;
+
+// ── Word wrapping ─────────────────────────────────────────────────────────────
+
+/*
+Function:   -wrap_line
+Purpose:    -Word-wrap a styled line to a maximum display width.
+
+Parameters:
+            - &Line line:  The line to wrap. Span styles are preserved.
+            - usize width:  Maximum width in terminal columns.
+
+Details:
+            - Breaks at whitespace; whitespace at a break is dropped.
+            - Words wider than the whole line are split by character.
+            - Widths are measured in terminal columns (unicode-width), so wide
+              characters such as CJK and emoji wrap correctly.
+            - Embedded newlines start a new line; whitespace right after one
+              is kept, so pasted code keeps its indentation.
+            - Always returns at least one line, so empty messages still take up
+              a row and every message has a predictable height.
+*/
+pub fn wrap_line(line: &Line, width: usize) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let mut out: Vec<Line<'static>> = Vec::new();
+    let mut current: Vec<Span<'static>> = Vec::new();
+    let mut current_width = 0;
+    let mut after_newline = false;
+
+    for span in &line.spans {
+        for token in tokens(&span.content) {
+            if token == "\n" {
+                out.push(Line::from(std::mem::take(&mut current)));
+                current_width = 0;
+                after_newline = true;
+                continue;
+            }
+            let indent = std::mem::take(&mut after_newline) && current_width == 0;
+            let token_width = token.width();
+            if token.chars().all(char::is_whitespace) {
+                // Keep inner spacing and indentation, but never start a
+                // wrapped line with it.
+                if (current_width > 0 || indent) && current_width + token_width <= width {
+                    push(&mut current, token, span.style);
+                    current_width += token_width;
+                } else if current_width > 0 {
+                    out.push(Line::from(std::mem::take(&mut current)));
+                    current_width = 0;
+                }
+                continue;
+            }
+            if current_width + token_width > width && current_width > 0 {
+                out.push(Line::from(std::mem::take(&mut current)));
+                current_width = 0;
+            }
+            if token_width <= width {
+                push(&mut current, token, span.style);
+                current_width += token_width;
+                continue;
+            }
+            // A single word longer than the line: hard-split it.
+            for c in token.chars() {
+                let mut buf = [0; 4];
+                let c = c.encode_utf8(&mut buf);
+                let w = c.width();
+                if current_width + w > width && current_width > 0 {
+                    out.push(Line::from(std::mem::take(&mut current)));
+                    current_width = 0;
+                }
+                push(&mut current, c, span.style);
+                current_width += w;
+            }
+        }
+    }
+    if !current.is_empty() || out.is_empty() {
+        out.push(Line::from(current));
+    }
+    out
+}
+
+/// Split text into runs of non-whitespace, runs of whitespace, and newlines.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut last_space: Option<bool> = None;
+    for (i, c) in text.char_indices() {
+        if c == '\n' {
+            if start < i {
+                out.push(&text[start..i]);
+            }
+            out.push("\n");
+            start = i + 1;
+            last_space = None;
+            continue;
+        }
+        let space = c.is_whitespace();
+        if last_space.is_some_and(|last| last != space) {
+            out.push(&text[start..i]);
+            start = i;
+        }
+        last_space = Some(space);
+    }
+    if start < text.len() {
+        out.push(&text[start..]);
+    }
+    out
+}
+
+/// Append text to a line under construction, merging with the previous span
+/// when the style matches to keep the span count down.
+fn push(spans: &mut Vec<Span<'static>>, text: &str, style: Style) {
+    if let Some(last) = spans.last_mut()
+        && last.style == style
+    {
+        last.content.to_mut().push_str(text);
+        return;
+    }
+    spans.push(Span::styled(text.to_string(), style));
+}